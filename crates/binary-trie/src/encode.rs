@@ -0,0 +1,152 @@
+//! Witness encoding of the nodes.
+//!
+//! An internal node is encoded as `0x00 || left hash || right hash` and a stem node as
+//! `0x01 || stem || bitmap || values`, where the 256-bit bitmap flags the present subindexes, most
+//! significant bit first, and the 32-byte values follow in subindex order. Nodes are looked up by
+//! the hash of the decoded node, not of its encoding.
+use crate::node::{hash_pair, StemNode, STEM_LEN};
+use alloc::vec::Vec;
+use alloy_primitives::{Bytes, B256};
+use core::fmt;
+
+const INTERNAL: u8 = 0;
+const STEM: u8 = 1;
+
+/// A witness node is not a valid encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The node starts with an unknown tag.
+    UnknownTag(u8),
+    /// The node is shorter or longer than its tag and bitmap require.
+    InvalidLength,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownTag(tag) => write!(f, "unknown node tag {tag}"),
+            Self::InvalidLength => write!(f, "invalid node length"),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// A node decoded from a witness.
+#[derive(Debug)]
+pub(crate) enum DecodedNode {
+    Internal([B256; 2]),
+    Stem(StemNode),
+}
+
+impl DecodedNode {
+    pub(crate) fn hash(&mut self) -> B256 {
+        match self {
+            Self::Internal([left, right]) => hash_pair(left, right),
+            Self::Stem(node) => node.hash(),
+        }
+    }
+}
+
+pub(crate) fn encode_internal(left: &B256, right: &B256) -> Bytes {
+    let mut out = Vec::with_capacity(65);
+    out.push(INTERNAL);
+    out.extend_from_slice(left.as_slice());
+    out.extend_from_slice(right.as_slice());
+    out.into()
+}
+
+pub(crate) fn encode_stem(node: &StemNode) -> Bytes {
+    let mut out = Vec::with_capacity(1 + STEM_LEN + 32 + 32 * node.values.len());
+    out.push(STEM);
+    out.extend_from_slice(&node.stem);
+    let mut bitmap = [0_u8; 32];
+    for subindex in node.values.keys() {
+        bitmap[usize::from(subindex / 8)] |= 0x80 >> (subindex % 8);
+    }
+    out.extend_from_slice(&bitmap);
+    for value in node.values.values() {
+        out.extend_from_slice(value.as_slice());
+    }
+    out.into()
+}
+
+pub(crate) fn decode(data: &[u8]) -> Result<DecodedNode, DecodeError> {
+    let (&tag, data) = data.split_first().ok_or(DecodeError::InvalidLength)?;
+    match tag {
+        INTERNAL => {
+            if data.len() != 64 {
+                return Err(DecodeError::InvalidLength);
+            }
+            let (left, right) = data.split_at(32);
+            Ok(DecodedNode::Internal([
+                B256::from_slice(left),
+                B256::from_slice(right),
+            ]))
+        }
+        STEM => {
+            if data.len() < STEM_LEN + 32 {
+                return Err(DecodeError::InvalidLength);
+            }
+            let (stem, data) = data.split_at(STEM_LEN);
+            let (bitmap, values) = data.split_at(32);
+            let subindexes = (0..=u8::MAX).filter(|subindex| {
+                bitmap[usize::from(subindex / 8)] & (0x80 >> (subindex % 8)) != 0
+            });
+            let count = bitmap
+                .iter()
+                .map(|byte| byte.count_ones() as usize)
+                .sum::<usize>();
+            if values.len() != 32 * count {
+                return Err(DecodeError::InvalidLength);
+            }
+
+            let mut node = StemNode::new(stem.try_into().expect("stem length"));
+            node.values = subindexes
+                .zip(values.chunks_exact(32).map(B256::from_slice))
+                .collect();
+            Ok(DecodedNode::Stem(node))
+        }
+        tag => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stem_roundtrip() {
+        let mut node = StemNode::new([7; STEM_LEN]);
+        node.values.insert(0, B256::repeat_byte(1));
+        node.values.insert(9, B256::repeat_byte(2));
+        node.values.insert(255, B256::repeat_byte(3));
+        let encoded = encode_stem(&node);
+        assert_eq!(encoded.len(), 1 + STEM_LEN + 32 + 3 * 32);
+
+        let DecodedNode::Stem(mut decoded) = decode(&encoded).unwrap() else {
+            panic!("expected a stem node");
+        };
+        assert_eq!(decoded.stem, node.stem);
+        assert_eq!(decoded.values, node.values);
+        assert_eq!(decoded.hash(), node.hash());
+    }
+
+    #[test]
+    fn invalid_nodes() {
+        assert_eq!(decode(&[]).unwrap_err(), DecodeError::InvalidLength);
+        assert_eq!(decode(&[2; 65]).unwrap_err(), DecodeError::UnknownTag(2));
+        assert_eq!(
+            decode(&[INTERNAL; 64]).unwrap_err(),
+            DecodeError::InvalidLength
+        );
+
+        let mut node = StemNode::new([7; STEM_LEN]);
+        node.values.insert(1, B256::repeat_byte(1));
+        let encoded = encode_stem(&node);
+        assert_eq!(
+            decode(&encoded[..encoded.len() - 1]).unwrap_err(),
+            DecodeError::InvalidLength
+        );
+    }
+}
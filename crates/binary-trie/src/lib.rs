@@ -0,0 +1,19 @@
+//! An experimental sparse binary trie in the style of EIP-7864, with the same witness-driven API as
+//! `ref-mpt`, to compare witness sizes and guest cycles with the hexary MPT.
+//!
+//! Keys are 32 bytes: a 31-byte stem selecting a stem node and a subindex selecting one of the 256
+//! 32-byte values of that stem. Internal nodes branch on the bits of the stem, most significant
+//! first, and a subtree holding a single stem is represented by its stem node. Nodes are hashed with
+//! SHA-256, where an empty subtree hashes to zero.
+#![no_std]
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+mod encode;
+mod node;
+mod trie;
+
+pub use alloy_primitives::B256;
+pub use encode::DecodeError;
+pub use trie::BinaryTrie;
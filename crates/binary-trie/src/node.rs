@@ -0,0 +1,136 @@
+//! Node types of the binary trie and their hashes.
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use alloy_primitives::B256;
+use sha2::{Digest as _, Sha256};
+
+/// Length of a stem, the key without its last byte.
+pub(crate) const STEM_LEN: usize = 31;
+
+/// The first 31 bytes of a key, shared by the 256 values of a stem node.
+pub(crate) type Stem = [u8; STEM_LEN];
+
+/// Splits a key into its stem and subindex.
+pub(crate) fn split_key(key: B256) -> (Stem, u8) {
+    let mut stem = [0; STEM_LEN];
+    stem.copy_from_slice(&key[..STEM_LEN]);
+    (stem, key[STEM_LEN])
+}
+
+/// Returns the bit of the stem an internal node at `depth` branches on, most significant first.
+pub(crate) fn bit(stem: &Stem, depth: usize) -> usize {
+    usize::from((stem[depth / 8] >> (7 - depth % 8)) & 1)
+}
+
+/// SHA-256 of `data`, except that 64 zero bytes, i.e. a pair of empty subtrees, hash to zero.
+pub(crate) fn hash(data: &[u8]) -> B256 {
+    if data.len() == 64 && data.iter().all(|byte| *byte == 0) {
+        return B256::ZERO;
+    }
+    B256::from_slice(&Sha256::digest(data))
+}
+
+/// Hash of the concatenation of two hashes.
+pub(crate) fn hash_pair(left: &B256, right: &B256) -> B256 {
+    let mut data = [0; 64];
+    data[..32].copy_from_slice(left.as_slice());
+    data[32..].copy_from_slice(right.as_slice());
+    hash(&data)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct InternalNode {
+    pub(crate) children: [Node; 2],
+    pub(crate) hash: Option<B256>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StemNode {
+    pub(crate) stem: Stem,
+    pub(crate) values: BTreeMap<u8, B256>,
+    pub(crate) hash: Option<B256>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) enum Node {
+    #[default]
+    Empty,
+    Internal(Box<InternalNode>),
+    Stem(Box<StemNode>),
+    /// A subtree only known by its hash.
+    Digest(B256),
+}
+
+impl StemNode {
+    pub(crate) const fn new(stem: Stem) -> Self {
+        Self {
+            stem,
+            values: BTreeMap::new(),
+            hash: None,
+        }
+    }
+
+    /// Merkle root of the hashes of the 256 values, where absent values hash to zero.
+    fn values_root(&self) -> B256 {
+        let mut level: Vec<B256> = (0..=u8::MAX)
+            .map(|subindex| {
+                self.values
+                    .get(&subindex)
+                    .map_or(B256::ZERO, |value| hash(value.as_slice()))
+            })
+            .collect();
+        while level.len() > 1 {
+            level = level
+                .chunks_exact(2)
+                .map(|pair| hash_pair(&pair[0], &pair[1]))
+                .collect();
+        }
+        level[0]
+    }
+
+    pub(crate) fn hash(&mut self) -> B256 {
+        if let Some(hash) = self.hash {
+            return hash;
+        }
+        // the stem is padded with a zero byte to 32 bytes
+        let mut stem = B256::ZERO;
+        stem[..STEM_LEN].copy_from_slice(&self.stem);
+        let hash = hash_pair(&stem, &self.values_root());
+        self.hash = Some(hash);
+        hash
+    }
+}
+
+impl Node {
+    pub(crate) fn hash(&mut self) -> B256 {
+        match self {
+            Self::Empty => B256::ZERO,
+            Self::Digest(hash) => *hash,
+            Self::Stem(node) => node.hash(),
+            Self::Internal(node) => {
+                if let Some(hash) = node.hash {
+                    return hash;
+                }
+                let [left, right] = &mut node.children;
+                let hash = hash_pair(&left.hash(), &right.hash());
+                node.hash = Some(hash);
+                hash
+            }
+        }
+    }
+
+    /// Returns the hash computed by the last [`Node::hash`] call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has been modified since.
+    pub(crate) fn cached_hash(&self) -> B256 {
+        match self {
+            Self::Empty => B256::ZERO,
+            Self::Digest(hash) => *hash,
+            Self::Stem(node) => node.hash.expect("hashed stem node"),
+            Self::Internal(node) => node.hash.expect("hashed internal node"),
+        }
+    }
+}
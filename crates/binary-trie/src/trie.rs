@@ -0,0 +1,366 @@
+//! The binary trie and its operations.
+use crate::encode::{decode, encode_internal, encode_stem, DecodeError, DecodedNode};
+use crate::node::{bit, split_key, InternalNode, Node, Stem, StemNode};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloy_primitives::map::B256Map;
+use alloy_primitives::{Bytes, B256};
+use core::mem;
+
+/// A sparse binary trie of 32-byte keys and 32-byte values.
+#[derive(Debug, Clone, Default)]
+pub struct BinaryTrie {
+    root: Node,
+}
+
+impl BinaryTrie {
+    /// Creates an empty trie.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value under `key`, overriding any previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below a node only known by its hash.
+    pub fn insert(&mut self, key: B256, value: B256) {
+        let (stem, subindex) = split_key(key);
+        self.root.insert(0, &stem, subindex, value);
+    }
+
+    /// Gets the value under `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below a node only known by its hash.
+    pub fn get(&self, key: B256) -> Option<&B256> {
+        let (stem, subindex) = split_key(key);
+        self.root.get(0, &stem, subindex)
+    }
+
+    /// Removes the value under `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below a node only known by its hash, or if removing it would
+    /// replace an internal node by a sibling only known by its hash, which might be a stem node.
+    pub fn remove(&mut self, key: B256) {
+        let (stem, subindex) = split_key(key);
+        self.root.remove(0, &stem, subindex);
+    }
+
+    /// Returns the root hash of the trie.
+    pub fn hash(&mut self) -> B256 {
+        self.root.hash()
+    }
+
+    /// Builds the trie with the given root from encoded witness nodes. Subtrees whose nodes are
+    /// missing are kept as hashes.
+    pub fn reveal_from_nodes<T: AsRef<[u8]>>(
+        root: B256,
+        nodes: impl IntoIterator<Item = T>,
+    ) -> Result<Self, DecodeError> {
+        let mut by_hash = B256Map::default();
+        for node in nodes {
+            let mut node = decode(node.as_ref())?;
+            by_hash.insert(node.hash(), node);
+        }
+        Ok(Self {
+            root: Node::reveal(root, &by_hash),
+        })
+    }
+
+    /// Returns the encoded nodes on the path from the root towards `key`, root first, which
+    /// reveal the value of the key or its absence.
+    pub fn proof(&mut self, key: B256) -> Vec<Bytes> {
+        self.hash();
+        let (stem, _) = split_key(key);
+        let mut proof = Vec::new();
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            match node {
+                Node::Internal(internal) => {
+                    let [left, right] = &internal.children;
+                    proof.push(encode_internal(&left.cached_hash(), &right.cached_hash()));
+                    node = &internal.children[bit(&stem, depth)];
+                    depth += 1;
+                }
+                Node::Stem(stem_node) => {
+                    proof.push(encode_stem(stem_node));
+                    return proof;
+                }
+                Node::Empty | Node::Digest(_) => return proof,
+            }
+        }
+    }
+}
+
+impl Node {
+    fn reveal(hash: B256, nodes: &B256Map<DecodedNode>) -> Self {
+        if hash.is_zero() {
+            return Self::Empty;
+        }
+        match nodes.get(&hash) {
+            None => Self::Digest(hash),
+            Some(DecodedNode::Internal([left, right])) => Self::Internal(Box::new(InternalNode {
+                children: [Self::reveal(*left, nodes), Self::reveal(*right, nodes)],
+                hash: Some(hash),
+            })),
+            Some(DecodedNode::Stem(node)) => Self::Stem(Box::new(StemNode {
+                stem: node.stem,
+                values: node.values.clone(),
+                hash: Some(hash),
+            })),
+        }
+    }
+
+    fn insert(&mut self, depth: usize, stem: &Stem, subindex: u8, value: B256) {
+        match self {
+            Self::Empty => {
+                let mut node = StemNode::new(*stem);
+                node.values.insert(subindex, value);
+                *self = Self::Stem(Box::new(node));
+            }
+            Self::Digest(_) => panic!("BinaryTrie: Unresolved node access"),
+            Self::Internal(internal) => {
+                internal.hash = None;
+                internal.children[bit(stem, depth)].insert(depth + 1, stem, subindex, value);
+            }
+            Self::Stem(node) if node.stem == *stem => {
+                node.values.insert(subindex, value);
+                node.hash = None;
+            }
+            Self::Stem(_) => {
+                let Self::Stem(existing) = mem::take(self) else {
+                    unreachable!()
+                };
+                let mut node = StemNode::new(*stem);
+                node.values.insert(subindex, value);
+                *self = Self::split(existing, Box::new(node), depth);
+            }
+        }
+    }
+
+    /// Builds the internal nodes below `depth` separating two stem nodes.
+    fn split(a: Box<StemNode>, b: Box<StemNode>, depth: usize) -> Self {
+        let (bit_a, bit_b) = (bit(&a.stem, depth), bit(&b.stem, depth));
+        let mut children = [Self::Empty, Self::Empty];
+        if bit_a == bit_b {
+            children[bit_a] = Self::split(a, b, depth + 1);
+        } else {
+            children[bit_a] = Self::Stem(a);
+            children[bit_b] = Self::Stem(b);
+        }
+        Self::Internal(Box::new(InternalNode {
+            children,
+            hash: None,
+        }))
+    }
+
+    fn get(&self, depth: usize, stem: &Stem, subindex: u8) -> Option<&B256> {
+        match self {
+            Self::Digest(_) => panic!("BinaryTrie: Unresolved node access"),
+            Self::Stem(node) if node.stem == *stem => node.values.get(&subindex),
+            Self::Empty | Self::Stem(_) => None,
+            Self::Internal(internal) => {
+                internal.children[bit(stem, depth)].get(depth + 1, stem, subindex)
+            }
+        }
+    }
+
+    /// Removes the value, returning whether it was present.
+    fn remove(&mut self, depth: usize, stem: &Stem, subindex: u8) -> bool {
+        match self {
+            Self::Empty => false,
+            Self::Digest(_) => panic!("BinaryTrie: Unresolved node access"),
+            Self::Stem(node) => {
+                if node.stem != *stem || node.values.remove(&subindex).is_none() {
+                    return false;
+                }
+                node.hash = None;
+                if node.values.is_empty() {
+                    *self = Self::Empty;
+                }
+                true
+            }
+            Self::Internal(internal) => {
+                let index = bit(stem, depth);
+                if !internal.children[index].remove(depth + 1, stem, subindex) {
+                    return false;
+                }
+                internal.hash = None;
+
+                // a subtree holding a single stem is represented by its stem node
+                let (child, sibling) = (&internal.children[index], &internal.children[1 - index]);
+                let lifted = match (child, sibling) {
+                    (Self::Empty, Self::Empty) => Some(None),
+                    (Self::Empty, Self::Stem(_)) => Some(Some(1 - index)),
+                    (Self::Stem(_), Self::Empty) => Some(Some(index)),
+                    (Self::Empty, Self::Digest(_)) => panic!("BinaryTrie: Unresolved node access"),
+                    _ => None,
+                };
+                match lifted {
+                    Some(Some(lifted)) => *self = mem::take(&mut internal.children[lifted]),
+                    Some(None) => *self = Self::Empty,
+                    None => {}
+                }
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{hash_pair, STEM_LEN};
+    use alloy_primitives::keccak256;
+    use std::collections::BTreeMap;
+
+    /// Computes the root from the definition: a set of stems hashes as its single stem node, or
+    /// as the pair of its halves split on the next bit.
+    fn reference_root(entries: &BTreeMap<B256, B256>) -> B256 {
+        let mut stems: BTreeMap<Stem, StemNode> = BTreeMap::new();
+        for (key, value) in entries {
+            let (stem, subindex) = split_key(*key);
+            stems
+                .entry(stem)
+                .or_insert_with(|| StemNode::new(stem))
+                .values
+                .insert(subindex, *value);
+        }
+        fn subtree(stems: &mut [StemNode], depth: usize) -> B256 {
+            match stems {
+                [] => B256::ZERO,
+                [node] => node.hash(),
+                _ => {
+                    let split = stems.partition_point(|node| bit(&node.stem, depth) == 0);
+                    let (left, right) = stems.split_at_mut(split);
+                    hash_pair(&subtree(left, depth + 1), &subtree(right, depth + 1))
+                }
+            }
+        }
+        subtree(&mut stems.into_values().collect::<Vec<_>>(), 0)
+    }
+
+    /// Keys spread over random stems, plus keys sharing stems and long common stem prefixes.
+    fn entries() -> BTreeMap<B256, B256> {
+        let mut entries = BTreeMap::new();
+        for i in 0_u64..300 {
+            let mut key = keccak256(i.to_be_bytes());
+            match i % 3 {
+                // shares the stem of the key of `i / 5`
+                0 => key[..STEM_LEN].copy_from_slice(&keccak256((i / 5).to_be_bytes())[..STEM_LEN]),
+                // differs from a common stem only in the last stem bits
+                1 => {
+                    key[..STEM_LEN].fill(0xaa);
+                    key[STEM_LEN - 1] = i as u8;
+                }
+                _ => {}
+            }
+            entries.insert(key, keccak256(key));
+        }
+        entries
+    }
+
+    #[test]
+    fn empty_root() {
+        assert_eq!(BinaryTrie::new().hash(), B256::ZERO);
+    }
+
+    #[test]
+    fn inserts_match_reference() {
+        let entries = entries();
+        let mut trie = BinaryTrie::new();
+        // insert in an order unrelated to the key order
+        for (key, value) in entries.iter().rev() {
+            trie.insert(*key, *value);
+        }
+        assert_eq!(trie.hash(), reference_root(&entries));
+        for (key, value) in &entries {
+            assert_eq!(trie.get(*key), Some(value));
+        }
+        assert_eq!(trie.get(B256::repeat_byte(0x55)), None);
+    }
+
+    #[test]
+    fn removals_match_reference() {
+        let mut entries = entries();
+        let mut trie = BinaryTrie::new();
+        for (key, value) in &entries {
+            trie.insert(*key, *value);
+        }
+        let keys: Vec<B256> = entries.keys().copied().collect();
+        for i in 0..keys.len() {
+            // remove in a scattered order, hashing in between so that cached hashes are reset
+            let key = keys[(i * 7) % keys.len()];
+            trie.remove(key);
+            entries.remove(&key);
+            if i % 25 == 0 {
+                assert_eq!(trie.hash(), reference_root(&entries));
+            }
+        }
+        assert!(entries.is_empty());
+        assert_eq!(trie.hash(), B256::ZERO);
+    }
+
+    #[test]
+    fn reveal_from_proofs() {
+        let entries = entries();
+        let mut trie = BinaryTrie::new();
+        for (key, value) in &entries {
+            trie.insert(*key, *value);
+        }
+        let root = trie.hash();
+
+        let keys: Vec<B256> = entries.keys().copied().step_by(10).collect();
+        let absent = B256::repeat_byte(0x55);
+        let nodes: Vec<Bytes> = keys
+            .iter()
+            .chain([&absent])
+            .flat_map(|key| trie.proof(*key))
+            .collect();
+        let mut revealed = BinaryTrie::reveal_from_nodes(root, &nodes).unwrap();
+        assert_eq!(revealed.hash(), root);
+        for key in &keys {
+            assert_eq!(revealed.get(*key), entries.get(key));
+        }
+        assert_eq!(revealed.get(absent), None);
+
+        // updates of revealed keys give the same roots as on the full trie; removals are limited
+        // to stems keeping other values, as emptying a stem may need its sibling
+        let shares_stem = |key: &B256| {
+            entries
+                .keys()
+                .filter(|other| other[..STEM_LEN] == key[..STEM_LEN])
+                .count()
+                > 1
+        };
+        for (i, key) in keys.iter().enumerate() {
+            if i % 2 == 1 && shares_stem(key) {
+                trie.remove(*key);
+                revealed.remove(*key);
+            } else {
+                trie.insert(*key, B256::repeat_byte(1));
+                revealed.insert(*key, B256::repeat_byte(1));
+            }
+        }
+        trie.insert(absent, B256::repeat_byte(2));
+        revealed.insert(absent, B256::repeat_byte(2));
+        assert_eq!(revealed.hash(), trie.hash());
+    }
+
+    #[test]
+    #[should_panic(expected = "BinaryTrie: Unresolved node access")]
+    fn unresolved_access() {
+        let mut trie = BinaryTrie::new();
+        for (key, value) in entries() {
+            trie.insert(key, value);
+        }
+        let root = trie.hash();
+        let revealed = BinaryTrie::reveal_from_nodes(root, Vec::<Bytes>::new()).unwrap();
+        revealed.get(B256::ZERO);
+    }
+}
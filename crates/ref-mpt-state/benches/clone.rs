@@ -0,0 +1,95 @@
+//! Benchmarks of [`SimpleSparseState::clone`], which copies the state trie and shares the
+//! storage tries, with every account and slot of the witness revealed.
+//!
+//! ```text
+//! cargo bench -p ref-mpt-state --bench clone
+//! ```
+// the dependencies of the library are not all used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::private::alloy_rlp;
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_trie::{proof::ProofRetainer, HashBuilder, Nibbles, TrieAccount};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ref_mpt_state::SimpleSparseState;
+use stateless::{ExecutionWitness, StatelessTrie};
+use std::collections::BTreeMap;
+
+/// The number of accounts of the cloned states. Every tenth account has 16 storage slots.
+const SIZES: [u64; 2] = [1_000, 10_000];
+
+/// The number of storage slots of the accounts with storage.
+const SLOTS: u64 = 16;
+
+// Builds a trie with a HashBuilder, appending all its nodes to `nodes`. Returns the root.
+fn build_trie(leaves: &BTreeMap<B256, Vec<u8>>, nodes: &mut Vec<Bytes>) -> B256 {
+    let targets = leaves.keys().map(Nibbles::unpack).collect();
+    let mut hash_builder = HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+    for (key, value) in leaves {
+        hash_builder.add_leaf(Nibbles::unpack(key), value);
+    }
+    let root = hash_builder.root();
+    nodes.extend(hash_builder.take_proof_nodes().values().cloned());
+    root
+}
+
+/// Builds the state of `size` accounts, with their storage tries revealed by reading them.
+fn state(size: u64) -> SimpleSparseState {
+    let addresses: Vec<Address> = (0..size)
+        .map(|i| Address::left_padding_from(&i.to_be_bytes()))
+        .collect();
+    let storage: BTreeMap<B256, Vec<u8>> = (1..=SLOTS)
+        .map(|slot| {
+            let hashed_slot = keccak256(B256::from(U256::from(slot)));
+            (hashed_slot, alloy_rlp::encode(U256::from(slot)))
+        })
+        .collect();
+
+    let mut nodes = Vec::new();
+    let storage_root = build_trie(&storage, &mut nodes);
+    let accounts = addresses
+        .iter()
+        .enumerate()
+        .map(|(i, address)| {
+            let account = TrieAccount {
+                nonce: 1,
+                balance: U256::from(i),
+                storage_root: if i % 10 == 0 {
+                    storage_root
+                } else {
+                    alloy_trie::EMPTY_ROOT_HASH
+                },
+                ..Default::default()
+            };
+            (keccak256(address), alloy_rlp::encode(account))
+        })
+        .collect();
+    let root = build_trie(&accounts, &mut nodes);
+    let witness = ExecutionWitness {
+        state: nodes,
+        ..Default::default()
+    };
+
+    let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+    for address in addresses.iter().step_by(10) {
+        for slot in 1..=SLOTS {
+            state.storage(*address, U256::from(slot)).unwrap();
+        }
+    }
+    state
+}
+
+fn clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("state_clone");
+    for size in SIZES {
+        let state = state(size);
+        group.throughput(Throughput::Elements(size));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &state, |b, state| {
+            b.iter(|| state.clone());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, clone);
+criterion_main!(benches);
@@ -0,0 +1,382 @@
+//! The generators of the states, witnesses and blocks of the benchmarks and examples: models of
+//! the state, the witnesses of their tries, and storage-heavy blocks.
+// each target uses a part of the generators
+#![allow(dead_code)]
+use alloy_primitives::private::alloy_rlp;
+use alloy_primitives::{keccak256, Bytes, B256, KECCAK256_EMPTY, U256};
+use alloy_trie::nodes::{BranchNode, ExtensionNode, LeafNode, RlpNode};
+use alloy_trie::{Nibbles, TrieAccount, TrieMask, EMPTY_ROOT_HASH};
+use reth_primitives_traits::account::Account;
+use reth_trie_common::{HashedPostState, HashedStorage};
+use stateless::ExecutionWitness;
+use std::collections::BTreeMap;
+
+/// The largest bytecode of the accounts, that of the contract size limit.
+const MAX_CODE_SIZE: u64 = 24_576;
+
+/// An account of a [`Model`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ModelAccount {
+    pub(crate) balance: U256,
+    /// The bytecode of a contract.
+    pub(crate) code: Option<Bytes>,
+    /// The non-zero storage slots, by hashed slot.
+    pub(crate) storage: BTreeMap<B256, U256>,
+}
+
+impl ModelAccount {
+    /// Returns the account as written by a block.
+    pub(crate) fn post_state(&self) -> Account {
+        Account {
+            nonce: 0,
+            balance: self.balance,
+            bytecode_hash: self.code.as_ref().map(keccak256),
+        }
+    }
+}
+
+/// Accounts by hashed address.
+pub(crate) type Model = BTreeMap<B256, ModelAccount>;
+
+/// The shape of the hashed keys of the generated tries, whose structure depends on it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum KeyDistribution {
+    /// Hashes of counters, like those of the addresses and slots of a chain.
+    Uniform,
+    /// Groups of 256 keys sharing their first 16 bytes.
+    Clustered,
+    /// Groups of 14 keys forming chains of branches joined by extensions of 3 nibbles.
+    Chains,
+    /// Groups of 16 keys differing only in their last nibble, under extensions of 63 nibbles.
+    NearCollisions,
+}
+
+impl KeyDistribution {
+    /// Returns the key `i`.
+    pub(crate) fn key(self, i: u64) -> B256 {
+        let hash = |i: u64| keccak256(B256::from(U256::from(i)));
+        match self {
+            Self::Uniform => hash(i),
+            Self::Clustered => {
+                let mut key = hash(i);
+                key[..16].copy_from_slice(&hash(i / 256)[..16]);
+                key
+            }
+            Self::Chains => {
+                let mut key = B256::ZERO;
+                key[..4].copy_from_slice(&hash(i / 14)[..4]);
+                // the nibble 11 + 4 * (i % 14), the low one of its byte
+                key[5 + 2 * (i % 14) as usize] = 1;
+                key
+            }
+            Self::NearCollisions => {
+                let mut key = hash(i / 16);
+                key[31] = (key[31] & 0xf0) | (i % 16) as u8;
+                key
+            }
+        }
+    }
+}
+
+/// The storage changes of the accounts with storage in a storage-heavy block.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StorageWrites {
+    /// The percentage of the slots of an account which are zeroed, the others are changed.
+    pub(crate) zeroed: u64,
+    /// The percentage of the accounts which are wiped first, and only write the added slots.
+    pub(crate) wiped: u64,
+    /// The number of slots added to every account.
+    pub(crate) added: u64,
+    /// The percentage of the accounts which are destroyed with their storage.
+    pub(crate) destroyed: u64,
+    /// The percentage of the destroyed accounts which are created again in the same block, with
+    /// a new balance, no bytecode and only the added slots.
+    pub(crate) recreated: u64,
+}
+
+/// A block changing the slots of the accounts with storage, and nothing else.
+pub(crate) const CHANGES: StorageWrites = StorageWrites {
+    zeroed: 0,
+    wiped: 0,
+    added: 0,
+    destroyed: 0,
+    recreated: 0,
+};
+
+/// A block mixing slot changes and removals, wipes, destructions and recreations, adding no slots.
+pub(crate) const MIXED: StorageWrites = StorageWrites {
+    zeroed: 25,
+    wiped: 10,
+    added: 0,
+    destroyed: 10,
+    recreated: 50,
+};
+
+/// Returns whether the item `i` is among the `percent` percent of the items picked, spread evenly.
+pub(crate) const fn picked(i: u64, percent: u64) -> bool {
+    (i + 1) * percent / 100 > i * percent / 100
+}
+
+/// The nibbles of a hashed key.
+type Path = [u8; 64];
+
+/// Returns the nibbles of `key`.
+fn path(key: &B256) -> Path {
+    let mut path = [0; 64];
+    for (i, byte) in key.iter().enumerate() {
+        path[2 * i] = byte >> 4;
+        path[2 * i + 1] = byte & 0x0f;
+    }
+    path
+}
+
+/// Returns the `targets`, sorted and sharing the nibbles of `path` before `from`, that also share
+/// those from `from` to `to`. Without targets, every node is proved.
+fn narrow<'a>(
+    targets: Option<&'a [Path]>,
+    path: &Path,
+    from: usize,
+    to: usize,
+) -> Option<&'a [Path]> {
+    targets.map(|targets| {
+        let start = targets.partition_point(|target| target[from..to] < path[from..to]);
+        let end = targets.partition_point(|target| target[from..to] <= path[from..to]);
+        &targets[start..end]
+    })
+}
+
+/// Returns whether the node at `depth` is proved, being the root or on the path of a target.
+fn proved(targets: Option<&[Path]>, depth: usize) -> bool {
+    depth == 0 || targets.is_none_or(|targets| !targets.is_empty())
+}
+
+/// Returns the reference of the node `rlp`, appending the node to `nodes` if it is hashed and
+/// `proved`.
+fn reference(rlp: Vec<u8>, proved: bool, nodes: &mut Vec<Bytes>) -> RlpNode {
+    let node = RlpNode::from_rlp(&rlp);
+    if node.as_hash().is_some() && proved {
+        nodes.push(rlp.into());
+    }
+    node
+}
+
+/// Encodes the node of the `leaves`, sorted and sharing their first `depth` nibbles, with
+/// [`reference`]. The `targets` share the same nibbles.
+fn encode_node(
+    leaves: &[(Path, Vec<u8>)],
+    targets: Option<&[Path]>,
+    depth: usize,
+    nodes: &mut Vec<Bytes>,
+) -> RlpNode {
+    let (path, value) = &leaves[0];
+    let rlp = if let [_] = leaves {
+        alloy_rlp::encode(LeafNode::new(
+            Nibbles::from_nibbles(&path[depth..]),
+            value.clone(),
+        ))
+    } else {
+        // the keys are distinct, so the first and the last leaves fork
+        let last = &leaves[leaves.len() - 1].0;
+        let fork = (depth..64).find(|&i| path[i] != last[i]).unwrap();
+        let branch_targets = narrow(targets, path, depth, fork);
+        let mut stack = Vec::new();
+        let mut mask = TrieMask::default();
+        for children in leaves.chunk_by(|a, b| a.0[fork] == b.0[fork]) {
+            let child = &children[0].0;
+            mask.set_bit(child[fork]);
+            let child_targets = narrow(branch_targets, child, fork, fork + 1);
+            stack.push(encode_node(children, child_targets, fork + 1, nodes));
+        }
+        let branch = alloy_rlp::encode(BranchNode::new(stack, mask));
+        if fork == depth {
+            branch
+        } else {
+            let child = reference(branch, proved(branch_targets, fork), nodes);
+            alloy_rlp::encode(ExtensionNode::new(
+                Nibbles::from_nibbles(&path[depth..fork]),
+                child,
+            ))
+        }
+    };
+    reference(rlp, proved(targets, depth), nodes)
+}
+
+// Builds the trie of `leaves`, sorted by key, in a single pass over them, appending to `nodes` its
+// nodes on the paths of the `targets`, sorted, or all of them without targets. Returns the root.
+// Unlike a HashBuilder with a ProofRetainer, which matches every node against every target, it
+// scales to the witnesses of the whole state of hundreds of thousands of accounts.
+fn build_trie(
+    leaves: &[(Path, Vec<u8>)],
+    targets: Option<&[Path]>,
+    nodes: &mut Vec<Bytes>,
+) -> B256 {
+    if leaves.is_empty() {
+        return EMPTY_ROOT_HASH;
+    }
+    let root = encode_node(leaves, targets, 0, nodes);
+    root.as_hash().unwrap_or_else(|| {
+        // a root shorter than a hash is inlined in no parent, so it is hashed anyway
+        nodes.push(Bytes::copy_from_slice(&root));
+        keccak256(&root[..])
+    })
+}
+
+/// Returns the bytecode of account `i`, of a deterministic size up to `MAX_CODE_SIZE`.
+fn bytecode(i: u64) -> Bytes {
+    let size = 256 + (i * 997) % (MAX_CODE_SIZE - 255);
+    let word = keccak256(i.to_be_bytes());
+    word.iter().copied().cycle().take(size as usize).collect()
+}
+
+/// Returns the state of `size` accounts, every tenth one with `slots` storage slots, and `code`
+/// percent of them with bytecode. The hashed addresses and slots follow `keys`.
+pub(crate) fn model(size: u64, slots: u64, code: u64, keys: KeyDistribution) -> Model {
+    (0..size)
+        .map(|i| {
+            let storage = if i % 10 == 0 {
+                (1..=slots)
+                    .map(|slot| (keys.key(slot), U256::from(slot + i)))
+                    .collect()
+            } else {
+                BTreeMap::new()
+            };
+            let account = ModelAccount {
+                balance: U256::from(i),
+                code: picked(i, code).then(|| bytecode(i)),
+                storage,
+            };
+            (keys.key(i), account)
+        })
+        .collect()
+}
+
+/// Returns the witness of the keys written by `block`, or of the whole state without a block, and
+/// the state root. The witness holds the bytecode of the accounts proved.
+pub(crate) fn witness(model: &Model, block: Option<&HashedPostState>) -> (ExecutionWitness, B256) {
+    let targets = |written: Vec<&B256>| {
+        let mut targets: Vec<Path> = written.into_iter().map(path).collect();
+        targets.sort_unstable();
+        targets
+    };
+    let mut nodes = Vec::new();
+    let mut codes = Vec::new();
+    // the model is sorted by hashed address, and its storage by hashed slot
+    let accounts: Vec<(Path, Vec<u8>)> = model
+        .iter()
+        .map(|(hashed_address, account)| {
+            let proved = block.is_none_or(|block| block.accounts.contains_key(hashed_address));
+            if let Some(code) = account.code.as_ref().filter(|_| proved) {
+                codes.push(code.clone());
+            }
+            let slots: Vec<(Path, Vec<u8>)> = account
+                .storage
+                .iter()
+                .map(|(hashed_slot, value)| (path(hashed_slot), alloy_rlp::encode(value)))
+                .collect();
+            let written = block.map(|block| {
+                targets(
+                    block
+                        .storages
+                        .get(hashed_address)
+                        .map(|storage| storage.storage.keys().collect())
+                        .unwrap_or_default(),
+                )
+            });
+            let account = TrieAccount {
+                balance: account.balance,
+                storage_root: build_trie(&slots, written.as_deref(), &mut nodes),
+                code_hash: account.code.as_ref().map_or(KECCAK256_EMPTY, keccak256),
+                ..Default::default()
+            };
+            (path(hashed_address), alloy_rlp::encode(account))
+        })
+        .collect();
+    let written = block.map(|block| targets(block.accounts.keys().collect()));
+    let root = build_trie(&accounts, written.as_deref(), &mut nodes);
+    let witness = ExecutionWitness {
+        state: nodes,
+        codes,
+        ..Default::default()
+    };
+    (witness, root)
+}
+
+/// Returns a block in which every account with storage gets a new balance and rewrites its
+/// storage as set by `writes`: its slots are zeroed or changed, or it is wiped first, and slots
+/// are added after the `slots` of the model, with keys following `keys`. Or it is destroyed, and
+/// maybe created again. The block is applied to `model`.
+pub(crate) fn storage_heavy(
+    model: &mut Model,
+    slots: u64,
+    keys: KeyDistribution,
+    writes: StorageWrites,
+) -> HashedPostState {
+    let StorageWrites {
+        zeroed,
+        wiped,
+        added,
+        destroyed,
+        recreated,
+    } = writes;
+    let mut post_state = HashedPostState::default();
+    let mut destroyed_accounts = Vec::new();
+    let mut destructions = 0;
+    let with_storage = model
+        .iter_mut()
+        .filter(|(_, account)| !account.storage.is_empty());
+    for (i, (hashed_address, account)) in (0..).zip(with_storage) {
+        let hashed_address = *hashed_address;
+        let destroyed = picked(i, destroyed);
+        if destroyed {
+            destructions += 1;
+            if !picked(destructions - 1, recreated) {
+                destroyed_accounts.push(hashed_address);
+                post_state.accounts.insert(hashed_address, None);
+                post_state
+                    .storages
+                    .insert(hashed_address, HashedStorage::new(true));
+                continue;
+            }
+            account.code = None;
+        }
+        account.balance += U256::from(1);
+        let storage = &mut account.storage;
+        let wiped = destroyed || picked(i, wiped);
+        let mut writes = HashedStorage::new(wiped);
+        if wiped {
+            storage.clear();
+        } else {
+            for (j, (slot, value)) in (0..).zip(storage.iter_mut()) {
+                *value = if picked(j, zeroed) {
+                    U256::ZERO
+                } else {
+                    *value + U256::from(1)
+                };
+                writes.storage.insert(*slot, *value);
+            }
+            storage.retain(|_, value| !value.is_zero());
+        }
+        for slot in slots + 1..=slots + added {
+            storage.insert(keys.key(slot), U256::from(slot));
+            writes.storage.insert(keys.key(slot), U256::from(slot));
+        }
+
+        post_state
+            .accounts
+            .insert(hashed_address, Some(account.post_state()));
+        post_state.storages.insert(hashed_address, writes);
+    }
+    for hashed_address in destroyed_accounts {
+        model.remove(&hashed_address);
+    }
+    post_state
+}
+
+/// Returns the number of slots written by a block.
+pub(crate) fn slot_writes(post_state: &HashedPostState) -> u64 {
+    post_state
+        .storages
+        .values()
+        .map(|storage| storage.storage.len() as u64)
+        .sum()
+}
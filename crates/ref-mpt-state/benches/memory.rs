@@ -0,0 +1,181 @@
+//! Peak heap and allocation counts of [`SimpleSparseState`], for states of 1,000 to 100,000
+//! accounts: building the state with [`StatelessTrie::new`], and updating every tenth account and
+//! its storage with [`StatelessTrie::calculate_state_root`].
+//!
+//! The peak is the one attributed to the state by the [`BudgetAllocator`] of the `alloc-budget`
+//! feature, counted from zero before each step, so that the peak of `calculate_state_root` comes
+//! on top of the state built by `new`. The allocations are counted by a wrapper around it.
+//!
+//! The peak RSS of `new` and `calculate_state_root` together is then compared between
+//! [`SimpleSparseState`] and zeth's [`SparseState`], which the budget does not track. Each
+//! implementation and size runs in a child process of its own, started with
+//! `--rss <implementation> <size>`, which reads its peak RSS from `/proc/self/status`, so this
+//! part requires Linux. The results are printed once rather than timed by criterion:
+//!
+//! ```text
+//! cargo bench -p ref-mpt-state --features alloc-budget --bench memory
+//! ```
+// the dependencies of the library are not all used by the benchmarks
+#![allow(unused_crate_dependencies)]
+use alloy_primitives::B256;
+use common::{model, storage_heavy, witness, KeyDistribution, CHANGES};
+use ref_mpt_state::budget::{self, BudgetAllocator};
+use ref_mpt_state::SimpleSparseState;
+use reth_trie_common::HashedPostState;
+use stateless::{ExecutionWitness, StatelessTrie};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zeth_mpt_state::SparseState;
+
+mod common;
+
+/// The number of accounts of the states. Every tenth account has 16 storage slots.
+const SIZES: [u64; 3] = [1_000, 10_000, 100_000];
+
+/// The number of storage slots of the accounts with storage.
+const SLOTS: u64 = 16;
+
+/// The state implementations whose peak RSS is compared.
+const IMPLEMENTATIONS: [&str; 2] = ["ref-mpt-state", "zeth-mpt-state"];
+
+/// Counts the allocations served by the wrapped allocator.
+struct Counting<A> {
+    inner: A,
+    allocations: AtomicUsize,
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Counting<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        unsafe { self.inner.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        unsafe { self.inner.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting<BudgetAllocator<System>> = Counting {
+    inner: BudgetAllocator::new(System),
+    allocations: AtomicUsize::new(0),
+};
+
+/// Returns the witness and the root of a state of `size` accounts, every tenth one with `SLOTS`
+/// slots, and a block updating the balance and the slots of the accounts with storage.
+fn block(size: u64) -> (ExecutionWitness, B256, HashedPostState) {
+    let mut model = model(size, SLOTS, 0, KeyDistribution::Uniform);
+    let (witness, root) = witness(&model, None);
+    let post_state = storage_heavy(&mut model, SLOTS, KeyDistribution::Uniform, CHANGES);
+    (witness, root, post_state)
+}
+
+/// Runs `f`, and prints the peak heap attributed to the state and the number of allocations.
+fn measure<T>(size: u64, name: &str, f: impl FnOnce() -> T) -> T {
+    budget::reset();
+    let allocations = ALLOC.allocations.load(Ordering::Relaxed);
+    let result = f();
+    let allocations = ALLOC.allocations.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{size:>7} accounts  {name:<20} peak {:>12} bytes  {allocations:>9} allocations",
+        budget::usage().peak
+    );
+    result
+}
+
+/// Returns the field `name` of `/proc/self/status`, in KiB.
+fn status_kib(name: &str) -> u64 {
+    let status = fs::read_to_string("/proc/self/status").expect("peak RSS requires Linux");
+    status
+        .lines()
+        .find_map(|line| {
+            let kib = line.strip_prefix(name)?.strip_prefix(':')?.trim();
+            kib.strip_suffix(" kB")?.parse().ok()
+        })
+        .unwrap_or_else(|| panic!("no {name} in /proc/self/status"))
+}
+
+/// Runs `new` and `calculate_state_root` with `T` on the block of `size` accounts, and prints the
+/// RSS before them and their peak RSS, in KiB. The peak RSS of a process only grows, so each run
+/// gets a process of its own; the peak is reset once the block is generated.
+fn peak_rss<T: StatelessTrie>(size: u64) {
+    let (witness, root, post_state) = block(size);
+    fs::write("/proc/self/clear_refs", "5").expect("failed to reset the peak RSS");
+    let baseline = status_kib("VmRSS");
+    let (mut state, _) = T::new(&witness, root).unwrap();
+    state.calculate_state_root(post_state).unwrap();
+    println!("{baseline} {}", status_kib("VmHWM"));
+}
+
+/// Prints a table of the peak RSS of every implementation and size, each measured by
+/// [`peak_rss`] in a child process running this benchmark with `--rss <implementation> <size>`.
+fn compare_rss() {
+    println!("peak RSS of new and calculate_state_root, in KiB, a process per configuration");
+    println!(
+        "{:>8}  {:<16} {:>10} {:>10} {:>10}",
+        "accounts", "implementation", "baseline", "peak", "increase"
+    );
+    let exe = env::current_exe().expect("the path of the benchmark");
+    for size in SIZES {
+        for name in IMPLEMENTATIONS {
+            let output = Command::new(&exe)
+                .args(["--rss", name, &size.to_string()])
+                .output()
+                .expect("failed to run the benchmark in a child process");
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(
+                output.status.success(),
+                "{name} {size}: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            let kib: Vec<u64> = stdout
+                .split_whitespace()
+                .map(|kib| kib.parse().unwrap())
+                .collect();
+            let [baseline, peak] = kib[..] else {
+                panic!("{name} {size}: unexpected output {stdout}");
+            };
+            println!(
+                "{size:>8}  {name:<16} {baseline:>10} {peak:>10} {:>10}",
+                peak - baseline
+            );
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--rss") {
+        let size = args[i + 2].parse().expect("the size of the state");
+        match args[i + 1].as_str() {
+            "ref-mpt-state" => peak_rss::<SimpleSparseState>(size),
+            "zeth-mpt-state" => peak_rss::<SparseState>(size),
+            name => panic!("unknown implementation {name}"),
+        }
+        return;
+    }
+
+    for size in SIZES {
+        let (witness, root, post_state) = block(size);
+        let witness_bytes: usize = witness.state.iter().map(|node| node.len()).sum();
+        println!(
+            "{size:>7} accounts  witness of {} nodes, {witness_bytes} bytes",
+            witness.state.len()
+        );
+        let (mut state, _) = measure(size, "new", || {
+            SimpleSparseState::new(&witness, root).unwrap()
+        });
+        measure(size, "calculate_state_root", || {
+            state.calculate_state_root(post_state).unwrap()
+        });
+    }
+    compare_rss();
+}
@@ -0,0 +1,236 @@
+//! Benchmarks replaying real blocks from the `StatelessInput` JSON fixtures in `test_data/` and in
+//! the directory named by `STATELESS_FIXTURES_DIR`, with [`SimpleSparseState`] and zeth's
+//! [`SparseState`]: building the state with [`StatelessTrie::new`], the account and storage reads
+//! of the execution, and [`StatelessTrie::calculate_state_root`].
+//!
+//! Each block is executed once, untimed, by `stateless_validation_with_trie` with a [`Recorder`]
+//! logging the calls made to the trie. The timed runs replay the log, so they follow the trie
+//! shapes and the access patterns of mainnet without executing the block again:
+//!
+//! ```text
+//! cargo bench -p ref-mpt-state --features parallel,signers --bench replay
+//! ```
+// the dependencies of the library are not all used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::map::B256Map;
+use alloy_primitives::{Address, B256, U256};
+use alloy_trie::TrieAccount;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use ref_mpt_state::signers::recover_public_keys;
+use ref_mpt_state::SimpleSparseState;
+use reth_chainspec::ChainSpec;
+use reth_evm_ethereum::EthEvmConfig;
+use reth_trie_common::HashedPostState;
+use revm_bytecode::Bytecode;
+use stateless::error::WitnessDbError;
+use stateless::validation::StatelessValidationError;
+use stateless::{
+    stateless_validation_with_trie, ExecutionWitness, Genesis, StatelessInput, StatelessTrie,
+};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use zeth_mpt_state::SparseState;
+
+/// Environment variable naming an extra directory of `StatelessInput` JSON fixtures.
+const FIXTURES_DIR_ENV: &str = "STATELESS_FIXTURES_DIR";
+
+/// A read of the execution of a block.
+#[derive(Debug, Clone, Copy)]
+enum Read {
+    Account(Address),
+    Storage(Address, U256),
+}
+
+/// The calls made to the trie by the execution of a block, in order.
+#[derive(Debug, Default)]
+struct Log {
+    pre_state_root: B256,
+    reads: Vec<Read>,
+    post_state: Option<HashedPostState>,
+}
+
+/// The log of the block being recorded.
+static LOG: Mutex<Log> = Mutex::new(Log {
+    pre_state_root: B256::ZERO,
+    reads: Vec::new(),
+    post_state: None,
+});
+
+/// A [`SimpleSparseState`] appending the calls made to it to [`LOG`].
+#[derive(Debug)]
+struct Recorder(SimpleSparseState);
+
+impl StatelessTrie for Recorder {
+    fn new(
+        witness: &ExecutionWitness,
+        pre_state_root: B256,
+    ) -> Result<(Self, B256Map<Bytecode>), StatelessValidationError> {
+        LOG.lock().unwrap().pre_state_root = pre_state_root;
+        let (state, bytecode) = SimpleSparseState::new(witness, pre_state_root)?;
+        Ok((Self(state), bytecode))
+    }
+
+    fn account(&self, address: Address) -> Result<Option<TrieAccount>, WitnessDbError> {
+        LOG.lock().unwrap().reads.push(Read::Account(address));
+        self.0.account(address)
+    }
+
+    fn storage(&self, address: Address, slot: U256) -> Result<U256, WitnessDbError> {
+        LOG.lock().unwrap().reads.push(Read::Storage(address, slot));
+        self.0.storage(address, slot)
+    }
+
+    fn calculate_state_root(
+        &mut self,
+        state: HashedPostState,
+    ) -> Result<B256, StatelessValidationError> {
+        LOG.lock().unwrap().post_state = Some(state.clone());
+        self.0.calculate_state_root(state)
+    }
+}
+
+/// A block to replay: its witness and the calls made to the trie by its execution.
+#[derive(Debug)]
+struct Replay {
+    name: String,
+    witness: ExecutionWitness,
+    pre_state_root: B256,
+    reads: Vec<Read>,
+    post_state: HashedPostState,
+    post_state_root: B256,
+}
+
+/// Returns the JSON files of a fixture directory, sorted by name.
+fn json_files(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("failed to read fixture directory {dir:?}: {err}"))
+        .map(|entry| {
+            entry
+                .expect("failed to read fixture directory entry")
+                .path()
+        })
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Executes the block of the fixture at `path`, recording the calls made to the trie.
+fn record(path: &Path) -> Replay {
+    let input: StatelessInput = serde_json::from_reader(BufReader::new(
+        File::open(path).unwrap_or_else(|err| panic!("failed to open {path:?}: {err}")),
+    ))
+    .unwrap_or_else(|err| panic!("failed to parse stateless input {path:?}: {err}"));
+    let genesis = Genesis {
+        config: input.chain_config.clone(),
+        ..Default::default()
+    };
+    let chain_spec: Arc<ChainSpec> = Arc::new(genesis.into());
+    let evm_config = EthEvmConfig::new(chain_spec.clone());
+    let public_keys = recover_public_keys(&input).expect("valid public key recovery");
+    let post_state_root = input.block.header.state_root;
+
+    *LOG.lock().unwrap() = Log::default();
+    stateless_validation_with_trie::<Recorder, ChainSpec, EthEvmConfig>(
+        input.block,
+        public_keys,
+        input.witness.clone(),
+        chain_spec,
+        evm_config,
+    )
+    .unwrap_or_else(|err| panic!("{path:?}: stateless validation error: {err}"));
+    let log = std::mem::take(&mut *LOG.lock().unwrap());
+
+    Replay {
+        name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+        witness: input.witness,
+        pre_state_root: log.pre_state_root,
+        reads: log.reads,
+        post_state: log.post_state.expect("the state root is calculated"),
+        post_state_root,
+    }
+}
+
+/// Records the blocks of every fixture of `test_data/` and of the directory named by
+/// `STATELESS_FIXTURES_DIR`, if set. A missing `test_data/` is skipped.
+fn load_replays() -> Vec<Replay> {
+    let mut paths = Vec::new();
+    let test_data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test_data");
+    if test_data.exists() {
+        paths.extend(json_files(&test_data));
+    }
+    if let Some(dir) = std::env::var_os(FIXTURES_DIR_ENV) {
+        paths.extend(json_files(Path::new(&dir)));
+    }
+    if paths.is_empty() {
+        eprintln!("skipping replay: no fixtures in {test_data:?} or ${FIXTURES_DIR_ENV}");
+    }
+    paths.iter().map(|path| record(path)).collect()
+}
+
+/// Performs the reads of the execution of a block.
+fn read<T: StatelessTrie>(state: &T, reads: &[Read]) {
+    for read in reads {
+        match *read {
+            Read::Account(address) => {
+                state.account(address).unwrap();
+            }
+            Read::Storage(address, slot) => {
+                state.storage(address, slot).unwrap();
+            }
+        }
+    }
+}
+
+/// Benchmarks the steps of the replay of `replay` with the trie `T`, named `name`.
+fn replay<T: StatelessTrie>(c: &mut Criterion, name: &str, replay: &Replay) {
+    let new_state = || T::new(&replay.witness, replay.pre_state_root).unwrap().0;
+    let read_state = || {
+        let state = new_state();
+        read(&state, &replay.reads);
+        state
+    };
+    assert_eq!(
+        read_state()
+            .calculate_state_root(replay.post_state.clone())
+            .unwrap(),
+        replay.post_state_root,
+        "{}",
+        replay.name
+    );
+
+    let id = BenchmarkId::new(name, &replay.name);
+    c.benchmark_group("replay_new")
+        .bench_function(id.clone(), |b| b.iter(new_state));
+    c.benchmark_group("replay_reads")
+        .bench_function(id.clone(), |b| {
+            b.iter_batched(
+                new_state,
+                |state| read(&state, &replay.reads),
+                BatchSize::LargeInput,
+            );
+        });
+    c.benchmark_group("replay_calculate_state_root")
+        .bench_function(id, |b| {
+            b.iter_batched(
+                || (read_state(), replay.post_state.clone()),
+                |(mut state, post_state)| state.calculate_state_root(post_state).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+}
+
+// The reads and the state root are timed on states that went through the previous steps, as in
+// the execution, in which the state root updates the storage tries that the reads revealed.
+fn replays(c: &mut Criterion) {
+    for block in load_replays() {
+        replay::<SimpleSparseState>(c, "ref-mpt-state", &block);
+        replay::<SparseState>(c, "zeth-mpt-state", &block);
+    }
+}
+
+criterion_group!(benches, replays);
+criterion_main!(benches);
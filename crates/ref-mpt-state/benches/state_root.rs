@@ -0,0 +1,422 @@
+//! Benchmarks of [`StatelessTrie::calculate_state_root`] on storage-heavy blocks, in which every
+//! account with storage changes, zeroes or adds slots, is wiped first, or is destroyed and maybe
+//! created again, in the proportions of a [`StorageWrites`]. And of sequences of blocks, with the
+//! state of each block built by [`SimpleSparseState::advance`] from that of the previous one or
+//! from its witness alone. And of [`SimpleSparseState::new`] on witnesses with the bytecode of a
+//! share of the accounts, of storage-heavy blocks on tries of each [`KeyDistribution`], of blocks
+//! removing accounts and slots as set by [`Transfers`], and of a block on a state of
+//! [`SOAK_SIZE`] accounts.
+//!
+//! ```text
+//! cargo bench -p ref-mpt-state --bench state_root
+//! ```
+// the dependencies of the library are not all used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::{B256, U256};
+use common::{
+    model, picked, slot_writes, storage_heavy, witness, KeyDistribution, Model, StorageWrites,
+    CHANGES, MIXED,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use ref_mpt_state::SimpleSparseState;
+use reth_trie_common::{HashedPostState, HashedStorage};
+use stateless::{ExecutionWitness, StatelessTrie};
+
+mod common;
+
+/// The number of accounts of the states. Every tenth account has storage.
+const SIZES: [u64; 2] = [1_000, 10_000];
+
+/// The number of accounts of the state of the soak benchmark, with 1,280,000 storage slots.
+const SOAK_SIZE: u64 = 200_000;
+
+/// The number of storage slots of the accounts with storage.
+const SLOTS: u64 = 64;
+
+/// The percentages of the accounts with bytecode of the witnesses of the bytecode benchmarks.
+const CODE: [u64; 3] = [0, 10, 50];
+
+/// The key distributions of the tries of the key distribution benchmarks, by name.
+const DISTRIBUTIONS: [(&str, KeyDistribution); 4] = [
+    ("uniform", KeyDistribution::Uniform),
+    ("clustered", KeyDistribution::Clustered),
+    ("chains", KeyDistribution::Chains),
+    ("near_collisions", KeyDistribution::NearCollisions),
+];
+
+/// The number of blocks of the sequences.
+const BLOCKS: u64 = 8;
+
+/// The storage-heavy blocks, by name: slot changes alone, slot removals, wipes, account
+/// destructions, destructions followed by recreations, and a mix of them.
+const WRITES: [(&str, StorageWrites); 6] = [
+    ("change", CHANGES),
+    (
+        "zero",
+        StorageWrites {
+            zeroed: 50,
+            ..CHANGES
+        },
+    ),
+    (
+        "wipe",
+        StorageWrites {
+            wiped: 50,
+            added: SLOTS / 4,
+            ..CHANGES
+        },
+    ),
+    (
+        "destroy",
+        StorageWrites {
+            destroyed: 50,
+            ..CHANGES
+        },
+    ),
+    (
+        "recreate",
+        StorageWrites {
+            added: SLOTS / 4,
+            destroyed: 50,
+            recreated: 100,
+            ..CHANGES
+        },
+    ),
+    (
+        "mixed",
+        StorageWrites {
+            added: SLOTS / 4,
+            ..MIXED
+        },
+    ),
+];
+
+/// The removals of a block of a sequence, among the accounts and slots it writes.
+#[derive(Debug, Clone, Copy)]
+struct Transfers {
+    /// The percentage of the written accounts which are deleted, e.g. as empty accounts touched.
+    deleted: u64,
+    /// The percentage of the written slots which are zeroed.
+    zeroed: u64,
+}
+
+/// The blocks of the removal benchmarks, by name: writes only, account deletions, slot removals,
+/// and both.
+const REMOVALS: [(&str, Transfers); 4] = [
+    (
+        "write",
+        Transfers {
+            deleted: 0,
+            zeroed: 0,
+        },
+    ),
+    (
+        "delete",
+        Transfers {
+            deleted: 50,
+            zeroed: 0,
+        },
+    ),
+    (
+        "zero",
+        Transfers {
+            deleted: 0,
+            zeroed: 50,
+        },
+    ),
+    (
+        "both",
+        Transfers {
+            deleted: 25,
+            zeroed: 25,
+        },
+    ),
+];
+
+/// Returns the block `number` of a sequence, applied to `model`: one account in twenty gets a new
+/// balance, and those with storage change 8 of their slots, with keys following `keys`. Some of
+/// the accounts are deleted instead, and some of the slots zeroed, as set by `removals`.
+fn transfers(
+    model: &mut Model,
+    keys: KeyDistribution,
+    number: u64,
+    removals: Transfers,
+) -> HashedPostState {
+    let mut post_state = HashedPostState::default();
+    let mut deleted_accounts = Vec::new();
+    let written = (0..)
+        .zip(model.iter_mut())
+        .filter(|(i, _)| (i / 10 + number) % 20 == 0);
+    for (w, (_, (hashed_address, account))) in (0..).zip(written) {
+        let hashed_address = *hashed_address;
+        if picked(w, removals.deleted) {
+            deleted_accounts.push(hashed_address);
+            post_state.accounts.insert(hashed_address, None);
+            continue;
+        }
+        account.balance += U256::from(1);
+        let storage = &mut account.storage;
+        if !storage.is_empty() {
+            let mut writes = HashedStorage::new(false);
+            for k in 0..8 {
+                let slot = keys.key(1 + (number + k * 8) % SLOTS);
+                let value = if picked(k, removals.zeroed) {
+                    storage.remove(&slot);
+                    U256::ZERO
+                } else {
+                    let value = storage.entry(slot).or_default();
+                    *value += U256::from(1);
+                    *value
+                };
+                writes.storage.insert(slot, value);
+            }
+            post_state.storages.insert(hashed_address, writes);
+        }
+        post_state
+            .accounts
+            .insert(hashed_address, Some(account.post_state()));
+    }
+    for hashed_address in deleted_accounts {
+        model.remove(&hashed_address);
+    }
+    post_state
+}
+
+fn storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trie_calculate_state_root_storage");
+    group.sample_size(10);
+    for size in SIZES {
+        let pre_model = model(size, SLOTS, 0, KeyDistribution::Uniform);
+        let (pre_witness, root) = witness(&pre_model, None);
+        let new_state = || SimpleSparseState::new(&pre_witness, root).unwrap().0;
+        for (name, writes) in WRITES {
+            let mut model = pre_model.clone();
+            let post_state = storage_heavy(&mut model, SLOTS, KeyDistribution::Uniform, writes);
+            assert_eq!(
+                new_state()
+                    .calculate_state_root(post_state.clone())
+                    .unwrap(),
+                witness(&model, None).1
+            );
+
+            // the state is built again for every iteration, as a clone would share its storage
+            // tries
+            group.throughput(Throughput::Elements(slot_writes(&post_state)));
+            group.bench_with_input(
+                BenchmarkId::new(name, size),
+                &post_state,
+                |b, post_state| {
+                    b.iter_batched(
+                        || (new_state(), post_state.clone()),
+                        |(mut state, post_state)| state.calculate_state_root(post_state).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+// The first block is built from its witness in both series, the others from the state of the
+// previous block or from their witness alone. The witnesses hold the proofs of the written keys.
+fn sequence(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multi_block");
+    group.sample_size(10);
+    let size = SIZES[1];
+    let mut model = model(size, SLOTS, 0, KeyDistribution::Uniform);
+    let blocks: Vec<(ExecutionWitness, B256, HashedPostState)> = (0..BLOCKS)
+        .map(|number| {
+            let mut post_model = model.clone();
+            let (_, writes) = REMOVALS[0];
+            let post_state = transfers(&mut post_model, KeyDistribution::Uniform, number, writes);
+            let (witness, root) = witness(&model, Some(&post_state));
+            model = post_model;
+            (witness, root, post_state)
+        })
+        .collect();
+    let post_states = || -> Vec<HashedPostState> {
+        blocks
+            .iter()
+            .map(|(_, _, post_state)| post_state.clone())
+            .collect()
+    };
+
+    let advance = |post_states: Vec<HashedPostState>| {
+        let mut state: Option<SimpleSparseState> = None;
+        let mut root = B256::ZERO;
+        for ((witness, pre_state_root, _), post_state) in blocks.iter().zip(post_states) {
+            let mut next = match state {
+                Some(prev) => SimpleSparseState::advance(prev, witness, *pre_state_root),
+                None => SimpleSparseState::new(witness, *pre_state_root),
+            }
+            .unwrap()
+            .0;
+            root = next.calculate_state_root(post_state).unwrap();
+            state = Some(next);
+        }
+        root
+    };
+    let rebuild = |post_states: Vec<HashedPostState>| {
+        let mut root = B256::ZERO;
+        for ((witness, pre_state_root, _), post_state) in blocks.iter().zip(post_states) {
+            let (mut state, _) = SimpleSparseState::new(witness, *pre_state_root).unwrap();
+            root = state.calculate_state_root(post_state).unwrap();
+        }
+        root
+    };
+    let post_state_root = witness(&model, None).1;
+    assert_eq!(advance(post_states()), post_state_root);
+    assert_eq!(rebuild(post_states()), post_state_root);
+
+    group.throughput(Throughput::Elements(BLOCKS));
+    group.bench_function(BenchmarkId::new("advance", size), |b| {
+        b.iter_batched(post_states, advance, BatchSize::LargeInput);
+    });
+    group.bench_function(BenchmarkId::new("new", size), |b| {
+        b.iter_batched(post_states, rebuild, BatchSize::LargeInput);
+    });
+    group.finish();
+}
+
+// `new` hashes the bytecode of the witness, so it takes longer as the witness holds more of it.
+fn new_bytecode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trie_new_bytecode");
+    let size = SIZES[1];
+    for code in CODE {
+        let (witness, root) = witness(&model(size, SLOTS, code, KeyDistribution::Uniform), None);
+        let (_, bytecode) = SimpleSparseState::new(&witness, root).unwrap();
+        assert_eq!(bytecode.len(), witness.codes.len());
+        let bytes = witness
+            .state
+            .iter()
+            .chain(&witness.codes)
+            .map(|bytes| bytes.len());
+        group.throughput(Throughput::Bytes(bytes.sum::<usize>() as u64));
+        group.bench_with_input(
+            BenchmarkId::new(format!("{code}%"), size),
+            &witness,
+            |b, witness| {
+                b.iter(|| SimpleSparseState::new(witness, root).unwrap());
+            },
+        );
+    }
+    group.finish();
+}
+
+// The mixed storage-heavy block on tries of each shape, whose depth and number of extensions
+// differ while the number of keys is the same.
+fn key_distribution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trie_calculate_state_root_keys");
+    group.sample_size(10);
+    let size = SIZES[1];
+    let (_, writes) = WRITES[WRITES.len() - 1];
+    for (name, keys) in DISTRIBUTIONS {
+        let mut model = model(size, SLOTS, 0, keys);
+        // the keys of a distribution are distinct
+        assert_eq!(model.len() as u64, size);
+        let (pre_witness, root) = witness(&model, None);
+        let post_state = storage_heavy(&mut model, SLOTS, keys, writes);
+        let new_state = || SimpleSparseState::new(&pre_witness, root).unwrap().0;
+        assert_eq!(
+            new_state()
+                .calculate_state_root(post_state.clone())
+                .unwrap(),
+            witness(&model, None).1
+        );
+
+        group.throughput(Throughput::Elements(slot_writes(&post_state)));
+        group.bench_with_input(
+            BenchmarkId::new(name, size),
+            &post_state,
+            |b, post_state| {
+                b.iter_batched(
+                    || (new_state(), post_state.clone()),
+                    |(mut state, post_state)| state.calculate_state_root(post_state).unwrap(),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+// The mixed storage-heavy block on a state of hundreds of thousands of accounts, whose witness
+// is generated in a single pass over the sorted keys of each trie.
+fn soak(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trie_calculate_state_root_soak");
+    group.sample_size(10);
+    let (_, writes) = WRITES[WRITES.len() - 1];
+    let mut model = model(SOAK_SIZE, SLOTS, 0, KeyDistribution::Uniform);
+    let (pre_witness, root) = witness(&model, None);
+    let post_state = storage_heavy(&mut model, SLOTS, KeyDistribution::Uniform, writes);
+    let new_state = || SimpleSparseState::new(&pre_witness, root).unwrap().0;
+    assert_eq!(
+        new_state()
+            .calculate_state_root(post_state.clone())
+            .unwrap(),
+        witness(&model, None).1
+    );
+
+    group.throughput(Throughput::Elements(slot_writes(&post_state)));
+    group.bench_with_input(
+        BenchmarkId::new("mixed", SOAK_SIZE),
+        &post_state,
+        |b, post_state| {
+            b.iter_batched(
+                || (new_state(), post_state.clone()),
+                |(mut state, post_state)| state.calculate_state_root(post_state).unwrap(),
+                BatchSize::LargeInput,
+            );
+        },
+    );
+    group.finish();
+}
+
+// Removals are applied after the insertions of the block, and may collapse the branches of the
+// removed keys. The witnesses hold the whole state, so that the remaining siblings are revealed.
+fn removals(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trie_calculate_state_root_removals");
+    group.sample_size(10);
+    for size in SIZES {
+        let pre_model = model(size, SLOTS, 0, KeyDistribution::Uniform);
+        let (pre_witness, root) = witness(&pre_model, None);
+        let new_state = || SimpleSparseState::new(&pre_witness, root).unwrap().0;
+        for (name, removals) in REMOVALS {
+            let mut model = pre_model.clone();
+            let post_state = transfers(&mut model, KeyDistribution::Uniform, 0, removals);
+            assert_eq!(
+                new_state()
+                    .calculate_state_root(post_state.clone())
+                    .unwrap(),
+                witness(&model, None).1
+            );
+
+            group.throughput(Throughput::Elements(post_state.accounts.len() as u64));
+            group.bench_with_input(
+                BenchmarkId::new(name, size),
+                &post_state,
+                |b, post_state| {
+                    b.iter_batched(
+                        || (new_state(), post_state.clone()),
+                        |(mut state, post_state)| state.calculate_state_root(post_state).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    storage,
+    sequence,
+    new_bytecode,
+    key_distribution,
+    removals,
+    soak
+);
+criterion_main!(benches);
@@ -0,0 +1,158 @@
+//! Runs a single configuration of [`StatelessTrie::new`], or of `new` followed by
+//! [`StatelessTrie::calculate_state_root`], in a loop, for a profiler such as perf or heaptrack to
+//! attach to. The state and the storage-heavy block are those generated for the `state_root`
+//! benchmark, the block being its `mixed` one:
+//!
+//! ```text
+//! cargo run --release -p ref-mpt-state --example profile -- --accounts 100000 --slots 64
+//! perf record -g target/release/examples/profile --impl zeth --step new
+//! ```
+//!
+//! The options are `--impl simple|zeth`, `--accounts <n>`, every tenth with `--slots <n>` slots,
+//! `--step new|root` and `--iterations <n>`.
+// the dependencies of the library are not all used by the example
+#![allow(unused_crate_dependencies)]
+use alloy_primitives::B256;
+use common::{model, slot_writes, storage_heavy, witness, KeyDistribution, StorageWrites, MIXED};
+use ref_mpt_state::SimpleSparseState;
+use reth_trie_common::HashedPostState;
+use stateless::{ExecutionWitness, StatelessTrie};
+use std::error::Error;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use zeth_mpt_state::SparseState;
+
+#[path = "../benches/common/mod.rs"]
+mod common;
+
+/// The state implementation profiled.
+#[derive(Debug, Clone, Copy)]
+enum Implementation {
+    Simple,
+    Zeth,
+}
+
+/// The work of an iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// Building the state from the witness.
+    New,
+    /// Building the state, then calculating the state root of the block.
+    Root,
+}
+
+/// The configuration profiled.
+#[derive(Debug)]
+struct Options {
+    implementation: Implementation,
+    accounts: u64,
+    slots: u64,
+    step: Step,
+    iterations: u64,
+}
+
+impl Options {
+    /// Parses the command line arguments.
+    fn parse() -> Result<Self, Box<dyn Error>> {
+        let mut options = Self {
+            implementation: Implementation::Simple,
+            accounts: 10_000,
+            slots: 64,
+            step: Step::Root,
+            iterations: 100,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value of {flag}"))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|err| format!("{flag} {value}: {err}"))
+            };
+            match flag.as_str() {
+                "--impl" => {
+                    options.implementation = match value.as_str() {
+                        "simple" => Implementation::Simple,
+                        "zeth" => Implementation::Zeth,
+                        _ => return Err(format!("unknown implementation {value}").into()),
+                    }
+                }
+                "--accounts" => options.accounts = number()?,
+                "--slots" => options.slots = number()?,
+                "--step" => {
+                    options.step = match value.as_str() {
+                        "new" => Step::New,
+                        "root" => Step::Root,
+                        _ => return Err(format!("unknown step {value}").into()),
+                    }
+                }
+                "--iterations" => options.iterations = number()?,
+                _ => return Err(format!("unknown option {flag}").into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Runs the iterations of `options` with the state `T`, after checking the root of the block
+/// once. Returns their total time.
+fn run<T: StatelessTrie>(
+    options: &Options,
+    witness: &ExecutionWitness,
+    root: B256,
+    post_state: &HashedPostState,
+    post_state_root: B256,
+) -> Duration {
+    let new_state = || T::new(witness, root).unwrap().0;
+    assert_eq!(
+        new_state()
+            .calculate_state_root(post_state.clone())
+            .unwrap(),
+        post_state_root
+    );
+
+    let start = Instant::now();
+    for _ in 0..options.iterations {
+        let mut state = new_state();
+        if options.step == Step::Root {
+            black_box(state.calculate_state_root(post_state.clone()).unwrap());
+        }
+        black_box(state);
+    }
+    start.elapsed()
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::parse()?;
+    let start = Instant::now();
+    let mut model = model(options.accounts, options.slots, 0, KeyDistribution::Uniform);
+    let (pre_witness, root) = witness(&model, None);
+    let writes = StorageWrites {
+        added: options.slots / 4,
+        ..MIXED
+    };
+    let post_state = storage_heavy(&mut model, options.slots, KeyDistribution::Uniform, writes);
+    let (_, post_state_root) = witness(&model, None);
+    println!(
+        "{} witness nodes, {} slot writes, generated in {:.1?}",
+        pre_witness.state.len(),
+        slot_writes(&post_state),
+        start.elapsed()
+    );
+
+    let elapsed = match options.implementation {
+        Implementation::Simple => {
+            run::<SimpleSparseState>(&options, &pre_witness, root, &post_state, post_state_root)
+        }
+        Implementation::Zeth => {
+            run::<SparseState>(&options, &pre_witness, root, &post_state, post_state_root)
+        }
+    };
+    println!(
+        "{options:?}: {:.1?} per iteration",
+        elapsed.div_f64(options.iterations.max(1) as f64)
+    );
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Block hashes of the ancestors of a block, from the headers of its witness.
+//!
+//! The `BLOCKHASH` opcode reads the hashes of the 256 blocks preceding the executed one. A guest
+//! driving the EVM itself takes them from [`ExecutionWitness::headers`], once the headers are
+//! linked by their parent hash up to the parent of the block:
+//!
+//! ```ignore
+//! let ancestors = AncestorHashes::new(&block.header, &witness.headers)?;
+//! let (state, codes) = SimpleSparseState::new(&witness, ancestors.pre_state_root())?;
+//! let hash = ancestors.block_hash(block.header.number - 1);
+//! ```
+//!
+//! [`ExecutionWitness::headers`]: stateless::ExecutionWitness::headers
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use alloy_consensus::Header;
+use alloy_primitives::private::alloy_rlp;
+use alloy_primitives::{keccak256, Bytes};
+use core::cmp::Reverse;
+use core::fmt;
+use ref_mpt::B256;
+
+/// The headers of a witness do not link up to the parent of the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AncestorError {
+    /// A header is not a valid RLP-encoded header.
+    InvalidHeader(alloy_rlp::Error),
+    /// The header of the parent of the block is missing.
+    MissingParent,
+    /// The header of block `number` does not follow the one of its child: its number is not the
+    /// next lower one, e.g. as a header is missing or listed twice, or its hash is not the parent
+    /// hash of the child.
+    BrokenChain {
+        /// The number of the header.
+        number: u64,
+    },
+}
+
+impl fmt::Display for AncestorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader(err) => write!(f, "invalid witness header: {err}"),
+            Self::MissingParent => write!(f, "parent header missing from the witness"),
+            Self::BrokenChain { number } => {
+                write!(f, "witness header {number} does not link to its child")
+            }
+        }
+    }
+}
+
+impl core::error::Error for AncestorError {}
+
+impl From<alloy_rlp::Error> for AncestorError {
+    fn from(err: alloy_rlp::Error) -> Self {
+        Self::InvalidHeader(err)
+    }
+}
+
+/// The hashes of the ancestors of a block whose headers are in its witness, by block number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AncestorHashes {
+    hashes: BTreeMap<u64, B256>,
+    pre_state_root: B256,
+}
+
+impl AncestorHashes {
+    /// Decodes the witness `headers`, in any order, and checks that they form a chain ending
+    /// with the parent of `block`: each header hashes to the parent hash of the next one.
+    ///
+    /// Block hashes are keccak hashes even under the `poseidon2` feature, which only swaps the
+    /// hash of the tries.
+    pub fn new(block: &Header, headers: &[Bytes]) -> Result<Self, AncestorError> {
+        let mut ancestors = headers
+            .iter()
+            .map(|rlp| Ok((alloy_rlp::decode_exact::<Header>(rlp)?, keccak256(rlp))))
+            .collect::<Result<Vec<_>, AncestorError>>()?;
+        ancestors.sort_unstable_by_key(|(header, _)| Reverse(header.number));
+
+        let pre_state_root = match ancestors.first() {
+            Some((parent, _)) if parent.number.checked_add(1) == Some(block.number) => {
+                parent.state_root
+            }
+            _ => return Err(AncestorError::MissingParent),
+        };
+        let mut hashes = BTreeMap::new();
+        let (mut child_number, mut child_parent_hash) = (block.number, block.parent_hash);
+        for (header, hash) in ancestors {
+            if header.number.checked_add(1) != Some(child_number) || hash != child_parent_hash {
+                return Err(AncestorError::BrokenChain {
+                    number: header.number,
+                });
+            }
+            hashes.insert(header.number, hash);
+            (child_number, child_parent_hash) = (header.number, header.parent_hash);
+        }
+        Ok(Self {
+            hashes,
+            pre_state_root,
+        })
+    }
+
+    /// Returns the hash of block `number`, or `None` if its header is not in the witness, i.e. if
+    /// it is not an ancestor of the block or is older than the oldest witness header.
+    ///
+    /// The EVM only asks for the 256 most recent ancestors, and answers zero for older blocks
+    /// without reading them.
+    pub fn block_hash(&self, number: u64) -> Option<B256> {
+        self.hashes.get(&number).copied()
+    }
+
+    /// Returns the state root of the parent of the block, i.e. the pre-state root.
+    pub const fn pre_state_root(&self) -> B256 {
+        self.pre_state_root
+    }
+
+    /// Returns the ancestor hashes by block number, from the oldest to the parent of the block.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, B256)> + '_ {
+        self.hashes.iter().map(|(number, hash)| (*number, *hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the encoded headers of blocks `0..len`, and the header of block `len`.
+    fn chain(len: u64) -> (Vec<Bytes>, Header) {
+        let mut headers = Vec::new();
+        let mut parent_hash = B256::ZERO;
+        for number in 0..len {
+            let header = Header {
+                number,
+                parent_hash,
+                state_root: B256::with_last_byte(number as u8),
+                ..Default::default()
+            };
+            let rlp = Bytes::from(alloy_rlp::encode(&header));
+            parent_hash = keccak256(&rlp);
+            headers.push(rlp);
+        }
+        let block = Header {
+            number: len,
+            parent_hash,
+            ..Default::default()
+        };
+        (headers, block)
+    }
+
+    #[test]
+    fn links_headers_in_any_order() {
+        let (mut headers, block) = chain(5);
+        headers.swap(0, 3);
+        let ancestors = AncestorHashes::new(&block, &headers).unwrap();
+        assert_eq!(ancestors.pre_state_root(), B256::with_last_byte(4));
+        assert_eq!(ancestors.block_hash(4), Some(block.parent_hash));
+        assert_eq!(ancestors.block_hash(0), Some(keccak256(&headers[3])));
+        assert_eq!(ancestors.block_hash(5), None);
+        assert_eq!(ancestors.iter().map(|(number, _)| number).max(), Some(4));
+
+        // only the two most recent ancestors, blocks 4 and 3
+        let recent = [headers[4].clone(), headers[0].clone()];
+        let ancestors = AncestorHashes::new(&block, &recent).unwrap();
+        assert_eq!(ancestors.block_hash(0), None);
+        assert_eq!(ancestors.iter().count(), 2);
+    }
+
+    #[test]
+    fn rejects_broken_chains() {
+        let (headers, block) = chain(5);
+        let new = |headers: &[Bytes]| AncestorHashes::new(&block, headers).unwrap_err();
+        assert_eq!(new(&[]), AncestorError::MissingParent);
+        assert_eq!(new(&headers[..4]), AncestorError::MissingParent);
+
+        // a gap, a duplicate, and a header which is not the parent of its child
+        let gap = [headers[4].clone(), headers[2].clone()];
+        assert_eq!(new(&gap), AncestorError::BrokenChain { number: 2 });
+        let duplicate = [headers[4].clone(), headers[3].clone(), headers[3].clone()];
+        assert_eq!(new(&duplicate), AncestorError::BrokenChain { number: 3 });
+        let other = Header {
+            number: 3,
+            ..Default::default()
+        };
+        let forged = [headers[4].clone(), alloy_rlp::encode(&other).into()];
+        assert_eq!(new(&forged), AncestorError::BrokenChain { number: 3 });
+        let wrong_parent = Header {
+            parent_hash: B256::ZERO,
+            ..block.clone()
+        };
+        assert_eq!(
+            AncestorHashes::new(&wrong_parent, &headers).unwrap_err(),
+            AncestorError::BrokenChain { number: 4 }
+        );
+
+        assert!(matches!(
+            new(&[Bytes::from_static(&[0xc0])]),
+            AncestorError::InvalidHeader(_)
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+//! Allocation budget for guests with a fixed heap.
+//!
+//! [`BudgetAllocator`] wraps the global allocator and counts the heap bytes allocated while
+//! [`SimpleSparseState`](crate::SimpleSparseState) reveals, reads or updates its tries. Once the
+//! count exceeds the limit set with [`set_budget`], `new`, `advance` and `calculate_state_root`
+//! fail with `StatelessExecutionFailed`, carrying the [`BudgetExceeded`] message, instead of the
+//! guest aborting on an exhausted heap:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: BudgetAllocator<MyAlloc> = BudgetAllocator::new(MyAlloc);
+//!
+//! ref_mpt_state::budget::set_budget(96 << 20);
+//! ```
+//!
+//! Allocations are still served after the budget is exceeded and the error surfaces at the next
+//! check, after each account update, so the heap must leave some headroom above the budget.
+//! The counters use plain loads and stores, which keeps the allocator usable on zkVM targets
+//! without atomic read-modify-write instructions; they are only exact on a single thread.
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The budget and the counters of [`BudgetAllocator`].
+static BUDGET: Budget = Budget::new();
+
+/// Heap bytes attributed to the state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Bytes currently allocated.
+    pub current: usize,
+    /// Highest value of `current` since the last [`reset`].
+    pub peak: usize,
+}
+
+/// Error returned once the attributed allocations exceeded the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// The budget in bytes.
+    pub budget: usize,
+    /// The attributed usage when the error was raised.
+    pub usage: Usage,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "allocation budget of {} bytes exceeded (peak {} bytes)",
+            self.budget, self.usage.peak
+        )
+    }
+}
+
+/// Sets the budget in bytes. The default is unlimited.
+pub fn set_budget(bytes: usize) {
+    BUDGET.limit.store(bytes, Ordering::Relaxed);
+}
+
+/// Returns the attributed heap usage.
+pub fn usage() -> Usage {
+    BUDGET.usage()
+}
+
+/// Resets the counters and clears an exceeded budget, e.g. between blocks validated by a host.
+pub fn reset() {
+    BUDGET.reset();
+}
+
+/// A [`GlobalAlloc`] wrapper counting the allocations attributed to the state.
+#[derive(Debug)]
+pub struct BudgetAllocator<A> {
+    inner: A,
+}
+
+impl<A> BudgetAllocator<A> {
+    /// Wraps the given allocator.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for BudgetAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            BUDGET.grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            BUDGET.grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        BUDGET.shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            BUDGET.shrink(layout.size());
+            BUDGET.grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Attributes the allocations to the state while alive.
+#[derive(Debug)]
+pub(crate) struct Scope(());
+
+impl Scope {
+    pub(crate) fn enter() -> Self {
+        let depth = BUDGET.depth.load(Ordering::Relaxed);
+        BUDGET.depth.store(depth + 1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let depth = BUDGET.depth.load(Ordering::Relaxed);
+        BUDGET.depth.store(depth - 1, Ordering::Relaxed);
+    }
+}
+
+/// Fails if the budget has been exceeded since the last [`reset`].
+pub(crate) fn check() -> Result<(), BudgetExceeded> {
+    BUDGET.check()
+}
+
+/// Counters of the allocations made inside a [`Scope`].
+///
+/// Frees are only counted inside a scope as well, so memory allocated by the state and dropped
+/// outside of it, e.g. the state itself, stays counted until [`reset`].
+#[derive(Debug)]
+struct Budget {
+    limit: AtomicUsize,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    depth: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl Budget {
+    const fn new() -> Self {
+        Self {
+            limit: AtomicUsize::new(usize::MAX),
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            depth: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    fn in_scope(&self) -> bool {
+        self.depth.load(Ordering::Relaxed) != 0
+    }
+
+    fn grow(&self, size: usize) {
+        if !self.in_scope() {
+            return;
+        }
+        let current = self.current.load(Ordering::Relaxed).saturating_add(size);
+        self.current.store(current, Ordering::Relaxed);
+        if current > self.peak.load(Ordering::Relaxed) {
+            self.peak.store(current, Ordering::Relaxed);
+        }
+        if current > self.limit.load(Ordering::Relaxed) {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn shrink(&self, size: usize) {
+        if !self.in_scope() {
+            return;
+        }
+        let current = self.current.load(Ordering::Relaxed).saturating_sub(size);
+        self.current.store(current, Ordering::Relaxed);
+    }
+
+    fn usage(&self) -> Usage {
+        Usage {
+            current: self.current.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.current.store(0, Ordering::Relaxed);
+        self.peak.store(0, Ordering::Relaxed);
+        self.exceeded.store(false, Ordering::Relaxed);
+    }
+
+    fn check(&self) -> Result<(), BudgetExceeded> {
+        if self.exceeded.load(Ordering::Relaxed) {
+            return Err(BudgetExceeded {
+                budget: self.limit.load(Ordering::Relaxed),
+                usage: self.usage(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_only_in_scope_and_latches_overflow() {
+        // A local instance, so that the global counters used by the state tests stay untouched.
+        let budget = Budget::new();
+        budget.limit.store(100, Ordering::Relaxed);
+
+        budget.grow(500);
+        assert_eq!(budget.usage(), Usage::default());
+
+        budget.depth.store(1, Ordering::Relaxed);
+        budget.grow(80);
+        budget.shrink(30);
+        assert_eq!(
+            budget.usage(),
+            Usage {
+                current: 50,
+                peak: 80
+            }
+        );
+        assert_eq!(budget.check(), Ok(()));
+
+        budget.grow(60);
+        budget.shrink(60);
+        assert_eq!(
+            budget.check(),
+            Err(BudgetExceeded {
+                budget: 100,
+                usage: Usage {
+                    current: 50,
+                    peak: 110
+                }
+            })
+        );
+
+        budget.reset();
+        assert_eq!(budget.check(), Ok(()));
+        assert_eq!(budget.usage(), Usage::default());
+    }
+}
@@ -0,0 +1,242 @@
+//! Dry-run check that a witness reveals every key a block declares it accesses.
+//!
+//! An access to a key lying below a node missing from the witness panics in the middle of the
+//! execution. Checking the declared keys up front fails fast instead, with the list of the
+//! missing nodes.
+use crate::SimpleSparseState;
+use alloc::vec::Vec;
+use alloy_primitives::map::B256Set;
+use alloy_primitives::private::alloy_rlp;
+use alloy_primitives::Address;
+use alloy_trie::TrieAccount;
+use core::fmt;
+use ref_mpt::{keccak256, InvalidNode, Lookup, Trie, B256};
+use stateless::ExecutionWitness;
+
+/// The accounts and storage slots a block declares it accesses, e.g. from the access lists of
+/// its transactions or from a previous execution.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionWitnessKeys {
+    /// Accessed accounts.
+    pub accounts: Vec<Address>,
+    /// Accessed storage slots with their account, which need not be listed in `accounts`.
+    pub slots: Vec<(Address, B256)>,
+}
+
+impl ExecutionWitnessKeys {
+    /// Takes the accounts from the `keys` of `witness`.
+    ///
+    /// The witness lists the preimages of the storage slots without their account, so no slot
+    /// is taken from it.
+    pub fn from_witness(witness: &ExecutionWitness) -> Self {
+        let accounts = witness
+            .keys
+            .iter()
+            .filter(|key| key.len() == Address::len_bytes())
+            .map(|key| Address::from_slice(key))
+            .collect();
+        Self {
+            accounts,
+            slots: Vec::new(),
+        }
+    }
+}
+
+/// A declared key the witness does not reveal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPath {
+    /// The path of the account in the state trie goes through a missing node.
+    Account {
+        /// The account.
+        address: Address,
+        /// Hash of the missing node.
+        digest: B256,
+    },
+    /// The path of the slot in the storage trie of the account goes through a missing node.
+    Storage {
+        /// The account.
+        address: Address,
+        /// The slot.
+        slot: B256,
+        /// Hash of the missing node.
+        digest: B256,
+    },
+}
+
+/// The witness is malformed, so that the declared keys cannot be checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletenessError {
+    /// The state trie holds an account which is not a valid RLP-encoded account.
+    InvalidAccount {
+        /// The account.
+        address: Address,
+        /// The decoding error.
+        err: alloy_rlp::Error,
+    },
+    /// A node of a storage trie found in the witness does not decode.
+    InvalidNode(InvalidNode),
+}
+
+impl fmt::Display for CompletenessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAccount { address, err } => {
+                write!(f, "MPT: Invalid account {address} in the state trie: {err}")
+            }
+            Self::InvalidNode(err) => err.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for CompletenessError {}
+
+impl From<InvalidNode> for CompletenessError {
+    fn from(err: InvalidNode) -> Self {
+        Self::InvalidNode(err)
+    }
+}
+
+impl SimpleSparseState {
+    /// Checks that every key of `keys` is resolvable from the tries and the witness, before
+    /// running the block. Returns the keys that are not, with the digest of the first missing node
+    /// on their path, in the order of `keys`.
+    ///
+    /// An unresolvable account is reported once, and its slots are not checked. The state is not
+    /// modified: storage tries not accessed yet are revealed for the check only, and missing nodes
+    /// are not fetched from a node provider.
+    ///
+    /// Fails if an account of `keys` does not decode, or a node of a storage trie revealed for the
+    /// check is invalid.
+    pub fn check_completeness(
+        &self,
+        keys: &ExecutionWitnessKeys,
+    ) -> Result<Vec<MissingPath>, CompletenessError> {
+        let state = self.state.borrow();
+        let storages = self.storages.borrow();
+        let mut missing = Vec::new();
+        let mut missing_accounts = B256Set::default();
+        let addresses = keys
+            .accounts
+            .iter()
+            .chain(keys.slots.iter().map(|(address, _)| address));
+        for address in addresses {
+            let hashed_address = keccak256(address);
+            if let Lookup::Unknown(digest) = state.lookup(hashed_address) {
+                if missing_accounts.insert(hashed_address) {
+                    missing.push(MissingPath::Account {
+                        address: *address,
+                        digest,
+                    });
+                }
+            }
+        }
+
+        let mut revealed: Option<(B256, Trie)> = None;
+        for (address, slot) in &keys.slots {
+            let hashed_address = keccak256(address);
+            if missing_accounts.contains(&hashed_address) {
+                continue;
+            }
+            let storage_trie = match storages.get(&hashed_address) {
+                Some(storage_trie) => storage_trie.as_ref(),
+                None => {
+                    // slots of an account are usually declared together
+                    if revealed.as_ref().map(|(hashed, _)| *hashed) != Some(hashed_address) {
+                        let account = state
+                            .get_decoded::<TrieAccount>(hashed_address)
+                            .map_err(|err| CompletenessError::InvalidAccount {
+                                address: *address,
+                                err,
+                            })?;
+                        let Some(account) = account else {
+                            // the slots of a nonexistent account are empty
+                            continue;
+                        };
+                        let trie =
+                            Trie::try_reveal_from_rlp(account.storage_root, &self.rlp_by_digest)?;
+                        revealed = Some((hashed_address, trie));
+                    }
+                    &revealed.as_ref().unwrap().1
+                }
+            };
+            if let Lookup::Unknown(digest) = storage_trie.lookup(keccak256(slot)) {
+                missing.push(MissingPath::Storage {
+                    address: *address,
+                    slot: *slot,
+                    digest,
+                });
+            }
+        }
+        Ok(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use alloy_primitives::{Bytes, U256};
+    use stateless::StatelessTrie;
+    use std::vec;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn reports_missing_paths() {
+        let pre_state: TestState = (1..=8_u8)
+            .map(|i| {
+                let storage = (1..=4)
+                    .map(|slot| (U256::from(slot), U256::from(i)))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect();
+        let (mut witness, root) = build_witness(&pre_state);
+        let keys = ExecutionWitnessKeys {
+            accounts: vec![Address::repeat_byte(1), Address::repeat_byte(0xff)],
+            slots: vec![
+                (Address::repeat_byte(2), B256::from(U256::from(1))),
+                (Address::repeat_byte(2), B256::from(U256::from(9))),
+                (Address::repeat_byte(0xff), B256::from(U256::from(1))),
+            ],
+        };
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        assert_eq!(state.check_completeness(&keys).unwrap(), vec![]);
+
+        // drop the root of the storage trie of the second account
+        let storage_root = state
+            .accounts_iter()
+            .find(|(hashed_address, _)| *hashed_address == keccak256(Address::repeat_byte(2)));
+        let storage_root = storage_root.unwrap().1.storage_root;
+        witness.state.retain(|rlp| keccak256(rlp) != storage_root);
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let missing: Vec<_> = [1, 9]
+            .map(|slot| MissingPath::Storage {
+                address: Address::repeat_byte(2),
+                slot: B256::from(U256::from(slot)),
+                digest: storage_root,
+            })
+            .into();
+        assert_eq!(state.check_completeness(&keys).unwrap(), missing);
+
+        // without the state root node, every account is missing and reported once
+        witness.state.retain(|rlp| keccak256(rlp) != root);
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let missing: Vec<_> = [1, 0xff, 2]
+            .map(|byte| MissingPath::Account {
+                address: Address::repeat_byte(byte),
+                digest: root,
+            })
+            .into();
+        assert_eq!(state.check_completeness(&keys).unwrap(), missing);
+
+        let witness = ExecutionWitness {
+            keys: vec![
+                Bytes::from(Address::repeat_byte(3).to_vec()),
+                Bytes::from(B256::ZERO.to_vec()),
+            ],
+            ..Default::default()
+        };
+        let keys = ExecutionWitnessKeys::from_witness(&witness);
+        assert_eq!(keys.accounts, vec![Address::repeat_byte(3)]);
+    }
+}
@@ -0,0 +1,276 @@
+//! Estimation of the witness size of an access list, without generating proofs.
+//!
+//! Keys are hashed, so the leaves of a trie are spread uniformly and the shape of the proofs only
+//! depends on the number of leaves: at depth `d`, a trie of `n` leaves has about `n / 16^d` leaves
+//! below each node, and the paths of `k` keys go through about `16^d (1 - (1 - 16^-d)^k)` distinct
+//! nodes. [`estimate_witness`] sums the expected nodes and their encoded sizes over the depths of
+//! the state trie and of the storage tries of the accessed accounts.
+//!
+//! The estimate covers the trie nodes only, not the bytecodes nor the headers. Nodes shorter than
+//! 32 bytes, which are embedded in their parent, are counted as separate nodes.
+use alloy_primitives::map::{AddressMap, B256Set};
+use alloy_primitives::Address;
+use ref_mpt::{keccak256, Trie, B256};
+
+/// A key of an access list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessedKey {
+    /// An account, in the state trie.
+    Account(Address),
+    /// A storage slot, in the storage trie of the account. The account is accessed too.
+    Storage(Address, B256),
+}
+
+/// The shape of a trie with hashed keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrieShape {
+    /// Number of leaves.
+    pub leaves: u64,
+    /// Average length of the values, before their RLP encoding as strings.
+    pub value_size: usize,
+}
+
+impl TrieShape {
+    /// Measures the revealed leaves of `trie`, which only covers the whole trie when it is fully
+    /// revealed.
+    pub fn measure(trie: &Trie) -> Self {
+        let (leaves, bytes) = trie.iter().fold((0, 0), |(leaves, bytes), (_, value)| {
+            (leaves + 1, bytes + value.len())
+        });
+        Self {
+            leaves,
+            value_size: bytes.checked_div(leaves as usize).unwrap_or_default(),
+        }
+    }
+}
+
+/// The shapes of the state trie and of the storage tries, e.g. measured on a recent state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrieStats {
+    /// The state trie.
+    pub state: TrieShape,
+    /// The storage tries of known accounts.
+    pub storages: AddressMap<TrieShape>,
+    /// The storage trie of the accounts missing from `storages`.
+    pub default_storage: TrieShape,
+}
+
+/// The estimated trie nodes of a witness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WitnessEstimate {
+    /// Number of distinct nodes.
+    pub nodes: usize,
+    /// Total size of the RLP-encoded nodes, in bytes.
+    pub bytes: usize,
+}
+
+impl core::ops::AddAssign for WitnessEstimate {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes += other.nodes;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Estimates the trie nodes of the witness needed to access `keys`, from the shapes of the tries.
+///
+/// Accessing a slot needs the proof of its account in the state trie, and the proof of the slot
+/// in the storage trie of the account. Absent keys are counted as present ones, as their proof
+/// ends at about the same depth.
+pub fn estimate_witness(keys: &[AccessedKey], trie_stats: &TrieStats) -> WitnessEstimate {
+    let mut accounts = B256Set::default();
+    let mut slots: AddressMap<B256Set> = AddressMap::default();
+    for key in keys {
+        let address = match key {
+            AccessedKey::Account(address) => address,
+            AccessedKey::Storage(address, slot) => {
+                slots.entry(*address).or_default().insert(*slot);
+                address
+            }
+        };
+        accounts.insert(keccak256(address));
+    }
+
+    let mut estimate = estimate_trie(accounts.len(), trie_stats.state);
+    for (address, slots) in &slots {
+        let shape = trie_stats
+            .storages
+            .get(address)
+            .copied()
+            .unwrap_or(trie_stats.default_storage);
+        estimate += estimate_trie(slots.len(), shape);
+    }
+    estimate
+}
+
+/// Estimates the nodes of the proofs of `keys` distinct keys in a trie of the given shape.
+fn estimate_trie(keys: usize, shape: TrieShape) -> WitnessEstimate {
+    if keys == 0 || shape.leaves == 0 {
+        return WitnessEstimate::default();
+    }
+    let others = shape.leaves - 1;
+    let (mut nodes, mut bytes) = (0.0, 0.0);
+    // expected number of branches on the path of a key, i.e. the depth of its leaf
+    let mut leaf_depth = 0.0;
+    let mut prefixes = 1.0_f64;
+    for depth in 0..64 {
+        let share = 1.0 / prefixes;
+        // a branch is on the path at this depth if another leaf shares the prefix of the key
+        let branch = 1.0 - pow(1.0 - share, others);
+        if branch < 1e-9 {
+            break;
+        }
+        let distinct = if depth == 0 {
+            1.0
+        } else {
+            prefixes * (1.0 - pow(1.0 - share, keys as u64))
+        };
+        // the children of the branch: the one of the key, and the others below another leaf
+        let other_child = 1.0 - pow(1.0 - share / 16.0, others);
+        let children = (15.0 * other_child + branch) / branch;
+
+        nodes += distinct * branch;
+        bytes += distinct * branch * branch_size(children);
+        leaf_depth += branch;
+        prefixes *= 16.0;
+    }
+
+    let leaves = keys.min(shape.leaves as usize) as f64;
+    nodes += leaves;
+    bytes += leaves * leaf_size(64.0 - leaf_depth, shape.value_size);
+    WitnessEstimate {
+        nodes: (nodes + 0.5) as usize,
+        bytes: (bytes + 0.5) as usize,
+    }
+}
+
+/// Returns `base` to the power of `exp`, which `core` lacks for floats.
+fn pow(mut base: f64, mut exp: u64) -> f64 {
+    let mut result = 1.0;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result *= base;
+        }
+        base *= base;
+        exp >>= 1;
+    }
+    result
+}
+
+/// Returns the length of an RLP list or string header for a payload of `len` bytes.
+fn header_size(len: f64) -> f64 {
+    match len {
+        len if len < 56.0 => 1.0,
+        len if len < 256.0 => 2.0,
+        _ => 3.0,
+    }
+}
+
+/// Returns the size of a branch with `children` hashed children and no value.
+fn branch_size(children: f64) -> f64 {
+    let payload = 33.0 * children + (16.0 - children) + 1.0;
+    header_size(payload) + payload
+}
+
+/// Returns the size of a leaf with `nibbles` nibbles of path and a value of `value_size` bytes.
+fn leaf_size(nibbles: f64, value_size: usize) -> f64 {
+    let path = nibbles.max(0.0) / 2.0 + 1.0;
+    let value = value_size as f64;
+    let payload = header_size(path) + path + header_size(value) + value;
+    header_size(payload) + payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{Bytes, U256};
+    use alloy_trie::proof::ProofRetainer;
+    use alloy_trie::{HashBuilder, Nibbles};
+    use std::collections::BTreeMap;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Returns the distinct nodes of the proofs of `keys` in the trie of `leaves`.
+    fn measure_proofs(leaves: &BTreeMap<B256, Bytes>, keys: &[B256]) -> WitnessEstimate {
+        let targets = keys.iter().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        for (key, value) in leaves {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hash_builder.root();
+        let proof_nodes = hash_builder.take_proof_nodes();
+        // nodes inlined in their parent are not part of the witness
+        let nodes: Vec<&Bytes> = proof_nodes
+            .values()
+            .filter(|rlp| rlp.len() >= 32 || keccak256(rlp) == root)
+            .collect();
+        WitnessEstimate {
+            nodes: nodes.len(),
+            bytes: nodes.iter().map(|rlp| rlp.len()).sum(),
+        }
+    }
+
+    fn assert_close(estimate: WitnessEstimate, actual: WitnessEstimate) {
+        let close = |estimate: usize, actual: usize| estimate.abs_diff(actual) * 5 <= actual;
+        assert!(
+            close(estimate.nodes, actual.nodes) && close(estimate.bytes, actual.bytes),
+            "estimated {estimate:?}, measured {actual:?}"
+        );
+    }
+
+    #[test]
+    fn estimates_match_proofs() {
+        let address = |i: u64| Address::left_padding_from(&i.to_be_bytes());
+        let slot = |i: u64| B256::from(U256::from(i));
+        let accounts: BTreeMap<B256, Bytes> = (0..8_000)
+            .map(|i| (keccak256(address(i)), Bytes::from([i as u8; 70])))
+            .collect();
+        let slots: BTreeMap<B256, Bytes> = (0..300)
+            .map(|i| (keccak256(slot(i)), Bytes::from([1_u8; 3])))
+            .collect();
+        let shape = |leaves: &BTreeMap<B256, Bytes>| {
+            let mut trie = Trie::new();
+            for (key, value) in leaves {
+                trie.insert(*key, value.clone());
+            }
+            TrieShape::measure(&trie)
+        };
+        let mut stats = TrieStats {
+            state: shape(&accounts),
+            ..Default::default()
+        };
+        stats.storages.insert(address(1), shape(&slots));
+        assert_eq!(
+            stats.state,
+            TrieShape {
+                leaves: 8_000,
+                value_size: 70
+            }
+        );
+
+        for accessed in [10, 100, 1000] {
+            let keys: Vec<_> = (0..accessed)
+                .map(|i| AccessedKey::Account(address(i * 7)))
+                .collect();
+            let hashed: Vec<_> = (0..accessed).map(|i| keccak256(address(i * 7))).collect();
+            assert_close(
+                estimate_witness(&keys, &stats),
+                measure_proofs(&accounts, &hashed),
+            );
+        }
+
+        // the slots of an account without known storage add nothing, those of the first account
+        // add the nodes of its storage trie
+        let mut keys = vec![AccessedKey::Storage(address(2), B256::ZERO)];
+        assert_eq!(
+            estimate_witness(&keys, &stats),
+            estimate_witness(&[AccessedKey::Account(address(2))], &stats)
+        );
+        keys.extend((0..20).map(|i| AccessedKey::Storage(address(1), slot(i))));
+        let hashed: Vec<_> = (0..20).map(|i| keccak256(slot(i))).collect();
+        let mut expected =
+            measure_proofs(&accounts, &[keccak256(address(1)), keccak256(address(2))]);
+        expected += measure_proofs(&slots, &hashed);
+        assert_close(estimate_witness(&keys, &stats), expected);
+    }
+}
@@ -0,0 +1,286 @@
+//! Building the state from a genesis allocation, without a witness.
+use crate::provider::Resolver;
+use crate::storage_cache::StorageCache;
+use crate::SimpleSparseState;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloy_genesis::{Genesis, GenesisAccount};
+use alloy_primitives::private::alloy_rlp;
+use alloy_primitives::{Address, KECCAK256_EMPTY, U256};
+use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
+use core::cell::RefCell;
+use ref_mpt::{keccak256, B256Map, Trie, B256};
+use revm_bytecode::Bytecode;
+use stateless::ExecutionWitness;
+
+impl SimpleSparseState {
+    /// Builds the fully revealed state of the genesis allocation, to execute the first block of a
+    /// chain without a witness. Returns the state with the bytecodes of the allocation by their
+    /// hash, and the genesis state root.
+    pub fn from_genesis(genesis: &Genesis) -> (Self, B256Map<Bytecode>, B256) {
+        let mut storages = B256Map::default();
+        let mut bytecode = B256Map::default();
+        let mut state = Trie::new();
+        for (address, account) in &genesis.alloc {
+            // zero values are not stored in the storage trie
+            let mut storage: Trie = account
+                .storage
+                .iter()
+                .flatten()
+                .map(|(slot, value)| (*slot, U256::from_be_bytes(value.0)))
+                .filter(|(_, value)| !value.is_zero())
+                .map(|(slot, value)| (keccak256(slot), alloy_rlp::encode(value).into()))
+                .collect();
+            let code_hash = match account.code.as_ref().filter(|code| !code.is_empty()) {
+                Some(code) => {
+                    let code_hash = keccak256(code);
+                    bytecode.insert(code_hash, Bytecode::new_raw(code.clone()));
+                    code_hash
+                }
+                None => KECCAK256_EMPTY,
+            };
+            let trie_account = TrieAccount {
+                nonce: account.nonce.unwrap_or_default(),
+                balance: account.balance,
+                storage_root: storage.hash(),
+                code_hash,
+            };
+            let hashed_address = keccak256(address);
+            state.insert_encodable(hashed_address, &trie_account);
+            storages.insert(hashed_address, Arc::new(storage));
+        }
+        let state_root = state.hash();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "ref_mpt_state",
+            %state_root,
+            accounts = genesis.alloc.len(),
+            "genesis"
+        );
+
+        (
+            Self {
+                state: RefCell::new(state),
+                storages: RefCell::new(storages),
+                rlp_by_digest: Default::default(),
+                resolver: Resolver::default(),
+                storage_cache: RefCell::new(StorageCache::new(0)),
+                pending: Default::default(),
+                #[cfg(feature = "shadow")]
+                shadow: Default::default(),
+            },
+            bytecode,
+            state_root,
+        )
+    }
+
+    /// Exports the revealed accounts, with their code and revealed storage, as a genesis
+    /// allocation, e.g. to replay a failing block on a local devnet.
+    ///
+    /// The tries only hold hashed addresses and slots, so accounts and slots are exported when
+    /// their preimage is in the `keys` of `witness`, and codes when they are in its `codes`.
+    /// Storage tries not accessed yet are revealed from the witness of the state.
+    pub fn to_alloc(&self, witness: &ExecutionWitness) -> BTreeMap<Address, GenesisAccount> {
+        let mut addresses = B256Map::default();
+        let mut slots = B256Map::default();
+        for key in &witness.keys {
+            match key.len() {
+                20 => {
+                    let address = Address::from_slice(key);
+                    addresses.insert(keccak256(address), address);
+                }
+                32 => {
+                    let slot = B256::from_slice(key);
+                    slots.insert(keccak256(slot), slot);
+                }
+                _ => {}
+            }
+        }
+        let codes = crate::hash_codes(witness, Bytecode::new_raw);
+
+        let state = self.state.borrow();
+        let storages = self.storages.borrow();
+        let mut alloc = BTreeMap::new();
+        for (hashed_address, value) in state.iter() {
+            let Some(address) = addresses.get(&hashed_address) else {
+                continue;
+            };
+            let Ok(account) = alloy_rlp::decode_exact::<TrieAccount>(value) else {
+                continue;
+            };
+            let revealed;
+            let storage_trie = match storages.get(&hashed_address) {
+                Some(storage_trie) => storage_trie.as_ref(),
+                None => {
+                    revealed = Trie::reveal_from_rlp(account.storage_root, &self.rlp_by_digest);
+                    &revealed
+                }
+            };
+            let storage: BTreeMap<B256, B256> = storage_trie
+                .iter()
+                .filter_map(|(hashed_slot, value)| {
+                    let value = alloy_rlp::decode_exact::<U256>(value).ok()?;
+                    Some((*slots.get(&hashed_slot)?, B256::from(value)))
+                })
+                .collect();
+            let code = (account.code_hash != KECCAK256_EMPTY)
+                .then(|| codes.get(&account.code_hash))
+                .flatten()
+                .map(Bytecode::original_bytes);
+            alloc.insert(
+                *address,
+                GenesisAccount {
+                    nonce: Some(account.nonce),
+                    balance: account.balance,
+                    code,
+                    storage: (account.storage_root != EMPTY_ROOT_HASH).then_some(storage),
+                    private_key: None,
+                },
+            );
+        }
+        alloc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{build_witness, TestState};
+    use crate::SimpleSparseState;
+    use alloy_genesis::{Genesis, GenesisAccount};
+    use alloy_primitives::{Address, Bytes, B256, U256};
+    use reth_trie_common::HashedPostState;
+    use stateless::{ExecutionWitness, StatelessTrie};
+    use std::collections::BTreeMap;
+    use std::vec;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn genesis_root_matches_witness_root() {
+        let mut pre_state: TestState = (1..=10_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        pre_state.get_mut(&Address::repeat_byte(1)).unwrap().1 = BTreeMap::from([
+            (U256::from(1), U256::from(7)),
+            (U256::from(2), U256::from(8)),
+        ]);
+        let (_, expected_root) = build_witness(&pre_state);
+
+        let mut genesis = Genesis::default();
+        for (address, (balance, storage)) in &pre_state {
+            let mut storage: BTreeMap<B256, B256> = storage
+                .iter()
+                .map(|(slot, value)| (B256::from(*slot), B256::from(*value)))
+                .collect();
+            // zero values are ignored
+            storage.insert(B256::with_last_byte(9), B256::ZERO);
+            let account = GenesisAccount {
+                balance: *balance,
+                storage: Some(storage),
+                ..Default::default()
+            };
+            genesis.alloc.insert(*address, account);
+        }
+
+        let (mut state, bytecode, root) = SimpleSparseState::from_genesis(&genesis);
+        assert_eq!(root, expected_root);
+        assert!(bytecode.is_empty());
+        let address = Address::repeat_byte(1);
+        assert_eq!(
+            state.storage(address, U256::from(2)).unwrap(),
+            U256::from(8)
+        );
+        assert_eq!(
+            state.account(address).unwrap().unwrap().balance,
+            U256::from(1)
+        );
+        assert_eq!(
+            state
+                .calculate_state_root(HashedPostState::default())
+                .unwrap(),
+            root
+        );
+
+        // an account with code
+        let code = Bytes::from_static(&[0x60, 0x00, 0x56]);
+        let contract = GenesisAccount {
+            nonce: Some(1),
+            code: Some(code.clone()),
+            ..Default::default()
+        };
+        genesis.alloc.insert(Address::repeat_byte(0x42), contract);
+        let (state, bytecode, _) = SimpleSparseState::from_genesis(&genesis);
+        let account = state.account(Address::repeat_byte(0x42)).unwrap().unwrap();
+        assert_eq!(account.nonce, 1);
+        assert_eq!(bytecode[&account.code_hash].original_bytes(), code);
+    }
+
+    #[test]
+    fn alloc_roundtrip() {
+        let mut genesis = Genesis::default();
+        for i in 1..=5_u8 {
+            let storage = (1..=i)
+                .map(|slot| (B256::with_last_byte(slot), B256::with_last_byte(slot + 100)))
+                .collect();
+            let account = GenesisAccount {
+                nonce: Some(i.into()),
+                balance: U256::from(i),
+                code: (i == 3).then(|| Bytes::from_static(&[0x60, 0x00, 0x56])),
+                storage: Some(storage),
+                private_key: None,
+            };
+            genesis.alloc.insert(Address::repeat_byte(i), account);
+        }
+        // an account without storage is exported without a storage map
+        let empty = GenesisAccount {
+            nonce: Some(0),
+            ..Default::default()
+        };
+        genesis.alloc.insert(Address::repeat_byte(9), empty);
+
+        let mut witness = ExecutionWitness::default();
+        for (address, account) in &genesis.alloc {
+            witness
+                .keys
+                .push(Bytes::copy_from_slice(address.as_slice()));
+            for slot in account.storage.iter().flat_map(BTreeMap::keys) {
+                witness.keys.push(Bytes::copy_from_slice(slot.as_slice()));
+            }
+            witness.codes.extend(account.code.clone());
+        }
+        let (state, _, _) = SimpleSparseState::from_genesis(&genesis);
+        assert_eq!(state.to_alloc(&witness), genesis.alloc);
+
+        // accounts without a preimage are skipped
+        witness.keys.retain(|key| key.len() == 32);
+        assert!(state.to_alloc(&witness).is_empty());
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn alloc_from_witness() {
+        let address = Address::repeat_byte(0x01);
+        let storage = BTreeMap::from([(U256::from(1), U256::from(7))]);
+        let pre_state = TestState::from([
+            (address, (U256::from(5), storage)),
+            (Address::repeat_byte(0x02), (U256::from(6), BTreeMap::new())),
+        ]);
+        let (mut witness, root) = build_witness(&pre_state);
+        witness.keys = vec![
+            Bytes::copy_from_slice(address.as_slice()),
+            Bytes::from(B256::from(U256::from(1))),
+        ];
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+
+        // the storage trie is revealed from the witness
+        let alloc = state.to_alloc(&witness);
+        assert_eq!(alloc.len(), 1);
+        assert_eq!(alloc[&address].balance, U256::from(5));
+        assert_eq!(
+            alloc[&address].storage,
+            Some(BTreeMap::from([(
+                B256::from(U256::from(1)),
+                B256::from(U256::from(7))
+            )]))
+        );
+    }
+}
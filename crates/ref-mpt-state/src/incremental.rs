@@ -0,0 +1,314 @@
+//! Streaming the state changes of a block as its transactions complete.
+//!
+//! Instead of collecting all changes in one `HashedPostState` for
+//! [`StatelessTrie::calculate_state_root`], an executor applies each change when its transaction
+//! completes. Writes go to the tries at once, so a change touching a node missing from the witness
+//! fails on the transaction that made it, and only the accounts to rehash and the slots to clear
+//! are kept until [`SimpleSparseState::finalize_root`].
+//!
+//! [`StatelessTrie::calculate_state_root`]: stateless::StatelessTrie::calculate_state_root
+use crate::{execution_failed, shared_hash, SimpleSparseState};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloy_primitives::map::B256Set;
+use alloy_primitives::private::alloy_rlp;
+use alloy_primitives::{Address, KECCAK256_EMPTY, U256};
+use alloy_trie::TrieAccount;
+use ref_mpt::{keccak256, B256Map, B256};
+use reth_primitives_traits::Account;
+use stateless::validation::StatelessValidationError;
+
+/// The streamed changes not applied to the tries yet.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PendingChanges {
+    /// The changed accounts, `None` for destroyed ones.
+    accounts: B256Map<Option<Account>>,
+    /// The slots cleared by account, for every account with storage changes.
+    cleared_slots: B256Map<B256Set>,
+    /// The storage tries of the finalized accounts, hashed in parallel once joined.
+    #[cfg(feature = "parallel")]
+    pub(crate) storage_roots: crate::parallel::RootTasks,
+}
+
+#[cfg(feature = "parallel")]
+impl PendingChanges {
+    /// Takes the slots cleared by an account, which stays among the changed accounts.
+    pub(crate) fn take_cleared_slots(&mut self, hashed_address: B256) -> B256Set {
+        core::mem::take(self.cleared_slots.entry(hashed_address).or_default())
+    }
+}
+
+impl SimpleSparseState {
+    /// Sets the account at `address`, or destroys it with its storage if `account` is `None`.
+    ///
+    /// The storage root of the account is computed by [`SimpleSparseState::finalize_root`]. A
+    /// destroyed account which is created again in a later transaction starts with an empty
+    /// storage. Fails if the account lies below a node missing from the witness.
+    pub fn apply_account_change(
+        &mut self,
+        address: Address,
+        account: Option<Account>,
+    ) -> Result<(), StatelessValidationError> {
+        let hashed_address = keccak256(address);
+        self.resolver
+            .run(self.state.get_mut(), |state| {
+                state.try_get(hashed_address).map(|_| ())
+            })
+            .map_err(execution_failed)?;
+        if account.is_none() {
+            self.clear_storage(hashed_address);
+            self.pending.cleared_slots.remove(&hashed_address);
+        }
+        self.pending.accounts.insert(hashed_address, account);
+        Ok(())
+    }
+
+    /// Sets a storage slot of the account at `address`, clearing it if `value` is zero.
+    ///
+    /// Non-zero values are inserted at once. Cleared slots are removed by
+    /// [`SimpleSparseState::finalize_root`], after all the insertions, as removing a leaf may
+    /// need a sibling subtree which a later insertion reveals. Fails if the slot lies below a node
+    /// missing from the witness, or if the account or a node of its storage trie does not decode.
+    pub fn apply_storage_change(
+        &mut self,
+        address: Address,
+        slot: U256,
+        value: U256,
+    ) -> Result<(), StatelessValidationError> {
+        let hashed_address = keccak256(address);
+        let hashed_slot = keccak256(B256::from(slot));
+        let resolver = self.resolver.clone();
+        if !self.storages.get_mut().contains_key(&hashed_address) {
+            // the storage trie is revealed from the account
+            resolver
+                .run(self.state.get_mut(), |state| {
+                    state.try_get(hashed_address).map(|_| ())
+                })
+                .map_err(execution_failed)?;
+        }
+        let storage_trie = self.storage_trie_mut(hashed_address)?;
+        if value.is_zero() {
+            resolver
+                .run_shared(storage_trie, |trie| trie.try_get(hashed_slot).map(|_| ()))
+                .map_err(execution_failed)?;
+        } else {
+            resolver
+                .run(Arc::make_mut(storage_trie), |trie| {
+                    trie.try_insert_encodable(hashed_slot, &value)
+                })
+                .map_err(execution_failed)?;
+        }
+        let cleared_slots = self
+            .pending
+            .cleared_slots
+            .entry(hashed_address)
+            .or_default();
+        if value.is_zero() {
+            cleared_slots.insert(hashed_slot);
+        } else {
+            cleared_slots.remove(&hashed_slot);
+        }
+        Ok(())
+    }
+
+    /// Applies the pending changes and returns the new state root, as
+    /// [`StatelessTrie::calculate_state_root`] does for a whole `HashedPostState`.
+    ///
+    /// Fails if removing a slot or an account needs a node missing from the witness, or if a
+    /// changed account or a node of its storage trie does not decode, in which case the state is
+    /// left partially updated and must be discarded.
+    ///
+    /// [`StatelessTrie::calculate_state_root`]: stateless::StatelessTrie::calculate_state_root
+    pub fn finalize_root(&mut self) -> Result<B256, StatelessValidationError> {
+        #[cfg(feature = "parallel")]
+        let storage_roots = self.join_storage_roots().map_err(execution_failed)?;
+        let pending = core::mem::take(&mut self.pending);
+        let resolver = self.resolver.clone();
+        let mut removed_accounts = Vec::new();
+
+        let mut touched: B256Set = pending.accounts.keys().copied().collect();
+        touched.extend(pending.cleared_slots.keys().copied());
+        for hashed_address in touched {
+            let account = match pending.accounts.get(&hashed_address) {
+                Some(None) => {
+                    removed_accounts.push(hashed_address);
+                    continue;
+                }
+                Some(Some(account)) => Some(*account),
+                // only the storage changed, the account keeps its other fields
+                None => None,
+            };
+
+            #[cfg(feature = "parallel")]
+            let storage_root = storage_roots.get(&hashed_address).copied();
+            #[cfg(not(feature = "parallel"))]
+            let storage_root = None;
+            let storage_root = match storage_root {
+                Some(storage_root) => storage_root,
+                None => {
+                    let storage_trie = self.storage_trie_mut(hashed_address)?;
+                    let cleared_slots = pending.cleared_slots.get(&hashed_address);
+                    for hashed_slot in cleared_slots.into_iter().flatten() {
+                        resolver
+                            .run(Arc::make_mut(storage_trie), |trie| {
+                                trie.try_remove(*hashed_slot)
+                            })
+                            .map_err(execution_failed)?;
+                    }
+                    shared_hash(storage_trie)
+                }
+            };
+
+            let account = match account {
+                Some(account) => TrieAccount {
+                    nonce: account.nonce,
+                    balance: account.balance,
+                    storage_root,
+                    code_hash: account.bytecode_hash.unwrap_or(KECCAK256_EMPTY),
+                },
+                None => {
+                    let value = resolver
+                        .run(self.state.get_mut(), |state| {
+                            state.try_get(hashed_address).map(|value| value.cloned())
+                        })
+                        .map_err(execution_failed)?;
+                    // slots cleared in a nonexistent account leave the state unchanged
+                    let Some(value) = value else {
+                        continue;
+                    };
+                    let account = alloy_rlp::decode_exact::<TrieAccount>(&value)
+                        .map_err(execution_failed)?;
+                    TrieAccount {
+                        storage_root,
+                        ..account
+                    }
+                }
+            };
+            resolver
+                .run(self.state.get_mut(), |state| {
+                    state.try_insert_encodable(hashed_address, &account)
+                })
+                .map_err(execution_failed)?;
+        }
+
+        for hashed_address in removed_accounts {
+            resolver
+                .run(self.state.get_mut(), |state| {
+                    state.try_remove(hashed_address)
+                })
+                .map_err(execution_failed)?;
+            self.storages.get_mut().remove(&hashed_address);
+        }
+        Ok(self.state.get_mut().hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use alloy_trie::{proof::ProofRetainer, HashBuilder, Nibbles};
+    use reth_trie_common::{HashedPostState, HashedStorage};
+    use stateless::{ExecutionWitness, StatelessTrie};
+    use std::vec;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn streamed_changes_match_post_state() {
+        let pre_state: TestState = (1..=10_u8)
+            .map(|i| {
+                let storage = (1..=8_u64)
+                    .map(|slot| (U256::from(slot), U256::from(slot + u64::from(i))))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect();
+        let (witness, root) = build_witness(&pre_state);
+        let account = |balance: u64| Account {
+            nonce: 1,
+            balance: U256::from(balance),
+            bytecode_hash: None,
+        };
+
+        // three transactions: a transfer writing slots, a self-destruct and a storage-only change
+        let (mut streamed, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let one = Address::repeat_byte(1);
+        streamed
+            .apply_account_change(one, Some(account(50)))
+            .unwrap();
+        streamed
+            .apply_storage_change(one, U256::from(1), U256::ZERO)
+            .unwrap();
+        streamed
+            .apply_storage_change(one, U256::from(2), U256::ZERO)
+            .unwrap();
+        streamed
+            .apply_storage_change(one, U256::from(20), U256::from(7))
+            .unwrap();
+        streamed
+            .apply_account_change(Address::repeat_byte(2), None)
+            .unwrap();
+        let three = Address::repeat_byte(3);
+        streamed
+            .apply_storage_change(three, U256::from(3), U256::ZERO)
+            .unwrap();
+        // a later transaction writes the slot cleared by the first one again
+        streamed
+            .apply_storage_change(one, U256::from(2), U256::from(9))
+            .unwrap();
+        let streamed_root = streamed.finalize_root().unwrap();
+
+        let mut post_state = HashedPostState::default();
+        post_state
+            .accounts
+            .insert(keccak256(one), Some(account(50)));
+        let mut storage = HashedStorage::new(false);
+        for (slot, value) in [(1, 0), (2, 9), (20, 7)] {
+            let hashed_slot = keccak256(B256::from(U256::from(slot)));
+            storage.storage.insert(hashed_slot, U256::from(value));
+        }
+        post_state.storages.insert(keccak256(one), storage);
+        post_state
+            .accounts
+            .insert(keccak256(Address::repeat_byte(2)), None);
+        // the calculated root only covers changed accounts, so the third one is listed
+        post_state.accounts.insert(
+            keccak256(three),
+            Some(Account {
+                nonce: 0,
+                balance: U256::from(3),
+                bytecode_hash: None,
+            }),
+        );
+        let mut storage = HashedStorage::new(false);
+        let hashed_slot = keccak256(B256::from(U256::from(3)));
+        storage.storage.insert(hashed_slot, U256::ZERO);
+        post_state.storages.insert(keccak256(three), storage);
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        assert_eq!(
+            streamed_root,
+            state.calculate_state_root(post_state).unwrap()
+        );
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn invalid_account_fails() {
+        let address = Address::repeat_byte(1);
+        let key = || Nibbles::unpack(keccak256(address));
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(vec![key()]));
+        // a leaf whose value is no RLP-encoded account
+        hash_builder.add_leaf(key(), &[0xff; 8]);
+        let root = hash_builder.root();
+        let witness = ExecutionWitness {
+            state: hash_builder.take_proof_nodes().values().cloned().collect(),
+            ..Default::default()
+        };
+
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        state
+            .apply_storage_change(address, U256::from(1), U256::from(1))
+            .unwrap_err();
+    }
+}
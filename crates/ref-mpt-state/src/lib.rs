@@ -1,14 +1,24 @@
 //! A sparse state implementation based on simple sparse trie.
 #![no_std]
+// the tests checking keccak roots are compiled out under the `poseidon2` feature, leaving their
+// helpers unused
+#![cfg_attr(
+    all(test, feature = "poseidon2"),
+    allow(unused, unused_crate_dependencies)
+)]
 extern crate alloc;
-#[cfg(test)]
+#[cfg(any(test, feature = "parallel"))]
 extern crate std;
+// the dev-dependencies of the benchmarks
+#[cfg(test)]
+use {criterion as _, serde_json as _, zeth_mpt_state as _};
 
-use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloy_primitives::private::alloy_rlp;
 use alloy_primitives::private::alloy_rlp::Decodable;
-use alloy_primitives::{keccak256, map::hash_map::Entry, Address, Bytes, KECCAK256_EMPTY, U256};
+use alloy_primitives::{map::hash_map::Entry, Address, Bytes, KECCAK256_EMPTY, U256};
 use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
 use core::cell::RefCell;
 use revm_bytecode::Bytecode;
@@ -16,54 +26,444 @@ use stateless::error::WitnessDbError;
 use stateless::validation::StatelessValidationError;
 use stateless::{ExecutionWitness, StatelessTrie};
 use reth_trie_common::HashedPostState;
-use ref_mpt::Trie;
-use ref_mpt::{B256Map, B256};
+use provider::Resolver;
+#[cfg(feature = "shadow")]
+use shadow::Shadow;
+use storage_cache::StorageCache;
+use incremental::PendingChanges;
+use ref_mpt::{InvalidNode, Trie, UnresolvedNode};
+use ref_mpt::RlpNodes;
+use ref_mpt::ValuePool;
+use ref_mpt::{keccak256, B256Map, B256};
+
+#[cfg(feature = "alloc-budget")]
+pub mod budget;
+pub mod ancestors;
+pub mod completeness;
+pub mod estimate;
+#[cfg(feature = "genesis")]
+mod genesis;
+mod incremental;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod parts;
+pub mod proof;
+mod provider;
+pub mod report;
+#[cfg(feature = "shadow")]
+mod shadow;
+#[cfg(feature = "signers")]
+pub mod signers;
+pub mod storage_cache;
+pub mod witness;
+
+/// The witness nodes by their hash, a sorted [`ref_mpt::WitnessIndex`] with `sorted-witness`.
+#[cfg(not(feature = "sorted-witness"))]
+pub type RlpByDigest = ref_mpt::WitnessMap;
+/// The witness nodes by their hash, a sorted [`ref_mpt::WitnessIndex`] with `sorted-witness`.
+#[cfg(feature = "sorted-witness")]
+pub type RlpByDigest = ref_mpt::WitnessIndex;
 
 /// Implementation of a simple sparse state based on simple_trie
 #[derive(Debug, Clone)]
 pub struct SimpleSparseState {
-    state: Trie,
-    storages: RefCell<B256Map<Box<Trie>>>,
-    rlp_by_digest: B256Map<Bytes>,
+    state: RefCell<Trie>,
+    storages: RefCell<B256Map<Arc<Trie>>>,
+    rlp_by_digest: RlpByDigest,
+    resolver: Resolver,
+    storage_cache: RefCell<StorageCache>,
+    pending: PendingChanges,
+    #[cfg(feature = "shadow")]
+    shadow: Shadow,
+}
+
+/// Hashes the RLP nodes of the witness.
+fn index_witness(witness: &ExecutionWitness) -> RlpByDigest {
+    #[cfg(not(feature = "sorted-witness"))]
+    let rlp_by_digest = RlpByDigest::from_execution_witness(witness);
+    #[cfg(feature = "sorted-witness")]
+    let rlp_by_digest = RlpByDigest::from_nodes(&witness.state);
+    rlp_by_digest
+}
+
+/// Reveals the nodes of a shared trie found in `rlp_by_digest`, copying the trie only if one of
+/// its unresolved nodes is there.
+fn reveal_shared<M: RlpNodes + ?Sized>(
+    trie: &mut Arc<Trie>,
+    rlp_by_digest: &M,
+) -> Result<(), InvalidNode> {
+    let revealable = trie
+        .unresolved()
+        .iter()
+        .any(|(_, digest)| rlp_by_digest.rlp(digest).is_some());
+    if revealable {
+        Arc::make_mut(trie).try_reveal(rlp_by_digest)?;
+    }
+    Ok(())
+}
+
+/// Fails the validation with an error of the tries, e.g. an [`UnresolvedNode`] when the witness
+/// misses a node the post-state changes need.
+fn execution_failed(err: impl core::fmt::Display) -> StatelessValidationError {
+    StatelessValidationError::StatelessExecutionFailed(err.to_string())
+}
+
+/// Returns the root hash of a shared trie, which is only copied if its hash is not cached.
+fn shared_hash(trie: &mut Arc<Trie>) -> B256 {
+    match trie.cached_hash() {
+        Some(hash) => hash,
+        None => Arc::make_mut(trie).hash(),
+    }
+}
+
+/// Hashes the bytecodes of the witness, analyzing them with `analyze`.
+///
+/// A code listed several times, e.g. the implementation shared by many proxies, is analyzed and
+/// stored once.
+fn hash_codes(
+    witness: &ExecutionWitness,
+    mut analyze: impl FnMut(Bytes) -> Bytecode,
+) -> B256Map<Bytecode> {
+    let mut bytecode = B256Map::with_capacity_and_hasher(witness.codes.len(), Default::default());
+    for code in &witness.codes {
+        if let Entry::Vacant(entry) = bytecode.entry(keccak256(code)) {
+            entry.insert(analyze(code.clone()));
+        }
+    }
+    bytecode
 }
 
 impl SimpleSparseState {
+    /// Builds the state of the next block from the state `prev` of the previous block, after its
+    /// `calculate_state_root`, and the witness of the next block.
+    ///
+    /// The tries of `prev` are kept with their revealed nodes and cached hashes, and only the
+    /// subtrees they are missing are revealed from the witness, instead of revealing the whole
+    /// witness again. If the post-state root of `prev` is not `pre_state_root`, e.g. after a
+    /// reorg, the state is built from the witness alone as with [`StatelessTrie::new`].
+    pub fn advance(
+        prev: Self,
+        witness: &ExecutionWitness,
+        pre_state_root: B256,
+    ) -> Result<(Self, B256Map<Bytecode>), StatelessValidationError> {
+        let Self {
+            state,
+            storages,
+            resolver,
+            storage_cache,
+            #[cfg(feature = "shadow")]
+            shadow,
+            ..
+        } = prev;
+        let mut state = state.into_inner();
+        let mut storage_cache = storage_cache.into_inner();
+        storage_cache.next_block();
+        if state.hash() != pre_state_root {
+            let (mut next, bytecode) = Self::new(witness, pre_state_root)?;
+            next.resolver = resolver;
+            next.storage_cache = RefCell::new(storage_cache);
+            #[cfg(feature = "shadow")]
+            {
+                next.shadow = shadow.next(witness, pre_state_root);
+            }
+            return Ok((next, bytecode));
+        }
+
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "ref_mpt_state", %pre_state_root, "advance");
+
+        let rlp_by_digest = index_witness(witness);
+        let invalid = |_| StatelessValidationError::WitnessRevealFailed { pre_state_root };
+        state.try_reveal(&rlp_by_digest).map_err(invalid)?;
+        // the storage tries match the storage roots of the post-state accounts
+        let mut storages = storages.into_inner();
+        for storage_trie in storages.values_mut() {
+            reveal_shared(storage_trie, &rlp_by_digest).map_err(invalid)?;
+        }
+        let bytecode = hash_codes(witness, Bytecode::new_raw);
+
+        #[cfg(feature = "alloc-budget")]
+        budget::check()
+            .map_err(|err| StatelessValidationError::StatelessExecutionFailed(err.to_string()))?;
+        Ok((
+            Self {
+                state: RefCell::new(state),
+                storages: RefCell::new(storages),
+                rlp_by_digest,
+                resolver,
+                storage_cache: RefCell::new(storage_cache),
+                pending: PendingChanges::default(),
+                #[cfg(feature = "shadow")]
+                shadow: shadow.next(witness, pre_state_root),
+            },
+            bytecode,
+        ))
+    }
+
+    /// Takes the storage tries of accounts whose storage root is in `cache` from the cache,
+    /// instead of revealing them from the witness, see [`StorageCache`].
+    pub fn with_storage_cache(mut self, mut cache: StorageCache) -> Self {
+        cache.next_block();
+        self.storage_cache = RefCell::new(cache);
+        #[cfg(feature = "shadow")]
+        self.shadow
+            .disable("the witness may omit the storage tries of the cache");
+        self
+    }
+
+    /// Returns the storage cache with the storage tries of the state added, to be passed to the
+    /// state of the next block. Called after `calculate_state_root`, the tries match the
+    /// post-state storage roots.
+    pub fn into_storage_cache(self) -> StorageCache {
+        let mut cache = self.storage_cache.into_inner();
+        cache.extend(self.storages.into_inner().into_values());
+        cache
+    }
+
+    /// Shares the identical account and storage values of the state's tries through `pool`, see
+    /// [`ValuePool`]. Long-lived hosts keeping states or their storage tries across blocks pass
+    /// the same pool to every state.
+    pub fn intern_values(&mut self, pool: &mut ValuePool) {
+        self.state.get_mut().intern_values(pool);
+        for storage in self.storages.get_mut().values_mut() {
+            Arc::make_mut(storage).intern_values(pool);
+        }
+    }
+
+    /// Reveals the storage tries of all the revealed accounts, then drops the witness nodes.
+    ///
+    /// Once every trie is revealed, the witness has no node left to reveal, and releasing it
+    /// reclaims the memory of the witness map for the execution of the block. This is worth it
+    /// when the block accesses most of the witnessed storage, as the storage tries of accounts
+    /// the block does not access are revealed too.
+    pub fn release_witness(mut self) -> Self {
+        let storages = self.storages.get_mut();
+        let storage_cache = self.storage_cache.get_mut();
+        for (hashed_address, value) in self.state.get_mut().iter() {
+            let Ok(account) = alloy_rlp::decode_exact::<TrieAccount>(value) else {
+                continue;
+            };
+            if account.storage_root != EMPTY_ROOT_HASH {
+                storages.entry(hashed_address).or_insert_with(|| {
+                    storage_cache
+                        .reveal(account.storage_root, &self.rlp_by_digest)
+                        .expect("MPT: Failed to decode trie node")
+                });
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "ref_mpt_state",
+            nodes = self.rlp_by_digest.len(),
+            storage_tries = storages.len(),
+            "release witness"
+        );
+        self.rlp_by_digest = RlpByDigest::default();
+        self
+    }
+
+    /// Returns the revealed accounts with their hashed address, in hashed address order, e.g. to
+    /// list what a witness exposes. Accounts lying below unresolved nodes are skipped.
+    ///
+    /// The accounts are decoded up front, as the state trie cannot stay borrowed by the iterator.
+    pub fn accounts_iter(&self) -> impl Iterator<Item = (B256, TrieAccount)> {
+        let accounts: Vec<_> = self
+            .state
+            .borrow()
+            .iter()
+            .filter_map(|(hashed_address, value)| {
+                let account = alloy_rlp::decode_exact::<TrieAccount>(value).ok()?;
+                Some((hashed_address, account))
+            })
+            .collect();
+        accounts.into_iter()
+    }
+
+    /// Reads several storage slots of an account at once, in the order of `slots`, like
+    /// [`StatelessTrie::storage`] for each slot.
+    ///
+    /// The address is hashed once and the slots are looked up together with
+    /// [`Trie::try_get_many`], so the nodes shared by their paths are walked once. As with
+    /// `storage`, the account must have been read before.
+    pub fn storage_many(&self, address: Address, slots: &[U256]) -> Vec<U256> {
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
+        let hashed_slots: Vec<B256> = slots
+            .iter()
+            .map(|slot| keccak256(B256::from(*slot)))
+            .collect();
+        let Some(values) = self.read_storage_trie(keccak256(address), |storage_trie| {
+            self.resolver
+                .run_shared(storage_trie, |trie| {
+                    let values = trie.try_get_many(&hashed_slots)?;
+                    Ok(values
+                        .into_iter()
+                        .map(|value| {
+                            value.map_or(U256::ZERO, |value| U256::decode(&mut &value[..]).unwrap())
+                        })
+                        .collect::<Vec<U256>>())
+                })
+                .expect("MPT: Unresolved node access")
+        }) else {
+            return alloc::vec![U256::ZERO; slots.len()];
+        };
+        #[cfg(feature = "shadow")]
+        for (slot, value) in slots.iter().zip(&values) {
+            self.shadow.storage(address, *slot, *value);
+        }
+        values
+    }
+
+    /// Reads several accounts at once, in the order of `addresses`, like
+    /// [`StatelessTrie::account`] for each address, e.g. to pre-warm the accounts of the access
+    /// lists of a block.
+    ///
+    /// The accounts are looked up together with [`Trie::try_get_many`], so the nodes shared by
+    /// their paths are walked once, and the storage tries of all of them are cached for the
+    /// following storage reads.
+    pub fn accounts_many(&self, addresses: &[Address]) -> Vec<Option<TrieAccount>> {
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
+        let hashed_addresses: Vec<B256> = addresses.iter().map(keccak256).collect();
+        let accounts: Vec<Option<TrieAccount>> = self
+            .resolver
+            .run(&mut self.state.borrow_mut(), |state| {
+                let values = state.try_get_many(&hashed_addresses)?;
+                Ok(values
+                    .into_iter()
+                    .map(|value| alloy_rlp::decode_exact(value?).ok())
+                    .collect())
+            })
+            .expect("MPT: Unresolved node access");
+        for (hashed_address, account) in hashed_addresses.iter().zip(&accounts) {
+            if let Some(account) = account {
+                self.cache_storage_trie(*hashed_address, account);
+            }
+        }
+        #[cfg(feature = "shadow")]
+        for (address, account) in addresses.iter().zip(&accounts) {
+            self.shadow.account(*address, *account);
+        }
+        accounts
+    }
+
+    /// Runs `read` on the storage trie of an account, or returns `None` if it is not revealed.
+    ///
+    /// The storage trie of an account passed to [`SimpleSparseState::finalize_storage`] is held
+    /// until its root is joined, and read once its root is computed.
+    fn read_storage_trie<R>(
+        &self,
+        hashed_address: B256,
+        read: impl FnOnce(&mut Arc<Trie>) -> R,
+    ) -> Option<R> {
+        #[cfg(feature = "parallel")]
+        if self.pending.storage_roots.contains(&hashed_address) {
+            return self
+                .pending
+                .storage_roots
+                .with_storage_trie(&hashed_address, read);
+        }
+        self.storages.borrow_mut().get_mut(&hashed_address).map(read)
+    }
+
+    /// Reveals the storage trie of an account read from the state trie, unless already cached.
+    fn cache_storage_trie(&self, hashed_address: B256, account: &TrieAccount) {
+        // the storage trie of the account is held until its root is joined
+        #[cfg(feature = "parallel")]
+        if self.pending.storage_roots.contains(&hashed_address) {
+            return;
+        }
+        if let Entry::Vacant(entry) = self.storages.borrow_mut().entry(hashed_address) {
+            if account.storage_root != EMPTY_ROOT_HASH {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(
+                    target: "ref_mpt_state",
+                    %hashed_address,
+                    storage_root = %account.storage_root,
+                    "reveal storage trie"
+                );
+                let t = self
+                    .storage_cache
+                    .borrow_mut()
+                    .reveal(account.storage_root, &self.rlp_by_digest)
+                    .expect("MPT: Failed to decode trie node");
+                entry.insert(t);
+            } else {
+                entry.insert(Arc::new(Trie::new()));
+            }
+        }
+    }
+
     /// Removes an account from the state.
-    fn remove_account(&mut self, hashed_address: &B256) {
-        self.state.remove(*hashed_address);
+    fn remove_account(&mut self, hashed_address: &B256) -> Result<(), UnresolvedNode> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(target: "ref_mpt_state", %hashed_address, "remove account");
+        self.resolver.run(self.state.get_mut(), |state| {
+            state.try_remove(*hashed_address)
+        })?;
         self.storages.get_mut().remove(hashed_address);
+        Ok(())
     }
 
     /// Clears the storage of an account.
-    fn clear_storage(&mut self, hashed_address: B256) -> &mut Box<Trie> {
-        match self.storages.get_mut().entry(hashed_address) {
+    ///
+    /// The trie is replaced by an empty one rather than cleared in place: no slot of the wiped
+    /// storage is read or proved after the wipe, and the slots written afterwards are inserted
+    /// into the empty trie, so none of its revealed nodes are worth keeping.
+    fn clear_storage(&mut self, hashed_address: B256) -> &mut Trie {
+        let storage_trie = match self.storages.get_mut().entry(hashed_address) {
             Entry::Occupied(mut entry) => {
-                entry.insert(Box::new(Trie::new()));
+                entry.insert(Arc::new(Trie::new()));
                 entry
             }
-            Entry::Vacant(entry) => entry.insert_entry(Box::new(Trie::new())),
+            Entry::Vacant(entry) => entry.insert_entry(Arc::new(Trie::new())),
         }
-        .into_mut()
+        .into_mut();
+        Arc::get_mut(storage_trie).unwrap()
     }
 
-    /// Returns a mutable version of the storage trie of the given account.
-    fn storage_trie_mut(&mut self, hashed_address: B256) -> alloy_rlp::Result<&mut Box<Trie>> {
+    /// Returns the storage trie of the given account, revealing it if needed. The trie may be
+    /// shared with a clone of the state, so it is copied with [`Arc::make_mut`] before mutations.
+    ///
+    /// Fails if the account is below an unresolved node, if it does not decode, or if a node of
+    /// its storage trie does not decode.
+    fn storage_trie_mut(
+        &mut self,
+        hashed_address: B256,
+    ) -> Result<&mut Arc<Trie>, StatelessValidationError> {
         let trie = match self.storages.get_mut().entry(hashed_address) {
             Entry::Occupied(entry) => entry.into_mut(),
             Entry::Vacant(entry) => {
                 // build the storage trie matching the storage root of the account
-                let storage_root =
-                    self.state
-                        .get(hashed_address)
-                        .map_or(EMPTY_ROOT_HASH, |value| {
-                            alloy_rlp::decode_exact::<TrieAccount>(value)
-                                .unwrap()
-                                .storage_root
-                        });
-                entry.insert(Box::new(Trie::reveal_from_rlp(
-                    storage_root,
-                    &self.rlp_by_digest,
-                )))
+                let storage_root = match self
+                    .resolver
+                    .run(self.state.get_mut(), |state| {
+                        state.try_get(hashed_address).map(|value| value.cloned())
+                    })
+                    .map_err(execution_failed)?
+                {
+                    Some(value) => {
+                        alloy_rlp::decode_exact::<TrieAccount>(value)
+                            .map_err(execution_failed)?
+                            .storage_root
+                    }
+                    None => EMPTY_ROOT_HASH,
+                };
+                #[cfg(feature = "tracing")]
+                tracing::trace!(
+                    target: "ref_mpt_state",
+                    %hashed_address,
+                    %storage_root,
+                    "reveal storage trie"
+                );
+                entry.insert(
+                    self.storage_cache
+                        .get_mut()
+                        .reveal(storage_root, &self.rlp_by_digest)
+                        .map_err(execution_failed)?,
+                )
             }
         };
 
@@ -71,6 +471,27 @@ impl SimpleSparseState {
     }
 }
 
+/// Prints a one-line summary of the state: its root, or `unhashed` if the state trie changed
+/// since it was last hashed, and the number of revealed storage tries and witness nodes. The
+/// summary is cheap, it neither hashes nor walks the tries.
+///
+/// Use the `Debug` implementation to dump the tries themselves.
+impl core::fmt::Display for SimpleSparseState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("SimpleSparseState { root: ")?;
+        match self.state.borrow().cached_hash() {
+            Some(root) => write!(f, "{root}")?,
+            None => f.write_str("unhashed")?,
+        }
+        write!(
+            f,
+            ", storage tries: {}, witness nodes: {} }}",
+            self.storages.borrow().len(),
+            self.rlp_by_digest.len()
+        )
+    }
+}
+
 impl StatelessTrie for SimpleSparseState {
     fn new(
         witness: &ExecutionWitness,
@@ -79,69 +500,86 @@ impl StatelessTrie for SimpleSparseState {
     where
         Self: Sized,
     {
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
+
         // fist, hash all the RLP nodes once
-        let rlp_by_digest: B256Map<_> = witness
-            .state
-            .iter()
-            .map(|rlp| (keccak256(rlp), rlp.clone()))
-            .collect();
+        let rlp_by_digest = index_witness(witness);
 
         // construct the state trie from the witness data and the given state root
-        let mut state = Trie::reveal_from_rlp(pre_state_root, &rlp_by_digest);
+        let mut state = Trie::try_reveal_from_rlp(pre_state_root, &rlp_by_digest)
+            .map_err(|_| StatelessValidationError::WitnessRevealFailed { pre_state_root })?;
 
         // hash all the supplied bytecode
-        let bytecode = witness
-            .codes
-            .iter()
-            .map(|code| (keccak256(code), Bytecode::new_raw(code.clone())))
-            .collect();
+        let bytecode = hash_codes(witness, Bytecode::new_raw);
 
         debug_assert_eq!(state.hash(), pre_state_root);
+        #[cfg(feature = "alloc-budget")]
+        budget::check()
+            .map_err(|err| StatelessValidationError::StatelessExecutionFailed(err.to_string()))?;
         Ok((
             SimpleSparseState {
-                state,
+                state: RefCell::new(state),
                 storages: RefCell::new(B256Map::default()),
                 rlp_by_digest,
+                resolver: Resolver::default(),
+                storage_cache: RefCell::new(StorageCache::new(0)),
+                pending: PendingChanges::default(),
+                #[cfg(feature = "shadow")]
+                shadow: Shadow::new(witness, pre_state_root),
             },
             bytecode,
         ))
     }
 
     fn account(&self, address: Address) -> Result<Option<TrieAccount>, WitnessDbError> {
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
         let hashed_address = keccak256(address);
-        match self.state.get(hashed_address) {
+        let value = self
+            .resolver
+            .run(&mut self.state.borrow_mut(), |state| {
+                state.try_get(hashed_address).map(|value| value.cloned())
+            })
+            .expect("MPT: Unresolved node access");
+        let account = match value {
             Some(value) => {
                 match alloy_rlp::decode_exact(value.as_ref()) as Result<TrieAccount, _> {
                     Ok(account) => {
-                        match self.storages.borrow_mut().entry(hashed_address) {
-                            Entry::Vacant(entry) => {
-                                if account.storage_root != EMPTY_ROOT_HASH {
-                                    let t = Box::new(Trie::reveal_from_rlp(
-                                        account.storage_root,
-                                        &self.rlp_by_digest,
-                                    ));
-                                    entry.insert(t);
-                                } else {
-                                    entry.insert(Box::new(Trie::new()));
-                                }
-                            }
-                            Entry::Occupied(_) => {}
-                        }
-                        Ok(Some(account))
+                        self.cache_storage_trie(hashed_address, &account);
+                        Some(account)
                     }
-                    Err(_) => Ok(None),
+                    Err(_) => None,
                 }
             }
-            None => Ok(None),
-        }
+            None => None,
+        };
+        #[cfg(feature = "shadow")]
+        self.shadow.account(address, account);
+        Ok(account)
     }
 
     fn storage(&self, address: Address, slot: U256) -> Result<U256, WitnessDbError> {
-        match self.storages.borrow_mut().get(&keccak256(address)) {
-            Some(storage_trie) => match storage_trie.get(keccak256(B256::from(slot))) {
-                Some(value) => Ok(U256::decode(&mut &value[..]).unwrap()),
-                None => Ok(U256::ZERO),
-            },
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
+        let hashed_slot = keccak256(B256::from(slot));
+        let value = self.read_storage_trie(keccak256(address), |storage_trie| {
+            self.resolver
+                .run_shared(storage_trie, |trie| {
+                    trie.try_get(hashed_slot).map(|value| value.cloned())
+                })
+                .expect("MPT: Unresolved node access")
+        });
+        match value {
+            Some(value) => {
+                let value = match value {
+                    Some(value) => U256::decode(&mut &value[..]).unwrap(),
+                    None => U256::ZERO,
+                };
+                #[cfg(feature = "shadow")]
+                self.shadow.storage(address, slot, value);
+                Ok(value)
+            }
             None => Ok(U256::ZERO),
         }
     }
@@ -150,7 +588,23 @@ impl StatelessTrie for SimpleSparseState {
         &mut self,
         state: HashedPostState,
     ) -> Result<B256, StatelessValidationError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            target: "ref_mpt_state",
+            "calculate_state_root",
+            accounts = state.accounts.len(),
+            storages = state.storages.len()
+        )
+        .entered();
+        #[cfg(feature = "alloc-budget")]
+        let _scope = budget::Scope::enter();
+        #[cfg(feature = "shadow")]
+        let shadow_root = self.shadow.calculate_state_root(&state);
         let mut removed_accounts = Vec::new();
+        // the storage tries are borrowed from `self` while the resolver runs on them
+        let resolver = self.resolver.clone();
+        #[cfg(feature = "parallel")]
+        let storage_roots = self.join_storage_roots().map_err(execution_failed)?;
 
         for (hashed_address, account) in state.accounts {
             // nonexisting accounts must be removed from the state
@@ -158,33 +612,36 @@ impl StatelessTrie for SimpleSparseState {
                 removed_accounts.push(hashed_address);
                 continue;
             };
+            #[cfg(feature = "tracing")]
+            let _account_span =
+                tracing::trace_span!(target: "ref_mpt_state", "account_update", %hashed_address)
+                    .entered();
 
             // apply storage changes before computing the storage root
+            #[cfg(feature = "tracing")]
+            let storage_root_span =
+                tracing::trace_span!(target: "ref_mpt_state", "storage_root").entered();
             let storage_root = match state.storages.get(&hashed_address) {
-                None => self.storage_trie_mut(hashed_address).unwrap().hash(),
+                // the streamed storage changes were applied before the root of the finalized
+                // storage was computed, only the others are applied on top
+                #[cfg(feature = "parallel")]
+                Some(storage) if !storage.wiped && storage_roots.contains_key(&hashed_address) => self
+                    .merge_storage_changes(hashed_address, storage)
+                    .map_err(execution_failed)?,
+                None => shared_hash(self.storage_trie_mut(hashed_address)?),
                 Some(storage) => {
                     let storage_trie = if storage.wiped {
                         self.clear_storage(hashed_address)
                     } else {
-                        self.storage_trie_mut(hashed_address).unwrap()
+                        Arc::make_mut(self.storage_trie_mut(hashed_address)?)
                     };
-
-                    // apply all state modifications
-                    for (hashed_key, value) in &storage.storage {
-                        if !value.is_zero() {
-                            storage_trie.insert(*hashed_key, alloy_rlp::encode(value).into());
-                        }
-                    }
-                    // removals must happen last, otherwise unresolved orphans might still exist
-                    for (hashed_key, value) in &storage.storage {
-                        if value.is_zero() {
-                            storage_trie.remove(*hashed_key);
-                        }
-                    }
-
-                    storage_trie.hash()
+                    resolver
+                        .run(storage_trie, |trie| trie.try_apply_hashed_storage(storage))
+                        .map_err(execution_failed)?
                 }
             };
+            #[cfg(feature = "tracing")]
+            drop(storage_root_span);
 
             // update/insert the account after all changes have been processed
             let account = TrieAccount {
@@ -193,15 +650,29 @@ impl StatelessTrie for SimpleSparseState {
                 storage_root,
                 code_hash: account.bytecode_hash.unwrap_or(KECCAK256_EMPTY),
             };
-            self.state
-                .insert(hashed_address, alloy_rlp::encode(account).into());
+            resolver
+                .run(self.state.get_mut(), |state| {
+                    state.try_insert_encodable(hashed_address, &account)
+                })
+                .map_err(execution_failed)?;
+
+            // bail out before the heap is exhausted, reads during execution are checked here too
+            #[cfg(feature = "alloc-budget")]
+            budget::check()
+                .map_err(|err| StatelessValidationError::StatelessExecutionFailed(err.to_string()))?;
         }
 
-        removed_accounts
-            .iter()
-            .for_each(|hashed_address| self.remove_account(hashed_address));
+        for hashed_address in &removed_accounts {
+            self.remove_account(hashed_address)
+                .map_err(execution_failed)?;
+        }
 
-        Ok(self.state.hash())
+        #[cfg(feature = "tracing")]
+        let _hash_span = tracing::debug_span!(target: "ref_mpt_state", "state_root_hash").entered();
+        let root = self.state.get_mut().hash();
+        #[cfg(feature = "shadow")]
+        self.shadow.check_state_root(shadow_root, root);
+        Ok(root)
     }
 }
 
@@ -210,9 +681,451 @@ mod tests {
     use super::*;
     use alloy_consensus::Header;
     use alloy_primitives::hex;
+    use alloy_trie::{proof::ProofRetainer, HashBuilder, Nibbles};
     use reth_primitives_traits::account::Account;
+    use reth_trie_common::HashedStorage;
+    use std::collections::BTreeMap;
+    use std::format;
     use std::println;
+    use std::vec;
+
+    /// Accounts (by address) with their balance and non-zero storage slots.
+    pub(crate) type TestState = BTreeMap<Address, (U256, BTreeMap<U256, U256>)>;
+
+    // Builds a trie with a HashBuilder, appending all its nodes to `nodes`. Returns the root.
+    fn build_trie(leaves: BTreeMap<B256, Vec<u8>>, nodes: &mut Vec<Bytes>) -> B256 {
+        let targets = leaves.keys().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hash_builder.root();
+        nodes.extend(hash_builder.take_proof_nodes().values().cloned());
+        root
+    }
+
+    // Builds a witness revealing the whole given state. Returns the witness and the state root.
+    pub(crate) fn build_witness(state: &TestState) -> (ExecutionWitness, B256) {
+        let mut nodes = Vec::new();
+        let mut accounts = BTreeMap::new();
+        for (address, (balance, storage)) in state {
+            let slots = storage
+                .iter()
+                .map(|(slot, value)| (keccak256(B256::from(*slot)), alloy_rlp::encode(value)))
+                .collect();
+            let account = TrieAccount {
+                nonce: 0,
+                balance: *balance,
+                storage_root: build_trie(slots, &mut nodes),
+                code_hash: KECCAK256_EMPTY,
+            };
+            accounts.insert(keccak256(address), alloy_rlp::encode(account));
+        }
+        let root = build_trie(accounts, &mut nodes);
+
+        let witness = ExecutionWitness {
+            state: nodes,
+            ..Default::default()
+        };
+        (witness, root)
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn wipe_then_write_storage() {
+        let address = Address::repeat_byte(0x01);
+        let other = Address::repeat_byte(0x02);
+        let mut pre_state = TestState::new();
+        pre_state.insert(
+            address,
+            (
+                U256::from(1),
+                BTreeMap::from([(U256::from(1), U256::from(5)), (U256::from(2), U256::from(6))]),
+            ),
+        );
+        pre_state.insert(
+            other,
+            (U256::from(2), BTreeMap::from([(U256::from(1), U256::from(9))])),
+        );
+        let (witness, pre_state_root) = build_witness(&pre_state);
+
+        let (mut state, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+        assert!(state.account(address).unwrap().is_some());
+        assert_eq!(state.storage(address, U256::from(1)).unwrap(), U256::from(5));
+
+        // Wipe the storage of `address` and write a new slot in the same block.
+        let mut storage = HashedStorage::new(true);
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(3))), U256::from(7));
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(1))), U256::ZERO);
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 0,
+                balance: U256::from(1),
+                bytecode_hash: None,
+            }),
+        );
+        post_state.storages.insert(keccak256(address), storage);
+
+        let mut expected = pre_state.clone();
+        expected.get_mut(&address).unwrap().1 = BTreeMap::from([(U256::from(3), U256::from(7))]);
+        let (_, expected_root) = build_witness(&expected);
+
+        assert_eq!(state.calculate_state_root(post_state).unwrap(), expected_root);
+    }
+
+    // the zeth trie of the shadow backend panics on the missing nodes
+    #[cfg(not(any(feature = "poseidon2", feature = "shadow")))]
+    #[test]
+    fn incomplete_witness_fails_the_state_root() {
+        let pre_state: TestState = (1..=20_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        let (mut witness, root) = build_witness(&pre_state);
+        // only the root node is left
+        witness.state.retain(|rlp| keccak256(rlp) == root);
+        let hashed_address = keccak256(Address::repeat_byte(1));
+        let account = Account {
+            nonce: 0,
+            balance: U256::from(1),
+            bytecode_hash: None,
+        };
+        let mut updated = HashedPostState::default();
+        updated.accounts.insert(hashed_address, Some(account));
+        let mut removed = HashedPostState::default();
+        removed.accounts.insert(hashed_address, None);
+        let mut storage = updated.clone();
+        let mut slots = HashedStorage::new(false);
+        slots.storage.insert(B256::ZERO, U256::from(1));
+        storage.storages.insert(hashed_address, slots);
+
+        for post_state in [updated, removed, storage] {
+            let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+            assert!(matches!(
+                state.calculate_state_root(post_state),
+                Err(StatelessValidationError::StatelessExecutionFailed(_))
+            ));
+        }
+
+        // a junk node under the pre-state root fails to reveal
+        let junk = Bytes::from_static(&[0xc1]);
+        let root = keccak256(&junk);
+        let witness = ExecutionWitness {
+            state: vec![junk],
+            ..Default::default()
+        };
+        assert!(matches!(
+            SimpleSparseState::new(&witness, root),
+            Err(StatelessValidationError::WitnessRevealFailed { pre_state_root }) if pre_state_root == root
+        ));
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn iterate_revealed_accounts() {
+        let pre_state: TestState = (1..=20_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        let (mut witness, root) = build_witness(&pre_state);
+
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let accounts: Vec<(B256, TrieAccount)> = state.accounts_iter().collect();
+        let mut expected: Vec<(B256, U256)> = pre_state
+            .iter()
+            .map(|(address, (balance, _))| (keccak256(address), *balance))
+            .collect();
+        expected.sort();
+        let balances: Vec<(B256, U256)> = accounts
+            .iter()
+            .map(|(hashed_address, account)| (*hashed_address, account.balance))
+            .collect();
+        assert_eq!(balances, expected);
+        assert!(accounts
+            .iter()
+            .all(|(_, account)| account.storage_root == EMPTY_ROOT_HASH));
+
+        // only the accounts revealed by the witness are listed
+        let proof = Trie::reveal_from_rlp(root, &index_witness(&witness))
+            .proof(expected[0].0)
+            .unwrap();
+        witness.state = proof;
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let hashed_addresses: Vec<B256> = state.accounts_iter().map(|(key, _)| key).collect();
+        assert!(hashed_addresses.contains(&expected[0].0));
+        assert!(hashed_addresses.len() < expected.len());
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn read_many_slots() {
+        let address = Address::repeat_byte(0x01);
+        let storage = (1..=50_u64)
+            .map(|slot| (U256::from(slot), U256::from(slot * 3)))
+            .collect();
+        let pre_state = TestState::from([(address, (U256::from(1), storage))]);
+        let (witness, root) = build_witness(&pre_state);
+
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let slots: Vec<U256> = (0..60_u64).rev().map(U256::from).collect();
+        // the storage trie is only revealed once the account is read
+        assert_eq!(state.storage_many(address, &slots), vec![U256::ZERO; 60]);
+        state.account(address).unwrap();
+        let expected: Vec<U256> = slots
+            .iter()
+            .map(|slot| state.storage(address, *slot).unwrap())
+            .collect();
+        assert_eq!(expected[10], U256::from(147));
+        assert_eq!(state.storage_many(address, &slots), expected);
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn read_many_accounts() {
+        let pre_state: TestState = (1..=20_u8)
+            .map(|i| {
+                let storage = BTreeMap::from([(U256::from(1), U256::from(i))]);
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect();
+        let (witness, root) = build_witness(&pre_state);
+
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let addresses: Vec<Address> = (0..=25_u8).rev().map(Address::repeat_byte).collect();
+        let accounts = state.accounts_many(&addresses);
+        let balances: Vec<Option<U256>> = accounts
+            .iter()
+            .map(|account| account.map(|account| account.balance))
+            .collect();
+        let expected: Vec<Option<U256>> = addresses
+            .iter()
+            .map(|address| pre_state.get(address).map(|(balance, _)| *balance))
+            .collect();
+        assert_eq!(balances, expected);
+        // the storage tries are cached for the storage reads
+        assert_eq!(state.storages.borrow().len(), 20);
+        let slot = U256::from(1);
+        assert_eq!(
+            state.storage(Address::repeat_byte(7), slot).unwrap(),
+            U256::from(7)
+        );
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn display_summary() {
+        let address = Address::repeat_byte(0x01);
+        let storage = BTreeMap::from([(U256::from(1), U256::from(5))]);
+        let pre_state = TestState::from([
+            (address, (U256::from(1), storage)),
+            (Address::repeat_byte(0x02), (U256::from(2), BTreeMap::new())),
+        ]);
+        let (witness, root) = build_witness(&pre_state);
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        state.account(address).unwrap();
+        let nodes = witness.state.len();
+        assert_eq!(
+            format!("{state}"),
+            format!(
+                "SimpleSparseState {{ root: {root}, storage tries: 1, witness nodes: {nodes} }}"
+            )
+        );
+
+        // a changed trie is not hashed for the summary
+        state.state.get_mut().insert_encodable(
+            keccak256(Address::repeat_byte(0x03)),
+            &TrieAccount::default(),
+        );
+        assert_eq!(
+            format!("{state}"),
+            format!(
+                "SimpleSparseState {{ root: unhashed, storage tries: 1, witness nodes: {nodes} }}"
+            )
+        );
+    }
+
+    // Sets the balance of an account and a storage slot in a post-state.
+    fn update(address: Address, balance: u64, slot: u64, value: u64) -> HashedPostState {
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 0,
+                balance: U256::from(balance),
+                bytecode_hash: None,
+            }),
+        );
+        let mut storage = HashedStorage::new(false);
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(slot))), U256::from(value));
+        post_state.storages.insert(keccak256(address), storage);
+        post_state
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn advance_to_next_block() {
+        let address = Address::repeat_byte(0x01);
+        let mut pre_state: TestState = (1..=20_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        pre_state.get_mut(&address).unwrap().1 = (1..=10_u64)
+            .map(|slot| (U256::from(slot), U256::from(slot)))
+            .collect();
+        let (witness, pre_state_root) = build_witness(&pre_state);
+        let (mut state, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+        state.account(address).unwrap();
+        let root = state
+            .calculate_state_root(update(address, 5, 3, 30))
+            .unwrap();
+
+        let mut next_pre_state = pre_state.clone();
+        let account = next_pre_state.get_mut(&address).unwrap();
+        account.0 = U256::from(5);
+        account.1.insert(U256::from(3), U256::from(30));
+        let (next_witness, next_pre_state_root) = build_witness(&next_pre_state);
+        assert_eq!(root, next_pre_state_root);
+
+        let (mut advanced, _) =
+            SimpleSparseState::advance(state.clone(), &next_witness, root).unwrap();
+        let (mut fresh, _) = SimpleSparseState::new(&next_witness, root).unwrap();
+        let other = Address::repeat_byte(0x02);
+        for state in [&advanced, &fresh] {
+            assert_eq!(
+                state.account(other).unwrap().unwrap().balance,
+                U256::from(2)
+            );
+            assert!(state.account(address).unwrap().is_some());
+            assert_eq!(
+                state.storage(address, U256::from(3)).unwrap(),
+                U256::from(30)
+            );
+        }
+        let next_root = fresh.calculate_state_root(update(other, 7, 1, 1)).unwrap();
+        assert_eq!(
+            advanced
+                .calculate_state_root(update(other, 7, 1, 1))
+                .unwrap(),
+            next_root
+        );
+
+        // a state not matching the pre-state root is rebuilt from the witness
+        let (mut rebuilt, _) = SimpleSparseState::advance(state, &witness, pre_state_root).unwrap();
+        assert_eq!(rebuilt.state.get_mut().hash(), pre_state_root);
+        assert_eq!(rebuilt.storages.get_mut().len(), 0);
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn release_witness_after_eager_reveal() {
+        let address = Address::repeat_byte(0x01);
+        let mut pre_state: TestState = (1..=20_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        for i in 1..=3_u8 {
+            pre_state.get_mut(&Address::repeat_byte(i)).unwrap().1 = (1..=10_u64)
+                .map(|slot| (U256::from(slot), U256::from(slot)))
+                .collect();
+        }
+        let (witness, pre_state_root) = build_witness(&pre_state);
+        let (state, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+        let mut state = state.release_witness();
+        assert!(state.rlp_by_digest.is_empty());
+        assert_eq!(state.storages.borrow().len(), 3);
+
+        // the storage is read and updated without the witness
+        assert!(state.account(address).unwrap().is_some());
+        assert_eq!(
+            state.storage(address, U256::from(4)).unwrap(),
+            U256::from(4)
+        );
+        let root = state
+            .calculate_state_root(update(address, 5, 3, 30))
+            .unwrap();
+        let account = pre_state.get_mut(&address).unwrap();
+        account.0 = U256::from(5);
+        account.1.insert(U256::from(3), U256::from(30));
+        assert_eq!(root, build_witness(&pre_state).1);
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn clones_share_storage_tries() {
+        let mut pre_state: TestState = (1..=3_u8)
+            .map(|i| {
+                let storage = (1..=10_u64)
+                    .map(|slot| (U256::from(slot), U256::from(slot)))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect();
+        let (witness, pre_state_root) = build_witness(&pre_state);
+        let (state, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+        for i in 1..=2_u8 {
+            let address = Address::repeat_byte(i);
+            assert!(state.account(address).unwrap().is_some());
+            assert_eq!(
+                state.storage(address, U256::from(4)).unwrap(),
+                U256::from(4)
+            );
+        }
+
+        // only the storage trie of the updated account is copied
+        let mut updated = state.clone();
+        let address = Address::repeat_byte(1);
+        let root = updated
+            .calculate_state_root(update(address, 5, 3, 30))
+            .unwrap();
+        let storages = state.storages.borrow();
+        let updated_storages = updated.storages.borrow();
+        let [one, two] = [1_u8, 2].map(|i| keccak256(Address::repeat_byte(i)));
+        assert!(!Arc::ptr_eq(&storages[&one], &updated_storages[&one]));
+        assert!(Arc::ptr_eq(&storages[&two], &updated_storages[&two]));
+        assert_eq!(
+            storages[&one].get(keccak256(B256::from(U256::from(3)))),
+            Some(&alloy_rlp::encode(U256::from(3)).into())
+        );
+
+        let account = pre_state.get_mut(&address).unwrap();
+        account.0 = U256::from(5);
+        account.1.insert(U256::from(3), U256::from(30));
+        assert_eq!(root, build_witness(&pre_state).1);
+    }
+
+    #[test]
+    fn deduplicates_codes() {
+        let (mut witness, root) = build_witness(&TestState::new());
+        let implementation = Bytes::from(hex!("0x6080604052"));
+        witness.codes = vec![
+            implementation.clone(),
+            Bytes::from(hex!("0x00")),
+            Bytes::copy_from_slice(&implementation),
+        ];
+        let mut analyzed = Vec::new();
+        let bytecode = hash_codes(&witness, |code| {
+            analyzed.push(code.clone());
+            Bytecode::new_raw(code)
+        });
+        assert_eq!(
+            analyzed,
+            [implementation.clone(), Bytes::from(hex!("0x00"))]
+        );
+        assert_eq!(
+            bytecode[&keccak256(&implementation)].original_bytes(),
+            implementation
+        );
+
+        let (_, bytecode) = SimpleSparseState::new(&witness, root).unwrap();
+        assert_eq!(bytecode.len(), 2);
+    }
 
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
     fn test_sparse_state() {
         let state: Vec<Bytes> = {
@@ -318,7 +1231,7 @@ mod tests {
         assert!(trie.is_ok(), "Error creating trie");
         let mut trie = trie.unwrap();
         // Verify root hash
-        assert_eq!(trie.0.state.hash(), pre_state_root);
+        assert_eq!(trie.0.state.get_mut().hash(), pre_state_root);
         assert_eq!(
             trie.0
                 .calculate_state_root(HashedPostState::default())
@@ -0,0 +1,362 @@
+//! Host-side validation of independent blocks, and hashing of the storage tries of a block,
+//! across a thread pool.
+use crate::provider::Resolver;
+use crate::{shared_hash, SimpleSparseState};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloy_primitives::map::B256Set;
+use alloy_primitives::private::alloy_rlp::Decodable;
+use alloy_primitives::{Address, U256};
+use core::cell::RefCell;
+use rayon::prelude::*;
+use ref_mpt::{keccak256, B256Map, Trie, UnresolvedNode, B256};
+use reth_chainspec::ChainSpec;
+use reth_evm_ethereum::EthEvmConfig;
+use reth_trie_common::HashedStorage;
+use stateless::validation::StatelessValidationError;
+use stateless::{stateless_validation_with_trie, Genesis, StatelessInput, UncompressedPublicKey};
+
+/// Validates a batch of independent blocks on the global rayon thread pool.
+///
+/// Every block is paired with the public keys recovered from its transactions' signatures (in
+/// transaction order) and validated against its own [`SimpleSparseState`] and chain spec. Results
+/// are returned in the input order, so a failing block does not affect the others.
+pub fn validate_blocks(
+    blocks: Vec<(StatelessInput, Vec<UncompressedPublicKey>)>,
+) -> Vec<Result<B256, StatelessValidationError>> {
+    blocks
+        .into_par_iter()
+        .map(|(input, public_keys)| {
+            let genesis = Genesis {
+                config: input.chain_config,
+                ..Default::default()
+            };
+            let chain_spec: Arc<ChainSpec> = Arc::new(genesis.into());
+            let evm_config = EthEvmConfig::new(chain_spec.clone());
+
+            stateless_validation_with_trie::<SimpleSparseState, ChainSpec, EthEvmConfig>(
+                input.block,
+                public_keys,
+                input.witness,
+                chain_spec,
+                evm_config,
+            )
+            .map(|(block_hash, _)| block_hash)
+        })
+        .collect()
+}
+
+/// The storage trie of an account whose storage changes are final, hashed once joined.
+///
+/// The roots are computed with [`rayon`] parallel iterators rather than spawned tasks waited on,
+/// so a thread of the pool joining them, e.g. one validating a block in [`validate_blocks`],
+/// takes part in the work instead of blocking the pool.
+#[derive(Debug, Clone)]
+struct RootTask {
+    storage_trie: Arc<Trie>,
+    /// The slots cleared by the account, removed before hashing.
+    cleared_slots: B256Set,
+    resolver: Resolver,
+    /// The root once computed, or the node missing to remove one of the cleared slots.
+    root: Option<Result<B256, UnresolvedNode>>,
+}
+
+impl RootTask {
+    /// Removes the cleared slots and hashes the storage trie, unless done already.
+    fn compute(&mut self) -> Result<B256, UnresolvedNode> {
+        if self.root.is_none() {
+            self.root = Some(self.remove_cleared_slots().map(|()| {
+                shared_hash(&mut self.storage_trie)
+            }));
+        }
+        self.root.unwrap()
+    }
+
+    fn remove_cleared_slots(&mut self) -> Result<(), UnresolvedNode> {
+        for hashed_slot in core::mem::take(&mut self.cleared_slots) {
+            self.resolver
+                .run(Arc::make_mut(&mut self.storage_trie), |trie| {
+                    trie.try_remove(hashed_slot)
+                })?;
+        }
+        Ok(())
+    }
+}
+
+/// The storage tries of the accounts passed to [`SimpleSparseState::finalize_storage`], by hashed
+/// address, with their roots once computed.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RootTasks(RefCell<B256Map<RootTask>>);
+
+impl RootTasks {
+    /// Returns whether the storage trie of an account is held until its root is joined.
+    pub(crate) fn contains(&self, hashed_address: &B256) -> bool {
+        self.0.borrow().contains_key(hashed_address)
+    }
+
+    /// Runs `read` on the storage trie of a finalized account, once hashed on the current thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a slot cleared by the account lies below a node missing from the witness.
+    pub(crate) fn with_storage_trie<R>(
+        &self,
+        hashed_address: &B256,
+        read: impl FnOnce(&mut Arc<Trie>) -> R,
+    ) -> Option<R> {
+        let mut tasks = self.0.borrow_mut();
+        let task = tasks.get_mut(hashed_address)?;
+        task.compute().expect("MPT: Unresolved node access");
+        Some(read(&mut task.storage_trie))
+    }
+}
+
+impl SimpleSparseState {
+    /// Marks the storage of the account at `address` as final, once all its storage changes have
+    /// been applied with [`SimpleSparseState::apply_storage_change`], so that its storage trie is
+    /// hashed on the rayon thread pool together with those of the other finalized accounts.
+    ///
+    /// The slots cleared by the account are removed on the thread pool too. The storage roots are
+    /// computed in parallel when joined by [`SimpleSparseState::finalize_root`], or by
+    /// [`StatelessTrie::calculate_state_root`] which then only applies the storage changes of the
+    /// account in its `HashedPostState` that differ from the trie, so the changes streamed already
+    /// are not hashed again. Storage reads of the account compute its root first. The storage of
+    /// the account must not be streamed afterwards.
+    ///
+    /// [`StatelessTrie::calculate_state_root`]: stateless::StatelessTrie::calculate_state_root
+    pub fn finalize_storage(&mut self, address: Address) {
+        let hashed_address = keccak256(address);
+        let cleared_slots = self.pending.take_cleared_slots(hashed_address);
+        let Some(storage_trie) = self.storages.get_mut().remove(&hashed_address) else {
+            // the storage is not revealed, so it did not change
+            return;
+        };
+        let task = RootTask {
+            storage_trie,
+            cleared_slots,
+            resolver: self.resolver.clone(),
+            root: None,
+        };
+        self.pending
+            .storage_roots
+            .0
+            .get_mut()
+            .insert(hashed_address, task);
+    }
+
+    /// Applies the storage changes of a `HashedPostState` to the storage trie of a finalized
+    /// account, skipping the slots which already hold their new value, and returns the storage
+    /// root.
+    pub(crate) fn merge_storage_changes(
+        &mut self,
+        hashed_address: B256,
+        storage: &HashedStorage,
+    ) -> Result<B256, UnresolvedNode> {
+        let resolver = self.resolver.clone();
+        let storage_trie = self.storages.get_mut().get_mut(&hashed_address).unwrap();
+        let mut changes = HashedStorage::new(false);
+        for (hashed_slot, value) in &storage.storage {
+            let current = resolver.run_shared(storage_trie, |trie| {
+                trie.try_get(*hashed_slot).map(|value| value.cloned())
+            })?;
+            let current = current.map_or(U256::ZERO, |current| {
+                U256::decode(&mut &current[..]).unwrap()
+            });
+            if current != *value {
+                changes.storage.insert(*hashed_slot, *value);
+            }
+        }
+        if changes.storage.is_empty() {
+            return Ok(shared_hash(storage_trie));
+        }
+        resolver.run(Arc::make_mut(storage_trie), |trie| {
+            trie.try_apply_hashed_storage(&changes)
+        })
+    }
+
+    /// Computes the storage roots of the finalized accounts in parallel and takes back their
+    /// storage tries. Returns the roots by hashed address.
+    pub(crate) fn join_storage_roots(&mut self) -> Result<B256Map<B256>, UnresolvedNode> {
+        let tasks: Vec<(B256, RootTask)> = core::mem::take(self.pending.storage_roots.0.get_mut())
+            .into_iter()
+            .collect();
+        let tasks: Vec<_> = tasks
+            .into_par_iter()
+            .map(|(hashed_address, mut task)| (hashed_address, task.compute(), task.storage_trie))
+            .collect();
+        let mut storage_roots = B256Map::default();
+        for (hashed_address, storage_root, storage_trie) in tasks {
+            self.storages.get_mut().insert(hashed_address, storage_trie);
+            storage_roots.insert(hashed_address, storage_root?);
+        }
+        Ok(storage_roots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use alloy_primitives::U256;
+    use reth_primitives_traits::Account;
+    use reth_trie_common::{HashedPostState, HashedStorage};
+    use stateless::StatelessTrie;
+
+    fn pre_state() -> TestState {
+        (1..=10_u8)
+            .map(|i| {
+                let storage = (1..=8_u64)
+                    .map(|slot| (U256::from(slot), U256::from(slot + u64::from(i))))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect()
+    }
+
+    fn slot_change(slot: u64, value: u64) -> (B256, U256) {
+        (keccak256(B256::from(U256::from(slot))), U256::from(value))
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn storage_roots_of_finalized_accounts() {
+        let (witness, root) = build_witness(&pre_state());
+        let account = Account {
+            nonce: 1,
+            balance: U256::from(50),
+            bytecode_hash: None,
+        };
+        let changes = [(1, 1, 0), (1, 2, 9), (1, 20, 7), (3, 3, 0), (3, 4, 0)];
+        let stream = |state: &mut SimpleSparseState| {
+            state
+                .apply_account_change(Address::repeat_byte(1), Some(account))
+                .unwrap();
+            for (byte, slot, value) in changes {
+                let address = Address::repeat_byte(byte);
+                state
+                    .apply_storage_change(address, U256::from(slot), U256::from(value))
+                    .unwrap();
+            }
+        };
+
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        stream(&mut state);
+        let expected = state.clone().finalize_root().unwrap();
+        state.finalize_storage(Address::repeat_byte(1));
+        state.finalize_storage(Address::repeat_byte(3));
+        // an account without storage changes is not hashed
+        state.finalize_storage(Address::repeat_byte(5));
+        let mut cloned = state.clone();
+        assert_eq!(state.finalize_root().unwrap(), expected);
+        assert_eq!(cloned.finalize_root().unwrap(), expected);
+
+        // the same changes as a post state, whose storage changes of the first account are
+        // ignored as its root is computed from the finalized storage
+        let mut post_state = HashedPostState::default();
+        post_state
+            .accounts
+            .insert(keccak256(Address::repeat_byte(1)), Some(account));
+        let mut storage = HashedStorage::new(false);
+        for (_, slot, value) in changes.into_iter().filter(|(byte, ..)| *byte == 1) {
+            let hashed_slot = keccak256(B256::from(U256::from(slot)));
+            storage.storage.insert(hashed_slot, U256::from(value));
+        }
+        post_state
+            .storages
+            .insert(keccak256(Address::repeat_byte(1)), storage);
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let expected = state.clone().calculate_state_root(post_state.clone());
+        for (slot, value) in [(1, 0), (2, 9), (20, 7)] {
+            let address = Address::repeat_byte(1);
+            state
+                .apply_storage_change(address, U256::from(slot), U256::from(value))
+                .unwrap();
+        }
+        state.finalize_storage(Address::repeat_byte(1));
+        assert_eq!(state.calculate_state_root(post_state), expected);
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn joins_on_a_pool_thread() {
+        let (witness, root) = build_witness(&pre_state());
+        let stream = |state: &mut SimpleSparseState| {
+            for byte in 1..=4 {
+                let address = Address::repeat_byte(byte);
+                state
+                    .apply_storage_change(address, U256::from(1), U256::from(100))
+                    .unwrap();
+                state.finalize_storage(address);
+            }
+            state.finalize_root().unwrap()
+        };
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let expected = stream(&mut state.clone());
+
+        // streaming on the only thread of a pool, as when validating blocks, does not wait for
+        // work queued behind it
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        assert_eq!(pool.install(move || stream(&mut state)), expected);
+    }
+
+    #[test]
+    // the second backend does not see the streamed changes
+    #[cfg(not(any(feature = "poseidon2", feature = "shadow")))]
+    fn reads_finalized_storage() {
+        let (witness, root) = build_witness(&pre_state());
+        let address = Address::repeat_byte(1);
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        for (slot, value) in [(1, 0), (2, 9), (20, 7)] {
+            state
+                .apply_storage_change(address, U256::from(slot), U256::from(value))
+                .unwrap();
+        }
+        state.finalize_storage(address);
+
+        // reads hash the finalized storage trie instead of missing it, and reading
+        // the account does not reveal the pre-state storage trie again
+        assert_eq!(state.storage(address, U256::from(3)).unwrap(), U256::from(4));
+        assert!(state.account(address).unwrap().is_some());
+        assert_eq!(state.storage(address, U256::from(2)).unwrap(), U256::from(9));
+        let slots = [1, 2, 20].map(U256::from);
+        assert_eq!(
+            state.storage_many(address, &slots),
+            [0, 9, 7].map(U256::from)
+        );
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn merges_post_state_storage() {
+        let (witness, root) = build_witness(&pre_state());
+        let address = Address::repeat_byte(1);
+        let mut storage = HashedStorage::new(false);
+        // the streamed changes, and one the post state adds
+        storage
+            .storage
+            .extend([slot_change(1, 0), slot_change(2, 9), slot_change(5, 55)]);
+        let mut post_state = HashedPostState::default();
+        post_state.storages.insert(keccak256(address), storage);
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 1,
+                balance: U256::from(50),
+                bytecode_hash: None,
+            }),
+        );
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let expected = state.clone().calculate_state_root(post_state.clone());
+
+        for (slot, value) in [(1, 0), (2, 9)] {
+            state
+                .apply_storage_change(address, U256::from(slot), U256::from(value))
+                .unwrap();
+        }
+        state.finalize_storage(address);
+        assert_eq!(state.calculate_state_root(post_state), expected);
+    }
+}
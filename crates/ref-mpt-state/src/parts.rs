@@ -0,0 +1,146 @@
+//! Taking a [`SimpleSparseState`] apart and putting it back together.
+//!
+//! Integrators pre-building the tries of a state, e.g. from a cache of their own, or
+//! post-processing them after a block, work on the [`StateParts`] outside of the
+//! [`StatelessTrie`](stateless::StatelessTrie) lifecycle.
+use crate::incremental::PendingChanges;
+use crate::provider::Resolver;
+use crate::storage_cache::StorageCache;
+use crate::{shared_hash, RlpByDigest, SimpleSparseState};
+use alloc::sync::Arc;
+use alloy_primitives::private::alloy_rlp;
+use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
+use core::cell::RefCell;
+use ref_mpt::{B256Map, Trie, B256};
+use stateless::validation::StatelessValidationError;
+
+/// The tries and the witness nodes of a [`SimpleSparseState`].
+#[derive(Debug, Clone)]
+pub struct StateParts {
+    /// The state trie.
+    pub state: Trie,
+    /// The storage tries revealed so far, by hashed address.
+    pub storages: B256Map<Arc<Trie>>,
+    /// The witness nodes by their hash, from which the unresolved nodes of the tries are revealed.
+    pub rlp_by_digest: RlpByDigest,
+}
+
+impl SimpleSparseState {
+    /// Returns the tries and the witness nodes of the state.
+    ///
+    /// The storage cache and the node provider are dropped, as are the changes streamed with
+    /// [`SimpleSparseState::apply_account_change`] and
+    /// [`SimpleSparseState::apply_storage_change`] and not finalized yet.
+    pub fn into_parts(self) -> StateParts {
+        StateParts {
+            state: self.state.into_inner(),
+            storages: self.storages.into_inner(),
+            rlp_by_digest: self.rlp_by_digest,
+        }
+    }
+
+    /// Builds a state from its parts, checking that the state trie has the root `pre_state_root`
+    /// and that every storage trie has the storage root of its account.
+    ///
+    /// A storage trie whose account is missing, or lies below an unresolved node, must be empty.
+    /// Fails with [`StatelessValidationError::WitnessRevealFailed`] otherwise.
+    pub fn from_parts(
+        parts: StateParts,
+        pre_state_root: B256,
+    ) -> Result<Self, StatelessValidationError> {
+        let StateParts {
+            mut state,
+            mut storages,
+            rlp_by_digest,
+        } = parts;
+        let invalid = || StatelessValidationError::WitnessRevealFailed { pre_state_root };
+        if state.hash() != pre_state_root {
+            return Err(invalid());
+        }
+        for (hashed_address, storage_trie) in &mut storages {
+            let storage_root = match state.try_get(*hashed_address) {
+                Ok(Some(value)) => {
+                    alloy_rlp::decode_exact::<TrieAccount>(value)
+                        .map_err(|_| invalid())?
+                        .storage_root
+                }
+                Ok(None) | Err(_) => EMPTY_ROOT_HASH,
+            };
+            if shared_hash(storage_trie) != storage_root {
+                return Err(invalid());
+            }
+        }
+
+        Ok(Self {
+            state: RefCell::new(state),
+            storages: RefCell::new(storages),
+            rlp_by_digest,
+            resolver: Resolver::default(),
+            storage_cache: RefCell::new(StorageCache::new(0)),
+            pending: PendingChanges::default(),
+            #[cfg(feature = "shadow")]
+            shadow: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use alloy_primitives::{Address, U256};
+    use ref_mpt::keccak256;
+    use reth_primitives_traits::Account;
+    use reth_trie_common::{HashedPostState, HashedStorage};
+    use stateless::StatelessTrie;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn parts_round_trip() {
+        let pre_state: TestState = (1..=4_u8)
+            .map(|i| {
+                let storage = (1..=4)
+                    .map(|slot| (U256::from(slot), U256::from(i)))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect();
+        let (witness, root) = build_witness(&pre_state);
+        let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let address = Address::repeat_byte(1);
+        assert!(state.account(address).unwrap().is_some());
+        assert_eq!(
+            state.storage(address, U256::from(1)).unwrap(),
+            U256::from(1)
+        );
+
+        let mut parts = state.into_parts();
+        assert_eq!(parts.storages.len(), 1);
+        let mut state = SimpleSparseState::from_parts(parts.clone(), root).unwrap();
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 0,
+                balance: U256::from(1),
+                bytecode_hash: None,
+            }),
+        );
+        let mut storage = HashedStorage::new(false);
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(2))), U256::from(9));
+        post_state.storages.insert(keccak256(address), storage);
+        let (mut expected, _) = SimpleSparseState::new(&witness, root).unwrap();
+        assert_eq!(
+            state.calculate_state_root(post_state.clone()).unwrap(),
+            expected.calculate_state_root(post_state).unwrap()
+        );
+
+        // a wrong root, and a storage trie not matching its account
+        assert!(SimpleSparseState::from_parts(parts.clone(), B256::ZERO).is_err());
+        let storage_trie = parts.storages.values_mut().next().unwrap();
+        Arc::make_mut(storage_trie).insert(B256::ZERO, alloy_rlp::encode(U256::from(1)).into());
+        assert!(SimpleSparseState::from_parts(parts, root).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Account and storage proofs of the revealed state.
+use crate::SimpleSparseState;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloy_primitives::private::alloy_rlp::Decodable;
+use alloy_primitives::{Address, Bytes, U256};
+use ref_mpt::{keccak256, UnresolvedNode, B256};
+
+/// Proof of an account and some of its storage slots, in the format of `eth_getProof`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountProof {
+    /// The address of the account.
+    pub address: Address,
+    /// RLP encoding of the account, `None` if the account does not exist.
+    pub account_rlp: Option<Bytes>,
+    /// Nodes of the state trie on the path of the account, starting with the root.
+    pub account_nodes: Vec<Bytes>,
+    /// Proofs of the requested storage slots, in the requested order.
+    pub storage_proofs: Vec<StorageProof>,
+}
+
+/// Proof of a storage slot of an account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageProof {
+    /// The slot.
+    pub slot: B256,
+    /// The value of the slot, zero if it is not set.
+    pub value: U256,
+    /// Nodes of the storage trie on the path of the slot, starting with the root.
+    pub nodes: Vec<Bytes>,
+}
+
+impl SimpleSparseState {
+    /// Proves an account and the given storage slots against the current state root, i.e. the
+    /// pre-state root, or the post-state root once the block has been applied with
+    /// `calculate_state_root`.
+    ///
+    /// Fails if the account or a slot lies below a subtree the witness did not reveal.
+    pub fn prove(
+        &mut self,
+        address: Address,
+        slots: &[B256],
+    ) -> Result<AccountProof, UnresolvedNode> {
+        let hashed_address = keccak256(address);
+        let state = self.state.get_mut();
+        let account_nodes = self
+            .resolver
+            .run(state, |state| state.proof(hashed_address))?;
+        let account_rlp = state.get(hashed_address).cloned();
+
+        let mut storage_proofs = Vec::with_capacity(slots.len());
+        if account_rlp.is_some() {
+            let resolver = self.resolver.clone();
+            let storage_trie = Arc::make_mut(
+                self.storage_trie_mut(hashed_address)
+                    .expect("MPT: Invalid account in the state trie"),
+            );
+            for slot in slots {
+                let hashed_slot = keccak256(slot);
+                let nodes = resolver.run(storage_trie, |trie| trie.proof(hashed_slot))?;
+                let value = storage_trie.get(hashed_slot).map_or(U256::ZERO, |value| {
+                    U256::decode(&mut &value[..]).expect("MPT: Invalid storage value")
+                });
+                storage_proofs.push(StorageProof {
+                    slot: *slot,
+                    value,
+                    nodes,
+                });
+            }
+        } else {
+            // an absent account has an empty storage
+            storage_proofs.extend(slots.iter().map(|slot| StorageProof {
+                slot: *slot,
+                value: U256::ZERO,
+                nodes: Vec::new(),
+            }));
+        }
+
+        Ok(AccountProof {
+            address,
+            account_rlp,
+            account_nodes,
+            storage_proofs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
+    use reth_primitives_traits::account::Account;
+    use reth_trie_common::{HashedPostState, HashedStorage};
+    use stateless::StatelessTrie;
+    use std::collections::BTreeMap;
+
+    // Checks the proofs against the given state root with the standalone verification functions.
+    fn verify(proof: &AccountProof, state_root: B256) -> Option<TrieAccount> {
+        ref_mpt::verify_proof(
+            state_root,
+            keccak256(proof.address),
+            proof.account_rlp.as_ref().map(|rlp| &rlp[..]),
+            &proof.account_nodes,
+        )
+        .unwrap();
+        let account = proof
+            .account_rlp
+            .as_ref()
+            .map(|rlp| TrieAccount::decode(&mut &rlp[..]).unwrap());
+        let storage_root = account.map_or(EMPTY_ROOT_HASH, |account| account.storage_root);
+        for storage_proof in &proof.storage_proofs {
+            let value = (!storage_proof.value.is_zero())
+                .then(|| alloy_primitives::private::alloy_rlp::encode(storage_proof.value));
+            ref_mpt::verify_proof(
+                storage_root,
+                keccak256(storage_proof.slot),
+                value.as_deref(),
+                &storage_proof.nodes,
+            )
+            .unwrap();
+        }
+        account
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn proves_pre_and_post_state() {
+        let address = Address::repeat_byte(0x01);
+        let mut pre_state: TestState = (1..=20_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        pre_state.get_mut(&address).unwrap().1 = (1..=30_u64)
+            .map(|slot| (U256::from(slot), U256::from(slot + 100)))
+            .collect();
+        let (witness, pre_state_root) = build_witness(&pre_state);
+        let (mut state, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+
+        let slots = [B256::with_last_byte(5), B256::with_last_byte(99)];
+        let proof = state.prove(address, &slots).unwrap();
+        assert_eq!(
+            verify(&proof, pre_state_root).unwrap().balance,
+            U256::from(1)
+        );
+        assert_eq!(proof.storage_proofs[0].value, U256::from(105));
+        assert_eq!(proof.storage_proofs[1].value, U256::ZERO);
+
+        let absent = state.prove(Address::repeat_byte(0xee), &slots).unwrap();
+        assert_eq!(verify(&absent, pre_state_root), None);
+
+        // update a slot and prove against the post-state root
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 1,
+                balance: U256::from(1),
+                bytecode_hash: None,
+            }),
+        );
+        let mut storage = HashedStorage::new(false);
+        storage.storage.insert(keccak256(slots[1]), U256::from(7));
+        post_state.storages.insert(keccak256(address), storage);
+        let post_state_root = state.calculate_state_root(post_state).unwrap();
+        let proof = state.prove(address, &slots).unwrap();
+        assert_eq!(verify(&proof, post_state_root).unwrap().nonce, 1);
+        assert_eq!(proof.storage_proofs[1].value, U256::from(7));
+    }
+}
@@ -0,0 +1,149 @@
+//! Resolving trie nodes missing from the witness.
+#[cfg(feature = "node-provider")]
+use crate::SimpleSparseState;
+use alloc::sync::Arc;
+use core::fmt;
+#[cfg(feature = "node-provider")]
+use ref_mpt::NodeProvider;
+use ref_mpt::{Trie, UnresolvedNode};
+
+/// Runs trie operations, fetching the nodes they miss from the node provider, if any.
+#[derive(Clone, Default)]
+pub(crate) struct Resolver {
+    #[cfg(feature = "node-provider")]
+    provider: Option<Arc<dyn NodeProvider + Send + Sync>>,
+}
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Resolver");
+        #[cfg(feature = "node-provider")]
+        debug.field("provider", &self.provider.is_some());
+        debug.finish()
+    }
+}
+
+impl Resolver {
+    /// Runs `op` on the trie, see [`Trie::resolving`]. Without a provider, `op` runs once.
+    pub(crate) fn run<T>(
+        &self,
+        trie: &mut Trie,
+        mut op: impl FnMut(&mut Trie) -> Result<T, UnresolvedNode>,
+    ) -> Result<T, UnresolvedNode> {
+        #[cfg(feature = "node-provider")]
+        if let Some(provider) = &self.provider {
+            return trie.resolving(&**provider, op);
+        }
+        op(trie)
+    }
+
+    /// Runs the read `op` on a shared trie, which is only copied if nodes must be fetched from the
+    /// node provider.
+    // without a provider, the trie is never copied
+    #[cfg_attr(
+        not(feature = "node-provider"),
+        allow(clippy::needless_pass_by_ref_mut)
+    )]
+    pub(crate) fn run_shared<T>(
+        &self,
+        trie: &mut Arc<Trie>,
+        mut op: impl FnMut(&Trie) -> Result<T, UnresolvedNode>,
+    ) -> Result<T, UnresolvedNode> {
+        match op(trie) {
+            #[cfg(feature = "node-provider")]
+            Err(_) if self.provider.is_some() => self.run(Arc::make_mut(trie), |trie| op(trie)),
+            result => result,
+        }
+    }
+}
+
+#[cfg(feature = "node-provider")]
+impl SimpleSparseState {
+    /// Fetches the trie nodes missing from the witness from `provider`, such as a database or a
+    /// remote node, instead of panicking when execution reaches them.
+    ///
+    /// The fetched nodes are checked against their hash, so the state roots are as trustworthy as
+    /// with a complete witness.
+    pub fn with_node_provider(
+        mut self,
+        provider: impl NodeProvider + Send + Sync + 'static,
+    ) -> Self {
+        self.resolver.provider = Some(Arc::new(provider));
+        #[cfg(feature = "shadow")]
+        self.shadow
+            .disable("the second backend cannot fetch the nodes missing from the witness");
+        self
+    }
+}
+
+#[cfg(all(test, feature = "node-provider"))]
+mod tests {
+    use crate::tests::{build_witness, TestState};
+    use crate::SimpleSparseState;
+    use alloy_primitives::{Address, U256};
+    use ref_mpt::{keccak256, B256Map, B256};
+    use reth_primitives_traits::account::Account;
+    use reth_trie_common::{HashedPostState, HashedStorage};
+    use stateless::{ExecutionWitness, StatelessTrie};
+    use std::collections::BTreeMap;
+    use std::vec;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn fetches_nodes_missing_from_the_witness() {
+        let address = Address::repeat_byte(0x01);
+        let mut pre_state: TestState = (1..=30_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        pre_state.get_mut(&address).unwrap().1 = (1..=30_u64)
+            .map(|slot| (U256::from(slot), U256::from(slot + 100)))
+            .collect();
+        let (witness, pre_state_root) = build_witness(&pre_state);
+
+        // the witness only holds the state root node, everything else is in the database
+        let database: B256Map<_> = witness
+            .state
+            .iter()
+            .map(|rlp| (keccak256(rlp), rlp.clone()))
+            .collect();
+        let partial = ExecutionWitness {
+            state: vec![database[&pre_state_root].clone()],
+            ..Default::default()
+        };
+        let (state, _) = SimpleSparseState::new(&partial, pre_state_root).unwrap();
+        let mut state = state.with_node_provider(database);
+
+        let account = state.account(address).unwrap().unwrap();
+        assert_eq!(account.balance, U256::from(1));
+        assert_eq!(
+            state.storage(address, U256::from(5)).unwrap(),
+            U256::from(105)
+        );
+        assert_eq!(state.account(Address::repeat_byte(0xee)).unwrap(), None);
+
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 1,
+                balance: U256::from(1),
+                bytecode_hash: None,
+            }),
+        );
+        post_state
+            .accounts
+            .insert(keccak256(Address::repeat_byte(0x02)), None);
+        let mut storage = HashedStorage::new(false);
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(7))), U256::ZERO);
+        post_state.storages.insert(keccak256(address), storage);
+
+        // the same update on the complete witness
+        let (mut complete, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+        assert_eq!(
+            state.calculate_state_root(post_state.clone()).unwrap(),
+            complete.calculate_state_root(post_state).unwrap()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Analysis of the trie nodes of execution witnesses.
+//!
+//! Witness producers shrink their proofs by dropping repeated nodes and nodes the block never
+//! reaches, and the depths of the reachable nodes show which levels of the tries dominate the
+//! size. [`analyze_witness`] measures them for a pre-state root.
+use crate::witness::Walker;
+use alloc::vec::Vec;
+use alloy_primitives::Bytes;
+use core::fmt;
+use ref_mpt::{keccak256, B256Map, B256};
+use stateless::ExecutionWitness;
+
+/// The report of [`analyze_witness`] on the trie nodes of a witness.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WitnessReport {
+    /// Trie nodes, counting every repetition.
+    pub nodes: usize,
+    /// Total size of the trie nodes, counting every repetition.
+    pub bytes: usize,
+    /// Trie nodes present more than once, counting every repetition.
+    pub duplicate_nodes: usize,
+    /// Total size of the repetitions of the trie nodes present more than once.
+    pub duplicate_bytes: usize,
+    /// Distinct trie nodes reachable from the state root or from the storage root of a reachable
+    /// account.
+    pub reachable_nodes: usize,
+    /// Total size of the reachable trie nodes.
+    pub reachable_bytes: usize,
+    /// Reachable nodes of the state trie by depth, the root being at depth 0. Nodes embedded in
+    /// their parent are not counted.
+    pub state_depths: Vec<usize>,
+    /// Reachable nodes of the storage tries by depth from their root. The nodes of storage tries
+    /// shared by several accounts are counted once.
+    pub storage_depths: Vec<usize>,
+}
+
+impl WitnessReport {
+    /// Returns the number of distinct trie nodes.
+    pub const fn distinct_nodes(&self) -> usize {
+        self.nodes - self.duplicate_nodes
+    }
+
+    /// Returns the fraction of the distinct trie nodes which are reachable, 1 for a witness
+    /// without nodes.
+    pub fn reachable_fraction(&self) -> f64 {
+        match self.distinct_nodes() {
+            0 => 1.0,
+            distinct => self.reachable_nodes as f64 / distinct as f64,
+        }
+    }
+}
+
+impl fmt::Display for WitnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "nodes           {} ({} bytes)", self.nodes, self.bytes)?;
+        writeln!(
+            f,
+            "duplicates      {} ({} bytes)",
+            self.duplicate_nodes, self.duplicate_bytes
+        )?;
+        writeln!(
+            f,
+            "reachable       {} of {} distinct ({:.1}%, {} bytes)",
+            self.reachable_nodes,
+            self.distinct_nodes(),
+            self.reachable_fraction() * 100.0,
+            self.reachable_bytes
+        )?;
+        for (name, depths) in [
+            ("state", &self.state_depths),
+            ("storage", &self.storage_depths),
+        ] {
+            write!(f, "{name:<8}depths")?;
+            for (depth, nodes) in depths.iter().enumerate() {
+                write!(f, " {depth}:{nodes}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Analyzes the trie nodes of a witness for the given pre-state root: their number and size, the
+/// repeated nodes, the nodes reachable from the state root or from the storage root of a reachable
+/// account, and the depths of the reachable nodes in their trie.
+pub fn analyze_witness(witness: &ExecutionWitness, pre_state_root: B256) -> WitnessReport {
+    let rlp_by_digest: B256Map<&Bytes> = witness
+        .state
+        .iter()
+        .map(|rlp| (keccak256(rlp), rlp))
+        .collect();
+    let Walker {
+        reachable, depths, ..
+    } = Walker::walk(&rlp_by_digest, pre_state_root);
+    let [state_depths, storage_depths] = depths;
+
+    let bytes = size(&witness.state);
+    let distinct_bytes = size(rlp_by_digest.values().copied());
+    let reachable_bytes = size(
+        rlp_by_digest
+            .iter()
+            .filter(|(digest, _)| reachable.contains(*digest))
+            .map(|(_, rlp)| *rlp),
+    );
+    WitnessReport {
+        nodes: witness.state.len(),
+        bytes,
+        duplicate_nodes: witness.state.len() - rlp_by_digest.len(),
+        duplicate_bytes: bytes - distinct_bytes,
+        reachable_nodes: state_depths.iter().chain(&storage_depths).sum(),
+        reachable_bytes,
+        state_depths,
+        storage_depths,
+    }
+}
+
+/// Returns the total size of `rlps`.
+fn size<'a>(rlps: impl IntoIterator<Item = &'a Bytes>) -> usize {
+    rlps.into_iter().map(|rlp| rlp.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use alloy_primitives::{Address, U256};
+    use std::collections::BTreeMap;
+    use std::string::ToString;
+    use std::vec;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn reports_duplicate_and_unreachable_nodes() {
+        let state: TestState = (1..=32u8)
+            .map(|i| {
+                let storage = BTreeMap::from([(U256::from(1), U256::from(i))]);
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect();
+        let (witness, pre_state_root) = build_witness(&state);
+        let clean = analyze_witness(&witness, pre_state_root);
+        assert_eq!(clean.duplicate_nodes, 0);
+        assert_eq!(clean.reachable_nodes, witness.state.len());
+        assert_eq!(clean.reachable_fraction(), 1.0);
+        assert_eq!(clean.state_depths[0], 1);
+        assert!(clean.state_depths.len() > 1);
+
+        // a node reached by no root
+        let mut state = witness.state.clone();
+        state.push(state[0].clone());
+        state.push(Bytes::from(vec![0x80; 40]));
+        let noisy = ExecutionWitness {
+            state,
+            ..Default::default()
+        };
+        let report = analyze_witness(&noisy, pre_state_root);
+        assert_eq!(report.nodes, witness.state.len() + 2);
+        assert_eq!(report.bytes, clean.bytes + witness.state[0].len() + 40);
+        assert_eq!(report.duplicate_nodes, 1);
+        assert_eq!(report.duplicate_bytes, witness.state[0].len());
+        assert_eq!(report.reachable_nodes, clean.reachable_nodes);
+        assert_eq!(report.reachable_bytes, clean.bytes);
+        assert_eq!(report.state_depths, clean.state_depths);
+        assert_eq!(report.storage_depths, clean.storage_depths);
+        let distinct = witness.state.len() + 1;
+        assert_eq!(
+            report.reachable_fraction(),
+            witness.state.len() as f64 / distinct as f64
+        );
+        assert!(report.to_string().contains("duplicates      1"));
+    }
+
+    #[test]
+    fn empty_witness() {
+        let report = analyze_witness(&ExecutionWitness::default(), B256::ZERO);
+        assert_eq!(report, WitnessReport::default());
+        assert_eq!(report.reachable_fraction(), 1.0);
+    }
+}
@@ -0,0 +1,220 @@
+//! Comparing the state against a second backend as a safety net.
+//!
+//! With the `shadow` feature, a [`SimpleSparseState`](crate::SimpleSparseState) built from a
+//! witness also builds zeth's [`SparseState`] from it, and drives it with every `account`,
+//! `storage` and `calculate_state_root` call of [`StatelessTrie`]. The first call on which the two
+//! disagree panics with the call, its arguments and both answers, along with the pre-state root of
+//! the block. The state never returns an error from these calls, so an error of the second
+//! backend is a divergence too.
+//!
+//! `accounts_many` and `storage_many` are checked one read at a time. Storage reads are checked
+//! once the storage trie of the account is revealed: before that, the state answers zero.
+//!
+//! States built from a genesis allocation or from their parts are not shadowed, and neither are
+//! the changes streamed with
+//! [`SimpleSparseState::apply_account_change`](crate::SimpleSparseState::apply_account_change)
+//! and its siblings. States backed by a storage cache or a node provider stop being shadowed, as
+//! their witness may lack nodes the second backend needs, with a `tracing` warning: the feature
+//! enables the `tracing` feature, so that the warning is never lost.
+use alloy_primitives::{Address, U256};
+use alloy_trie::TrieAccount;
+use core::fmt;
+use ref_mpt::B256;
+use reth_trie_common::HashedPostState;
+use stateless::validation::StatelessValidationError;
+use stateless::{ExecutionWitness, StatelessTrie};
+use zeth_mpt_state::SparseState;
+
+/// The second backend of a state, with the pre-state root of the block, if any.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Shadow {
+    backend: Option<(SparseState, B256)>,
+}
+
+impl Shadow {
+    /// Builds the second backend from the witness of a state.
+    pub(crate) fn new(witness: &ExecutionWitness, pre_state_root: B256) -> Self {
+        let backend = match SparseState::new(witness, pre_state_root) {
+            Ok((backend, _)) => backend,
+            Err(err) => divergence(pre_state_root, format_args!("new"), &"Ok(_)", &err),
+        };
+        Self {
+            backend: Some((backend, pre_state_root)),
+        }
+    }
+
+    /// Stops checking the state, warning with the `reason`.
+    pub(crate) fn disable(&mut self, reason: &str) {
+        if let Some((_, pre_state_root)) = self.backend.take() {
+            tracing::warn!(
+                target: "ref_mpt_state",
+                %pre_state_root,
+                "shadow backend disabled: {reason}"
+            );
+        }
+    }
+
+    /// Builds the second backend for the next block of an advanced state, unless this state is
+    /// not shadowed.
+    pub(crate) fn next(&self, witness: &ExecutionWitness, pre_state_root: B256) -> Self {
+        match self.backend {
+            Some(_) => Self::new(witness, pre_state_root),
+            None => Self::default(),
+        }
+    }
+
+    /// Checks the account returned by `account(address)`.
+    pub(crate) fn account(&self, address: Address, account: Option<TrieAccount>) {
+        if let Some((backend, pre_state_root)) = &self.backend {
+            let expected = backend.account(address);
+            check(
+                *pre_state_root,
+                format_args!("account({address})"),
+                account,
+                expected,
+            );
+        }
+    }
+
+    /// Checks the value returned by `storage(address, slot)`.
+    pub(crate) fn storage(&self, address: Address, slot: U256, value: U256) {
+        if let Some((backend, pre_state_root)) = &self.backend {
+            // the state also reveals storage tries without an `account` call, e.g. in
+            // `release_witness`, while the backend only builds them on reading the account
+            let expected = match backend.account(address) {
+                Ok(Some(_)) => backend.storage(address, slot),
+                Ok(None) => Ok(U256::ZERO),
+                Err(err) => Err(err),
+            };
+            check(
+                *pre_state_root,
+                format_args!("storage({address}, {slot})"),
+                value,
+                expected,
+            );
+        }
+    }
+
+    /// Computes the state root of the second backend, to be checked against the state's with
+    /// [`Shadow::check_state_root`].
+    pub(crate) fn calculate_state_root(
+        &mut self,
+        state: &HashedPostState,
+    ) -> Option<Result<B256, StatelessValidationError>> {
+        let (backend, _) = self.backend.as_mut()?;
+        Some(backend.calculate_state_root(state.clone()))
+    }
+
+    /// Checks the root returned by `calculate_state_root` against the root of the second backend.
+    pub(crate) fn check_state_root(
+        &self,
+        expected: Option<Result<B256, StatelessValidationError>>,
+        root: B256,
+    ) {
+        if let (Some((_, pre_state_root)), Some(expected)) = (&self.backend, expected) {
+            check(
+                *pre_state_root,
+                format_args!("calculate_state_root"),
+                root,
+                expected,
+            );
+        }
+    }
+}
+
+/// Panics unless the second backend returned the same value.
+fn check<T: PartialEq + fmt::Debug, E: fmt::Debug>(
+    pre_state_root: B256,
+    call: fmt::Arguments<'_>,
+    value: T,
+    expected: Result<T, E>,
+) {
+    if expected.as_ref().ok() != Some(&value) {
+        divergence(pre_state_root, call, &value, &expected);
+    }
+}
+
+fn divergence(
+    pre_state_root: B256,
+    call: fmt::Arguments<'_>,
+    result: &dyn fmt::Debug,
+    expected: &dyn fmt::Debug,
+) -> ! {
+    panic!(
+        "MPT: shadow divergence in {call} on pre-state {pre_state_root}: \
+         ref-mpt returned {result:?}, zeth-mpt returned {expected:?}"
+    )
+}
+
+// the second backend hashes with keccak
+#[cfg(all(test, not(feature = "poseidon2")))]
+mod tests {
+    use crate::tests::{build_witness, TestState};
+    use crate::SimpleSparseState;
+    use alloy_primitives::{Address, U256};
+    use ref_mpt::{keccak256, B256};
+    use reth_primitives_traits::Account;
+    use reth_trie_common::{HashedPostState, HashedStorage};
+    use stateless::StatelessTrie;
+
+    fn pre_state() -> TestState {
+        (1..=4_u8)
+            .map(|i| {
+                let storage = (1..=4)
+                    .map(|slot| (U256::from(slot), U256::from(i)))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect()
+    }
+
+    fn post_state(address: Address) -> HashedPostState {
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            keccak256(address),
+            Some(Account {
+                nonce: 1,
+                balance: U256::from(7),
+                bytecode_hash: None,
+            }),
+        );
+        let mut storage = HashedStorage::new(false);
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(1))), U256::ZERO);
+        storage
+            .storage
+            .insert(keccak256(B256::from(U256::from(9))), U256::from(9));
+        post_state.storages.insert(keccak256(address), storage);
+        post_state
+    }
+
+    #[test]
+    fn agrees_with_zeth() {
+        let (witness, root) = build_witness(&pre_state());
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let address = Address::repeat_byte(2);
+        assert!(state.account(address).unwrap().is_some());
+        assert_eq!(
+            state.storage(address, U256::from(3)).unwrap(),
+            U256::from(2)
+        );
+        assert!(state.account(Address::repeat_byte(9)).unwrap().is_none());
+        state.calculate_state_root(post_state(address)).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "MPT: shadow divergence in calculate_state_root")]
+    fn panics_on_divergence() {
+        let (witness, root) = build_witness(&pre_state());
+        let (mut state, _) = SimpleSparseState::new(&witness, root).unwrap();
+        let address = Address::repeat_byte(2);
+        assert!(state.account(address).unwrap().is_some());
+        // a change only the state sees
+        state
+            .state
+            .get_mut()
+            .insert(B256::ZERO, alloy_primitives::Bytes::from_static(&[1]));
+        state.calculate_state_root(post_state(address)).unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+//! Recovery of the public keys of the transaction signers, passed to the stateless validation
+//! with the block. Hosts recover them when preparing the inputs of a guest, which then only checks
+//! the signatures.
+use alloc::vec::Vec;
+use alloy_primitives::{Signature, B256};
+pub use k256::ecdsa::Error;
+use k256::ecdsa::{RecoveryId, VerifyingKey};
+use stateless::{StatelessInput, UncompressedPublicKey};
+
+/// Recovers the uncompressed public key from a transaction signature and signing hash.
+pub fn recover_public_key(sig: &Signature, hash: B256) -> Result<UncompressedPublicKey, Error> {
+    let mut sig_bytes = [0u8; 64];
+    sig_bytes[..32].copy_from_slice(&sig.r().to_be_bytes::<32>());
+    sig_bytes[32..].copy_from_slice(&sig.s().to_be_bytes::<32>());
+
+    let signature = k256::ecdsa::Signature::from_slice(&sig_bytes)?;
+    let recid = RecoveryId::new(sig.v(), false);
+    let key = VerifyingKey::recover_from_prehash(hash.as_slice(), &signature, recid)?;
+
+    let mut bytes = [0u8; 65];
+    bytes.copy_from_slice(key.to_encoded_point(false).as_bytes());
+    Ok(UncompressedPublicKey(bytes))
+}
+
+/// Recovers the public keys of all transactions in the block, in transaction order.
+pub fn recover_public_keys(input: &StatelessInput) -> Result<Vec<UncompressedPublicKey>, Error> {
+    input
+        .block
+        .body
+        .transactions
+        .iter()
+        .map(|tx| recover_public_key(tx.signature(), tx.signature_hash()))
+        .collect()
+}
@@ -0,0 +1,155 @@
+//! Storage tries kept across blocks, keyed by their root hash.
+use crate::{reveal_shared, shared_hash};
+use alloc::sync::Arc;
+use alloy_trie::EMPTY_ROOT_HASH;
+use ref_mpt::{B256Map, InvalidNode, RlpNodes, Trie, B256};
+
+/// Storage tries of previous blocks by their root hash.
+///
+/// A state built [`with_storage_cache`] takes the storage trie of an account from the cache when
+/// its storage root is cached, instead of revealing it from the witness, and hands the cache back
+/// with all its storage tries [`into_storage_cache`]. Popular contracts appear in most witnesses
+/// with an unchanged storage root, and keep their revealed nodes and cached hashes from block to
+/// block.
+///
+/// Tries not used for `max_age` blocks are evicted.
+///
+/// [`with_storage_cache`]: crate::SimpleSparseState::with_storage_cache
+/// [`into_storage_cache`]: crate::SimpleSparseState::into_storage_cache
+#[derive(Debug, Clone)]
+pub struct StorageCache {
+    /// The tries with the block in which they were last used.
+    tries: B256Map<(u64, Arc<Trie>)>,
+    block: u64,
+    max_age: u64,
+}
+
+impl StorageCache {
+    /// Creates an empty cache keeping the storage tries used in the last `max_age` blocks.
+    pub fn new(max_age: u64) -> Self {
+        Self {
+            tries: B256Map::default(),
+            block: 0,
+            max_age,
+        }
+    }
+
+    /// Returns the number of cached tries.
+    pub fn len(&self) -> usize {
+        self.tries.len()
+    }
+
+    /// Returns `true` if no trie is cached.
+    pub fn is_empty(&self) -> bool {
+        self.tries.is_empty()
+    }
+
+    /// Removes all cached tries.
+    pub fn clear(&mut self) {
+        self.tries.clear();
+    }
+
+    /// Starts a new block.
+    pub(crate) const fn next_block(&mut self) {
+        self.block += 1;
+    }
+
+    /// Returns the storage trie with the given root, taken from the cache and completed with the
+    /// witness nodes, or revealed from the witness if it is not cached. Fails if a witness node
+    /// does not decode.
+    pub(crate) fn reveal<M: RlpNodes + ?Sized>(
+        &mut self,
+        storage_root: B256,
+        rlp_by_digest: &M,
+    ) -> Result<Arc<Trie>, InvalidNode> {
+        match self.tries.remove(&storage_root) {
+            Some((_, mut trie)) => {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(target: "ref_mpt_state", %storage_root, "cached storage trie");
+                reveal_shared(&mut trie, rlp_by_digest)?;
+                Ok(trie)
+            }
+            None => Trie::try_reveal_from_rlp(storage_root, rlp_by_digest).map(Arc::new),
+        }
+    }
+
+    /// Caches the storage tries used in the current block, and evicts the tries which are too old.
+    pub(crate) fn extend(&mut self, tries: impl IntoIterator<Item = Arc<Trie>>) {
+        for mut trie in tries {
+            let root = shared_hash(&mut trie);
+            if root != EMPTY_ROOT_HASH {
+                self.tries.insert(root, (self.block, trie));
+            }
+        }
+        let (block, max_age) = (self.block, self.max_age);
+        self.tries
+            .retain(|_, (last_used, _)| block - *last_used <= max_age);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use crate::SimpleSparseState;
+    use alloy_primitives::{Address, U256};
+    use reth_trie_common::HashedPostState;
+    use stateless::{ExecutionWitness, StatelessTrie};
+    use std::collections::BTreeMap;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn reuses_storage_tries_across_blocks() {
+        let address = Address::repeat_byte(0x01);
+        let storage: BTreeMap<U256, U256> = (1..=20_u64)
+            .map(|slot| (U256::from(slot), U256::from(slot + 100)))
+            .collect();
+        let mut pre_state: TestState = (1..=10_u8)
+            .map(|i| (Address::repeat_byte(i), (U256::from(i), BTreeMap::new())))
+            .collect();
+        pre_state.get_mut(&address).unwrap().1 = storage.clone();
+        let (witness, pre_state_root) = build_witness(&pre_state);
+
+        let (state, _) = SimpleSparseState::new(&witness, pre_state_root).unwrap();
+        let mut state = state.with_storage_cache(StorageCache::new(1));
+        state.account(address).unwrap();
+        assert_eq!(
+            state.storage(address, U256::from(3)).unwrap(),
+            U256::from(103)
+        );
+        let root = state
+            .calculate_state_root(HashedPostState::default())
+            .unwrap();
+        let cache = state.into_storage_cache();
+        assert_eq!(cache.len(), 1);
+
+        // the next witness does not hold the storage trie of the account
+        let (storage_witness, _) =
+            build_witness(&BTreeMap::from([(address, (U256::ZERO, storage))]));
+        let next_witness = ExecutionWitness {
+            state: witness
+                .state
+                .iter()
+                .filter(|rlp| !storage_witness.state.contains(rlp))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+        let (state, _) = SimpleSparseState::new(&next_witness, root).unwrap();
+        let state = state.with_storage_cache(cache);
+        state.account(address).unwrap();
+        assert_eq!(
+            state.storage(address, U256::from(3)).unwrap(),
+            U256::from(103)
+        );
+        let mut cache = state.into_storage_cache();
+        assert_eq!(cache.len(), 1);
+
+        // the trie is evicted once it is unused for more than one block
+        for expected in [1, 0] {
+            let (state, _) = SimpleSparseState::new(&witness, root).unwrap();
+            cache = state.with_storage_cache(cache).into_storage_cache();
+            assert_eq!(cache.len(), expected);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Normalization of execution witnesses.
+//!
+//! Witnesses of the same block from different producers differ in redundancy and order: nodes may
+//! be repeated, nodes of tries the block never touches may be included, and the order is whatever
+//! the producer's database iteration gave. Normalizing makes them comparable and smaller.
+use alloc::vec::Vec;
+use alloy_primitives::map::B256Set;
+use alloy_primitives::private::alloy_rlp::Decodable;
+use alloy_primitives::Bytes;
+use alloy_trie::nodes::{RlpNode, TrieNode};
+use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
+use ref_mpt::{keccak256, B256Map, B256};
+use stateless::ExecutionWitness;
+
+/// What [`normalize_witness`] removed from a witness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NormalizationStats {
+    /// Trie nodes present more than once, counting every repetition.
+    pub duplicate_nodes: usize,
+    /// Distinct trie nodes not reachable from the state root or a storage root.
+    pub unreachable_nodes: usize,
+    /// Bytecodes present more than once, counting every repetition.
+    pub duplicate_codes: usize,
+    /// Total size of the trie nodes and bytecodes before normalization.
+    pub bytes_before: usize,
+    /// Total size of the trie nodes and bytecodes after normalization.
+    pub bytes_after: usize,
+}
+
+/// Normalizes a witness for the given pre-state root.
+///
+/// Removes duplicate trie nodes and bytecodes and drops the trie nodes that are not reachable from
+/// the state root or from the storage root of a reachable account. Nodes, codes and keys are
+/// sorted, headers are kept as they are.
+pub fn normalize_witness(
+    witness: &ExecutionWitness,
+    pre_state_root: B256,
+) -> (ExecutionWitness, NormalizationStats) {
+    let rlp_by_digest: B256Map<&Bytes> = witness
+        .state
+        .iter()
+        .map(|rlp| (keccak256(rlp), rlp))
+        .collect();
+
+    let walker = Walker::walk(&rlp_by_digest, pre_state_root);
+
+    let mut state: Vec<Bytes> = rlp_by_digest
+        .iter()
+        .filter(|(digest, _)| walker.reachable.contains(*digest))
+        .map(|(_, rlp)| (*rlp).clone())
+        .collect();
+    state.sort_unstable();
+    let mut codes = witness.codes.clone();
+    codes.sort_unstable();
+    codes.dedup();
+    let mut keys = witness.keys.clone();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let size = |items: &[Bytes]| items.iter().map(|item| item.len()).sum::<usize>();
+    let stats = NormalizationStats {
+        duplicate_nodes: witness.state.len() - rlp_by_digest.len(),
+        unreachable_nodes: rlp_by_digest.len() - state.len(),
+        duplicate_codes: witness.codes.len() - codes.len(),
+        bytes_before: size(&witness.state) + size(&witness.codes),
+        bytes_after: size(&state) + size(&codes),
+    };
+    let normalized = ExecutionWitness {
+        state,
+        codes,
+        keys,
+        headers: witness.headers.clone(),
+    };
+    (normalized, stats)
+}
+
+/// Marks the nodes reachable from a root, collecting the storage roots of the account leaves.
+pub(crate) struct Walker<'a> {
+    rlp_by_digest: &'a B256Map<&'a Bytes>,
+    /// The digests of the reachable nodes, including those missing from the witness.
+    pub(crate) reachable: B256Set,
+    storage_roots: Vec<B256>,
+    /// The reachable nodes of the witness in the state trie and in the storage tries, by depth in
+    /// hashed nodes from their root.
+    pub(crate) depths: [Vec<usize>; 2],
+}
+
+impl<'a> Walker<'a> {
+    /// Walks the state trie of `pre_state_root` and the storage tries of its accounts.
+    pub(crate) fn walk(rlp_by_digest: &'a B256Map<&'a Bytes>, pre_state_root: B256) -> Self {
+        let mut walker = Walker {
+            rlp_by_digest,
+            reachable: B256Set::default(),
+            storage_roots: Vec::new(),
+            depths: Default::default(),
+        };
+        walker.visit_digest(pre_state_root, true, 0);
+        while let Some(storage_root) = walker.storage_roots.pop() {
+            walker.visit_digest(storage_root, false, 0);
+        }
+        walker
+    }
+
+    fn visit_digest(&mut self, digest: B256, accounts: bool, depth: usize) {
+        if digest == EMPTY_ROOT_HASH || !self.reachable.insert(digest) {
+            return;
+        }
+        if let Some(rlp) = self.rlp_by_digest.get(&digest) {
+            let depths = &mut self.depths[usize::from(!accounts)];
+            if depths.len() <= depth {
+                depths.resize(depth + 1, 0);
+            }
+            depths[depth] += 1;
+            self.visit(rlp, accounts, depth + 1);
+        }
+    }
+
+    fn visit_child(&mut self, child: &RlpNode, accounts: bool, depth: usize) {
+        match child.as_hash() {
+            Some(digest) => self.visit_digest(digest, accounts, depth),
+            None => self.visit(child.as_slice(), accounts, depth),
+        }
+    }
+
+    /// Visits the children of a node, at `depth` if hashed.
+    fn visit(&mut self, mut rlp: &[u8], accounts: bool, depth: usize) {
+        // nodes which do not decode are kept if referenced, but not descended into
+        match TrieNode::decode(&mut rlp) {
+            Ok(TrieNode::Branch(branch)) => {
+                for child in &branch.stack {
+                    self.visit_child(child, accounts, depth);
+                }
+            }
+            Ok(TrieNode::Extension(extension)) => {
+                self.visit_child(&extension.child, accounts, depth)
+            }
+            Ok(TrieNode::Leaf(leaf)) if accounts => {
+                if let Ok(account) = TrieAccount::decode(&mut &leaf.value[..]) {
+                    self.storage_roots.push(account.storage_root);
+                }
+            }
+            Ok(TrieNode::Leaf(_) | TrieNode::EmptyRoot) | Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{build_witness, TestState};
+    use crate::SimpleSparseState;
+    use alloy_primitives::{Address, U256};
+    use stateless::StatelessTrie;
+    use std::vec;
+
+    fn test_state(accounts: u8) -> TestState {
+        (1..=accounts)
+            .map(|i| {
+                let storage = (1..=u64::from(i))
+                    .map(|slot| (U256::from(slot), U256::from(slot * 7)))
+                    .collect();
+                (Address::repeat_byte(i), (U256::from(i), storage))
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn removes_duplicate_and_unreachable_nodes() {
+        let (witness, pre_state_root) = build_witness(&test_state(8));
+        // a different state sharing its storage tries with the first accounts
+        let other_state = test_state(3)
+            .into_iter()
+            .map(|(address, (_, storage))| (address, (U256::from(1000), storage)))
+            .collect();
+        let (unrelated, _) = build_witness(&other_state);
+
+        let mut state = witness.state.clone();
+        state.extend(witness.state.iter().take(5).cloned());
+        let extra: Vec<Bytes> = unrelated
+            .state
+            .iter()
+            .filter(|rlp| !witness.state.contains(rlp))
+            .cloned()
+            .collect();
+        assert!(!extra.is_empty());
+        state.extend(extra.iter().cloned());
+        state.reverse();
+        let code = Bytes::from_static(&[0x60, 0x00]);
+        let noisy = ExecutionWitness {
+            state,
+            codes: vec![code.clone(), code.clone()],
+            ..Default::default()
+        };
+
+        let (normalized, stats) = normalize_witness(&noisy, pre_state_root);
+        let mut expected = witness.state.clone();
+        expected.sort_unstable();
+        expected.dedup();
+        assert_eq!(normalized.state, expected);
+        assert_eq!(normalized.codes, vec![code]);
+        assert_eq!(
+            stats,
+            NormalizationStats {
+                duplicate_nodes: 5 + witness.state.len() - expected.len(),
+                unreachable_nodes: extra.len(),
+                duplicate_codes: 1,
+                bytes_before: noisy.state.iter().map(|item| item.len()).sum::<usize>() + 4,
+                bytes_after: expected.iter().map(|item| item.len()).sum::<usize>() + 2,
+            }
+        );
+
+        // the normalized witness is a fixed point and still reveals the state
+        assert_eq!(
+            normalize_witness(&normalized, pre_state_root).0.state,
+            normalized.state
+        );
+        let (sparse_state, _) = SimpleSparseState::new(&normalized, pre_state_root).unwrap();
+        let account = sparse_state.account(Address::repeat_byte(8)).unwrap();
+        assert_eq!(account.unwrap().balance, U256::from(8));
+        assert_eq!(
+            sparse_state
+                .storage(Address::repeat_byte(8), U256::from(8))
+                .unwrap(),
+            U256::from(56)
+        );
+    }
+
+    #[test]
+    fn empty_state() {
+        let witness = ExecutionWitness {
+            state: vec![Bytes::from_static(&[0x80])],
+            ..Default::default()
+        };
+        let (normalized, stats) = normalize_witness(&witness, EMPTY_ROOT_HASH);
+        assert!(normalized.state.is_empty());
+        assert_eq!(stats.unreachable_nodes, 1);
+    }
+}
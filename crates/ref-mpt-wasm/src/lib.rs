@@ -0,0 +1,189 @@
+//! `wasm-bindgen` bindings to check execution witnesses, compute trie roots and extract proofs in
+//! the browser, e.g. for light-client dashboards.
+//!
+//! Hashes, keys and values are passed as `0x`-prefixed hex strings, and witnesses as the JSON of an
+//! `ExecutionWitness`, as in the `witness` field of the `StatelessInput` fixtures:
+//!
+//! ```text
+//! wasm-pack build crates/ref-mpt-wasm --target web
+//! ```
+use alloy_primitives::{hex, Bytes, B256};
+use alloy_rlp::Decodable;
+use alloy_trie::nodes::TrieNode;
+use alloy_trie::EMPTY_ROOT_HASH;
+use core::fmt;
+use ref_mpt::{keccak256, B256Map, Trie};
+use ref_mpt_state::SimpleSparseState;
+use stateless::{ExecutionWitness, StatelessTrie};
+use wasm_bindgen::prelude::*;
+
+/// Checks that the witness reveals the state trie with the given root.
+///
+/// Throws if a witness node is not a valid trie node, the root node is missing, or the sparse
+/// state cannot be built from the witness.
+#[wasm_bindgen(js_name = verifyWitness)]
+pub fn verify_witness(witness_json: &str, pre_state_root: &str) -> Result<(), JsError> {
+    let witness = serde_json::from_str(witness_json)?;
+    Ok(check_witness(&witness, pre_state_root.parse()?)?)
+}
+
+/// Computes the root of a trie from a JSON array of `[key, value]` pairs with pre-hashed
+/// 32-byte keys.
+#[wasm_bindgen(js_name = computeRoot)]
+pub fn compute_root(entries_json: &str) -> Result<String, JsError> {
+    let entries: Vec<(B256, Bytes)> = serde_json::from_str(entries_json)?;
+    Ok(trie_root(entries).to_string())
+}
+
+/// Extracts the proof of the pre-hashed `key` in the trie with the given root from the witness
+/// nodes: the nodes on the path from the root towards the key, root first.
+///
+/// The proof shows the absence of the key if it is not in the trie.
+#[wasm_bindgen(js_name = generateProof)]
+pub fn generate_proof(witness_json: &str, root: &str, key: &str) -> Result<Vec<String>, JsError> {
+    let witness = serde_json::from_str(witness_json)?;
+    let proof = witness_proof(&witness, root.parse()?, key.parse()?)?;
+    Ok(proof.iter().map(hex::encode_prefixed).collect())
+}
+
+/// Error of a witness check or proof extraction.
+#[derive(Debug)]
+pub enum Error {
+    /// A witness node is not a valid trie node.
+    Rlp(alloy_rlp::Error),
+    /// A node with the given hash is referenced but not in the witness.
+    MissingNode(B256),
+    /// The trie or the sparse state could not be revealed from the witness.
+    Reveal(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rlp(err) => write!(f, "invalid witness node: {err}"),
+            Self::MissingNode(hash) => write!(f, "node {hash} missing from the witness"),
+            Self::Reveal(err) => write!(f, "failed to reveal the trie from the witness: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<alloy_rlp::Error> for Error {
+    fn from(err: alloy_rlp::Error) -> Self {
+        Self::Rlp(err)
+    }
+}
+
+/// Checks that all witness nodes decode and that they reveal the state trie of `pre_state_root`.
+pub fn check_witness(witness: &ExecutionWitness, pre_state_root: B256) -> Result<(), Error> {
+    for rlp in &witness.state {
+        TrieNode::decode(&mut &rlp[..])?;
+    }
+    if pre_state_root != EMPTY_ROOT_HASH
+        && !witness.state.iter().any(|rlp| keccak256(rlp) == pre_state_root)
+    {
+        return Err(Error::MissingNode(pre_state_root));
+    }
+    SimpleSparseState::new(witness, pre_state_root)
+        .map_err(|err| Error::Reveal(err.to_string()))?;
+    Ok(())
+}
+
+/// Returns the root of the trie with the given entries.
+pub fn trie_root(entries: impl IntoIterator<Item = (B256, Bytes)>) -> B256 {
+    let mut trie = Trie::new();
+    for (key, value) in entries {
+        trie.insert(key, value);
+    }
+    trie.hash()
+}
+
+/// Returns the witness nodes on the path from `root` towards `key`, root first.
+///
+/// Nodes inlined in their parent are part of the parent's RLP and are not repeated.
+pub fn witness_proof(
+    witness: &ExecutionWitness,
+    root: B256,
+    key: B256,
+) -> Result<Vec<Bytes>, Error> {
+    let nodes: B256Map<Bytes> =
+        witness.state.iter().map(|rlp| (keccak256(rlp), rlp.clone())).collect();
+    let mut trie =
+        Trie::try_reveal_from_rlp(root, &nodes).map_err(|err| Error::Reveal(err.to_string()))?;
+    trie.proof(key).map_err(|err| Error::MissingNode(err.digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_trie::{proof::ProofRetainer, HashBuilder, Nibbles};
+    use std::collections::BTreeMap;
+
+    // Builds a witness with all nodes of the trie of `entries`. Returns the witness and the root.
+    fn build_witness(entries: &BTreeMap<B256, Bytes>) -> (ExecutionWitness, B256) {
+        let targets = entries.keys().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        for (key, value) in entries {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hash_builder.root();
+        let witness = ExecutionWitness {
+            state: hash_builder.take_proof_nodes().values().cloned().collect(),
+            ..Default::default()
+        };
+        (witness, root)
+    }
+
+    fn entries() -> BTreeMap<B256, Bytes> {
+        (0u64..64)
+            .map(|i| {
+                // short values are inlined into their parent branch
+                let len = if i % 3 == 0 { 1 } else { 40 };
+                (keccak256(i.to_be_bytes()), Bytes::from(vec![i as u8 + 1; len]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn root_and_witness() {
+        let entries = entries();
+        let (witness, root) = build_witness(&entries);
+        assert_eq!(trie_root(entries), root);
+        check_witness(&witness, root).unwrap();
+
+        assert!(matches!(
+            check_witness(&witness, B256::repeat_byte(1)),
+            Err(Error::MissingNode(_))
+        ));
+        let invalid = ExecutionWitness {
+            state: vec![Bytes::from_static(&[0xc3, 0x01, 0x02, 0x03])],
+            ..Default::default()
+        };
+        assert!(matches!(check_witness(&invalid, root), Err(Error::Rlp(_))));
+    }
+
+    #[test]
+    fn proofs_verify() {
+        let entries = entries();
+        let (witness, root) = build_witness(&entries);
+        for (key, value) in &entries {
+            let proof = witness_proof(&witness, root, *key).unwrap();
+            alloy_trie::proof::verify_proof(root, Nibbles::unpack(key), Some(value.to_vec()), &proof)
+                .unwrap();
+        }
+
+        let absent = keccak256([0xff]);
+        let proof = witness_proof(&witness, root, absent).unwrap();
+        alloy_trie::proof::verify_proof(root, Nibbles::unpack(absent), None, &proof).unwrap();
+
+        // the nodes below the root are missing from the witness
+        let root_only = ExecutionWitness {
+            state: witness.state.iter().filter(|rlp| keccak256(rlp) == root).cloned().collect(),
+            ..Default::default()
+        };
+        let key = *entries.keys().next().unwrap();
+        assert!(matches!(witness_proof(&root_only, root, key), Err(Error::MissingNode(_))));
+    }
+}
@@ -0,0 +1,37 @@
+//! Benchmarks of [`Trie::clone`], which copies every node of the trie, at the sizes of the
+//! storage tries and state tries of a block.
+//!
+//! ```text
+//! cargo bench -p ref-mpt --bench clone
+//! ```
+// the dev-dependencies of the unit tests are not used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::{Bytes, keccak256};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use ref_mpt::Trie;
+
+/// The number of keys of the cloned tries.
+const SIZES: [u64; 3] = [1_000, 10_000, 100_000];
+
+fn clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone");
+    for size in SIZES {
+        let mut trie = Trie::new();
+        for i in 0..size {
+            trie.insert(
+                keccak256(i.to_be_bytes()),
+                Bytes::from(i.to_be_bytes().repeat(4)),
+            );
+        }
+        trie.hash();
+        group.throughput(Throughput::Elements(size));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &trie, |b, trie| {
+            b.iter(|| trie.clone());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, clone);
+criterion_main!(benches);
@@ -0,0 +1,114 @@
+//! Benchmarks of the hash caches: a trie of 10,000 keys is hashed again after updating `k` of
+//! its keys, with the cached hashes of the untouched subtrees and without them, compared with
+//! zeth's `CachedTrie` and `zeth_mpt::Trie`, which caches no hash. The throughput is the number of
+//! updates, so that the results read as the cost of hashing per update.
+//!
+//! ```text
+//! cargo bench -p ref-mpt --bench hash_cache
+//! ```
+// the dev-dependencies of the unit tests are not used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::{B256, Bytes, keccak256};
+use criterion::measurement::WallTime;
+use criterion::{
+    BatchSize, BenchmarkGroup, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main,
+};
+use ref_mpt::Trie;
+use zeth_mpt::CachedTrie;
+
+/// The number of keys of the trie.
+const SIZE: u64 = 10_000;
+
+/// The number of keys updated between two hashes.
+const UPDATES: [u64; 4] = [1, 10, 100, 1_000];
+
+fn key(i: u64) -> B256 {
+    keccak256(i.to_be_bytes())
+}
+
+fn value(i: u64, version: u64) -> Bytes {
+    Bytes::from([i.to_be_bytes(), version.to_be_bytes()].concat().repeat(2))
+}
+
+/// The keys updated between two hashes, spread over the trie.
+fn updated(updates: u64) -> impl Iterator<Item = u64> {
+    (0..updates).map(move |i| i * (SIZE / updates))
+}
+
+/// Benchmarks updating `updates` keys of a copy of `trie` with `insert`, then hashing it.
+fn bench<T: Clone>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &str,
+    updates: u64,
+    trie: &T,
+    insert: impl Fn(&mut T, B256, Bytes),
+    hash: impl Fn(&mut T) -> B256,
+) {
+    group.bench_with_input(BenchmarkId::new(name, updates), trie, |b, trie| {
+        b.iter_batched_ref(
+            || trie.clone(),
+            |trie| {
+                for i in updated(updates) {
+                    insert(trie, key(i), value(i, 1));
+                }
+                hash(trie)
+            },
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+fn hash_after_updates(c: &mut Criterion) {
+    let mut trie = Trie::new();
+    let mut cached = CachedTrie::default();
+    let mut uncached = zeth_mpt::Trie::default();
+    for i in 0..SIZE {
+        trie.insert(key(i), value(i, 0));
+        cached.insert(key(i), value(i, 0));
+        uncached.insert(key(i), value(i, 0));
+    }
+    trie.hash();
+    cached.hash();
+
+    let mut group = c.benchmark_group("hash_after_updates");
+    for updates in UPDATES {
+        group.throughput(Throughput::Elements(updates));
+        bench(
+            &mut group,
+            "ref-mpt",
+            updates,
+            &trie,
+            Trie::insert,
+            Trie::hash,
+        );
+        bench(
+            &mut group,
+            "ref-mpt-uncached",
+            updates,
+            &trie,
+            Trie::insert,
+            |trie| trie.hash_uncached(),
+        );
+        bench(
+            &mut group,
+            "zeth-mpt",
+            updates,
+            &cached,
+            |trie, key, value| trie.insert(key, value),
+            CachedTrie::hash,
+        );
+        bench(
+            &mut group,
+            "zeth-mpt-uncached",
+            updates,
+            &uncached,
+            |trie, key, value| trie.insert(key, value),
+            |trie| trie.hash_slow(),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hash_after_updates);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Benchmarks of [`Trie::reveal_from_rlp`] alone, from a [`WitnessMap`] built beforehand: the
+//! decoding of the witness nodes into a trie, without hashing them into the map.
+//!
+//! ```text
+//! cargo bench -p ref-mpt --bench reveal
+//! ```
+// the dev-dependencies of the unit tests are not used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::{B256, keccak256};
+use alloy_trie::proof::ProofRetainer;
+use alloy_trie::{HashBuilder, Nibbles};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use ref_mpt::{Trie, WitnessMap};
+
+/// The number of keys of the revealed tries. The depth of the tries grows with it.
+const SIZES: [u64; 3] = [1_000, 10_000, 100_000];
+
+/// The number of keys whose proofs make up the partial witnesses.
+const PROVED: usize = 100;
+
+/// Returns the root and the nodes of the trie holding the keys `0..size`, restricted to the
+/// proofs of the first `proved` keys.
+fn witness(size: u64, proved: usize) -> (B256, WitnessMap) {
+    let mut keys: Vec<B256> = (0..size).map(|i| keccak256(i.to_be_bytes())).collect();
+    let retainer = ProofRetainer::new(keys[..proved].iter().map(Nibbles::unpack).collect());
+    keys.sort_unstable();
+    let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+    for key in &keys {
+        hash_builder.add_leaf(Nibbles::unpack(key), &key.repeat(2));
+    }
+    let root = hash_builder.root();
+    let nodes = hash_builder.take_proof_nodes().values().cloned().collect();
+    (root, nodes)
+}
+
+fn bench_reveal(c: &mut Criterion, name: &str, proved: impl Fn(u64) -> usize) {
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        let (root, nodes) = witness(size, proved(size));
+        group.throughput(Throughput::Elements(nodes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &nodes, |b, nodes| {
+            b.iter(|| Trie::reveal_from_rlp(root, nodes));
+        });
+    }
+    group.finish();
+}
+
+// Every key is proved, so the whole trie is revealed.
+fn full(c: &mut Criterion) {
+    bench_reveal(c, "reveal_full", |size| size as usize);
+}
+
+// The same number of proofs in deeper and deeper tries: the branches hold one revealed child and
+// digests for the others.
+fn partial(c: &mut Criterion) {
+    bench_reveal(c, "reveal_partial", |_| PROVED);
+}
+
+criterion_group!(benches, full, partial);
+criterion_main!(benches);
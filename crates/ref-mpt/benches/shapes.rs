@@ -0,0 +1,139 @@
+//! Benchmarks of [`Trie`] on pathological shapes, which stress the recursion depth and the
+//! invalidation of the cached hashes: revealing the trie from its nodes, hashing it from scratch,
+//! and removing a key before hashing it again. The throughput of the reveals is the size of the
+//! witness, and that of the hashes its number of nodes, to compare the shapes with each other.
+//!
+//! ```text
+//! cargo bench -p ref-mpt --bench shapes
+//! ```
+// the dev-dependencies of the unit tests are not used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::{B256, keccak256};
+use alloy_trie::proof::ProofRetainer;
+use alloy_trie::{HashBuilder, Nibbles};
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use ref_mpt::{Trie, WitnessMap};
+
+/// A trie given by its root and nodes, with the key removed by the benchmarks.
+struct Shape {
+    name: &'static str,
+    root: B256,
+    nodes: WitnessMap,
+    removed: B256,
+}
+
+impl Shape {
+    /// Builds the trie holding `keys`, revealed from the proofs of `revealed`.
+    fn new(name: &'static str, keys: &[B256], revealed: &[B256], removed: B256) -> Self {
+        let mut sorted = keys.to_vec();
+        sorted.sort_unstable();
+        let retainer = ProofRetainer::new(revealed.iter().map(Nibbles::unpack).collect());
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for key in &sorted {
+            hash_builder.add_leaf(Nibbles::unpack(key), &key.repeat(2));
+        }
+        let root = hash_builder.root();
+        let nodes = hash_builder.take_proof_nodes().values().cloned().collect();
+        Self {
+            name,
+            root,
+            nodes,
+            removed,
+        }
+    }
+
+    fn trie(&self) -> Trie {
+        Trie::reveal_from_rlp(self.root, &self.nodes)
+    }
+}
+
+/// A chain of 64 branches, each holding a leaf and the next branch: the keys diverge from the
+/// same key at every nibble in turn. The deepest key is removed.
+fn chain() -> Shape {
+    let base = B256::repeat_byte(0x11);
+    let mut keys = vec![base];
+    for nibble in 0..64 {
+        let mut key = base;
+        key[nibble / 2] ^= if nibble % 2 == 0 { 0x30 } else { 0x03 };
+        keys.push(key);
+    }
+    Shape::new("chain", &keys, &keys, base)
+}
+
+/// 4096 keys filling the first three levels of branches, each with 16 children.
+fn fanout() -> Shape {
+    let keys: Vec<B256> = (0..4096_u16)
+        .map(|i| {
+            let mut key = keccak256(i.to_be_bytes());
+            key[0] = (i >> 4) as u8;
+            key[1] = (key[1] & 0x0f) | ((i & 0x0f) << 4) as u8;
+            key
+        })
+        .collect();
+    Shape::new("fanout", &keys, &keys, keys[0])
+}
+
+/// The proof of a single key of a trie of 10,000 keys: every branch holds one revealed child,
+/// the others are digests. The first key whose removal needs no other node is proved and removed.
+fn partial() -> Shape {
+    let keys: Vec<B256> = (0..10_000_u64)
+        .map(|i| keccak256(i.to_be_bytes()))
+        .collect();
+    keys.iter()
+        .map(|key| Shape::new("partial", &keys, &[*key], *key))
+        .find(|shape| shape.trie().try_remove(shape.removed).is_ok())
+        .unwrap()
+}
+
+fn shapes() -> [Shape; 3] {
+    [chain(), fanout(), partial()]
+}
+
+fn reveal(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reveal");
+    for shape in shapes() {
+        group.throughput(Throughput::Bytes(shape.nodes.total_bytes() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shape.name),
+            &shape,
+            |b, shape| {
+                b.iter(|| shape.trie());
+            },
+        );
+    }
+    group.finish();
+}
+
+fn hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash_uncached");
+    for shape in shapes() {
+        group.throughput(Throughput::Elements(shape.nodes.len() as u64));
+        let trie = shape.trie();
+        group.bench_with_input(BenchmarkId::from_parameter(shape.name), &trie, |b, trie| {
+            b.iter(|| trie.hash_uncached());
+        });
+    }
+    group.finish();
+}
+
+fn remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove_and_hash");
+    for shape in shapes() {
+        let trie = shape.trie();
+        group.bench_with_input(BenchmarkId::from_parameter(shape.name), &trie, |b, trie| {
+            b.iter_batched_ref(
+                || trie.clone(),
+                |trie| {
+                    trie.remove(shape.removed);
+                    trie.hash()
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, reveal, hash, remove);
+criterion_main!(benches);
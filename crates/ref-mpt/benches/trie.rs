@@ -0,0 +1,116 @@
+//! Micro-benchmarks of the node-level operations of [`Trie`]: inserting and removing a single
+//! key, and hashing the trie again after an insertion, compared with zeth's [`CachedTrie`].
+//!
+//! ```text
+//! cargo bench -p ref-mpt --bench trie
+//! ```
+// the dev-dependencies of the unit tests are not used by the benchmarks, and `criterion_group!`
+// declares an undocumented public function
+#![allow(unused_crate_dependencies, missing_docs)]
+use alloy_primitives::{B256, Bytes, keccak256};
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use ref_mpt::Trie;
+use zeth_mpt::CachedTrie;
+
+/// The number of keys of the benchmarked tries.
+const SIZES: [u64; 3] = [100, 1_000, 10_000];
+
+fn key(i: u64) -> B256 {
+    keccak256(i.to_be_bytes())
+}
+
+fn value(i: u64) -> Bytes {
+    Bytes::from(i.to_be_bytes().repeat(4))
+}
+
+/// Builds both tries with the keys `0..size`, with all their hashes cached.
+fn tries(size: u64) -> (Trie, CachedTrie) {
+    let mut trie = Trie::new();
+    let mut cached = CachedTrie::default();
+    for i in 0..size {
+        trie.insert(key(i), value(i));
+        cached.insert(key(i), value(i));
+    }
+    trie.hash();
+    cached.hash();
+    (trie, cached)
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for size in SIZES {
+        let (trie, cached) = tries(size);
+        group.bench_with_input(BenchmarkId::new("ref-mpt", size), &trie, |b, trie| {
+            b.iter_batched_ref(
+                || trie.clone(),
+                |trie| trie.insert(key(size), value(size)),
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_with_input(BenchmarkId::new("zeth-mpt", size), &cached, |b, cached| {
+            b.iter_batched_ref(
+                || cached.clone(),
+                |cached| cached.insert(key(size), value(size)),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove");
+    for size in SIZES {
+        let (trie, cached) = tries(size);
+        let removed = size / 2;
+        group.bench_with_input(BenchmarkId::new("ref-mpt", size), &trie, |b, trie| {
+            b.iter_batched_ref(
+                || trie.clone(),
+                |trie| trie.remove(key(removed)),
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_with_input(BenchmarkId::new("zeth-mpt", size), &cached, |b, cached| {
+            b.iter_batched_ref(
+                || cached.clone(),
+                |cached| cached.remove(key(removed)),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// Only the path of the inserted key is hashed again, the rest of the trie keeps its hashes.
+fn hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash");
+    for size in SIZES {
+        let (trie, cached) = tries(size);
+        group.bench_with_input(BenchmarkId::new("ref-mpt", size), &trie, |b, trie| {
+            b.iter_batched_ref(
+                || {
+                    let mut trie = trie.clone();
+                    trie.insert(key(size), value(size));
+                    trie
+                },
+                Trie::hash,
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_with_input(BenchmarkId::new("zeth-mpt", size), &cached, |b, cached| {
+            b.iter_batched_ref(
+                || {
+                    let mut cached = cached.clone();
+                    cached.insert(key(size), value(size));
+                    cached
+                },
+                CachedTrie::hash,
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert, remove, hash);
+criterion_main!(benches);
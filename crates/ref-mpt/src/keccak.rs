@@ -0,0 +1,120 @@
+//! Keccak-256 used for all trie and state hashing.
+//!
+//! By default this is `alloy_primitives::keccak256`. Inside a zkVM guest, the `sp1-accel` feature
+//! runs the sponge on top of SP1's keccak permutation syscall, and the `risc0-accel` feature goes
+//! through `tiny-keccak`, which RISC Zero guests patch with the accelerated fork. Routing through
+//! `tiny-keccak` explicitly keeps the acceleration even if another crate enables a different
+//! keccak backend of `alloy-primitives`.
+//!
+//! The experimental, non-consensus `poseidon2` feature replaces keccak with a Poseidon2 hash (see
+//! `poseidon2.rs`), to measure the share of keccak in the guest cost.
+//!
+//! With several of these features enabled, e.g. with `--all-features`, `poseidon2` takes
+//! precedence over `sp1-accel`, which takes precedence over `risc0-accel`.
+use alloy_primitives::B256;
+
+/// Computes the Keccak-256 hash of `bytes`, or its Poseidon2 hash under the `poseidon2` feature.
+#[inline]
+pub fn keccak256(bytes: impl AsRef<[u8]>) -> B256 {
+    let bytes = bytes.as_ref();
+    #[cfg(feature = "poseidon2")]
+    {
+        crate::poseidon2::hash(bytes)
+    }
+    #[cfg(all(feature = "sp1-accel", target_os = "zkvm", not(feature = "poseidon2")))]
+    {
+        // SAFETY: the syscall permutes the 25 lanes of the state in place.
+        sponge(bytes, |state| unsafe {
+            syscall_keccak_permute(state)
+        })
+    }
+    #[cfg(all(
+        feature = "risc0-accel",
+        not(any(all(feature = "sp1-accel", target_os = "zkvm"), feature = "poseidon2"))
+    ))]
+    {
+        use tiny_keccak::Hasher;
+
+        let mut hash = B256::ZERO;
+        let mut hasher = tiny_keccak::Keccak::v256();
+        hasher.update(bytes);
+        hasher.finalize(&mut hash.0);
+        hash
+    }
+    #[cfg(not(any(
+        all(feature = "sp1-accel", target_os = "zkvm"),
+        feature = "risc0-accel",
+        feature = "poseidon2"
+    )))]
+    {
+        alloy_primitives::keccak256(bytes)
+    }
+}
+
+// The syscall is defined by `sp1-zkvm`, which every SP1 guest links; `sp1-lib` declares it the
+// same way.
+#[cfg(all(feature = "sp1-accel", target_os = "zkvm"))]
+extern "C" {
+    fn syscall_keccak_permute(state: *mut [u64; 25]);
+}
+
+/// Keccak-256 sponge over an external `keccak-f[1600]` permutation.
+#[cfg(any(test, all(feature = "sp1-accel", target_os = "zkvm")))]
+fn sponge(bytes: &[u8], mut permute: impl FnMut(&mut [u64; 25])) -> B256 {
+    const RATE: usize = 136;
+
+    fn absorb(state: &mut [u64; 25], block: &[u8]) {
+        for (lane, word) in state.iter_mut().zip(block.chunks_exact(8)) {
+            *lane ^= u64::from_le_bytes(word.try_into().expect("8-byte word"));
+        }
+    }
+
+    let mut state = [0_u64; 25];
+    let mut blocks = bytes.chunks_exact(RATE);
+    for block in &mut blocks {
+        absorb(&mut state, block);
+        permute(&mut state);
+    }
+
+    // Keccak padding: a one bit after the message and a one bit at the end of the block.
+    let remainder = blocks.remainder();
+    let mut last = [0_u8; RATE];
+    last[..remainder.len()].copy_from_slice(remainder);
+    last[remainder.len()] ^= 0x01;
+    last[RATE - 1] ^= 0x80;
+    absorb(&mut state, &last);
+    permute(&mut state);
+
+    let mut hash = B256::ZERO;
+    for (word, lane) in hash.0.chunks_exact_mut(8).zip(state) {
+        word.copy_from_slice(&lane.to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    #[test]
+    fn sponge_matches_keccak256() {
+        // Lengths around the rate exercise padding in a full and in a fresh block.
+        for len in [0, 1, 31, 32, 135, 136, 137, 271, 272, 1000] {
+            let bytes = vec![0xa5_u8; len];
+            assert_eq!(
+                sponge(&bytes, tiny_keccak::keccakf),
+                alloy_primitives::keccak256(&bytes),
+                "length {len}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "poseidon2"))]
+    fn keccak256_matches_alloy() {
+        for bytes in [&b""[..], b"hello", &[0x5a; 300]] {
+            assert_eq!(keccak256(bytes), alloy_primitives::keccak256(bytes));
+        }
+    }
+}
@@ -1,12 +1,52 @@
 //! A sparse Simple Merkle Patricia trie implementation.
 #![no_std]
+// the tests checking keccak roots are compiled out under the `poseidon2` feature, leaving their
+// helpers unused
+#![cfg_attr(
+    all(test, feature = "poseidon2"),
+    allow(unused, unused_crate_dependencies)
+)]
 extern crate alloc;
 #[cfg(test)]
 extern crate std;
+// the dev-dependencies of the benchmarks
+#[cfg(test)]
+use {criterion as _, zeth_mpt as _};
+// `poseidon2`, and `sp1-accel` in SP1 guests, take precedence over `risc0-accel`, leaving its
+// `tiny-keccak` unused
+#[cfg(all(
+    feature = "risc0-accel",
+    any(feature = "poseidon2", all(feature = "sp1-accel", target_os = "zkvm"))
+))]
+use tiny_keccak as _;
 
+mod keccak;
+mod ordered;
+#[cfg(feature = "poseidon2")]
+mod poseidon2;
+mod proof;
+mod provider;
 mod trie;
+mod witness_index;
 
 pub use alloy_primitives::B256;
 pub use alloy_trie::Nibbles;
+pub use keccak::keccak256;
+pub use ordered::{ordered_trie_root, ordered_trie_root_with_encoder, withdrawals_root};
+#[cfg(feature = "requests")]
+pub use ordered::requests_hash;
+pub use proof::{ProofError, verify_multiproof, verify_proof};
+pub use provider::NodeProvider;
 pub use trie::B256Map;
+pub use trie::ShardedTrie;
 pub use trie::Trie;
+pub use trie::TrieIter;
+#[cfg(feature = "journal")]
+pub use trie::{Journal, JournalEntry, JournalError};
+pub use trie::Lookup;
+pub use trie::{NodeKind, TraceNode};
+pub use trie::{InvalidNode, UnresolvedNode};
+pub use trie::ValuePool;
+pub use witness_index::{RlpNodes, WitnessIndex, WitnessMap};
+#[cfg(feature = "fuzzing")]
+pub use trie::fuzzing;
@@ -0,0 +1,119 @@
+//! Roots committed to by block headers besides the state root.
+//!
+//! The transactions, receipts and withdrawals of a block are committed to by the root of an
+//! ordered trie, keyed by the RLP encoding of the item index. These roots are computed with the
+//! same [`Trie`] as the state, so a guest validates every header root with this crate alone.
+//!
+//! Header roots are consensus values: under the experimental `poseidon2` feature, which swaps the
+//! hash of the [`Trie`], they are still hashed with keccak, by the `HashBuilder` of
+//! `alloy_trie`.
+use crate::trie::Trie;
+use alloc::vec::Vec;
+use alloy_primitives::B256;
+use alloy_rlp::Encodable;
+use alloy_trie::Nibbles;
+
+/// Computes the root of the ordered trie of `items`, each stored as its RLP encoding.
+pub fn ordered_trie_root<T: Encodable>(items: &[T]) -> B256 {
+    ordered_trie_root_with_encoder(items, |item, out| item.encode(out))
+}
+
+/// Computes the root of the ordered trie of `items`, each stored as encoded by `encode`.
+///
+/// Transactions and receipts are stored with their EIP-2718 encoding rather than plain RLP.
+pub fn ordered_trie_root_with_encoder<T, F>(items: &[T], mut encode: F) -> B256
+where
+    F: FnMut(&T, &mut Vec<u8>),
+{
+    // header roots are hashed with keccak, see the module documentation
+    if cfg!(feature = "poseidon2") {
+        return alloy_trie::root::ordered_trie_root_with_encoder(items, encode);
+    }
+    let mut trie = Trie::new();
+    for (index, item) in items.iter().enumerate() {
+        let mut value = Vec::new();
+        encode(item, &mut value);
+        // the RLP encodings of the indexes are prefix-free, so no value sits on a branch
+        trie.insert_path(Nibbles::unpack(alloy_rlp::encode(index)), value.into());
+    }
+    trie.hash()
+}
+
+/// Computes the `withdrawals_root` of a block header from the EIP-4895 withdrawals of the block,
+/// such as `alloy_eips::eip4895::Withdrawal`.
+pub fn withdrawals_root<T: Encodable>(withdrawals: &[T]) -> B256 {
+    ordered_trie_root(withdrawals)
+}
+
+/// Computes the EIP-7685 `requests_hash` of a block header from the requests of the block, each
+/// being the request type followed by the request data.
+///
+/// Requests without data are not committed to, so the hash of no request is the hash of requests
+/// carrying a type only.
+#[cfg(feature = "requests")]
+pub fn requests_hash<R: AsRef<[u8]>>(requests: &[R]) -> B256 {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for request in requests.iter().map(AsRef::as_ref) {
+        if request.len() > 1 {
+            hasher.update(Sha256::digest(request));
+        }
+    }
+    B256::from_slice(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::Bytes;
+    use alloy_trie::EMPTY_ROOT_HASH;
+    use std::vec;
+
+    #[test]
+    fn ordered_roots_match_alloy() {
+        assert_eq!(ordered_trie_root::<Bytes>(&[]), EMPTY_ROOT_HASH);
+        // the number of items crosses the one- and two-byte RLP encodings of the index, and
+        // short items are embedded in their parent node
+        for len in [1, 2, 16, 127, 128, 129, 300] {
+            let items: Vec<Bytes> = (0..len)
+                .map(|i: usize| Bytes::from(vec![i as u8; i % 40]))
+                .collect();
+            assert_eq!(
+                ordered_trie_root(&items),
+                alloy_trie::root::ordered_trie_root(&items),
+                "{len} items"
+            );
+            assert_eq!(withdrawals_root(&items), ordered_trie_root(&items));
+        }
+
+        // items stored with a custom encoding
+        let items: Vec<u64> = (0..50).collect();
+        let encode = |item: &u64, out: &mut Vec<u8>| {
+            out.push(0x02);
+            item.encode(out);
+        };
+        assert_eq!(
+            ordered_trie_root_with_encoder(&items, encode),
+            alloy_trie::root::ordered_trie_root_with_encoder(&items, encode)
+        );
+    }
+
+    #[cfg(feature = "requests")]
+    #[test]
+    fn requests_hash_skips_empty_requests() {
+        use alloy_primitives::b256;
+        use sha2::{Digest, Sha256};
+
+        let empty = b256!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(requests_hash::<Bytes>(&[]), empty);
+        assert_eq!(requests_hash(&[[0x00], [0x01], [0x02]]), empty);
+
+        let deposit = [0x00, 0xaa, 0xbb];
+        let expected = Sha256::digest(Sha256::digest(deposit));
+        assert_eq!(
+            requests_hash(&[&deposit[..], &[0x01]]),
+            B256::from_slice(&expected)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Experimental Poseidon2 hash replacing keccak-256 under the `poseidon2` feature.
+//!
+//! **Not consensus compatible.** Trie roots computed with this backend do not match Ethereum
+//! roots, and witness nodes are looked up by their Poseidon2 digest. It exists to measure how
+//! much of the guest cost is keccak rather than trie logic when targeting hash-friendly proof
+//! systems. [`verify_proof`](crate::verify_proof) decodes nodes with `alloy-trie`, which still
+//! references children by their keccak hash, and the tests comparing roots with `alloy-trie`'s
+//! `HashBuilder` only pass without this feature.
+//!
+//! The permutation is Poseidon2 over the Goldilocks field with width 12 (rate 8, capacity 4),
+//! the `x^7` S-box, 8 full and 22 partial rounds. The round constants and the internal diagonal
+//! are derived from a fixed seed rather than from the reference Grain LFSR, so the hash matches
+//! no published instance, while its cost matches a standard one.
+use alloy_primitives::B256;
+
+/// The Goldilocks prime `2^64 - 2^32 + 1`.
+const P: u64 = 0xffff_ffff_0000_0001;
+/// `2^64 mod P`.
+const EPSILON: u64 = 0xffff_ffff;
+
+const WIDTH: usize = 12;
+const RATE: usize = 8;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 22;
+/// Bytes packed in a field element, so that every packed element is below `P`.
+const BYTES_PER_ELEMENT: usize = 7;
+
+const EXTERNAL_CONSTANTS: [[u64; WIDTH]; FULL_ROUNDS] = {
+    let mut constants = [[0; WIDTH]; FULL_ROUNDS];
+    let mut seed = 0;
+    let mut round = 0;
+    while round < FULL_ROUNDS {
+        let mut i = 0;
+        while i < WIDTH {
+            constants[round][i] = next_constant(&mut seed);
+            i += 1;
+        }
+        round += 1;
+    }
+    constants
+};
+
+const INTERNAL_CONSTANTS: [u64; PARTIAL_ROUNDS] = {
+    let mut constants = [0; PARTIAL_ROUNDS];
+    // continue the sequence of the external constants
+    let mut seed = (FULL_ROUNDS * WIDTH) as u64;
+    let mut round = 0;
+    while round < PARTIAL_ROUNDS {
+        constants[round] = next_constant(&mut seed);
+        round += 1;
+    }
+    constants
+};
+
+/// The diagonal of the internal matrix, minus the identity: `M_I = diag(μ) + 1`.
+const INTERNAL_DIAGONAL: [u64; WIDTH] = {
+    let mut diagonal = [0; WIDTH];
+    let mut i = 0;
+    while i < WIDTH {
+        diagonal[i] = (1 << i) + 1;
+        i += 1;
+    }
+    diagonal
+};
+
+/// Returns the next field element of a `SplitMix64` sequence.
+const fn next_constant(seed: &mut u64) -> u64 {
+    *seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    canonical(z ^ (z >> 31))
+}
+
+/// Reduces a 128-bit value to a 64-bit representative, not necessarily below `P`.
+#[inline]
+const fn reduce(x: u128) -> u64 {
+    let (lo, hi) = (x as u64, (x >> 64) as u64);
+    let (hi_hi, hi_lo) = (hi >> 32, hi & EPSILON);
+    // 2^96 = -1 and 2^64 = EPSILON modulo P
+    let (mut t0, borrow) = lo.overflowing_sub(hi_hi);
+    if borrow {
+        t0 = t0.wrapping_sub(EPSILON);
+    }
+    let (t1, carry) = t0.overflowing_add(hi_lo * EPSILON);
+    t1.wrapping_add(EPSILON * carry as u64)
+}
+
+#[inline]
+const fn canonical(x: u64) -> u64 {
+    if x >= P { x - P } else { x }
+}
+
+#[inline]
+const fn mul(a: u64, b: u64) -> u64 {
+    reduce(a as u128 * b as u128)
+}
+
+#[inline]
+const fn sbox(x: u64) -> u64 {
+    let x2 = mul(x, x);
+    let x4 = mul(x2, x2);
+    mul(mul(x4, x2), x)
+}
+
+/// The external linear layer `circ(2 M4, M4, M4)`.
+fn external_layer(state: &mut [u64; WIDTH]) {
+    let mut mixed = [0_u128; WIDTH];
+    for (chunk, out) in state.chunks_exact(4).zip(mixed.chunks_exact_mut(4)) {
+        let [a, b, c, d] = [0, 1, 2, 3].map(|i| u128::from(chunk[i]));
+        // M4 = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]]
+        out[0] = 5 * a + 7 * b + c + 3 * d;
+        out[1] = 4 * a + 6 * b + c + d;
+        out[2] = a + 3 * b + 5 * c + 7 * d;
+        out[3] = a + b + 4 * c + 6 * d;
+    }
+    let sums: [u128; 4] = core::array::from_fn(|i| mixed.iter().skip(i).step_by(4).sum());
+    for (i, (element, mixed)) in state.iter_mut().zip(mixed).enumerate() {
+        *element = reduce(mixed + sums[i % 4]);
+    }
+}
+
+/// The internal linear layer `diag(μ) + 1`.
+fn internal_layer(state: &mut [u64; WIDTH]) {
+    let sum: u128 = state.iter().map(|&x| u128::from(x)).sum();
+    for (element, diagonal) in state.iter_mut().zip(INTERNAL_DIAGONAL) {
+        *element = reduce(u128::from(*element) * u128::from(diagonal) + sum);
+    }
+}
+
+fn full_round(state: &mut [u64; WIDTH], constants: &[u64; WIDTH]) {
+    for (element, constant) in state.iter_mut().zip(constants) {
+        *element = sbox(reduce(u128::from(*element) + u128::from(*constant)));
+    }
+    external_layer(state);
+}
+
+fn permute(state: &mut [u64; WIDTH]) {
+    external_layer(state);
+    let (first, last) = EXTERNAL_CONSTANTS.split_at(FULL_ROUNDS / 2);
+    for constants in first {
+        full_round(state, constants);
+    }
+    for constant in INTERNAL_CONSTANTS {
+        state[0] = sbox(reduce(u128::from(state[0]) + u128::from(constant)));
+        internal_layer(state);
+    }
+    for constants in last {
+        full_round(state, constants);
+    }
+}
+
+/// Computes the Poseidon2 sponge hash of `bytes`.
+///
+/// The bytes, padded with a one byte and zeros to a whole number of rate blocks, are packed
+/// into field elements of 7 little-endian bytes. The hash is the first 4 elements of the
+/// squeezed state, as little-endian 64-bit words.
+pub(crate) fn hash(bytes: &[u8]) -> B256 {
+    const BLOCK: usize = RATE * BYTES_PER_ELEMENT;
+
+    fn absorb(state: &mut [u64; WIDTH], block: &[u8]) {
+        for (element, chunk) in state.iter_mut().zip(block.chunks_exact(BYTES_PER_ELEMENT)) {
+            let mut word = [0; 8];
+            word[..BYTES_PER_ELEMENT].copy_from_slice(chunk);
+            *element = reduce(u128::from(*element) + u128::from(u64::from_le_bytes(word)));
+        }
+        permute(state);
+    }
+
+    let mut state = [0; WIDTH];
+    let mut blocks = bytes.chunks_exact(BLOCK);
+    for block in &mut blocks {
+        absorb(&mut state, block);
+    }
+    let remainder = blocks.remainder();
+    let mut last = [0; BLOCK];
+    last[..remainder.len()].copy_from_slice(remainder);
+    last[remainder.len()] = 1;
+    absorb(&mut state, &last);
+
+    let mut hash = B256::ZERO;
+    for (word, element) in hash.0.chunks_exact_mut(8).zip(state) {
+        word.copy_from_slice(&canonical(element).to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::vec;
+
+    #[test]
+    fn reduce_matches_modulo() {
+        for x in [
+            0,
+            1,
+            P as u128,
+            u128::from(u64::MAX),
+            u128::MAX,
+            u128::from(P) * 12345 + 7,
+        ] {
+            assert_eq!(u128::from(canonical(reduce(x))), x % u128::from(P), "{x}");
+        }
+        assert_eq!(canonical(mul(P - 1, P - 1)), 1);
+    }
+
+    #[test]
+    fn distinct_inputs_hash_differently() {
+        // lengths around the block size exercise padding in a full and in a fresh block
+        let mut hashes = HashSet::new();
+        for len in [1, 7, 55, 56, 57, 111, 112, 532] {
+            for byte in [0_u8, 1] {
+                assert!(hashes.insert(hash(&vec![byte; len])), "length {len}");
+            }
+        }
+        assert!(hashes.insert(hash(&[])));
+        assert_eq!(hash(b"trie"), hash(b"trie"));
+        assert_ne!(hash(b"trie"), alloy_primitives::keccak256(b"trie"));
+    }
+}
@@ -0,0 +1,268 @@
+//! Verification of Merkle proofs against a root hash without building a trie.
+use crate::witness_index::WitnessIndex;
+use alloy_primitives::{B256, Bytes};
+use alloy_rlp::Decodable;
+use alloy_trie::nodes::{RlpNode, TrieNode};
+use alloy_trie::{EMPTY_ROOT_HASH, Nibbles};
+use core::fmt;
+
+/// A proof does not show the expected value under a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// A node on the path of the key is not among the proof nodes.
+    MissingNode(B256),
+    /// A node on the path of the key is not a valid trie node.
+    InvalidNode(alloy_rlp::Error),
+    /// The proof shows a different value under the key than the expected one, where `None` is an
+    /// absent key.
+    ValueMismatch {
+        /// The key proven.
+        key: B256,
+        /// The expected value.
+        expected: Option<Bytes>,
+        /// The value shown by the proof.
+        actual: Option<Bytes>,
+    },
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingNode(digest) => write!(f, "MPT: Missing proof node ({digest})"),
+            Self::InvalidNode(err) => write!(f, "MPT: Invalid proof node: {err}"),
+            Self::ValueMismatch {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "MPT: Proof value mismatch for key {key}: expected {expected:?}, got {actual:?}"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for ProofError {}
+
+impl From<alloy_rlp::Error> for ProofError {
+    fn from(err: alloy_rlp::Error) -> Self {
+        Self::InvalidNode(err)
+    }
+}
+
+/// Verifies that the proof `nodes` show `expected_value` under the pre-hashed `key` in the trie
+/// with the given root, where `None` expects the key to be absent.
+///
+/// The nodes are looked up by their hash, so their order does not matter and unrelated nodes are
+/// ignored.
+pub fn verify_proof<'a>(
+    root: B256,
+    key: B256,
+    expected_value: Option<&[u8]>,
+    nodes: impl IntoIterator<Item = &'a Bytes>,
+) -> Result<(), ProofError> {
+    let index = WitnessIndex::from_nodes(nodes);
+    verify(&index, root, key, expected_value)
+}
+
+/// Verifies several keys against the same proof nodes, see [`verify_proof`]. Fails on the first
+/// entry that does not verify.
+pub fn verify_multiproof<'a>(
+    root: B256,
+    entries: &[(B256, Option<Bytes>)],
+    nodes: impl IntoIterator<Item = &'a Bytes>,
+) -> Result<(), ProofError> {
+    let index = WitnessIndex::from_nodes(nodes);
+    entries.iter().try_for_each(|(key, expected_value)| {
+        verify(
+            &index,
+            root,
+            *key,
+            expected_value.as_ref().map(|value| &value[..]),
+        )
+    })
+}
+
+fn verify(
+    index: &WitnessIndex,
+    root: B256,
+    key: B256,
+    expected_value: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    let actual = lookup(index, root, key)?;
+    if actual.as_ref().map(|value| &value[..]) == expected_value {
+        Ok(())
+    } else {
+        Err(ProofError::ValueMismatch {
+            key,
+            expected: expected_value.map(Bytes::copy_from_slice),
+            actual,
+        })
+    }
+}
+
+/// Returns the value under `key` following the path from the root through the proof nodes.
+fn lookup(index: &WitnessIndex, root: B256, key: B256) -> Result<Option<Bytes>, ProofError> {
+    if root == EMPTY_ROOT_HASH {
+        return Ok(None);
+    }
+    let path = Nibbles::unpack(key);
+    let mut offset = 0;
+    let mut rlp = index
+        .get(&root)
+        .ok_or(ProofError::MissingNode(root))?
+        .clone();
+    loop {
+        let child = match TrieNode::decode(&mut &rlp[..])? {
+            TrieNode::EmptyRoot => return Ok(None),
+            TrieNode::Leaf(leaf) => {
+                return Ok((path.slice(offset..) == leaf.key).then_some(leaf.value.into()));
+            }
+            TrieNode::Extension(extension) => {
+                if !path.slice(offset..).starts_with(&extension.key) {
+                    return Ok(None);
+                }
+                offset += extension.key.len();
+                extension.child
+            }
+            TrieNode::Branch(branch) => {
+                // keys have a fixed length, so the path never ends at a branch
+                let Some(nibble) = path.get(offset) else {
+                    return Ok(None);
+                };
+                let mask = branch.state_mask.get();
+                if mask & (1 << nibble) == 0 {
+                    return Ok(None);
+                }
+                offset += 1;
+                // the stack holds the children of the set bits, in nibble order
+                let position = (mask & ((1 << nibble) - 1)).count_ones() as usize;
+                branch.stack[position].clone()
+            }
+        };
+        rlp = resolve(index, &child)?;
+    }
+}
+
+/// Returns the RLP encoding of a child, inlined in its parent or referenced by its hash.
+fn resolve(index: &WitnessIndex, child: &RlpNode) -> Result<Bytes, ProofError> {
+    match child.as_hash() {
+        Some(digest) => index
+            .get(&digest)
+            .cloned()
+            .ok_or(ProofError::MissingNode(digest)),
+        None => Ok(Bytes::copy_from_slice(child)),
+    }
+}
+
+// the proofs are built by `HashBuilder`, which hashes with keccak
+#[cfg(all(test, not(feature = "poseidon2")))]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+    use alloc::vec::Vec;
+    use alloy_trie::{HashBuilder, proof::ProofRetainer};
+    use std::vec;
+
+    // Builds a trie of the given keys, with a value derived from the key, and returns the root and
+    // the proof nodes of the targets.
+    fn build(keys: &[B256], targets: &[B256]) -> (B256, Vec<Bytes>) {
+        let mut sorted = keys.to_vec();
+        sorted.sort_unstable();
+        let retainer = ProofRetainer::new(targets.iter().map(Nibbles::unpack).collect());
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for key in &sorted {
+            hash_builder.add_leaf(Nibbles::unpack(key), &value(key));
+        }
+        let root = hash_builder.root();
+        let nodes = hash_builder.take_proof_nodes().values().cloned().collect();
+        (root, nodes)
+    }
+
+    fn value(key: &B256) -> Vec<u8> {
+        key[..usize::from(key[0] % 32) + 1].to_vec()
+    }
+
+    fn keys(count: u64) -> Vec<B256> {
+        (0..count).map(|i| keccak256(i.to_be_bytes())).collect()
+    }
+
+    #[test]
+    fn verifies_present_and_absent_keys() {
+        let keys = keys(200);
+        let absent = keccak256(b"absent");
+        let (root, nodes) = build(&keys, &[keys[7], absent]);
+
+        verify_proof(root, keys[7], Some(&value(&keys[7])), &nodes).unwrap();
+        verify_proof(root, absent, None, &nodes).unwrap();
+        assert_eq!(
+            verify_proof(root, keys[7], Some(&[1, 2, 3]), &nodes),
+            Err(ProofError::ValueMismatch {
+                key: keys[7],
+                expected: Some(Bytes::from_static(&[1, 2, 3])),
+                actual: Some(value(&keys[7]).into()),
+            })
+        );
+        assert!(matches!(
+            verify_proof(root, absent, Some(&[1]), &nodes),
+            Err(ProofError::ValueMismatch { actual: None, .. })
+        ));
+    }
+
+    #[test]
+    fn missing_node() {
+        let keys = keys(200);
+        let (root, nodes) = build(&keys, &[keys[3]]);
+        let root_node = nodes.iter().find(|rlp| keccak256(rlp) == root).unwrap();
+        assert!(matches!(
+            verify_proof(root, keys[3], Some(&value(&keys[3])), [root_node]),
+            Err(ProofError::MissingNode(_))
+        ));
+        assert_eq!(
+            verify_proof(root, keys[3], None, []),
+            Err(ProofError::MissingNode(root))
+        );
+        assert_eq!(verify_proof(EMPTY_ROOT_HASH, keys[3], None, []), Ok(()));
+    }
+
+    #[test]
+    fn inlined_nodes() {
+        // keys differing in the last nibble end in leaves inlined in a branch, itself inlined in
+        // the extension at the root
+        let keys = [B256::with_last_byte(0x12), B256::with_last_byte(0x13)];
+        let retainer = ProofRetainer::new(keys.iter().map(Nibbles::unpack).collect());
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        hash_builder.add_leaf(Nibbles::unpack(keys[0]), &[1]);
+        hash_builder.add_leaf(Nibbles::unpack(keys[1]), &[2]);
+        let root = hash_builder.root();
+        let nodes: Vec<Bytes> = hash_builder.take_proof_nodes().values().cloned().collect();
+
+        let entries = vec![
+            (keys[0], Some(Bytes::from_static(&[1]))),
+            (keys[1], Some(Bytes::from_static(&[2]))),
+            (B256::with_last_byte(0x14), None),
+            (B256::ZERO, None),
+        ];
+        // the root node alone proves all keys
+        let root_node = nodes.iter().filter(|rlp| keccak256(rlp) == root);
+        verify_multiproof(root, &entries, root_node).unwrap();
+    }
+
+    #[test]
+    fn multiproof() {
+        let keys = keys(500);
+        let targets: Vec<B256> = keys.iter().step_by(50).copied().collect();
+        let (root, nodes) = build(&keys, &targets);
+        let mut entries: Vec<_> = targets
+            .iter()
+            .map(|key| (*key, Some(Bytes::from(value(key)))))
+            .collect();
+        verify_multiproof(root, &entries, &nodes).unwrap();
+
+        entries[4].1 = None;
+        assert!(matches!(
+            verify_multiproof(root, &entries, &nodes),
+            Err(ProofError::ValueMismatch { key, .. }) if key == targets[4]
+        ));
+    }
+}
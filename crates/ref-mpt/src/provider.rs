@@ -0,0 +1,166 @@
+//! Fetching trie nodes missing from a witness.
+use crate::keccak::keccak256;
+use crate::trie::{B256Map, Trie, UnresolvedNode};
+use crate::witness_index::WitnessIndex;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloy_primitives::{B256, Bytes};
+
+/// A source of RLP encoded trie nodes by their hash, such as a database or a remote node, which
+/// resolves the nodes a witness does not include.
+pub trait NodeProvider {
+    /// Returns the RLP encoding of the node with the given hash, if known.
+    fn node(&self, hash: B256) -> Option<Bytes>;
+}
+
+impl NodeProvider for B256Map<Bytes> {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        self.get(&hash).cloned()
+    }
+}
+
+impl NodeProvider for WitnessIndex {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        self.get(&hash).cloned()
+    }
+}
+
+impl<P: NodeProvider + ?Sized> NodeProvider for &P {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        (**self).node(hash)
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<P: NodeProvider + ?Sized> NodeProvider for Arc<P> {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        (**self).node(hash)
+    }
+}
+
+impl Trie {
+    /// Runs `op` on the trie, and whenever it fails on an unresolved node, fetches the node from
+    /// the provider, reveals it and runs `op` again. `op` must leave the trie unchanged when it
+    /// fails, like [`Trie::try_insert`] and [`Trie::try_remove`] do, or be harmless to run again.
+    ///
+    /// Fails with the unresolved node if the provider does not know it, or returns a node which
+    /// does not match its hash.
+    pub fn resolving<P, T>(
+        &mut self,
+        provider: &P,
+        mut op: impl FnMut(&mut Self) -> Result<T, UnresolvedNode>,
+    ) -> Result<T, UnresolvedNode>
+    where
+        P: NodeProvider + ?Sized,
+    {
+        loop {
+            let unresolved = match op(self) {
+                Err(unresolved) => unresolved,
+                result => return result,
+            };
+            let rlp = provider
+                .node(unresolved.digest)
+                .filter(|rlp| keccak256(rlp) == unresolved.digest)
+                .ok_or(unresolved)?;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(target: "ref_mpt", digest = %unresolved.digest, "fetched node");
+            let mut nodes = B256Map::default();
+            nodes.insert(unresolved.digest, rlp);
+            self.reveal(&nodes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_trie::{HashBuilder, Nibbles, proof::ProofRetainer};
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    // Counts the nodes fetched from the underlying map.
+    struct Counting<'a> {
+        nodes: &'a B256Map<Bytes>,
+        fetched: Cell<usize>,
+    }
+
+    impl NodeProvider for Counting<'_> {
+        fn node(&self, hash: B256) -> Option<Bytes> {
+            self.fetched.set(self.fetched.get() + 1);
+            self.nodes.node(hash)
+        }
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn resolves_missing_nodes() {
+        let keys: Vec<B256> = (0_u64..100).map(|i| keccak256(i.to_be_bytes())).collect();
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        let targets = sorted.iter().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        for key in &sorted {
+            hash_builder.add_leaf(Nibbles::unpack(key), &[0x42; 40]);
+        }
+        let root = hash_builder.root();
+        let nodes: B256Map<Bytes> = hash_builder
+            .take_proof_nodes()
+            .values()
+            .map(|rlp| (keccak256(rlp), rlp.clone()))
+            .collect();
+
+        // start from the root hash only
+        let mut trie = Trie::reveal_from_rlp(root, &B256Map::default());
+        let provider = Counting {
+            nodes: &nodes,
+            fetched: Cell::new(0),
+        };
+        let value = trie
+            .resolving(&provider, |trie| {
+                trie.try_get(keys[0]).map(|value| value.cloned())
+            })
+            .unwrap();
+        assert_eq!(value.unwrap()[..], [0x42; 40]);
+        let fetched = provider.fetched.get();
+        assert!(fetched > 1);
+
+        // revealed nodes are not fetched again
+        trie.resolving(&provider, |trie| trie.try_get(keys[0]).map(|_| ()))
+            .unwrap();
+        assert_eq!(provider.fetched.get(), fetched);
+
+        trie.resolving(&provider, |trie| trie.try_remove(keys[1]))
+            .unwrap();
+        trie.resolving(&provider, |trie| {
+            trie.try_insert(keys[2], Bytes::from_static(&[1]))
+        })
+        .unwrap();
+        assert_eq!(trie.try_get(keys[2]).unwrap().unwrap()[..], [1]);
+    }
+
+    #[test]
+    fn unknown_or_invalid_nodes() {
+        let mut full = Trie::new();
+        for i in 0_u64..20 {
+            full.insert(keccak256(i.to_be_bytes()), Bytes::from_static(&[0x42; 40]));
+        }
+        let root = full.hash();
+        let key = keccak256(0_u64.to_be_bytes());
+
+        let mut trie = Trie::reveal_from_rlp(root, &B256Map::default());
+        let empty = B256Map::<Bytes>::default();
+        assert_eq!(
+            trie.resolving(&empty, |trie| trie.try_get(key).map(|_| ())),
+            Err(UnresolvedNode { digest: root })
+        );
+
+        // a node which does not hash to the requested digest is rejected
+        let mut wrong = B256Map::default();
+        wrong.insert(root, Bytes::from_static(&[0xc0]));
+        assert_eq!(
+            trie.resolving(&wrong, |trie| trie.try_get(key).map(|_| ())),
+            Err(UnresolvedNode { digest: root })
+        );
+    }
+}
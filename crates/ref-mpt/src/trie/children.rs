@@ -2,11 +2,12 @@
 //! It stores an additional bit flag indicating which child is not empty. Only for an optimization purpose
 use alloc::boxed::Box;
 use crate::trie::TrieNode;
+use alloy_trie::TrieMask;
 use core::slice::{Iter, IterMut};
 
 #[derive(Debug, Clone, Default)]
 pub(super) struct BranchNodeChildrenArray {
-    children: [Option<Box<TrieNode>>; 16],
+    children: Box<[Option<Box<TrieNode>>; 16]>,
     flags: u16,
 }
 
@@ -14,24 +15,34 @@ impl BranchNodeChildrenArray {
     #[inline]
     pub(super) fn new() -> Self {
         Self {
-            children: [const { None }; 16],
+            children: Box::new([const { None }; 16]),
             flags: 0,
         }
     }
 
+    // Indices are nibbles, so they are always below 16. The `safe` feature (and Miri) use checked
+    // indexing instead, for audits that reject unconditional unchecked accesses.
     #[inline]
     pub(super) fn get(&self, idx: usize) -> &Option<Box<TrieNode>> {
-        unsafe { self.children.get_unchecked(idx) }
+        #[cfg(any(feature = "safe", miri))]
+        {
+            &self.children[idx]
+        }
+        #[cfg(not(any(feature = "safe", miri)))]
+        unsafe {
+            self.children.get_unchecked(idx)
+        }
     }
 
     #[inline]
     pub(super) fn get_mut(&mut self, idx: usize) -> Option<&mut Box<TrieNode>> {
+        #[cfg(any(feature = "safe", miri))]
+        {
+            self.children[idx].as_mut()
+        }
+        #[cfg(not(any(feature = "safe", miri)))]
         unsafe {
-            if let Some(child) = self.children.get_unchecked_mut(idx) {
-                Some(child)
-            } else {
-                None
-            }
+            self.children.get_unchecked_mut(idx).as_mut()
         }
     }
 
@@ -47,6 +58,12 @@ impl BranchNodeChildrenArray {
         self.flags &= !(1 << idx);
     }
 
+    /// Returns the occupancy mask, bit `i` being set if child `i` is not empty.
+    #[inline]
+    pub(super) const fn mask(&self) -> TrieMask {
+        TrieMask::new(self.flags)
+    }
+
     #[inline]
     pub(super) fn is_empty(&self) -> bool {
         self.flags == 0
@@ -67,6 +84,20 @@ impl BranchNodeChildrenArray {
         }
     }
 
+    /// Panics if `flags` disagrees with the occupied children or if [`Self::one_child_left`]
+    /// disagrees with a naive scan of them.
+    #[cfg(any(test, kani, feature = "fuzzing"))]
+    pub(super) fn check_flags(&mut self) {
+        let mut occupancy = 0_u16;
+        let mut single_child = None;
+        for (idx, _) in self.children.iter().enumerate().filter(|(_, child)| child.is_some()) {
+            single_child = (occupancy == 0).then_some(idx);
+            occupancy |= 1 << idx;
+        }
+        assert_eq!(self.flags, occupancy, "MPT: Inconsistent branch children flags");
+        assert_eq!(self.one_child_left().map(|(idx, _)| idx), single_child);
+    }
+
     #[inline]
     pub(super) fn iter_mut(&mut self) -> IterMut<'_, Option<Box<TrieNode>>> {
         self.children.iter_mut()
@@ -77,3 +108,94 @@ impl BranchNodeChildrenArray {
         self.children.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::nodes::LeafNode;
+    use alloy_primitives::Bytes;
+    use alloy_trie::Nibbles;
+
+    fn leaf() -> Box<TrieNode> {
+        Box::new(TrieNode::Leaf(LeafNode {
+            path: Nibbles::default(),
+            value: Bytes::from([1_u8]),
+            hash: None,
+        }))
+    }
+
+    #[test]
+    fn flags_follow_inserts_and_removals() {
+        let mut children = BranchNodeChildrenArray::new();
+        children.check_flags();
+        assert!(children.is_empty());
+
+        children.insert(0, leaf());
+        children.insert(15, leaf());
+        children.check_flags();
+        assert!(children.one_child_left().is_none());
+
+        children.remove(0);
+        children.check_flags();
+        assert_eq!(children.one_child_left().map(|(idx, _)| idx), Some(15));
+
+        // Removing an empty slot leaves the flags untouched.
+        children.remove(3);
+        children.check_flags();
+
+        children.remove(15);
+        children.check_flags();
+        assert!(children.is_empty());
+        assert!(children.one_child_left().is_none());
+    }
+}
+
+#[cfg(kani)]
+mod verification {
+    use super::*;
+    use crate::trie::nodes::LeafNode;
+    use alloy_primitives::Bytes;
+    use alloy_trie::Nibbles;
+
+    fn leaf() -> Box<TrieNode> {
+        Box::new(TrieNode::Leaf(LeafNode {
+            path: Nibbles::default(),
+            value: Bytes::new(),
+            hash: None,
+        }))
+    }
+
+    fn any_index() -> usize {
+        kani::any_where(|idx: &usize| *idx < 16)
+    }
+
+    /// The flags match the occupied children after any short sequence of inserts and removals.
+    #[kani::proof]
+    #[kani::unwind(17)]
+    fn flags_match_occupancy() {
+        let mut children = BranchNodeChildrenArray::new();
+        for _ in 0..3 {
+            let idx = any_index();
+            if kani::any() {
+                children.insert(idx, leaf());
+            } else {
+                children.remove(idx);
+            }
+        }
+        children.check_flags();
+        assert_eq!(children.is_empty(), children.iter().all(Option::is_none));
+    }
+
+    /// The unchecked accesses stay in bounds and see exactly the inserted child.
+    #[kani::proof]
+    #[kani::unwind(17)]
+    fn unchecked_access_in_bounds() {
+        let mut children = BranchNodeChildrenArray::new();
+        let idx = any_index();
+        children.insert(idx, leaf());
+
+        let other = any_index();
+        assert_eq!(children.get(other).is_some(), other == idx);
+        assert_eq!(children.get_mut(other).is_some(), other == idx);
+    }
+}
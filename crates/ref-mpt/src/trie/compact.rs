@@ -0,0 +1,137 @@
+//! Collapsing clean subtrees back into digest nodes, to bound the memory of a long-lived trie.
+use super::nodes::{DigestNode, TrieNode};
+use crate::trie::Trie;
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use alloy_trie::Nibbles;
+
+impl TrieNode {
+    // Collapses the clean subtrees of the node starting `depth` nibbles or more below the root,
+    // the node itself starting `offset` nibbles below the root.
+    fn compact(&mut self, offset: usize, depth: usize, is_root: bool) {
+        if let Branch(branch) = self {
+            let child_offset = offset + branch.path.len() + 1;
+            for child in branch.children.iter_mut().flatten() {
+                child.compact(child_offset, depth, false);
+            }
+        }
+        if offset >= depth {
+            if let Some(digest) = self.clean_digest(is_root) {
+                *self = Digest(digest);
+            }
+        }
+    }
+
+    // Returns the digest node standing for the node, if its hash is cached and its parent
+    // references it by its hash rather than inlining it.
+    fn clean_digest(&mut self, is_root: bool) -> Option<DigestNode> {
+        let (hash, encoded_len) = match self {
+            Leaf(leaf) => (leaf.hash?, leaf.with_encoding(<[u8]>::len)),
+            Branch(branch) => (branch.hash?, branch.encode().len()),
+            // a digest holds no subtree
+            Digest(_) => return None,
+        };
+        (is_root || encoded_len >= 32).then_some(DigestNode {
+            path: Nibbles::default(),
+            value: hash,
+            hash: Some(hash),
+        })
+    }
+}
+
+impl Trie {
+    /// Replaces the subtrees whose hash is cached, i.e. which did not change since the trie was
+    /// last hashed, with digest nodes, freeing their branches and leaves. The nodes starting less
+    /// than `depth` nibbles below the root are kept, and the root hash is unchanged.
+    ///
+    /// A compacted subtree needs to be revealed again before it is accessed, e.g. from the next
+    /// witness with [`Trie::reveal`].
+    pub fn compact(&mut self, depth: usize) {
+        if let Some(root) = self.root.as_mut() {
+            root.compact(0, depth, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keccak256;
+    use crate::trie::{B256Map, Trie};
+    use alloy_primitives::{B256, Bytes};
+    use std::vec::Vec;
+
+    fn keys(count: u64) -> Vec<B256> {
+        (0..count).map(|i| keccak256(i.to_be_bytes())).collect()
+    }
+
+    #[test]
+    fn compact_keeps_root_hash() {
+        let keys = keys(300);
+        let mut trie = Trie::new();
+        for key in &keys {
+            trie.insert(*key, Bytes::copy_from_slice(&key[..20]));
+        }
+        let root = trie.hash();
+        let nodes: B256Map<Bytes> = keys
+            .iter()
+            .flat_map(|key| trie.proof(*key).unwrap())
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+
+        for depth in [0, 1, 2, 64] {
+            let mut compacted = trie.clone();
+            compacted.compact(depth);
+            assert_eq!(compacted.hash(), root);
+            assert_eq!(compacted.try_get(keys[0]).is_err(), depth < 64);
+
+            // the revealed trie is equivalent to the original one
+            compacted.reveal(&nodes);
+            for key in &keys {
+                assert_eq!(compacted.get(*key), trie.get(*key));
+            }
+            compacted.insert(B256::ZERO, Bytes::from_static(&[1]));
+            let mut expected = trie.clone();
+            expected.insert(B256::ZERO, Bytes::from_static(&[1]));
+            assert_eq!(compacted.hash(), expected.hash());
+        }
+    }
+
+    #[test]
+    fn modified_subtrees_are_kept() {
+        let keys = keys(100);
+        let mut trie = Trie::new();
+        for key in &keys {
+            trie.insert(*key, Bytes::from_static(&[7; 40]));
+        }
+        trie.hash();
+        // the path of the new key is not hashed yet
+        let key = keccak256(b"new");
+        trie.insert(key, Bytes::from_static(&[8]));
+        trie.compact(1);
+        assert_eq!(trie.get(key), Some(&Bytes::from_static(&[8])));
+        assert!(trie.try_get(keys[0]).is_err());
+
+        let mut expected = Trie::new();
+        for key in &keys {
+            expected.insert(*key, Bytes::from_static(&[7; 40]));
+        }
+        expected.insert(key, Bytes::from_static(&[8]));
+        assert_eq!(trie.hash(), expected.hash());
+    }
+
+    #[test]
+    fn inlined_nodes_are_kept() {
+        // keys differing in the last nibble end in leaves inlined in their branch
+        let mut trie = Trie::new();
+        trie.insert(B256::with_last_byte(0x12), Bytes::from_static(&[1]));
+        trie.insert(B256::with_last_byte(0x13), Bytes::from_static(&[2]));
+        let root = trie.hash();
+        trie.compact(1);
+        assert_eq!(
+            trie.get(B256::with_last_byte(0x12)),
+            Some(&Bytes::from_static(&[1]))
+        );
+        assert_eq!(trie.hash(), root);
+        trie.compact(0);
+        assert_eq!(trie.hash(), root);
+    }
+}
@@ -37,7 +37,8 @@ impl Display for Trie {
                 Digest(digest) => write!(
                     f,
                     "Digest {{ path: {:?}, digest: {:?} }}",
-                    digest.path, digest.value
+                    digest.path.to_vec(),
+                    digest.value
                 ),
             }
         }
@@ -45,3 +46,144 @@ impl Display for Trie {
         fmt_node(f, &self.root.as_ref().unwrap(), 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    //! Golden tests pinning the printed structure of small tries, so that wrong path splits or
+    //! orphaned nodes show up even when the root hash still matches.
+    use crate::trie::{B256Map, Trie};
+    use alloy_primitives::{Bytes, B256, hex, keccak256};
+    use alloy_trie::Nibbles;
+    use std::format;
+    use std::vec::Vec;
+
+    fn reveal(root_rlp: Vec<u8>) -> Trie {
+        let root_hash = keccak256(&root_rlp);
+        let mut rlp_map = B256Map::default();
+        rlp_map.insert(root_hash, Bytes::from(root_rlp));
+        Trie::reveal_from_rlp(root_hash, &rlp_map)
+    }
+
+    fn assert_display(trie: &Trie, expected: &[&str]) {
+        assert_eq!(format!("{trie}"), expected.join("\n"));
+    }
+
+    #[test]
+    fn empty_trie() {
+        assert_display(&Trie::new(), &["Trie { EMPTY }"]);
+    }
+
+    #[test]
+    fn inserted_paths() {
+        let mut trie = Trie::new();
+        trie.insert_path(Nibbles::from_nibbles([1_u8, 2, 3]), Bytes::from([1_u8]));
+        trie.insert_path(Nibbles::from_nibbles([1_u8, 2, 4]), Bytes::from([2_u8]));
+        trie.insert_path(Nibbles::from_nibbles([5_u8, 0, 0]), Bytes::from([3_u8]));
+        assert_display(
+            &trie,
+            &[
+                "Branch []",
+                "    None",
+                "    Branch [2]",
+                "        None",
+                "        None",
+                "        None",
+                "        Leaf { path: [], value: 0x01 }",
+                "        Leaf { path: [], value: 0x02 }",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "        None",
+                "    None",
+                "    None",
+                "    None",
+                "    Leaf { path: [0, 0], value: 0x03 }",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+            ],
+        );
+
+        // Removing a leaf collapses the branch below nibble 1 into its remaining leaf.
+        trie.remove_path(Nibbles::from_nibbles([1_u8, 2, 4]));
+        assert_display(
+            &trie,
+            &[
+                "Branch []",
+                "    None",
+                "    Leaf { path: [2, 3], value: 0x01 }",
+                "    None",
+                "    None",
+                "    None",
+                "    Leaf { path: [0, 0], value: 0x03 }",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+            ],
+        );
+    }
+
+    #[test]
+    fn revealed_extension_to_digest() {
+        // Extension [1] pointing to a hashed node.
+        let mut rlp = hex!("e2 11 a0").to_vec();
+        rlp.extend_from_slice(&[0x11; 32]);
+        assert_display(
+            &reveal(rlp),
+            &["Digest { path: [1], digest: 0x1111111111111111111111111111111111111111111111111111111111111111 }"],
+        );
+    }
+
+    #[test]
+    fn revealed_branch_of_digests() {
+        // Branch with hashed children at nibbles 3 and 7.
+        let mut rlp = hex!("f8 51 80 80 80 a0").to_vec();
+        rlp.extend_from_slice(B256::repeat_byte(0x33).as_slice());
+        rlp.extend_from_slice(&hex!("80 80 80 a0"));
+        rlp.extend_from_slice(B256::repeat_byte(0x77).as_slice());
+        rlp.extend_from_slice(&[0x80; 9]);
+        assert_display(
+            &reveal(rlp),
+            &[
+                "Branch []",
+                "    None",
+                "    None",
+                "    None",
+                "    Digest { path: [], digest: 0x3333333333333333333333333333333333333333333333333333333333333333 }",
+                "    None",
+                "    None",
+                "    None",
+                "    Digest { path: [], digest: 0x7777777777777777777777777777777777777777777777777777777777777777 }",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+                "    None",
+            ],
+        );
+    }
+}
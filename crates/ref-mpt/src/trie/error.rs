@@ -0,0 +1,38 @@
+//! Errors of operations on a partially revealed trie, and of revealing it.
+use alloy_primitives::B256;
+use core::fmt;
+
+/// An operation needed a node which is only known by its digest.
+///
+/// The trie is left unchanged when an operation fails with this error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnresolvedNode {
+    /// Hash of the unresolved node.
+    pub digest: B256,
+}
+
+impl fmt::Display for UnresolvedNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MPT: Unresolved node access ({})", self.digest)
+    }
+}
+
+impl core::error::Error for UnresolvedNode {}
+
+/// A node found under its digest is not a valid RLP encoded trie node.
+///
+/// Nodes are looked up by the hash of their encoding, so this only happens with a map which
+/// does not key the nodes by their hash, or with junk hashing to a referenced digest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidNode {
+    /// Hash under which the invalid node was found.
+    pub digest: B256,
+}
+
+impl fmt::Display for InvalidNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MPT: Failed to decode trie node ({})", self.digest)
+    }
+}
+
+impl core::error::Error for InvalidNode {}
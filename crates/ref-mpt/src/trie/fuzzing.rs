@@ -0,0 +1,29 @@
+//! Node-level entry points for the fuzz targets. Not part of the stable API.
+use alloc::vec::Vec;
+use super::nodes::TrieNode;
+use super::Trie;
+
+/// Decodes a single RLP encoded trie node and returns its canonical re-encoding.
+///
+/// Returns `Ok(None)` for the empty node. Malformed input must result in an error, never in a
+/// panic.
+pub fn decode_node(mut rlp: &[u8]) -> Result<Option<Vec<u8>>, alloy_rlp::Error> {
+    Ok(TrieNode::decode(&mut rlp)?.map(|mut node| node.encode()))
+}
+
+/// Checks the children flags of every resolved branch in the trie, see
+/// `BranchNodeChildrenArray::check_flags`.
+pub fn check_branch_flags(trie: &mut Trie) {
+    fn check(node: &mut TrieNode) {
+        if let TrieNode::Branch(branch) = node {
+            branch.children.check_flags();
+            for child in branch.children.iter_mut().flatten() {
+                check(child);
+            }
+        }
+    }
+
+    if let Some(root) = trie.root.as_mut() {
+        check(root);
+    }
+}
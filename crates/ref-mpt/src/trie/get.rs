@@ -1,57 +1,321 @@
 //! Implementation of getting an element from the MPT trie according to the element's path value.
+use super::UnresolvedNode;
 use super::nodes::{BranchNode, DigestNode, LeafNode, TrieNode};
+use crate::trie::Trie;
 use crate::trie::TrieNode::{Branch, Digest, Leaf};
-use alloy_primitives::Bytes;
-use alloy_trie::Nibbles;
+use alloc::vec;
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes};
+use alloy_trie::{Nibbles, TrieMask};
 
 impl LeafNode {
-    fn get(&self, path: Nibbles) -> Option<&Bytes> {
+    fn get(&self, path: Nibbles) -> Result<Option<&Bytes>, UnresolvedNode> {
         if self.path == path {
-            Some(&self.value)
+            Ok(Some(&self.value))
         } else {
-            None
+            Ok(None)
         }
     }
 }
 
 impl BranchNode {
-    fn get(&self, path: Nibbles) -> Option<&Bytes> {
+    fn get(&self, path: Nibbles) -> Result<Option<&Bytes>, UnresolvedNode> {
         // It is only possible in case when the `self.path` is a prefix of `path`,
         // otherwise return None.
         let common_prefix_len = self.path.common_prefix_length(&path);
         if common_prefix_len == self.path.len() {
             if path.len() == common_prefix_len {
-                return None;
+                return Ok(None);
             }
             if let Some(child) = self.children.get(path[common_prefix_len] as usize) {
                 child.get(path.slice(common_prefix_len + 1..))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     }
 }
 
 impl DigestNode {
-    fn get(&self, path: Nibbles) -> Option<&Bytes> {
+    fn get(&self, path: Nibbles) -> Result<Option<&Bytes>, UnresolvedNode> {
         // Disallow access to the digest node child, but allow when accessing a path which is
         // a prefix of the digest node path.
         if path.common_prefix_length(&self.path) < self.path.len() {
-            return None;
+            Ok(None)
         } else {
-            panic!("MPT: Unresolved node access")
+            Err(UnresolvedNode { digest: self.value })
         }
     }
 }
 
 impl TrieNode {
-    pub(super) fn get(&self, path: Nibbles) -> Option<&Bytes> {
+    // Pushes the masks of the branches along `path`, `prefix` being the path of the node.
+    fn branch_masks(
+        &self,
+        mut prefix: Nibbles,
+        path: Nibbles,
+        masks: &mut Vec<(Nibbles, TrieMask)>,
+    ) -> Result<(), UnresolvedNode> {
+        match self {
+            Leaf(_) => Ok(()),
+            Branch(branch) => {
+                if !path.starts_with(&branch.path) {
+                    return Ok(());
+                }
+                prefix.extend_from_slice(&branch.path);
+                masks.push((prefix.clone(), branch.children.mask()));
+                let path = path.slice(branch.path.len()..);
+                if path.is_empty() {
+                    return Ok(());
+                }
+                match branch.children.get(path[0] as usize) {
+                    Some(child) => {
+                        prefix.push(path[0]);
+                        child.branch_masks(prefix, path.slice(1..), masks)
+                    }
+                    None => Ok(()),
+                }
+            }
+            Digest(digest) => digest.get(path).map(|_| ()),
+        }
+    }
+
+    pub(super) fn get(&self, path: Nibbles) -> Result<Option<&Bytes>, UnresolvedNode> {
         match self {
             Leaf(leaf) => leaf.get(path),
             Branch(branch) => branch.get(path),
             Digest(digest) => digest.get(path),
         }
     }
+
+    // Sets the values of the sorted `paths` below the node, whose path starts at nibble `depth`
+    // of each path, at their index in `values`. The paths sharing a branch are walked together.
+    fn get_many<'a>(
+        &'a self,
+        depth: usize,
+        paths: &[(usize, Nibbles)],
+        values: &mut [Option<&'a Bytes>],
+    ) -> Result<(), UnresolvedNode> {
+        match self {
+            Leaf(leaf) => {
+                for (index, path) in paths {
+                    if path[depth..] == leaf.path[..] {
+                        values[*index] = Some(&leaf.value);
+                    }
+                }
+                Ok(())
+            }
+            Branch(branch) => {
+                let child_depth = depth + branch.path.len();
+                // the paths going to the same child are adjacent
+                let groups = paths.chunk_by(|(_, a), (_, b)| {
+                    a.get(depth..=child_depth) == b.get(depth..=child_depth)
+                });
+                for group in groups {
+                    let (_, path) = &group[0];
+                    if !path[depth..].starts_with(&branch.path) || path.len() == child_depth {
+                        continue;
+                    }
+                    if let Some(child) = branch.children.get(path[child_depth] as usize) {
+                        child.get_many(child_depth + 1, group, values)?;
+                    }
+                }
+                Ok(())
+            }
+            Digest(digest) => {
+                if paths
+                    .iter()
+                    .any(|(_, path)| path[depth..].starts_with(&digest.path))
+                {
+                    return Err(UnresolvedNode {
+                        digest: digest.value,
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The result of looking up a key in a partially revealed trie, see [`Trie::lookup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup<'a> {
+    /// The key is in the trie, with this value.
+    Present(&'a Bytes),
+    /// The key is provably not in the trie.
+    Absent,
+    /// The key lies below the unresolved node with this hash, so the revealed nodes prove
+    /// neither its membership nor its absence.
+    Unknown(B256),
+}
+
+impl Trie {
+    /// Looks up a pre-hashed 32-byte `key`, telling a provably absent key from a key lying below
+    /// an unresolved node, which [`Trie::get`] and [`Trie::try_get`] do not tell apart from a
+    /// value without matching on the error.
+    pub fn lookup(&self, key: B256) -> Lookup<'_> {
+        match self.try_get(key) {
+            Ok(Some(value)) => Lookup::Present(value),
+            Ok(None) => Lookup::Absent,
+            Err(err) => Lookup::Unknown(err.digest),
+        }
+    }
+
+    /// Gets the values associated with the pre-hashed 32-byte `keys`, in the order of `keys`,
+    /// failing if a key lies below an unresolved node.
+    ///
+    /// The keys are walked down together, so the nodes on the common prefix of several keys are
+    /// visited once, e.g. for the slots of a contract read in bulk.
+    pub fn try_get_many(&self, keys: &[B256]) -> Result<Vec<Option<&Bytes>>, UnresolvedNode> {
+        let mut values = vec![None; keys.len()];
+        if let Some(root) = self.root.as_ref() {
+            let mut paths: Vec<_> = keys.iter().map(Nibbles::unpack).enumerate().collect();
+            paths.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
+            root.get_many(0, &paths, &mut values)?;
+        }
+        Ok(values)
+    }
+
+    /// Returns the child occupancy masks of the branches along the path of a pre-hashed 32-byte
+    /// `key`, from the root down, each with the path of the branch. The path of a branch held by
+    /// an extension node includes the extension's path.
+    ///
+    /// Fails if the key lies below an unresolved node.
+    pub fn branch_masks(&self, key: B256) -> Result<Vec<(Nibbles, TrieMask)>, UnresolvedNode> {
+        let mut masks = Vec::new();
+        if let Some(root) = self.root.as_ref() {
+            root.branch_masks(Nibbles::default(), Nibbles::unpack(key), &mut masks)?;
+        }
+        Ok(masks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lookup;
+    use crate::trie::{B256Map, Trie};
+    use alloy_primitives::{B256, Bytes};
+    use alloy_trie::{Nibbles, TrieMask};
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn branch_masks_along_path() {
+        let key = |bytes: [u8; 2]| {
+            let mut key = B256::ZERO;
+            key[..2].copy_from_slice(&bytes);
+            key
+        };
+        let mut trie = Trie::new();
+        assert_eq!(trie.branch_masks(B256::ZERO), Ok(vec![]));
+        // a root branch with children 1 and 2, the latter an extension to a branch at 0x2a0
+        for bytes in [[0x10, 0], [0x2a, 0x01], [0x2a, 0x02], [0x2a, 0x0f]] {
+            trie.insert(key(bytes), Bytes::from_static(&[1; 40]));
+        }
+        let root_mask = TrieMask::new(0b110);
+        assert_eq!(
+            trie.branch_masks(key([0x2a, 0x0f])),
+            Ok(vec![
+                (Nibbles::default(), root_mask),
+                (
+                    Nibbles::from_nibbles([2, 0xa, 0]),
+                    TrieMask::new(0b1000_0000_0000_0110)
+                ),
+            ])
+        );
+        // the path diverges from the extension
+        assert_eq!(
+            trie.branch_masks(key([0x2b, 0])),
+            Ok(vec![(Nibbles::default(), root_mask)])
+        );
+        assert_eq!(
+            trie.branch_masks(key([0x10, 0])),
+            Ok(vec![(Nibbles::default(), root_mask)])
+        );
+
+        // the branch below the extension is unresolved
+        let root = trie.hash();
+        let nodes: B256Map<Bytes> = trie
+            .proof(key([0x10, 0]))
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (crate::keccak256(&rlp), rlp))
+            .collect();
+        let revealed = Trie::reveal_from_rlp(root, &nodes);
+        assert_eq!(
+            revealed.branch_masks(key([0x10, 0])),
+            Ok(vec![(Nibbles::default(), root_mask)])
+        );
+        assert!(revealed.branch_masks(key([0x2a, 0x0f])).is_err());
+    }
+
+    #[test]
+    fn lookup_tells_absent_from_unknown() {
+        let mut trie = Trie::new();
+        assert_eq!(trie.lookup(B256::ZERO), Lookup::Absent);
+        let value = Bytes::from_static(&[1; 40]);
+        for byte in [0x10, 0x20, 0x21] {
+            trie.insert(B256::repeat_byte(byte), value.clone());
+        }
+        assert_eq!(
+            trie.lookup(B256::repeat_byte(0x10)),
+            Lookup::Present(&value)
+        );
+
+        // only the path to the first key is revealed, the branch at 0x2 is a digest
+        let root = trie.hash();
+        let nodes: B256Map<Bytes> = trie
+            .proof(B256::repeat_byte(0x10))
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (crate::keccak256(&rlp), rlp))
+            .collect();
+        let revealed = Trie::reveal_from_rlp(root, &nodes);
+        assert_eq!(
+            revealed.lookup(B256::repeat_byte(0x10)),
+            Lookup::Present(&value)
+        );
+        assert_eq!(revealed.lookup(B256::repeat_byte(0x11)), Lookup::Absent);
+        assert_eq!(revealed.lookup(B256::repeat_byte(0x30)), Lookup::Absent);
+        assert!(matches!(
+            revealed.lookup(B256::repeat_byte(0x21)),
+            Lookup::Unknown(digest) if !nodes.contains_key(&digest)
+        ));
+    }
+
+    #[test]
+    fn get_many_matches_get() {
+        let mut trie = Trie::new();
+        let keys: Vec<B256> = (0..200_u32)
+            .map(|i| crate::keccak256(i.to_be_bytes()))
+            .collect();
+        for (i, key) in keys.iter().enumerate().step_by(2) {
+            trie.insert(*key, Bytes::from(vec![i as u8; i % 40 + 1]));
+        }
+        // unsorted keys with duplicates, present and absent
+        let mut queried = keys.clone();
+        queried.extend_from_slice(&keys[..10]);
+        queried.reverse();
+        let expected: Vec<_> = queried.iter().map(|key| trie.get(*key)).collect();
+        assert_eq!(trie.try_get_many(&queried), Ok(expected));
+        assert_eq!(Trie::new().try_get_many(&queried[..3]), Ok(vec![None; 3]));
+
+        // a key below an unresolved node fails the whole batch
+        let root = trie.hash();
+        let nodes: B256Map<Bytes> = trie
+            .proof(keys[0])
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (crate::keccak256(&rlp), rlp))
+            .collect();
+        let revealed = Trie::reveal_from_rlp(root, &nodes);
+        assert_eq!(
+            revealed.try_get_many(&keys[..1]),
+            Ok(vec![trie.get(keys[0])])
+        );
+        let unresolved = revealed.try_get_many(&keys).unwrap_err();
+        assert!(!nodes.contains_key(&unresolved.digest));
+    }
 }
@@ -1,12 +1,19 @@
 //! Hashing element implementation for different node's types of MPT.
 use alloc::vec::Vec;
 use super::nodes::{BranchNode, DigestNode, LeafNode, TrieNode};
+use crate::trie::Trie;
 use crate::trie::TrieNode::{Branch, Digest, Leaf};
 use crate::trie::rlp::encode_list_header;
-use alloy_primitives::private::alloy_rlp::Encodable;
-use alloy_primitives::{B256, keccak256};
+use alloy_primitives::private::alloy_rlp::{BufMut, Encodable, Header};
+use crate::keccak256;
+use alloy_primitives::B256;
+use alloy_trie::EMPTY_ROOT_HASH;
 use alloy_trie::nodes::encode_path_leaf;
 
+/// Size of the stack buffer nodes are encoded into when hashed. It fits every digest node and any
+/// leaf holding an account, the largest values of the state trie.
+const STACK_ENCODING_LEN: usize = 160;
+
 impl TrieNode {
     pub(super) fn hash(&mut self) -> B256 {
         match self {
@@ -15,24 +22,76 @@ impl TrieNode {
             Digest(digest) => digest.hash(),
         }
     }
+
+    // Appends the node as a branch child: its encoding if shorter than 32 bytes, otherwise the
+    // encoded hash, which is cached so that clean subtrees can be told apart.
+    pub(super) fn encode_as_child(&mut self, out: &mut Vec<u8>) {
+        match self {
+            Leaf(leaf) => {
+                let hash = leaf.with_encoding(|rlp| {
+                    if rlp.len() < 32 {
+                        out.extend_from_slice(rlp);
+                        None
+                    } else {
+                        Some(keccak256(rlp))
+                    }
+                });
+                if let Some(hash) = hash {
+                    hash.encode(out);
+                    leaf.hash = Some(hash);
+                }
+            }
+            Branch(branch) => {
+                let mut encoded = branch.encode();
+                if encoded.len() < 32 {
+                    out.append(&mut encoded);
+                } else {
+                    let hash = keccak256(encoded);
+                    hash.encode(out);
+                    branch.hash = Some(hash);
+                }
+            }
+            Digest(digest) => {
+                if digest.path.is_empty() {
+                    digest.value.encode(out);
+                } else {
+                    digest.hash()[..].encode(out);
+                }
+            }
+        }
+    }
+
+    // Returns RLP encoding of the node.
+    #[cfg(feature = "fuzzing")]
+    pub(super) fn encode(&mut self) -> Vec<u8> {
+        match self {
+            Leaf(leaf) => leaf.encode(),
+            Branch(branch) => branch.encode(),
+            Digest(digest) => digest.encode(),
+        }
+    }
 }
 
 impl LeafNode {
     // Returns RLP encoding of the leaf node.
+    pub(super) fn encode(&self) -> Vec<u8> {
+        self.with_encoding(<[u8]>::to_vec)
+    }
+
+    // Calls `f` with the RLP encoding of the leaf node, encoded on the stack if it is small enough.
     // https://ethereum.org/pl/developers/docs/data-structures-and-encoding/patricia-merkle-trie/#optimization
-    fn encode(&self) -> Vec<u8> {
+    pub(super) fn with_encoding<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
         // Encode the path of the leaf. It is not RLP encoding.
         // It is encoding of the path according to
         // https://ethereum.org/pl/developers/docs/data-structures-and-encoding/patricia-merkle-trie/#specification
         let path = encode_path_leaf(&self.path, true);
-        // Prepare RLP encoded list header with a pre-allocated vector buffer.
         // The list contains two elements, the encoded `path` and `value`
         // Warning: `.length()` computes the *RLP* representation length of the value it is called on.
-        let mut out = encode_list_header(path.length() + self.value.length());
-
-        path.encode(&mut out);
-        self.value[..].encode(&mut out);
-        out
+        let payload_length = path.length() + self.value.length();
+        with_stack_encoding(payload_length, f, |out| {
+            path.encode(out);
+            self.value[..].encode(out);
+        })
     }
 
     // Returns hash of the leaf node.
@@ -41,8 +100,7 @@ impl LeafNode {
         match self.hash {
             Some(hash) => hash,
             None => {
-                //keccak256(self.encode())
-                self.hash = Some(keccak256(self.encode()));
+                self.hash = Some(self.with_encoding(|rlp| keccak256(rlp)));
                 self.hash.unwrap()
             }
         }
@@ -50,32 +108,17 @@ impl LeafNode {
 }
 
 impl BranchNode {
-    // Returns RLP encoding of the branch node.
+    // Returns RLP encoding of the branch node, ignoring its path.
     // https://ethereum.org/pl/developers/docs/data-structures-and-encoding/patricia-merkle-trie/#optimization
-    fn encode(&mut self) -> Vec<u8> {
+    pub(super) fn encode_branch(&mut self) -> Vec<u8> {
         static EMPTY_NODE: u8 = 0x80;
 
         let mut encoded: Vec<u8> = Vec::default();
 
         for child in self.children.iter_mut() {
-            if let Some(child) = child {
-                match child.as_mut() {
-                    Leaf(leaf) => {
-                        encoded.append(&mut shorten_encoding(leaf.encode()));
-                    }
-                    Branch(branch) => {
-                        encoded.append(&mut shorten_encoding(branch.encode()));
-                    }
-                    Digest(digest) => {
-                        if digest.path.is_empty() {
-                            digest.value.encode(&mut encoded);
-                        } else {
-                            digest.hash()[..].encode(&mut encoded);
-                        }
-                    }
-                }
-            } else {
-                encoded.push(EMPTY_NODE);
+            match child {
+                Some(child) => child.encode_as_child(&mut encoded),
+                None => encoded.push(EMPTY_NODE),
             }
         }
 
@@ -85,7 +128,19 @@ impl BranchNode {
         // TODO: Check performance of this appending
         let mut encoded_branch = encode_list_header(encoded.len());
         encoded_branch.append(&mut encoded);
+        encoded_branch
+    }
 
+    // Returns RLP encoding of the branch node, or of the extension node holding it if the branch
+    // has a path.
+    pub(super) fn encode(&mut self) -> Vec<u8> {
+        let encoded_branch = self.encode_branch();
+        self.with_extension(encoded_branch)
+    }
+
+    // Returns the encoding of the extension node holding the branch, given the encoding of the
+    // branch, or the encoding of the branch if it has no path.
+    fn with_extension(&self, encoded_branch: Vec<u8>) -> Vec<u8> {
         if self.path.is_empty() {
             encoded_branch
         } else {
@@ -120,7 +175,7 @@ impl BranchNode {
 }
 
 impl DigestNode {
-    fn encode(&self) -> Vec<u8> {
+    pub(super) fn encode(&self) -> Vec<u8> {
         if self.path.is_empty() {
             let mut encoded_digest = Vec::with_capacity(33);
             self.value.encode(&mut encoded_digest);
@@ -146,14 +201,114 @@ impl DigestNode {
                     self.hash = Some(self.value);
                     self.value
                 } else {
-                    self.hash = Some(keccak256(self.encode()));
-                    self.hash.unwrap()
+                    let encoded_path = encode_path_leaf(&self.path, false);
+                    let payload_length = encoded_path.length() + self.value.length();
+                    let hash = with_stack_encoding(payload_length, |rlp| keccak256(rlp), |out| {
+                        encoded_path.encode(out);
+                        self.value.encode(out);
+                    });
+                    self.hash = Some(hash);
+                    hash
                 }
             }
         }
     }
 }
 
+// Encodings computed from scratch, ignoring and not touching the cached hashes.
+impl TrieNode {
+    fn hash_uncached(&self) -> B256 {
+        match self {
+            Digest(digest) if digest.path.is_empty() => digest.value,
+            _ => keccak256(self.encode_uncached()),
+        }
+    }
+
+    fn encode_uncached(&self) -> Vec<u8> {
+        match self {
+            Leaf(leaf) => leaf.encode(),
+            Branch(branch) => branch.with_extension(branch.encode_branch_uncached()),
+            Digest(digest) => digest.encode(),
+        }
+    }
+
+    fn encode_as_child_uncached(&self, out: &mut Vec<u8>) {
+        match self {
+            Digest(digest) if digest.path.is_empty() => digest.value.encode(out),
+            _ => out.append(&mut shorten_encoding(self.encode_uncached())),
+        }
+    }
+}
+
+impl BranchNode {
+    fn encode_branch_uncached(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::default();
+        for child in self.children.iter() {
+            match child {
+                Some(child) => child.encode_as_child_uncached(&mut encoded),
+                None => encoded.push(0x80),
+            }
+        }
+        // the empty branch value
+        encoded.push(0x80);
+        let mut encoded_branch = encode_list_header(encoded.len());
+        encoded_branch.append(&mut encoded);
+        encoded_branch
+    }
+}
+
+impl Trie {
+    /// Returns the root hash of the trie recomputed from scratch, ignoring the cached hashes and
+    /// leaving them untouched.
+    ///
+    /// Slower than [`Trie::hash`], it is a ground truth for audits and consistency checks which
+    /// must not depend on the hash caches being right.
+    pub fn hash_uncached(&self) -> B256 {
+        self.root
+            .as_ref()
+            .map_or(EMPTY_ROOT_HASH, TrieNode::hash_uncached)
+    }
+
+    /// Returns the root hash if it is cached, i.e. if the trie was not modified since it was last
+    /// hashed or revealed. Unlike [`Trie::hash`], it does not need `&mut self`, e.g. for a trie
+    /// shared behind an `Arc`.
+    pub const fn cached_hash(&self) -> Option<B256> {
+        match &self.root {
+            None => Some(EMPTY_ROOT_HASH),
+            Some(Branch(branch)) => branch.hash,
+            Some(Leaf(leaf)) => leaf.hash,
+            Some(Digest(digest)) => digest.hash,
+        }
+    }
+}
+
+// Encodes a list with the given payload, written by `encode_payload`, and calls `f` with the
+// encoding. Encodings up to `STACK_ENCODING_LEN` bytes are written to a stack buffer, so hashing
+// the node does not allocate.
+#[inline]
+fn with_stack_encoding<R>(
+    payload_length: usize,
+    f: impl FnOnce(&[u8]) -> R,
+    encode_payload: impl FnOnce(&mut dyn BufMut),
+) -> R {
+    let header = Header {
+        list: true,
+        payload_length,
+    };
+    let length = header.length() + payload_length;
+    if length > STACK_ENCODING_LEN {
+        let mut out = encode_list_header(payload_length);
+        encode_payload(&mut out);
+        return f(&out);
+    }
+    let mut buf = [0_u8; STACK_ENCODING_LEN];
+    let mut out = &mut buf[..length];
+    header.encode(&mut out);
+    encode_payload(&mut out);
+    debug_assert!(out.is_empty());
+    f(&buf[..length])
+}
+
 // Encodes a branch child node depending on the child data length.
 #[inline]
 fn shorten_encoding(b: Vec<u8>) -> Vec<u8> {
@@ -166,193 +321,196 @@ fn shorten_encoding(b: Vec<u8>) -> Vec<u8> {
     }
 }
 
-// Test cases from https://github.com/ipsilon/evmone/blob/31bf2116792032e572394e86cc99d6227e1e98b1/test/unittests/state_mpt_test.cpp#L59-L183
 #[cfg(test)]
 mod tests {
-    use crate::trie::Trie;
-    use alloy_primitives::private::alloy_rlp::Encodable;
-    use alloy_primitives::{Bytes, hex, keccak256};
-    use alloy_trie::{HashBuilder, Nibbles};
-    use std::{println, vec};
+    use crate::keccak256;
+    use crate::trie::TrieNode::Branch;
+    use crate::trie::nodes::BranchNode;
+    use crate::trie::{B256Map, Trie};
+    use alloy_primitives::{B256, Bytes, hex};
+    use alloy_trie::{EMPTY_ROOT_HASH, HashBuilder, Nibbles};
+    use std::string::{String, ToString};
     use std::vec::Vec;
+    use std::{format, vec};
 
-    #[test]
-    fn test_leaf_node_example1() {
-        let mut trie = Trie::new();
-        trie.insert_path(Nibbles::unpack(hex!("010203")), Bytes::from("hello"));
-        assert_eq!(
-            trie.hash(),
-            hex!("82c8fd36022fbc91bd6b51580cfd941d3d9994017d59ab2e8293ae9c94c3ab6e")
-        );
+    /// MPT test vectors exported from evmone, shared with the `zeth-mpt` tests.
+    const EVMONE_VECTORS: &str = include_str!("../../../../test_vectors/evmone_state_mpt.json");
+
+    fn decode_hex(value: &serde_json::Value) -> Vec<u8> {
+        hex::decode(value.as_str().expect("hex string")).expect("valid hex")
     }
 
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
-    fn test_branch_node_example1() {
-        // A trie of single branch node and two leaf nodes with paths of length 2.
-        // The branch node has leaf nodes at positions [4] and [5].
-        // {4:1, 5:a}
+    fn evmone_vectors() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(EVMONE_VECTORS).expect("valid evmone vectors");
+        for test in vectors["tests"].as_array().expect("test list") {
+            let mut trie = Trie::new();
+            for entry in test["entries"].as_array().expect("entry list") {
+                trie.insert_path(
+                    Nibbles::unpack(decode_hex(&entry[0])),
+                    Bytes::from(decode_hex(&entry[1])),
+                );
+            }
+            assert_eq!(
+                trie.hash_uncached().as_slice(),
+                decode_hex(&test["root"]),
+                "{}",
+                test["name"]
+            );
+            assert_eq!(
+                trie.hash().as_slice(),
+                decode_hex(&test["root"]),
+                "{}",
+                test["name"]
+            );
+        }
+    }
 
+    // Node encodings of the evmone examples, from
+    // https://github.com/ipsilon/evmone/blob/31bf2116792032e572394e86cc99d6227e1e98b1/test/unittests/state_mpt_test.cpp#L59-L183
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn evmone_node_encodings() {
         let value1 = Bytes::from("v___________________________1");
-        let key1 = Nibbles::unpack(hex!("0x41"));
-        let path1 = [0x4u8, 0x1];
-        let encoded_path1 = vec![0x30u8 | path1[1]];
-        let mut leaf_node1 = vec![];
-        vec![Bytes::from(encoded_path1), value1.clone()].encode(&mut leaf_node1);
-        assert_eq!(
-            leaf_node1,
-            hex!("df319d765f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f31")
-        );
-
         let value2 = Bytes::from("v___________________________2");
-        let key2 = Nibbles::unpack(hex!("0x5a"));
-        let path2 = [0x5u8, 0xa];
-        let encoded_path2 = vec![0x30u8 | path2[1]];
-        let mut leaf_node2 = vec![];
-        vec![Bytes::from(encoded_path2), value2.clone()].encode(&mut leaf_node2);
-        assert_eq!(
-            leaf_node2,
-            hex!("df3a9d765f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f32")
-        );
+        let child = |branch: &BranchNode, nibble: usize| {
+            let child = branch.children.get(nibble).as_ref().unwrap();
+            hex::encode(child.encode_uncached())
+        };
 
+        // branch_node_example1: {4:1, 5:a}
         let mut trie = Trie::new();
-        trie.insert_path(key1, value1);
-        trie.insert_path(key2, value2);
+        trie.insert_path(Nibbles::unpack(hex!("41")), value1.clone());
+        trie.insert_path(Nibbles::unpack(hex!("5a")), value2.clone());
+        let Some(Branch(branch)) = &trie.root else {
+            panic!("the root is a branch");
+        };
+        assert!(branch.path.is_empty());
         assert_eq!(
-            trie.hash(),
-            hex!("1aaa6f712413b9a115730852323deb5f5d796c29151a60a1f55f41a25354cd26")
+            child(branch, 4),
+            "df319d765f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f31"
         );
-    }
-
-    #[test]
-    fn test_branch_node_of_3() {
-        // A trie of single branch node and three leaf nodes with paths of length 2.
-        // The branch node has leaf nodes at positions [0], [1] and [2]. All leaves have path 0.
-        // {0:0 1:0 2:0}
-
-        let mut trie = Trie::new();
-        trie.insert_path(Nibbles::unpack(hex!("0x00")), Bytes::from("X"));
-        trie.insert_path(Nibbles::unpack(hex!("0x10")), Bytes::from("Y"));
-        trie.insert_path(Nibbles::unpack(hex!("0x20")), Bytes::from("Z"));
         assert_eq!(
-            trie.hash(),
-            hex!("5c5154e8d108dcf8b9946c8d33730ec8178345ce9d36e6feed44f0134515482d")
+            child(branch, 5),
+            "df3a9d765f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f32"
         );
-    }
-
-    #[test]
-    fn test_leaf_node_with_empty_path() {
-        // Both inserted leaves have empty path in the end.
-        // 0:{0:"X", 1:"Y"}
-        let mut trie = Trie::new();
-        trie.insert_path(Nibbles::unpack(hex!("0x00")), Bytes::from("X"));
-        trie.insert_path(Nibbles::unpack(hex!("0x01")), Bytes::from("Y"));
-        println!("{}", trie);
         assert_eq!(
             trie.hash(),
-            hex!("0a923005d10fbd4e571655cec425db7c5091db03c33891224073a55d3abc2415")
+            hex!("1aaa6f712413b9a115730852323deb5f5d796c29151a60a1f55f41a25354cd26")
         );
-    }
-
-    #[test]
-    fn test_extension_node_example1() {
-        // A trie of an extension node followed by a branch node with
-        // two leaves with single nibble paths.
-        // 5858:{4:1, 5:a}
-
-        let value1 = Bytes::from("v___________________________1");
-        let key1 = Nibbles::unpack(hex!("0x585841"));
-        let _path1 = [0x5u8, 0x8, 0x5, 0x8, 0x4, 0x1];
 
-        let value2 = Bytes::from("v___________________________2");
-        let key2 = Nibbles::unpack(hex!("0x58585a"));
-        let _path2 = [0x5u8, 0x8, 0x5, 0x8, 0x5, 0xa];
-
-        let encoded_common_path = vec![0x00u8, 0x58, 0x58];
-
-        // The hash of the branch node. See the branch_node_example test.
-        let branch_node_hash =
-            hex!("1aaa6f712413b9a115730852323deb5f5d796c29151a60a1f55f41a25354cd26");
-
-        let mut extension_node = vec![];
-        vec![
-            Bytes::from(encoded_common_path),
-            Bytes::from(branch_node_hash),
-        ]
-        .encode(&mut extension_node);
+        // extension_node_example1: 5858:{4:1, 5:a}, an extension to the branch above
+        let mut trie = Trie::new();
+        trie.insert_path(Nibbles::unpack(hex!("585841")), value1.clone());
+        trie.insert_path(Nibbles::unpack(hex!("58585a")), value2.clone());
+        let Some(root) = &trie.root else {
+            panic!("the trie is not empty");
+        };
         assert_eq!(
-            keccak256(extension_node),
-            hex!("3eefc183db443d44810b7d925684eb07256e691d5c9cb13215660107121454f9")
+            hex::encode(root.encode_uncached()),
+            "e583005858a01aaa6f712413b9a115730852323deb5f5d796c29151a60a1f55f41a25354cd26"
         );
-
-        let mut trie = Trie::new();
-        trie.insert_path(key1, value1);
-        trie.insert_path(key2, value2);
         assert_eq!(
             trie.hash(),
             hex!("3eefc183db443d44810b7d925684eb07256e691d5c9cb13215660107121454f9")
         );
-    }
 
-    #[test]
-    fn test_extension_node_example2() {
-        // A trie of an extension node followed by a branch node with
-        // two leaves with longer paths.
-        // 585:{8:41, 9:5a}
-
-        let value1 = Bytes::from("v___________________________1");
-        let key1 = Nibbles::unpack(hex!("0x585841"));
-        let path1 = [0x5u8, 0x8, 0x5, 0x8, 0x4, 0x1];
-
-        let value2 = Bytes::from("v___________________________2");
-        let key2 = Nibbles::unpack(hex!("0x58595a"));
-        let path2 = [0x5u8, 0x8, 0x5, 0x9, 0x5, 0xa];
-
-        let common_path = [0x5u8, 0x8, 0x5];
-        let encoded_path1 = vec![0x20u8, ((path1[4] << 4) | path1[5])];
-        assert_eq!(hex::encode(&encoded_path1), "2041");
-        let encoded_path2 = vec![0x20u8, ((path2[4] << 4) | path2[5])];
-        assert_eq!(hex::encode(&encoded_path2), "205a");
-
-        let mut node1 = Vec::new();
-        vec![Bytes::from(encoded_path1), value1.clone()].encode(&mut node1);
+        // extension_node_example2: 585:{8:41, 9:5a}
+        let mut trie = Trie::new();
+        trie.insert_path(Nibbles::unpack(hex!("585841")), value1);
+        trie.insert_path(Nibbles::unpack(hex!("58595a")), value2);
+        let Some(root @ Branch(branch)) = &trie.root else {
+            panic!("the root is an extension");
+        };
         assert_eq!(
-            hex::encode(&node1),
+            child(branch, 8),
             "e18220419d765f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f31"
         );
-        let mut node2 = Vec::new();
-        vec![Bytes::from(encoded_path2), value2.clone()].encode(&mut node2);
         assert_eq!(
-            hex::encode(&node2),
+            child(branch, 9),
             "e182205a9d765f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f5f32"
         );
-
-        let branch_node_hash =
-            hex!("01746f8ab5a4cc5d6175cbd9ea9603357634ec06b2059f90710243f098e0ee82");
-
-        let encoded_common_path = vec![
-            0x10u8 | common_path[0],
-            ((common_path[1] << 4) | common_path[2]),
-        ];
-        let mut extension_node = Vec::new();
-        vec![
-            Bytes::from(encoded_common_path),
-            Bytes::from(branch_node_hash),
-        ]
-        .encode(&mut extension_node);
         assert_eq!(
-            hex::encode(keccak256(extension_node)),
-            "ac28c08fa3ff1d0d2cc9a6423abb7af3f4dcc37aa2210727e7d3009a9b4a34e8"
+            keccak256(branch.encode_branch_uncached()),
+            hex!("01746f8ab5a4cc5d6175cbd9ea9603357634ec06b2059f90710243f098e0ee82")
+        );
+        assert_eq!(
+            hex::encode(root.encode_uncached()),
+            "e4821585a001746f8ab5a4cc5d6175cbd9ea9603357634ec06b2059f90710243f098e0ee82"
         );
-
-        let mut trie = Trie::new();
-        trie.insert_path(key1, value1);
-        trie.insert_path(key2, value2);
         assert_eq!(
             trie.hash(),
             hex!("ac28c08fa3ff1d0d2cc9a6423abb7af3f4dcc37aa2210727e7d3009a9b4a34e8")
         );
     }
 
+    /// Test vectors written by `zkmpt vectors`, replayed to catch changes in the trie.
+    const REF_MPT_VECTORS: &str = include_str!("../../../../test_vectors/ref_mpt_vectors.json");
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn ref_mpt_vectors() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(REF_MPT_VECTORS).expect("valid ref-mpt vectors");
+        for test in vectors["operations"].as_array().expect("test list") {
+            let mut trie = Trie::new();
+            for op in test["ops"].as_array().expect("operation list") {
+                if let Some([key, value]) = op["insert"].as_array().map(Vec::as_slice) {
+                    let key = B256::from_slice(&decode_hex(key));
+                    trie.insert(key, Bytes::from(decode_hex(value)));
+                } else {
+                    trie.remove(B256::from_slice(&decode_hex(&op["remove"])));
+                }
+            }
+            assert_eq!(
+                trie.hash().as_slice(),
+                decode_hex(&test["root"]),
+                "{}",
+                test["name"]
+            );
+        }
+
+        for test in vectors["reveals"].as_array().expect("test list") {
+            let nodes: B256Map<Bytes> = test["nodes"]
+                .as_array()
+                .expect("node list")
+                .iter()
+                .map(|rlp| Bytes::from(decode_hex(rlp)))
+                .map(|rlp| (keccak256(&rlp), rlp))
+                .collect();
+            let root = B256::from_slice(&decode_hex(&test["root"]));
+            let mut trie = Trie::reveal_from_rlp(root, &nodes);
+            let leaves: Vec<_> = trie
+                .iter()
+                .map(|(key, value)| serde_json::json!([key.to_string(), value.to_string()]))
+                .collect();
+            assert_eq!(
+                leaves,
+                *test["leaves"].as_array().unwrap(),
+                "{}",
+                test["name"]
+            );
+            let unresolved: Vec<_> = trie
+                .unresolved()
+                .into_iter()
+                .map(|(path, digest)| {
+                    let path: String = path.iter().map(|nibble| format!("{nibble:x}")).collect();
+                    serde_json::json!([path, digest.to_string()])
+                })
+                .collect();
+            assert_eq!(
+                unresolved,
+                *test["unresolved"].as_array().unwrap(),
+                "{}",
+                test["name"]
+            );
+            assert_eq!(trie.hash(), root, "{}", test["name"]);
+        }
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
     fn test_branch_child_encoding_matches_hash_builder() {
         let mut trie = Trie::new();
@@ -370,4 +528,61 @@ mod tests {
 
         assert_eq!(trie.hash(), hash_builder.root());
     }
+
+    #[test]
+    fn uncached_hash_ignores_caches() {
+        let keys: Vec<B256> = (0_u64..100).map(|i| keccak256(i.to_be_bytes())).collect();
+        let mut trie = Trie::new();
+        assert_eq!(trie.hash_uncached(), EMPTY_ROOT_HASH);
+        for key in &keys {
+            trie.insert(*key, Bytes::copy_from_slice(&key[..20]));
+        }
+        let uncached = trie.hash_uncached();
+        assert_eq!(trie.cached_hash(), None);
+        assert_eq!(trie.hash(), uncached);
+        assert_eq!(trie.cached_hash(), Some(uncached));
+
+        // a partially revealed trie
+        let nodes: B256Map<Bytes> = trie
+            .proof(keys[0])
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+        assert_eq!(
+            Trie::reveal_from_rlp(uncached, &nodes).hash_uncached(),
+            uncached
+        );
+
+        // a wrong cached hash is not used, and is left untouched
+        let Some(Branch(root)) = trie.root.as_mut() else {
+            panic!("the root is a branch");
+        };
+        root.hash = Some(B256::ZERO);
+        assert_eq!(trie.hash_uncached(), uncached);
+        assert_eq!(trie.hash(), B256::ZERO);
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn leaves_around_stack_encoding_len() {
+        // single leaves, and leaves under a branch, encoded both on the stack and on the heap
+        for value_len in [1, 30, 100, 120, 125, 126, 127, 128, 140, 200, 1000] {
+            let value = vec![0xab_u8; value_len];
+            let paths = [
+                Nibbles::unpack([0x12; 32]),
+                Nibbles::unpack([0x34; 32]),
+                Nibbles::unpack([0x35; 32]),
+            ];
+            for count in 1..=paths.len() {
+                let mut trie = Trie::new();
+                let mut hash_builder = HashBuilder::default();
+                for path in &paths[..count] {
+                    trie.insert_path(path.clone(), Bytes::from(value.clone()));
+                    hash_builder.add_leaf(path.clone(), &value);
+                }
+                assert_eq!(trie.hash(), hash_builder.root(), "{value_len} {count}");
+            }
+        }
+    }
 }
@@ -2,6 +2,7 @@
 use alloc::boxed::Box;
 use crate::trie::TrieNode::{Branch, Digest, Leaf};
 use super::nodes::{BranchNode, DigestNode, LeafNode, TrieNode, BranchNodeChildrenArray};
+use super::UnresolvedNode;
 use alloy_primitives::Bytes;
 use alloy_trie::Nibbles;
 
@@ -23,7 +24,7 @@ impl BranchNode {
         }
     }
 
-    fn insert(&mut self, path: Nibbles, value: Bytes) {
+    fn insert(&mut self, path: Nibbles, value: Bytes) -> Result<(), UnresolvedNode> {
         let common_prefix_len = self.path.common_prefix_length(&path);
 
         if common_prefix_len == self.path.len() {
@@ -35,8 +36,7 @@ impl BranchNode {
                 Some(child) => {
                     // If the child is not empty, recursively go into the branch. Consume the first
                     // path nibble as it encodes the branch index.
-                    child.insert(path.slice(common_prefix_len + 1..), value);
-                    return;
+                    return child.insert(path.slice(common_prefix_len + 1..), value);
                 }
                 None => {
                     // If the index branch is empty, insert the new leaf there.
@@ -71,11 +71,12 @@ impl BranchNode {
                 }),
             );
         }
+        Ok(())
     }
 }
 
 impl TrieNode {
-    pub(super) fn insert(&mut self, path: Nibbles, value: Bytes) {
+    pub(super) fn insert(&mut self, path: Nibbles, value: Bytes) -> Result<(), UnresolvedNode> {
         self.clear_cache();
         match self {
             Leaf(leaf) => {
@@ -107,9 +108,7 @@ impl TrieNode {
                     ));
                 }
             }
-            Branch(branch) => {
-                branch.insert(path, value);
-            }
+            Branch(branch) => branch.insert(path, value)?,
             Digest(digest) => {
                 let common_prefix_len = path.common_prefix_length(&digest.path);
                 if common_prefix_len < digest.path.len() {
@@ -133,12 +132,14 @@ impl TrieNode {
                             hash: None,
                         }),
                     ));
-                    return;
                 } else {
                     // Adding to an unresolved node is impossible.
-                    panic!("MPT: Unresolved node access");
+                    return Err(UnresolvedNode {
+                        digest: digest.value,
+                    });
                 }
             }
         }
+        Ok(())
     }
 }
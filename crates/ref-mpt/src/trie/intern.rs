@@ -0,0 +1,157 @@
+//! Sharing identical leaf values between the leaves of one or more tries, and releasing the
+//! buffers they were sliced from.
+use super::nodes::TrieNode;
+use crate::trie::Trie;
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use alloy_primitives::Bytes;
+use alloy_primitives::map::HashSet;
+
+/// A pool of distinct leaf values.
+///
+/// Interning the values of tries through a pool makes the leaves holding identical values, such
+/// as the encoding of an empty account or common storage values, share a single buffer.
+#[derive(Debug, Clone, Default)]
+pub struct ValuePool {
+    values: HashSet<Bytes>,
+}
+
+impl ValuePool {
+    /// Creates an empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the pooled value equal to `value`, adding it to the pool if it is not pooled yet.
+    pub fn intern(&mut self, value: &Bytes) -> Bytes {
+        match self.values.get(value) {
+            Some(pooled) => pooled.clone(),
+            None => {
+                self.values.insert(value.clone());
+                value.clone()
+            }
+        }
+    }
+
+    /// Returns the pooled value equal to `value`, adding an exactly sized copy of it to the pool
+    /// if it is not pooled yet.
+    fn intern_copy(&mut self, value: &[u8]) -> Bytes {
+        if let Some(pooled) = self.values.get(value) {
+            return pooled.clone();
+        }
+        let value = Bytes::copy_from_slice(value);
+        self.values.insert(value.clone());
+        value
+    }
+
+    /// Returns the number of distinct values in the pool.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the pool holds no value.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl TrieNode {
+    fn intern_values(&mut self, pool: &mut ValuePool, copy: bool) {
+        match self {
+            Leaf(leaf) if copy => leaf.value = pool.intern_copy(&leaf.value),
+            Leaf(leaf) => leaf.value = pool.intern(&leaf.value),
+            Branch(branch) => {
+                for child in branch.children.iter_mut().flatten() {
+                    child.intern_values(pool, copy);
+                }
+            }
+            Digest(_) => {}
+        }
+    }
+}
+
+impl Trie {
+    /// Replaces the values of the revealed leaves with the equal values of the pool, so that
+    /// identical values are stored once. The duplicate buffers are freed once no other clone of
+    /// them is alive.
+    pub fn intern_values(&mut self, pool: &mut ValuePool) {
+        if let Some(root) = self.root.as_mut() {
+            root.intern_values(pool, false);
+        }
+    }
+
+    /// Releases the memory the revealed leaf values hold beyond their own bytes.
+    ///
+    /// A value inserted as a slice of a larger buffer, such as a block or a network message,
+    /// keeps the whole buffer alive as long as the leaf. Each distinct value is copied to an
+    /// exactly sized buffer shared by the leaves holding it, so values interned through a
+    /// [`ValuePool`] stay shared within the trie, but not with other tries. The nodes need no
+    /// compaction: removals collapse the branches left with a single child, and the nodes store
+    /// their paths and hashes inline. Long-lived hosts call it after heavy removals or updates.
+    pub fn shrink_to_fit(&mut self) {
+        if let Some(root) = self.root.as_mut() {
+            root.intern_values(&mut ValuePool::new(), true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+    use alloy_primitives::B256;
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn identical_values_share_a_buffer() {
+        let mut first = Trie::new();
+        let mut second = Trie::new();
+        for i in 0_u64..50 {
+            // fresh buffers holding one of five values
+            let value = Bytes::from(vec![(i % 5) as u8; 40]);
+            first.insert(keccak256(i.to_be_bytes()), value.clone());
+            second.insert(keccak256((i + 100).to_be_bytes()), value.to_vec().into());
+        }
+        let (first_root, second_root) = (first.hash(), second.hash());
+
+        let mut pool = ValuePool::new();
+        first.intern_values(&mut pool);
+        second.intern_values(&mut pool);
+        assert_eq!(pool.len(), 5);
+
+        let key = |i: u64| keccak256(i.to_be_bytes());
+        let (a, b) = (first.get(key(0)).unwrap(), second.get(key(105)).unwrap());
+        assert_eq!(a, b);
+        assert_eq!(a.as_ptr(), b.as_ptr());
+        assert_eq!(first.hash(), first_root);
+        assert_eq!(second.hash(), second_root);
+        assert_eq!(second.get(B256::ZERO), None);
+    }
+
+    #[test]
+    fn shrink_to_fit_releases_sliced_buffers() {
+        let buffer = Bytes::from(vec![7_u8; 4096]);
+        let mut trie = Trie::new();
+        for i in 0_u64..20 {
+            trie.insert(keccak256(i.to_be_bytes()), buffer.slice(..40));
+        }
+        let root = trie.hash();
+
+        trie.shrink_to_fit();
+        let values: Vec<&Bytes> = trie.iter().map(|(_, value)| value).collect();
+        assert_eq!(values.len(), 20);
+        // the values no longer point into the buffer, and identical values share one copy
+        assert!(
+            values
+                .iter()
+                .all(|value| !buffer.as_ptr_range().contains(&value.as_ptr()))
+        );
+        assert!(
+            values
+                .iter()
+                .all(|value| value.as_ptr() == values[0].as_ptr())
+        );
+        assert_eq!(*values[0], buffer.slice(..40));
+        assert_eq!(trie.hash(), root);
+    }
+}
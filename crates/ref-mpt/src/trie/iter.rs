@@ -0,0 +1,153 @@
+//! Iteration over the revealed leaves of the trie.
+use super::nodes::TrieNode;
+use crate::trie::Trie;
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes};
+use alloy_trie::Nibbles;
+
+/// Iterator over the revealed values of a [`Trie`] with their keys, in key order.
+///
+/// Returned by [`Trie::iter`].
+#[derive(Debug, Clone)]
+pub struct TrieIter<'a> {
+    /// The nodes left to visit with their path, the next one last.
+    stack: Vec<(Nibbles, &'a TrieNode)>,
+}
+
+impl<'a> Iterator for TrieIter<'a> {
+    type Item = (B256, &'a Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((prefix, node)) = self.stack.pop() {
+            match node {
+                Leaf(leaf) => {
+                    let path = prefix.join(&leaf.path);
+                    return Some((B256::from_slice(&path.pack()), &leaf.value));
+                }
+                Branch(branch) => {
+                    let prefix = prefix.join(&branch.path);
+                    for (nibble, child) in branch.children.iter().enumerate().rev() {
+                        if let Some(child) = child {
+                            let mut path = prefix.clone();
+                            path.push(nibble as u8);
+                            self.stack.push((path, child));
+                        }
+                    }
+                }
+                // the values below a digest are not revealed
+                Digest(_) => {}
+            }
+        }
+        None
+    }
+}
+
+impl Trie {
+    /// Returns an iterator over the revealed values with their pre-hashed 32-byte keys, in key
+    /// order. Values lying below unresolved nodes are skipped.
+    pub fn iter(&self) -> TrieIter<'_> {
+        TrieIter {
+            stack: self
+                .root
+                .iter()
+                .map(|root| (Nibbles::default(), root))
+                .collect(),
+        }
+    }
+
+    /// Returns the unresolved nodes, only known by their hash, with the path of the subtree they
+    /// stand for, in path order.
+    pub fn unresolved(&self) -> Vec<(Nibbles, B256)> {
+        let mut unresolved = Vec::new();
+        let mut stack: Vec<_> = self
+            .root
+            .iter()
+            .map(|root| (Nibbles::default(), root))
+            .collect();
+        while let Some((prefix, node)) = stack.pop() {
+            match node {
+                Leaf(_) => {}
+                Branch(branch) => {
+                    let prefix = prefix.join(&branch.path);
+                    for (nibble, child) in branch.children.iter().enumerate().rev() {
+                        if let Some(child) = child {
+                            let mut path = prefix.clone();
+                            path.push(nibble as u8);
+                            stack.push((path, child));
+                        }
+                    }
+                }
+                Digest(digest) => unresolved.push((prefix.join(&digest.path), digest.value)),
+            }
+        }
+        unresolved
+    }
+}
+
+impl<'a> IntoIterator for &'a Trie {
+    type Item = (B256, &'a Bytes);
+    type IntoIter = TrieIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keccak256;
+    use crate::trie::{B256Map, Trie};
+    use alloy_primitives::{B256, Bytes};
+    use alloy_trie::Nibbles;
+    use std::collections::BTreeMap;
+    use std::vec::Vec;
+
+    #[test]
+    fn iterates_revealed_values_in_key_order() {
+        assert_eq!(Trie::new().iter().count(), 0);
+        let values: BTreeMap<B256, Bytes> = (0_u64..200)
+            .map(|i| {
+                (
+                    keccak256(i.to_be_bytes()),
+                    Bytes::from(i.to_be_bytes().to_vec()),
+                )
+            })
+            .collect();
+        let mut trie: Trie = values.clone().into_iter().collect();
+        let collected: Vec<(B256, Bytes)> = trie
+            .iter()
+            .map(|(key, value)| (key, value.clone()))
+            .collect();
+        assert_eq!(collected, values.clone().into_iter().collect::<Vec<_>>());
+
+        // only the leaves on the proven path are revealed
+        let key = *values.keys().next().unwrap();
+        let nodes: B256Map<Bytes> = trie
+            .proof(key)
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+        let revealed = Trie::reveal_from_rlp(trie.hash(), &nodes);
+        let keys: Vec<B256> = revealed.iter().map(|(key, _)| key).collect();
+        assert!(keys.contains(&key));
+        assert!(keys.len() < values.len());
+        assert!(keys.iter().all(|key| values.contains_key(key)));
+
+        // the other subtrees are unresolved, and none of them holds the proven key
+        assert!(trie.unresolved().is_empty());
+        let unresolved = revealed.unresolved();
+        assert!(!unresolved.is_empty());
+        assert!(unresolved.is_sorted());
+        for (path, digest) in unresolved {
+            assert!(!nodes.contains_key(&digest));
+            assert!(!Nibbles::unpack(key).starts_with(&path));
+            assert!(
+                values
+                    .keys()
+                    .any(|key| Nibbles::unpack(key).starts_with(&path))
+            );
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! Recording the operations applied to a trie, to replay them without the witness.
+//!
+//! A journal starts with a snapshot of the trie in the format of [`Trie::save`], followed by the
+//! successful operations in order. Reveals only record the nodes they used, and hashes record the
+//! root they returned, so that a replay finds the first operation where it diverges.
+//!
+//! A journal is exported as a script of one operation per line, with hex encoded arguments:
+//!
+//! ```text
+//! trie <saved trie>
+//! insert <key> <value>
+//! remove <key>
+//! clear
+//! reveal <node> <node> ...
+//! hash <root>
+//! ```
+use super::{InvalidNode, UnresolvedNode};
+use super::nodes::TrieNode;
+use crate::keccak256;
+use crate::trie::{B256Map, Trie};
+use crate::witness_index::RlpNodes;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes, hex};
+use core::cell::RefCell;
+use core::fmt;
+
+/// An operation recorded in a [`Journal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEntry {
+    /// A value was inserted, see [`Trie::insert`].
+    Insert {
+        /// The pre-hashed key.
+        key: B256,
+        /// The inserted value.
+        value: Bytes,
+    },
+    /// A key was removed, see [`Trie::remove`].
+    Remove {
+        /// The pre-hashed key.
+        key: B256,
+    },
+    /// The trie was cleared, see [`Trie::clear`].
+    Clear,
+    /// Unresolved nodes were revealed, see [`Trie::reveal`].
+    Reveal {
+        /// The RLP encoded nodes used by the reveal.
+        nodes: Vec<Bytes>,
+    },
+    /// The trie was hashed, see [`Trie::hash`].
+    Hash {
+        /// The returned root.
+        root: B256,
+    },
+}
+
+/// A journal failed to parse or to replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    /// The line of the script with the given 1-based number is malformed.
+    Script(usize),
+    /// The snapshot of the trie does not load.
+    Snapshot(alloy_rlp::Error),
+    /// The operation with the given index needed an unresolved node.
+    Unresolved {
+        /// The index of the operation.
+        index: usize,
+        /// The unresolved node.
+        node: UnresolvedNode,
+    },
+    /// The hash with the given index returned a different root than when it was recorded.
+    Diverged {
+        /// The index of the hash.
+        index: usize,
+        /// The recorded root.
+        expected: B256,
+        /// The replayed root.
+        actual: B256,
+    },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Script(line) => write!(f, "MPT: Malformed journal script at line {line}"),
+            Self::Snapshot(err) => write!(f, "MPT: Invalid journal snapshot: {err}"),
+            Self::Unresolved { index, node } => write!(f, "MPT: Journal operation {index}: {node}"),
+            Self::Diverged {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "MPT: Journal diverged at operation {index}: expected root {expected}, got {actual}"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for JournalError {}
+
+/// The operations applied to a [`Trie`] since [`Trie::start_journal`], replayable without the
+/// witness the trie was revealed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Journal {
+    /// The trie when the journal started, in the format of [`Trie::save`].
+    snapshot: Bytes,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Returns the recorded operations, in order.
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Replays the operations on the snapshot of the trie, and returns the resulting trie.
+    ///
+    /// Fails at the first operation which needs an unresolved node, or at the first hash
+    /// returning a different root than when it was recorded.
+    pub fn replay(&self) -> Result<Trie, JournalError> {
+        let mut trie = Trie::load(&mut self.snapshot.as_ref()).map_err(JournalError::Snapshot)?;
+        for (index, entry) in self.entries.iter().enumerate() {
+            let unresolved = |node| JournalError::Unresolved { index, node };
+            match entry {
+                JournalEntry::Insert { key, value } => {
+                    trie.try_insert(*key, value.clone()).map_err(unresolved)?;
+                }
+                JournalEntry::Remove { key } => trie.try_remove(*key).map_err(unresolved)?,
+                JournalEntry::Clear => trie.clear(),
+                JournalEntry::Reveal { nodes } => {
+                    let nodes: B256Map<Bytes> = nodes
+                        .iter()
+                        .map(|rlp| (keccak256(rlp), rlp.clone()))
+                        .collect();
+                    trie.reveal(&nodes);
+                }
+                JournalEntry::Hash { root } => {
+                    let actual = trie.hash();
+                    if actual != *root {
+                        return Err(JournalError::Diverged {
+                            index,
+                            expected: *root,
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(trie)
+    }
+
+    /// Parses a script written by the [`Display`](fmt::Display) implementation. Empty lines and
+    /// lines starting with `#` are ignored.
+    pub fn from_script(script: &str) -> Result<Self, JournalError> {
+        let mut snapshot = None;
+        let mut entries = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || JournalError::Script(index + 1);
+            let mut words = line.split_whitespace();
+            let command = words.next().ok_or_else(malformed)?;
+            let mut arg = || words.next().ok_or_else(malformed);
+            let key = |word: &str| word.parse::<B256>().map_err(|_| malformed());
+            let bytes = |word: &str| hex::decode(word).map(Bytes::from).map_err(|_| malformed());
+            let entry = match command {
+                "trie" => {
+                    snapshot = Some(bytes(arg()?)?);
+                    continue;
+                }
+                "insert" => JournalEntry::Insert {
+                    key: key(arg()?)?,
+                    value: bytes(arg()?)?,
+                },
+                "remove" => JournalEntry::Remove { key: key(arg()?)? },
+                "clear" => JournalEntry::Clear,
+                "reveal" => JournalEntry::Reveal {
+                    nodes: words.map(bytes).collect::<Result<_, _>>()?,
+                },
+                "hash" => JournalEntry::Hash { root: key(arg()?)? },
+                _ => return Err(malformed()),
+            };
+            entries.push(entry);
+        }
+        Ok(Self {
+            snapshot: snapshot.ok_or(JournalError::Script(1))?,
+            entries,
+        })
+    }
+
+    /// Records the reveal of the unresolved nodes of `root`.
+    fn reveal<M: RlpNodes + ?Sized>(
+        &mut self,
+        root: &mut TrieNode,
+        rlp_rep_map: &M,
+    ) -> Result<(), InvalidNode> {
+        let recording = Recording {
+            nodes: rlp_rep_map,
+            used: RefCell::new(Vec::new()),
+        };
+        let revealed = root.try_reveal(&recording);
+        let mut nodes = recording.used.into_inner();
+        // the reveal stopped at the last node used, which did not decode
+        if revealed.is_err() {
+            nodes.pop();
+        }
+        if !nodes.is_empty() {
+            self.entries.push(JournalEntry::Reveal { nodes });
+        }
+        revealed
+    }
+}
+
+impl fmt::Display for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "trie {}", self.snapshot)?;
+        for entry in &self.entries {
+            match entry {
+                JournalEntry::Insert { key, value } => writeln!(f, "insert {key} {value}")?,
+                JournalEntry::Remove { key } => writeln!(f, "remove {key}")?,
+                JournalEntry::Clear => writeln!(f, "clear")?,
+                JournalEntry::Reveal { nodes } => {
+                    write!(f, "reveal")?;
+                    for node in nodes {
+                        write!(f, " {node}")?;
+                    }
+                    writeln!(f)?;
+                }
+                JournalEntry::Hash { root } => writeln!(f, "hash {root}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Witness nodes recording the nodes looked up in them.
+struct Recording<'a, M: ?Sized> {
+    nodes: &'a M,
+    used: RefCell<Vec<Bytes>>,
+}
+
+impl<M: RlpNodes + ?Sized> RlpNodes for Recording<'_, M> {
+    fn rlp(&self, digest: &B256) -> Option<&Bytes> {
+        let rlp = self.nodes.rlp(digest)?;
+        self.used.borrow_mut().push(rlp.clone());
+        Some(rlp)
+    }
+
+    fn node_count(&self) -> usize {
+        self.nodes.node_count()
+    }
+}
+
+impl Trie {
+    /// Starts recording the operations applied to the trie in a [`Journal`], from a snapshot of
+    /// the trie. An ongoing journal is discarded.
+    ///
+    /// When a fuzzer or a block finds a divergence, the journal is a reproducer holding only the
+    /// revealed nodes and the witness nodes used after it started, rather than the whole witness.
+    pub fn start_journal(&mut self) {
+        let mut snapshot = Vec::new();
+        self.save(&mut snapshot);
+        self.journal = Some(Box::new(Journal {
+            snapshot: snapshot.into(),
+            entries: Vec::new(),
+        }));
+    }
+
+    /// Returns the journal being recorded, if any.
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_deref()
+    }
+
+    /// Stops recording and returns the journal, if any.
+    pub fn take_journal(&mut self) -> Option<Journal> {
+        self.journal.take().map(|journal| *journal)
+    }
+
+    /// Records an operation if a journal is being recorded.
+    pub(super) fn record(&mut self, entry: JournalEntry) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.entries.push(entry);
+        }
+    }
+
+    /// Reveals the unresolved nodes from `rlp_rep_map` through the journal, if any. Returns
+    /// `None` if no journal is being recorded.
+    pub(super) fn reveal_recorded<M: RlpNodes + ?Sized>(
+        &mut self,
+        rlp_rep_map: &M,
+    ) -> Option<Result<(), InvalidNode>> {
+        let journal = self.journal.as_mut()?;
+        Some(match self.root.as_mut() {
+            Some(root) => journal.reveal(root, rlp_rep_map),
+            None => Ok(()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn proof_nodes(trie: &mut Trie, key: B256) -> B256Map<Bytes> {
+        trie.proof(key)
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect()
+    }
+
+    #[test]
+    fn replays_recorded_operations() {
+        let keys: Vec<B256> = (0_u64..50).map(|i| keccak256(i.to_be_bytes())).collect();
+        let mut full: Trie = keys
+            .iter()
+            .map(|key| (*key, Bytes::copy_from_slice(&key[..8])))
+            .collect();
+        let mut trie = Trie::reveal_from_rlp(full.hash(), &proof_nodes(&mut full, keys[0]));
+
+        trie.start_journal();
+        // failed operations are not recorded
+        let unresolved = trie
+            .try_insert(keys[1], Bytes::from_static(&[1]))
+            .unwrap_err();
+        assert!(trie.journal().unwrap().entries().is_empty());
+        trie.reveal(&proof_nodes(&mut full, keys[1]));
+        trie.insert(keys[1], Bytes::from_static(&[1]));
+        trie.remove(keys[0]);
+        let root = trie.hash();
+        trie.clear();
+        trie.hash();
+        let journal = trie.take_journal().unwrap();
+        assert!(trie.journal().is_none());
+
+        let entries = journal.entries();
+        assert_eq!(entries.len(), 6);
+        let JournalEntry::Reveal { nodes } = &entries[0] else {
+            panic!("expected a reveal, got {:?}", entries[0]);
+        };
+        assert!(nodes.iter().any(|rlp| keccak256(rlp) == unresolved.digest));
+        assert_eq!(entries[3], JournalEntry::Hash { root });
+
+        // the script round-trips and replays to the same trie
+        let script = journal.to_string();
+        assert_eq!(Journal::from_script(&script), Ok(journal.clone()));
+        assert_eq!(journal.replay().unwrap().hash(), trie.hash());
+
+        // a replay diverging from the recorded roots fails at the hash
+        let mut tampered = journal.clone();
+        tampered.entries[1] = JournalEntry::Insert {
+            key: keys[1],
+            value: Bytes::from_static(&[2]),
+        };
+        assert!(matches!(
+            tampered.replay(),
+            Err(JournalError::Diverged { index: 3, expected, .. }) if expected == root
+        ));
+        assert_eq!(
+            Journal::from_script(&script.replace("remove ", "delete ")),
+            Err(JournalError::Script(4))
+        );
+        assert_eq!(Journal::from_script(""), Err(JournalError::Script(1)));
+    }
+}
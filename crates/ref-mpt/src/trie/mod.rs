@@ -1,16 +1,40 @@
 mod display;
+mod error;
 mod get;
 mod hash;
 mod insert;
+mod intern;
+mod iter;
+#[cfg(feature = "journal")]
+mod journal;
 mod remove;
 mod reveal;
 mod rlp;
+mod sharded;
+#[cfg(feature = "reth-trie-common")]
+mod storage;
+mod trace;
 mod trie;
 mod children;
+mod compact;
 mod nodes;
+mod persist;
+mod proof;
+#[cfg(test)]
+mod proptests;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
 
 use core::fmt::Debug;
 use nodes::TrieNode;
+pub use error::{InvalidNode, UnresolvedNode};
+pub use get::Lookup;
+pub use intern::ValuePool;
+pub use iter::TrieIter;
+#[cfg(feature = "journal")]
+pub use journal::{Journal, JournalEntry, JournalError};
+pub use sharded::ShardedTrie;
+pub use trace::{NodeKind, TraceNode};
 pub use trie::B256Map;
 
 
@@ -18,4 +42,7 @@ pub use trie::B256Map;
 #[derive(Debug, Clone)]
 pub struct Trie {
     root: Option<TrieNode>,
+    /// The operations applied since [`Trie::start_journal`].
+    #[cfg(feature = "journal")]
+    journal: Option<alloc::boxed::Box<Journal>>,
 }
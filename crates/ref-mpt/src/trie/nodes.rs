@@ -33,4 +33,10 @@ pub(crate) enum TrieNode {
     Branch(BranchNode),
     Leaf(LeafNode),
     Digest(DigestNode),
-}
\ No newline at end of file
+}
+
+// Every node is allocated with the size of the largest variant, which must not be the branch: the
+// children of a branch live in their own allocation for that. A revealed branch holds a node for
+// each of its children, mostly digests of the subtrees a proof skips, so branches are less than
+// half the nodes of a trie.
+const _: () = assert!(size_of::<BranchNode>() <= size_of::<LeafNode>());
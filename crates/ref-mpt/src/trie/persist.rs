@@ -0,0 +1,246 @@
+//! Compact binary serialization of a revealed trie, to skip the reveal when the same trie is
+//! needed again.
+//!
+//! The format starts with a magic and a version, followed by the root in pre-order. Every node
+//! starts with a tag, whose high bit flags a cached hash following the path:
+//!
+//! - `path`: the number of nibbles followed by the nibbles packed in bytes,
+//! - branch: `0x01`, path, hash, 16-bit mask of the children, children in order,
+//! - leaf: `0x02`, path, hash, 32-bit length of the value, value,
+//! - digest: `0x03`, path, hash, digest.
+//!
+//! An empty trie has the tag `0x00` instead of a root.
+use super::nodes::{BranchNode, BranchNodeChildrenArray, DigestNode, LeafNode, TrieNode};
+use crate::trie::Trie;
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use alloc::boxed::Box;
+use alloy_primitives::{B256, Bytes};
+use alloy_rlp::{BufMut, Error};
+use alloy_trie::Nibbles;
+
+const MAGIC: &[u8; 4] = b"RMPT";
+const VERSION: u8 = 1;
+
+const EMPTY: u8 = 0x00;
+const BRANCH: u8 = 0x01;
+const LEAF: u8 = 0x02;
+const DIGEST: u8 = 0x03;
+const HASHED: u8 = 0x80;
+
+fn put_path(path: &Nibbles, out: &mut dyn BufMut) {
+    out.put_u8(path.len() as u8);
+    out.put_slice(&path.pack());
+}
+
+fn put_node(node: &TrieNode, out: &mut dyn BufMut) {
+    let (tag, path, hash) = match node {
+        Branch(branch) => (BRANCH, &branch.path, branch.hash),
+        Leaf(leaf) => (LEAF, &leaf.path, leaf.hash),
+        Digest(digest) => (DIGEST, &digest.path, digest.hash),
+    };
+    out.put_u8(if hash.is_some() { tag | HASHED } else { tag });
+    put_path(path, out);
+    if let Some(hash) = hash {
+        out.put_slice(hash.as_slice());
+    }
+    match node {
+        Branch(branch) => {
+            let mask = branch
+                .children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| child.is_some())
+                .fold(0_u16, |mask, (nibble, _)| mask | (1 << nibble));
+            out.put_u16(mask);
+            for child in branch.children.iter().flatten() {
+                put_node(child, out);
+            }
+        }
+        Leaf(leaf) => {
+            out.put_u32(leaf.value.len() as u32);
+            out.put_slice(&leaf.value);
+        }
+        Digest(digest) => out.put_slice(digest.value.as_slice()),
+    }
+}
+
+const fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if buf.len() < len {
+        return Err(Error::InputTooShort);
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_u8(buf: &mut &[u8]) -> Result<u8, Error> {
+    Ok(take(buf, 1)?[0])
+}
+
+fn take_b256(buf: &mut &[u8]) -> Result<B256, Error> {
+    Ok(B256::from_slice(take(buf, 32)?))
+}
+
+fn take_path(buf: &mut &[u8]) -> Result<Nibbles, Error> {
+    let len = usize::from(take_u8(buf)?);
+    if len > 64 {
+        return Err(Error::Custom("MPT: Invalid path length"));
+    }
+    let packed = take(buf, len.div_ceil(2))?;
+    if len % 2 == 1 && packed[len / 2] & 0x0f != 0 {
+        return Err(Error::Custom("MPT: Invalid path padding"));
+    }
+    Ok(Nibbles::unpack(packed).slice(..len))
+}
+
+fn take_node(buf: &mut &[u8]) -> Result<TrieNode, Error> {
+    let tag = take_u8(buf)?;
+    let path = take_path(buf)?;
+    let hash = if tag & HASHED != 0 {
+        Some(take_b256(buf)?)
+    } else {
+        None
+    };
+    match tag & !HASHED {
+        BRANCH => {
+            let mask = u16::from_be_bytes(take(buf, 2)?.try_into().unwrap());
+            if mask.count_ones() < 2 {
+                return Err(Error::Custom("MPT: Branch with less than two children"));
+            }
+            let mut children = BranchNodeChildrenArray::new();
+            for nibble in (0..16).filter(|nibble| mask & (1 << nibble) != 0) {
+                children.insert(nibble, Box::new(take_node(buf)?));
+            }
+            Ok(Branch(BranchNode {
+                children,
+                path,
+                hash,
+            }))
+        }
+        LEAF => {
+            let len = u32::from_be_bytes(take(buf, 4)?.try_into().unwrap());
+            let value = Bytes::copy_from_slice(take(buf, len as usize)?);
+            Ok(Leaf(LeafNode { path, value, hash }))
+        }
+        DIGEST => Ok(Digest(DigestNode {
+            path,
+            value: take_b256(buf)?,
+            hash,
+        })),
+        _ => Err(Error::Custom("MPT: Invalid node tag")),
+    }
+}
+
+impl Trie {
+    /// Writes the trie in a compact binary format, including the unresolved digests and the
+    /// cached hashes, so that [`Trie::load`] restores it without revealing or hashing it again.
+    pub fn save(&self, out: &mut dyn BufMut) {
+        out.put_slice(MAGIC);
+        out.put_u8(VERSION);
+        match &self.root {
+            Some(root) => put_node(root, out),
+            None => out.put_u8(EMPTY),
+        }
+    }
+
+    /// Reads a trie written by [`Trie::save`] from the start of `buf`, advancing it past the trie.
+    /// The buffer can be a memory-mapped file, only the values are copied out of it.
+    ///
+    /// The cached hashes are trusted, so the buffer must come from a trusted source.
+    pub fn load(buf: &mut &[u8]) -> Result<Self, Error> {
+        if take(buf, MAGIC.len())? != MAGIC || take_u8(buf)? != VERSION {
+            return Err(Error::Custom("MPT: Invalid saved trie header"));
+        }
+        if buf.first() == Some(&EMPTY) {
+            *buf = &buf[1..];
+            return Ok(Self::new());
+        }
+        Ok(Self::from_root(Some(take_node(buf)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+    use crate::trie::B256Map;
+    use std::vec;
+    use std::vec::Vec;
+
+    fn saved(trie: &Trie) -> Vec<u8> {
+        let mut out = Vec::new();
+        trie.save(&mut out);
+        out
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut trie = Trie::new();
+        assert_eq!(
+            Trie::load(&mut &saved(&trie)[..]).unwrap().hash(),
+            trie.hash()
+        );
+
+        for i in 0_u64..500 {
+            let value = vec![i as u8; usize::from(i as u8 % 70)];
+            trie.insert(keccak256(i.to_be_bytes()), value.into());
+        }
+        // keys with long common prefixes, stored in extensions
+        trie.insert(B256::with_last_byte(1), Bytes::from_static(&[1]));
+        trie.insert(B256::with_last_byte(2), Bytes::from_static(&[2]));
+        let root = trie.hash();
+
+        let data = saved(&trie);
+        let mut buf = &data[..];
+        let mut loaded = Trie::load(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        // the cached hashes are restored
+        assert_eq!(saved(&loaded), data);
+        assert_eq!(loaded.hash(), root);
+        for i in 0_u64..500 {
+            let key = keccak256(i.to_be_bytes());
+            assert_eq!(loaded.get(key), trie.get(key));
+        }
+
+        // the trie without cached hashes
+        loaded.insert(B256::with_last_byte(3), Bytes::from_static(&[3]));
+        let mut reloaded = Trie::load(&mut &saved(&loaded)[..]).unwrap();
+        assert_eq!(reloaded.hash(), loaded.hash());
+    }
+
+    #[test]
+    fn unresolved_nodes() {
+        let mut trie = Trie::new();
+        for i in 0_u64..100 {
+            trie.insert(keccak256(i.to_be_bytes()), Bytes::from_static(&[7; 40]));
+        }
+        let root = trie.hash();
+        // only the root branch is revealed
+        let root_rlp = trie
+            .proof(keccak256(0_u64.to_be_bytes()))
+            .unwrap()
+            .remove(0);
+        let mut rlp_map = B256Map::default();
+        rlp_map.insert(root, root_rlp);
+        let partial = Trie::reveal_from_rlp(root, &rlp_map);
+
+        let mut loaded = Trie::load(&mut &saved(&partial)[..]).unwrap();
+        assert_eq!(loaded.hash(), root);
+        assert!(loaded.try_get(keccak256(0_u64.to_be_bytes())).is_err());
+    }
+
+    #[test]
+    fn invalid_data() {
+        let mut trie = Trie::new();
+        trie.insert(B256::ZERO, Bytes::from_static(&[1]));
+        trie.insert(B256::repeat_byte(0x11), Bytes::from_static(&[2]));
+        let data = saved(&trie);
+        for len in 0..data.len() {
+            assert!(Trie::load(&mut &data[..len]).is_err(), "{len}");
+        }
+        let mut wrong_tag = data;
+        wrong_tag[5] = 0x04;
+        assert!(Trie::load(&mut &wrong_tag[..]).is_err());
+        assert!(Trie::load(&mut &b"RMPT\x02\x00"[..]).is_err());
+    }
+}
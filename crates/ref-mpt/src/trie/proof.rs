@@ -0,0 +1,197 @@
+//! Generating Merkle proofs from the revealed part of the trie.
+use super::UnresolvedNode;
+use super::nodes::{BranchNode, DigestNode, LeafNode, TrieNode};
+use crate::trie::Trie;
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes};
+use alloy_trie::Nibbles;
+
+// Appends the encoding of a node to the proof, unless it is inlined in its parent.
+fn push(proof: &mut Vec<Bytes>, encoded: Vec<u8>, is_root: bool) {
+    if is_root || encoded.len() >= 32 {
+        proof.push(encoded.into());
+    }
+}
+
+impl LeafNode {
+    fn proof(&self, proof: &mut Vec<Bytes>, is_root: bool) {
+        push(proof, self.encode(), is_root);
+    }
+}
+
+impl BranchNode {
+    fn proof(
+        &mut self,
+        path: Nibbles,
+        proof: &mut Vec<Bytes>,
+        is_root: bool,
+    ) -> Result<(), UnresolvedNode> {
+        // A branch with a path is held by an extension node, which comes first in the proof.
+        let is_root = if self.path.is_empty() {
+            is_root
+        } else {
+            push(proof, self.encode(), is_root);
+            false
+        };
+        if !path.starts_with(&self.path) {
+            return Ok(());
+        }
+        push(proof, self.encode_branch(), is_root);
+
+        let path = path.slice(self.path.len()..);
+        if path.is_empty() {
+            return Ok(());
+        }
+        match self.children.get_mut(path[0] as usize) {
+            Some(child) => child.proof(path.slice(1..), proof, false),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DigestNode {
+    fn proof(
+        &self,
+        path: Nibbles,
+        proof: &mut Vec<Bytes>,
+        is_root: bool,
+    ) -> Result<(), UnresolvedNode> {
+        if !self.path.is_empty() {
+            push(proof, self.encode(), is_root);
+        }
+        // Only a path diverging from the digest's path is proven without its node.
+        if path.starts_with(&self.path) {
+            Err(UnresolvedNode { digest: self.value })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl TrieNode {
+    fn proof(
+        &mut self,
+        path: Nibbles,
+        proof: &mut Vec<Bytes>,
+        is_root: bool,
+    ) -> Result<(), UnresolvedNode> {
+        match self {
+            Leaf(leaf) => {
+                leaf.proof(proof, is_root);
+                Ok(())
+            }
+            Branch(branch) => branch.proof(path, proof, is_root),
+            Digest(digest) => digest.proof(path, proof, is_root),
+        }
+    }
+}
+
+impl Trie {
+    /// Returns the RLP encoded nodes on the path of the `key` key, starting with the root, which
+    /// prove the value under the key or its absence. Nodes inlined in their parent are not listed.
+    /// The proof of an empty trie is empty.
+    ///
+    /// Fails if the path of the key leads to an unresolved node.
+    pub fn proof(&mut self, key: B256) -> Result<Vec<Bytes>, UnresolvedNode> {
+        let mut proof = Vec::new();
+        if let Some(root) = self.root.as_mut() {
+            root.proof(Nibbles::unpack(key), &mut proof, true)?;
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trie::{B256Map, Trie};
+    use crate::{keccak256, verify_proof};
+    use alloy_primitives::{B256, Bytes};
+    use alloy_trie::{HashBuilder, Nibbles, proof::ProofRetainer};
+    use std::vec::Vec;
+
+    fn keys(count: u64) -> Vec<B256> {
+        (0..count).map(|i| keccak256(i.to_be_bytes())).collect()
+    }
+
+    #[test]
+    fn proofs_verify() {
+        let keys = keys(300);
+        let mut trie = Trie::new();
+        for key in &keys {
+            trie.insert(*key, Bytes::copy_from_slice(&key[..3]));
+        }
+        let root = trie.hash();
+
+        for key in keys.iter().step_by(17) {
+            let proof = trie.proof(*key).unwrap();
+            verify_proof(root, *key, Some(&key[..3]), &proof).unwrap();
+        }
+        let absent = keccak256(b"absent");
+        let proof = trie.proof(absent).unwrap();
+        verify_proof(root, absent, None, &proof).unwrap();
+        assert!(Trie::new().proof(absent).unwrap().is_empty());
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn matches_hash_builder_proofs() {
+        // keys sharing long prefixes, so the trie has extensions and inlined nodes
+        let mut keys: Vec<B256> = (0_u8..24)
+            .map(|i| {
+                let mut key = B256::repeat_byte(0x42);
+                key[31] = i;
+                key[i as usize % 4] = i / 4;
+                key
+            })
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let targets = keys.iter().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        let mut trie = Trie::new();
+        for key in &keys {
+            hash_builder.add_leaf(Nibbles::unpack(key), &[1]);
+            trie.insert(*key, Bytes::from_static(&[1]));
+        }
+        let root = hash_builder.root();
+        assert_eq!(trie.hash(), root);
+        let proof_nodes = hash_builder.take_proof_nodes();
+
+        for key in &keys {
+            let expected: Vec<Bytes> = proof_nodes
+                .matching_nodes_sorted(&Nibbles::unpack(key))
+                .into_iter()
+                .map(|(_, rlp)| rlp)
+                .filter(|rlp| rlp.len() >= 32 || keccak256(rlp) == root)
+                .collect();
+            assert_eq!(trie.proof(*key).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn unresolved_path() {
+        let keys = keys(64);
+        let mut trie = Trie::new();
+        for key in &keys {
+            trie.insert(*key, Bytes::from_static(&[7; 40]));
+        }
+        let root = trie.hash();
+        let root_rlp = trie.proof(keys[0]).unwrap().remove(0);
+        assert_eq!(keccak256(&root_rlp), root);
+
+        // only the root node is revealed
+        let mut rlp_map = B256Map::default();
+        rlp_map.insert(root, root_rlp);
+        let mut partial = Trie::reveal_from_rlp(root, &rlp_map);
+        assert!(partial.proof(keys[0]).is_err());
+        assert!(
+            Trie::reveal_from_rlp(root, &B256Map::default())
+                .proof(keys[0])
+                .is_err()
+        );
+        assert_eq!(partial.hash(), root);
+    }
+}
@@ -0,0 +1,133 @@
+//! Property tests of the trie operations against `HashBuilder` and a `BTreeMap` model.
+use crate::Trie;
+use alloy_primitives::{B256, Bytes};
+use alloy_trie::{HashBuilder, Nibbles};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use std::vec::Vec;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(B256, Bytes),
+    Remove(B256),
+    Hash,
+}
+
+// Keys are either random or share their last 30 bytes, so that both wide branches and long
+// extensions are generated.
+fn key() -> impl Strategy<Value = B256> {
+    prop_oneof![
+        any::<[u8; 32]>().prop_map(B256::from),
+        any::<[u8; 2]>().prop_map(|prefix| {
+            let mut key = B256::ZERO;
+            key[..2].copy_from_slice(&prefix);
+            key
+        }),
+    ]
+}
+
+// Non-empty values, both shorter and longer than a hash.
+fn value() -> impl Strategy<Value = Bytes> {
+    prop::collection::vec(any::<u8>(), 1..40).prop_map(Bytes::from)
+}
+
+fn entries() -> impl Strategy<Value = BTreeMap<B256, Bytes>> {
+    prop::collection::btree_map(key(), value(), 0..64)
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (key(), value()).prop_map(|(key, value)| Op::Insert(key, value)),
+        2 => key().prop_map(Op::Remove),
+        1 => Just(Op::Hash),
+    ]
+}
+
+fn hash_builder_root(entries: &BTreeMap<B256, Bytes>) -> B256 {
+    let mut hash_builder = HashBuilder::default();
+    for (key, value) in entries {
+        hash_builder.add_leaf(Nibbles::unpack(*key), value);
+    }
+    hash_builder.root()
+}
+
+fn build_trie<'a>(entries: impl IntoIterator<Item = (&'a B256, &'a Bytes)>) -> Trie {
+    let mut trie = Trie::new();
+    for (key, value) in entries {
+        trie.insert(*key, value.clone());
+    }
+    trie
+}
+
+proptest! {
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn root_matches_hash_builder(entries in entries()) {
+        prop_assert_eq!(build_trie(&entries).hash(), hash_builder_root(&entries));
+    }
+
+    #[test]
+    fn insertion_order_independence(
+        shuffled in entries()
+            .prop_map(|entries| entries.into_iter().collect::<Vec<_>>())
+            .prop_shuffle()
+    ) {
+        let mut trie = build_trie(shuffled.iter().map(|(key, value)| (key, value)));
+        let entries: BTreeMap<_, _> = shuffled.into_iter().collect();
+        prop_assert_eq!(trie.hash(), build_trie(&entries).hash());
+    }
+
+    #[test]
+    fn remove_inverts_insert(entries in entries(), extra in entries()) {
+        let mut trie = build_trie(&entries);
+        let root = trie.hash();
+
+        let extra: BTreeMap<_, _> =
+            extra.into_iter().filter(|(key, _)| !entries.contains_key(key)).collect();
+        for (key, value) in &extra {
+            trie.insert(*key, value.clone());
+        }
+        for key in extra.keys() {
+            trie.remove(*key);
+        }
+
+        prop_assert_eq!(trie.hash(), root);
+        for (key, value) in &entries {
+            prop_assert_eq!(trie.get(*key), Some(value));
+        }
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn cached_hashes_stay_consistent(
+        entries in entries(),
+        ops in prop::collection::vec(op(), 0..64),
+    ) {
+        let mut trie = build_trie(&entries);
+        let mut model = entries;
+        for op in ops {
+            match op {
+                Op::Insert(key, value) => {
+                    trie.insert(key, value.clone());
+                    model.insert(key, value);
+                }
+                Op::Remove(key) => {
+                    trie.remove(key);
+                    model.remove(&key);
+                }
+                // Caches every node hash, which must be invalidated by later operations.
+                Op::Hash => {
+                    trie.hash();
+                }
+            }
+        }
+
+        let root = hash_builder_root(&model);
+        prop_assert_eq!(trie.hash_uncached(), root);
+        prop_assert_eq!(trie.hash(), root);
+        prop_assert_eq!(build_trie(&model).hash(), root);
+        for (key, value) in &model {
+            prop_assert_eq!(trie.get(*key), Some(value));
+        }
+    }
+}
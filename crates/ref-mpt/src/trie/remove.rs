@@ -1,6 +1,7 @@
 //! Removing an element from MPT implementation for different node's types.
 use alloc::boxed::Box;
-use super::nodes::{BranchNode, LeafNode, TrieNode};
+use super::nodes::{BranchNode, DigestNode, LeafNode, TrieNode};
+use super::UnresolvedNode;
 use crate::trie::TrieNode::{Branch, Digest, Leaf};
 use alloy_trie::Nibbles;
 
@@ -16,53 +17,83 @@ impl BranchNode {
         self.children.one_child_left()
     }
 
-    fn remove(&mut self, path: Nibbles) {
+    // Returns the only sibling of the child at `idx` if it is a digest which cannot be collapsed
+    // into this branch once the child is removed.
+    fn unresolved_only_sibling(&self, idx: usize) -> Option<UnresolvedNode> {
+        let mut siblings = self
+            .children
+            .iter()
+            .enumerate()
+            .filter_map(|(i, child)| if i == idx { None } else { child.as_ref() });
+        match (siblings.next(), siblings.next()) {
+            (Some(sibling), None) => match sibling.as_ref() {
+                Digest(digest) if digest.path.is_empty() => Some(UnresolvedNode {
+                    digest: digest.value,
+                }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn remove(&mut self, path: Nibbles) -> Result<(), UnresolvedNode> {
         let common_prefix_len = self.path.common_prefix_length(&path);
         if common_prefix_len == self.path.len() {
             if path.len() == common_prefix_len {
-                return;
+                return Ok(());
             }
             let idx = path.at(common_prefix_len);
+            let unresolved_sibling = self.unresolved_only_sibling(idx);
             let maybe_child = self.children.get_mut(idx);
             match maybe_child {
                 Some(child) => {
                     // Enter the child recursively
-                    child.remove(path.slice(common_prefix_len + 1..));
+                    child.remove(path.slice(common_prefix_len + 1..))?;
                     // If the leaf is removed or the branch child is empty,
                     // remove the child from the branch,
-                    match child.as_mut() {
-                        Leaf(leaf) => {
-                            if leaf.path == path.slice(common_prefix_len + 1..) {
-                                self.children.remove(idx);
-                            }
+                    let remove_child = match child.as_mut() {
+                        Leaf(leaf) => leaf.path == path.slice(common_prefix_len + 1..),
+                        Branch(branch) => branch.is_empty(),
+                        // The digest path diverges from the removed path, nothing was removed.
+                        Digest(_) => false,
+                    };
+                    if remove_child {
+                        // The branch would have to collapse onto an unresolved node.
+                        if let Some(unresolved) = unresolved_sibling {
+                            return Err(unresolved);
                         }
-                        Branch(branch) => {
-                            if branch.is_empty() {
-                                self.children.remove(idx);
-                            }
-                        }
-                        Digest(_) => panic!("MPT: Unresolved node access"),
+                        self.children.remove(idx);
                     }
                 }
                 None => {}
             }
         }
+        Ok(())
     }
 }
 
 impl TrieNode {
-    pub(super) fn remove(&mut self, path: Nibbles) {
+    pub(super) fn remove(&mut self, path: Nibbles) -> Result<(), UnresolvedNode> {
         self.clear_cache();
         match self {
             Leaf(_) => {}
             Branch(branch) => {
-                branch.remove(path);
+                branch.remove(path)?;
                 // If only one child left in the branch:
                 // 1. Branch left -> prepend the parent path to the child branch. Remove parent.
                 // 2. Leaf left -> prepend the branch path to the leaf node path and replace the branch
                 // with the leaf.
+                // 3. Digest with a path left -> it is an extension of an unresolved branch, prepend
+                // the branch path to the digest path and replace the branch with the digest.
                 let mut branch_path = branch.path.clone();
                 if let Some((child_idx, child)) = branch.only_one_child_left() {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(
+                        target: "ref_mpt",
+                        path = ?branch_path,
+                        child = child_idx,
+                        "collapse branch"
+                    );
                     match child.as_mut() {
                         Branch(child_branch) => {
                             let mut new_path = core::mem::take(&mut branch_path);
@@ -86,11 +117,30 @@ impl TrieNode {
                                 hash: None,
                             });
                         }
+                        Digest(child_digest) if !child_digest.path.is_empty() => {
+                            let mut new_path = branch_path;
+                            new_path.push_unchecked(child_idx as u8);
+                            new_path = new_path.join(&child_digest.path);
+
+                            *self = Digest(DigestNode {
+                                path: new_path,
+                                value: child_digest.value,
+                                hash: None,
+                            });
+                        }
                         Digest(_) => panic!("MPT: Unresolved node access"),
                     }
                 }
             }
-            Digest(_) => panic!("MPT: Unresolved node access"),
+            Digest(digest) => {
+                // Removing a path which diverges from the digest path is a no-op.
+                if digest.path.common_prefix_length(&path) == digest.path.len() {
+                    return Err(UnresolvedNode {
+                        digest: digest.value,
+                    });
+                }
+            }
         }
+        Ok(())
     }
 }
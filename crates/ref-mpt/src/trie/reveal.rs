@@ -1,10 +1,11 @@
 //! Building the MPT with the root hash and the trie nodes' values stored in a (hash)->(rlp encoded value) map.
 //! This implementation stores hash if the nodes in a simple caching mechanism which greatly optimizes a
 //! number of necessary hash calculations and node's rlp encodings.
-use crate::trie::B256Map;
+use crate::trie::InvalidNode;
 use crate::trie::TrieNode;
 use crate::trie::TrieNode::{Branch, Digest, Leaf};
-use alloy_primitives::{B256, Bytes};
+use crate::witness_index::RlpNodes;
+use alloy_primitives::B256;
 
 impl TrieNode {
     fn set_cache(&mut self, hash: B256) {
@@ -37,35 +38,47 @@ impl TrieNode {
 }
 
 impl TrieNode {
-    pub(super) fn reveal(&mut self, rlp_rep_map: &B256Map<Bytes>) {
+    // Reveals the digests found in the map. A digest whose node does not decode is left
+    // unresolved, the digests revealed before it stay revealed.
+    pub(super) fn try_reveal<M: RlpNodes + ?Sized>(
+        &mut self,
+        rlp_rep_map: &M,
+    ) -> Result<(), InvalidNode> {
         match self {
             Leaf(_) => {}
             Branch(branch) => {
                 for child in branch.children.iter_mut() {
                     match child {
                         Some(child) => {
-                            child.reveal(rlp_rep_map);
+                            child.try_reveal(rlp_rep_map)?;
                         }
                         None => {}
                     }
                 }
             }
-            Digest(digest) => match rlp_rep_map.get(&digest.value) {
+            Digest(digest) => match rlp_rep_map.rlp(&digest.value) {
                 Some(rlp) => {
-                    let mut node = TrieNode::decode(&mut &rlp[..])
-                        .expect("MPT: Failed to decode trie node")
-                        .expect("MPT: Empty trie node");
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(target: "ref_mpt", digest = %digest.value, "resolve digest");
+                    // the empty node never hangs under a digest
+                    let Ok(Some(mut node)) = TrieNode::decode(&mut &rlp[..]) else {
+                        return Err(InvalidNode {
+                            digest: digest.value,
+                        });
+                    };
 
                     match node {
                         Digest(ref digest_node) => {
                             if digest_node.path.is_empty() {
                                 // The digest value does not reveal anything but the hash.
-                                return;
+                                return Ok(());
                             }
                         }
                         Branch(ref mut branch) => {
-                            // The digest reveals to branch. Assign the digest's path to the branch.
-                            branch.path = core::mem::take(&mut digest.path);
+                            // The digest reveals to branch. Prepend the digest's path to the branch
+                            // path, which is not empty when the digest reveals to an extension
+                            // with an inlined branch.
+                            branch.path = digest.path.join(&branch.path);
                         }
                         Leaf(_) => {}
                     }
@@ -73,12 +86,14 @@ impl TrieNode {
                     // Set cache based on the hash of the digest node which reveals to non-digest or
                     // digest with a non-empty path. At this moment the digest hash should be cached.
                     node.set_cache(digest.hash());
-                    node.reveal(rlp_rep_map);
+                    let revealed = node.try_reveal(rlp_rep_map);
                     *self = node;
+                    revealed?;
                 }
                 None => {}
             },
         }
+        Ok(())
     }
 }
 
@@ -86,12 +101,15 @@ impl TrieNode {
 mod tests {
     use super::*;
     use alloc::borrow::ToOwned;
-    use crate::trie::Trie;
-    use alloy_primitives::{hex, keccak256};
+    use crate::keccak256;
+    use crate::trie::{B256Map, Trie};
+    use alloy_primitives::Bytes;
+    use alloy_primitives::hex;
     use alloy_trie::Nibbles;
     use std::vec;
     use std::vec::Vec;
 
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
     fn reveal_from_rlp() {
         let state: Vec<Bytes> = {
@@ -153,4 +171,40 @@ mod tests {
         trie.insert_path(key, Bytes::from([1_u8]));
         assert_eq!(trie.hash(), root_hash);
     }
+
+    #[test]
+    fn invalid_nodes_are_errors() {
+        let keys: Vec<B256> = (0_u64..50).map(|i| keccak256(i.to_be_bytes())).collect();
+        let mut full: Trie = keys
+            .iter()
+            .map(|key| (*key, Bytes::copy_from_slice(&key[..8])))
+            .collect();
+        let root = full.hash();
+        let proof = full.proof(keys[0]).unwrap();
+        let digest = keccak256(&proof[1]);
+
+        // a truncated node, and the empty node, under the digest of the second node of the proof
+        for junk in [&[0xc1][..], &[0x80]] {
+            let mut rlp_map: B256Map<Bytes> = proof
+                .iter()
+                .map(|rlp| (keccak256(rlp), rlp.clone()))
+                .collect();
+            rlp_map.insert(digest, Bytes::copy_from_slice(junk));
+            assert_eq!(
+                Trie::try_reveal_from_rlp(root, &rlp_map).unwrap_err(),
+                InvalidNode { digest }
+            );
+
+            // the invalid node stays unresolved, and is revealed from valid nodes
+            let root_node = B256Map::from_iter([(root, proof[0].clone())]);
+            let mut trie = Trie::reveal_from_rlp(root, &root_node);
+            assert_eq!(trie.try_reveal(&rlp_map), Err(InvalidNode { digest }));
+            let unresolved = trie.unresolved();
+            assert!(unresolved.iter().any(|(_, node)| *node == digest));
+            rlp_map.insert(digest, proof[1].clone());
+            trie.try_reveal(&rlp_map).unwrap();
+            assert_eq!(trie.get(keys[0]), full.get(keys[0]));
+            assert_eq!(trie.hash(), root);
+        }
+    }
 }
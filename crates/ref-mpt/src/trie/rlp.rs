@@ -30,10 +30,10 @@ impl TrieNode {
                     for (idx, element) in list[..16].iter().enumerate() {
                         if *element != &[EMPTY_STRING_CODE] {
                             let mut element_ref = element.as_ref();
-                            children.insert(idx, Box::new(
-                                TrieNode::decode(&mut element_ref)?
-                                    .expect("MPT: Unable to decode branch child node."),
-                            ));
+                            let child = TrieNode::decode(&mut element_ref)?.ok_or(
+                                alloy_rlp::Error::Custom("MPT: Unable to decode branch child node."),
+                            )?;
+                            children.insert(idx, Box::new(child));
                         }
                     }
                     if list[16] != &[EMPTY_STRING_CODE] {
@@ -62,10 +62,16 @@ impl TrieNode {
                     } else {
                         let mut value_ref = value.as_ref();
                         let mut node = TrieNode::decode(&mut value_ref)?
-                            .expect("MPT: Empty node in extension.");
+                            .ok_or(alloy_rlp::Error::Custom("MPT: Empty node in extension."))?;
                         match &mut node {
-                            Branch(branch) => branch.path = path,
-                            Digest(digest) => digest.path = path,
+                            // An extension must have a non-empty path and cannot point to another
+                            // extension.
+                            Branch(branch) if !path.is_empty() && branch.path.is_empty() => {
+                                branch.path = path
+                            }
+                            Digest(digest) if !path.is_empty() && digest.path.is_empty() => {
+                                digest.path = path
+                            }
                             _ => {
                                 return Err(alloy_rlp::Error::Custom(
                                     "MPT: Invalid extension node.",
@@ -115,3 +121,105 @@ pub(super) fn encode_list_header(payload_length: usize) -> Vec<u8> {
     header.encode(&mut out);
     out
 }
+
+#[cfg(kani)]
+mod verification {
+    use super::*;
+    use alloy_rlp::Encodable;
+
+    /// Decoding arbitrary bytes as a path never panics.
+    #[kani::proof]
+    #[kani::unwind(6)]
+    fn decode_path_never_panics() {
+        let bytes: [u8; 4] = kani::any();
+        let len = kani::any_where(|len: &usize| *len <= bytes.len());
+        let _ = decode_path(&mut &bytes[..len]);
+    }
+
+    /// The flag nibble selects the node type and whether one or two nibbles are dropped, and the
+    /// remaining nibbles are those of the payload.
+    #[kani::proof]
+    #[kani::unwind(8)]
+    fn decode_path_parity() {
+        let payload: [u8; 3] = kani::any();
+        let len = kani::any_where(|len: &usize| (1..=payload.len()).contains(len));
+        let payload = &payload[..len];
+        kani::assume(payload[0] >> 4 <= 0b0011);
+
+        let mut rlp = Vec::new();
+        payload.encode(&mut rlp);
+        let (path, is_leaf) = decode_path(&mut rlp.as_slice()).expect("valid path encoding");
+
+        let flag = payload[0] >> 4;
+        assert_eq!(is_leaf, flag & 0b0010 != 0);
+        let skipped = if flag & 0b0001 != 0 { 1 } else { 2 };
+        assert_eq!(path.len(), 2 * len - skipped);
+        for idx in 0..path.len() {
+            let pos = idx + skipped;
+            let nibble = (payload[pos / 2] >> (4 * (1 - pos % 2))) & 0x0f;
+            assert_eq!(path.at(idx), usize::from(nibble));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::hex;
+
+    fn decode(rlp: &[u8]) -> Result<Option<TrieNode>, alloy_rlp::Error> {
+        TrieNode::decode(&mut &rlp[..])
+    }
+
+    #[test]
+    fn empty_extension_child_is_an_error() {
+        // Extension with path [0, 0, 1] pointing to an empty node.
+        assert_eq!(
+            decode(&hex!("c4821001 80")[..]).unwrap_err(),
+            alloy_rlp::Error::Custom("MPT: Empty node in extension.")
+        );
+    }
+
+    #[test]
+    fn extension_with_empty_path_is_an_error() {
+        // Extension with an empty path pointing to a digest.
+        let mut rlp = hex!("e2 00 a0").to_vec();
+        rlp.extend_from_slice(&[0x11; 32]);
+        assert_eq!(
+            decode(&rlp).unwrap_err(),
+            alloy_rlp::Error::Custom("MPT: Invalid extension node.")
+        );
+    }
+
+    #[test]
+    fn extension_to_extension_is_an_error() {
+        // Extension [1] pointing to an inlined extension [2] pointing to a digest.
+        let mut inner = hex!("e2 12 a0").to_vec();
+        inner.extend_from_slice(&[0x11; 32]);
+        // The inner extension is 35 bytes long, so wrap it manually in a list header.
+        let mut rlp = hex!("e4 11").to_vec();
+        rlp.extend_from_slice(&inner);
+        assert_eq!(
+            decode(&rlp).unwrap_err(),
+            alloy_rlp::Error::Custom("MPT: Invalid extension node.")
+        );
+    }
+
+    #[test]
+    fn malformed_inputs_do_not_panic() {
+        for rlp in [
+            &hex!("")[..],
+            &hex!("80"),
+            &hex!("c0"),
+            &hex!("c180"),
+            &hex!("c28080"),
+            &hex!("c3800080"),
+            &hex!("c2c0c0"),
+            &hex!("a0"),
+            &hex!("8111"),
+            &hex!("d1808080808080808080808080808080808080"),
+        ] {
+            let _ = decode(rlp);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! A trie split into 16 independent subtries by the first nibble of the keys.
+use super::UnresolvedNode;
+use super::nodes::{BranchNode, BranchNodeChildrenArray, TrieNode};
+use crate::keccak256;
+use crate::trie::{B256Map, Trie};
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use crate::trie::rlp::encode_list_header;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes};
+use alloy_rlp::EMPTY_STRING_CODE;
+use alloy_trie::{EMPTY_ROOT_HASH, Nibbles};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// A trie partitioned by the first nibble of the keys into 16 shards, combined under a root
+/// branch.
+///
+/// Each shard holds the keys of its nibble without that nibble, so it is the subtrie below the
+/// root branch. The shards are updated and hashed independently, in parallel with the `parallel`
+/// feature, which pays off for batches of thousands of keys on a host.
+#[derive(Debug, Clone)]
+pub struct ShardedTrie {
+    shards: [Trie; 16],
+}
+
+impl Default for ShardedTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns the shard of the key and the path of the key within the shard.
+fn split(key: B256) -> (usize, Nibbles) {
+    let path = Nibbles::unpack(key);
+    (path[0] as usize, path.slice(1..))
+}
+
+// Returns the path of the node, which for a branch or digest node is the path of its extension.
+const fn path_mut(node: &mut TrieNode) -> &mut Nibbles {
+    match node {
+        Leaf(leaf) => &mut leaf.path,
+        Branch(branch) => &mut branch.path,
+        Digest(digest) => &mut digest.path,
+    }
+}
+
+impl ShardedTrie {
+    /// Creates an empty trie.
+    pub fn new() -> Self {
+        Self {
+            shards: core::array::from_fn(|_| Trie::new()),
+        }
+    }
+
+    /// Splits a trie into its shards. Fails if the root of the trie is unresolved.
+    pub fn from_trie(trie: Trie) -> Result<Self, UnresolvedNode> {
+        let mut sharded = Self::new();
+        let Some(mut root) = trie.root else {
+            return Ok(sharded);
+        };
+        let path = path_mut(&mut root);
+        if path.is_empty() {
+            match root {
+                Branch(mut branch) => {
+                    for (nibble, child) in branch.children.iter_mut().enumerate() {
+                        sharded.shards[nibble].root = child.take().map(|child| *child);
+                    }
+                }
+                Digest(digest) => {
+                    return Err(UnresolvedNode {
+                        digest: digest.value,
+                    });
+                }
+                // a leaf always has a full path
+                Leaf(_) => unreachable!("MPT: Leaf with an empty path at the root"),
+            }
+        } else {
+            // a root with a path lies entirely in the shard of its first nibble
+            let nibble = path[0] as usize;
+            *path = path.slice(1..);
+            root.clear_cache();
+            sharded.shards[nibble].root = Some(root);
+        }
+        Ok(sharded)
+    }
+
+    /// Joins the shards into a single trie.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the root is unresolved, see [`ShardedTrie::try_into_trie`].
+    pub fn into_trie(self) -> Trie {
+        self.try_into_trie().expect("MPT: Unresolved node access")
+    }
+
+    /// Joins the shards into a single trie, failing if the only non-empty shard has an unresolved
+    /// root without a path: the root of the trie is that node below the nibble of its shard, an
+    /// extension or a leaf depending on the unknown node, like a branch collapsing in
+    /// [`Trie::try_remove`].
+    pub fn try_into_trie(self) -> Result<Trie, UnresolvedNode> {
+        Ok(match self.non_empty_shards() {
+            0 => Trie::new(),
+            1 => {
+                let (nibble, shard) = self
+                    .shards
+                    .into_iter()
+                    .enumerate()
+                    .find(|(_, shard)| shard.root.is_some())
+                    .unwrap();
+                let mut root = shard.root.unwrap();
+                check_resolved(&root)?;
+                prepend(&mut root, nibble);
+                Trie::from_root(Some(root))
+            }
+            _ => {
+                let mut children = BranchNodeChildrenArray::new();
+                for (nibble, shard) in self.shards.into_iter().enumerate() {
+                    if let Some(root) = shard.root {
+                        children.insert(nibble, Box::new(root));
+                    }
+                }
+                Trie::from_root(Some(Branch(BranchNode {
+                    children,
+                    path: Nibbles::default(),
+                    hash: None,
+                })))
+            }
+        })
+    }
+
+    fn non_empty_shards(&self) -> usize {
+        self.shards
+            .iter()
+            .filter(|shard| shard.root.is_some())
+            .count()
+    }
+
+    /// Returns the value under the `key` key, see [`Trie::get`].
+    pub fn get(&self, key: B256) -> Option<&Bytes> {
+        let (shard, path) = split(key);
+        self.shards[shard].get_path(path)
+    }
+
+    /// Inserts a value under the `key` key, see [`Trie::insert`].
+    pub fn insert(&mut self, key: B256, value: Bytes) {
+        let (shard, path) = split(key);
+        self.shards[shard].insert_path(path, value);
+    }
+
+    /// Removes the value under the `key` key, see [`Trie::remove`].
+    pub fn remove(&mut self, key: B256) {
+        let (shard, path) = split(key);
+        self.shards[shard].remove_path(path);
+    }
+
+    /// Applies a batch of updates, where `None` removes the key. The shards are updated in
+    /// parallel with the `parallel` feature.
+    ///
+    /// A key updated several times takes its last update. Within a shard the insertions are then
+    /// applied before the removals, so a removal never collapses a branch onto a sibling the batch
+    /// is about to insert next to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a key lies below an unresolved node.
+    pub fn apply(&mut self, updates: impl IntoIterator<Item = (B256, Option<Bytes>)>) {
+        let mut batches: [B256Map<Option<Bytes>>; 16] = Default::default();
+        for (key, value) in updates {
+            batches[split(key).0].insert(key, value);
+        }
+
+        let apply_batch = |(shard, batch): (&mut Trie, B256Map<Option<Bytes>>)| {
+            let (inserts, removals): (Vec<_>, Vec<_>) =
+                batch.into_iter().partition(|(_, value)| value.is_some());
+            for (key, value) in inserts {
+                shard.insert_path(split(key).1, value.unwrap());
+            }
+            for (key, _) in removals {
+                shard.remove_path(split(key).1);
+            }
+        };
+        #[cfg(feature = "parallel")]
+        self.shards
+            .par_iter_mut()
+            .zip(batches)
+            .for_each(apply_batch);
+        #[cfg(not(feature = "parallel"))]
+        self.shards.iter_mut().zip(batches).for_each(apply_batch);
+    }
+
+    /// Returns the root hash of the trie. The shards are hashed in parallel with the `parallel`
+    /// feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the root is unresolved, see [`ShardedTrie::try_into_trie`].
+    pub fn hash(&mut self) -> B256 {
+        self.try_hash().expect("MPT: Unresolved node access")
+    }
+
+    /// Returns the root hash of the trie, failing if the root is unresolved, see
+    /// [`ShardedTrie::try_into_trie`].
+    pub fn try_hash(&mut self) -> Result<B256, UnresolvedNode> {
+        Ok(match self.non_empty_shards() {
+            0 => EMPTY_ROOT_HASH,
+            1 => {
+                // the root is the root of the only shard, extended with its nibble
+                let (nibble, root) = self
+                    .shards
+                    .iter_mut()
+                    .enumerate()
+                    .find_map(|(nibble, shard)| shard.root.as_mut().map(|root| (nibble, root)))
+                    .unwrap();
+                check_resolved(root)?;
+                prepend(root, nibble);
+                let hash = root.hash();
+                *path_mut(root) = path_mut(root).slice(1..);
+                root.clear_cache();
+                hash
+            }
+            _ => {
+                // each shard root is referenced by the root branch, by its hash or inlined
+                let encode_child = |shard: &mut Trie| {
+                    shard.root.as_mut().map(|root| {
+                        let mut encoded = Vec::with_capacity(33);
+                        root.encode_as_child(&mut encoded);
+                        encoded
+                    })
+                };
+                #[cfg(feature = "parallel")]
+                let children: Vec<_> = self.shards.par_iter_mut().map(encode_child).collect();
+                #[cfg(not(feature = "parallel"))]
+                let children: Vec<_> = self.shards.iter_mut().map(encode_child).collect();
+
+                let mut encoded = Vec::with_capacity(16 * 33 + 1);
+                for child in children {
+                    match child {
+                        Some(mut child) => encoded.append(&mut child),
+                        None => encoded.push(EMPTY_STRING_CODE),
+                    }
+                }
+                // the root branch has no value
+                encoded.push(EMPTY_STRING_CODE);
+                let mut encoded_branch = encode_list_header(encoded.len());
+                encoded_branch.append(&mut encoded);
+                keccak256(encoded_branch)
+            }
+        })
+    }
+}
+
+// Fails if the node, the root of the only non-empty shard, is unresolved without a path.
+fn check_resolved(node: &TrieNode) -> Result<(), UnresolvedNode> {
+    match node {
+        Digest(digest) if digest.path.is_empty() => Err(UnresolvedNode {
+            digest: digest.value,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Prepends the nibble to the path of the node, which was the root of its shard.
+fn prepend(node: &mut TrieNode, nibble: usize) {
+    let path = path_mut(node);
+    *path = Nibbles::from_nibbles([nibble as u8]).join(path);
+    node.clear_cache();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    fn entries(count: u64) -> Vec<(B256, Bytes)> {
+        (0..count)
+            .map(|i| (keccak256(i.to_be_bytes()), Bytes::from(vec![i as u8; 40])))
+            .collect()
+    }
+
+    #[test]
+    fn matches_trie() {
+        for count in [0, 1, 2, 3, 40, 500] {
+            let mut trie = Trie::new();
+            let mut sharded = ShardedTrie::new();
+            for (key, value) in entries(count) {
+                trie.insert(key, value.clone());
+                sharded.insert(key, value);
+            }
+            assert_eq!(sharded.hash(), trie.hash(), "{count}");
+            // hashing restores the shard
+            assert_eq!(sharded.hash(), trie.hash(), "{count}");
+            for (key, value) in entries(count) {
+                assert_eq!(sharded.get(key), Some(&value));
+            }
+            assert_eq!(sharded.clone().into_trie().hash(), trie.hash());
+            assert_eq!(
+                ShardedTrie::from_trie(trie.clone()).unwrap().hash(),
+                trie.hash()
+            );
+        }
+    }
+
+    #[test]
+    fn apply_batches() {
+        let entries = entries(300);
+        let mut sharded = ShardedTrie::new();
+        sharded.apply(
+            entries
+                .iter()
+                .map(|(key, value)| (*key, Some(value.clone()))),
+        );
+
+        // remove all but the last two entries, and overwrite one of them
+        let mut trie = Trie::new();
+        trie.insert(entries[298].0, entries[298].1.clone());
+        trie.insert(entries[299].0, Bytes::from_static(&[1]));
+        let mut updates: Vec<_> = entries[..298].iter().map(|(key, _)| (*key, None)).collect();
+        updates.push((entries[299].0, Some(Bytes::from_static(&[1]))));
+        sharded.apply(updates);
+        assert_eq!(sharded.hash(), trie.hash());
+
+        sharded.apply(entries[298..].iter().map(|(key, _)| (*key, None)));
+        assert_eq!(sharded.hash(), EMPTY_ROOT_HASH);
+
+        // the last update of a key wins
+        let (key, value) = entries[0].clone();
+        sharded.apply([(key, Some(value.clone())), (key, None)]);
+        assert_eq!(sharded.get(key), None);
+        sharded.apply([(key, None), (key, Some(value.clone()))]);
+        assert_eq!(sharded.get(key), Some(&value));
+    }
+
+    #[test]
+    fn single_shard_roots() {
+        // all keys in shard 0x1, under an extension, a leaf or a branch root
+        let keys = [
+            B256::with_last_byte(1) | B256::repeat_byte(0x10),
+            B256::with_last_byte(2) | B256::repeat_byte(0x10),
+        ];
+        let mut sharded = ShardedTrie::new();
+        let mut trie = Trie::new();
+        sharded.insert(keys[0], Bytes::from_static(&[1]));
+        trie.insert(keys[0], Bytes::from_static(&[1]));
+        assert_eq!(sharded.hash(), trie.hash());
+        sharded.insert(keys[1], Bytes::from_static(&[2]));
+        trie.insert(keys[1], Bytes::from_static(&[2]));
+        assert_eq!(sharded.hash(), trie.hash());
+        assert_eq!(
+            ShardedTrie::from_trie(trie.clone()).unwrap().hash(),
+            trie.hash()
+        );
+        assert_eq!(sharded.into_trie().hash(), trie.hash());
+    }
+
+    #[test]
+    fn unresolved_root() {
+        let mut trie = Trie::new();
+        for (key, value) in entries(20) {
+            trie.insert(key, value);
+        }
+        let root = trie.hash();
+        let unresolved = Trie::reveal_from_rlp(root, &crate::B256Map::default());
+        assert_eq!(
+            ShardedTrie::from_trie(unresolved).unwrap_err(),
+            UnresolvedNode { digest: root }
+        );
+    }
+
+    #[test]
+    fn unresolved_single_shard_root() {
+        // two leaves in shards 0x0 and 0x1, too long to be inlined in the root branch
+        let mut keys = [B256::with_last_byte(1), B256::with_last_byte(2)];
+        keys[1][0] = 0x10;
+        let mut trie = Trie::new();
+        for key in keys {
+            trie.insert(key, Bytes::from(vec![1; 40]));
+        }
+        let root = trie.hash();
+        let nodes: crate::B256Map<Bytes> = trie
+            .proof(keys[1])
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+        let mut trie = Trie::reveal_from_rlp(root, &nodes);
+        let mut sharded = ShardedTrie::from_trie(trie.clone()).unwrap();
+        assert_eq!(sharded.try_hash(), Ok(root));
+
+        // removing the revealed leaf leaves the trie with the unresolved leaf of shard 0x0, whose
+        // path is unknown
+        let err = trie.try_remove(keys[1]).unwrap_err();
+        sharded.remove(keys[1]);
+        assert_eq!(sharded.try_hash(), Err(err));
+        assert_eq!(sharded.try_into_trie().unwrap_err(), err);
+    }
+}
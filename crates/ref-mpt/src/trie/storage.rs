@@ -0,0 +1,111 @@
+//! Applying the storage changes of a block, as reth hands them to a state root computation.
+use crate::trie::{Trie, UnresolvedNode};
+use alloy_primitives::B256;
+use reth_trie_common::HashedStorage;
+
+impl Trie {
+    /// Applies the changes of `storage` to this storage trie and returns the new storage root.
+    /// A wiped storage clears the trie first, non-zero values are inserted RLP-encoded and zero
+    /// values remove their slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a slot lies below an unresolved node, see [`Trie::try_apply_hashed_storage`].
+    pub fn apply_hashed_storage(&mut self, storage: &HashedStorage) -> B256 {
+        self.try_apply_hashed_storage(storage)
+            .expect("MPT: Unresolved node access")
+    }
+
+    /// Applies the changes of `storage` and returns the new storage root, see
+    /// [`Trie::apply_hashed_storage`], failing instead of panicking if a slot lies below an
+    /// unresolved node.
+    ///
+    /// All insertions are applied before the removals: removing a slot may collapse a branch onto
+    /// its only other child, which must not be an unresolved node if a slot is about to be
+    /// inserted below the branch anyway. On error the trie holds part of the changes, but applying
+    /// them again is harmless, so that [`Trie::resolving`] can run it until the missing nodes are
+    /// revealed.
+    pub fn try_apply_hashed_storage(
+        &mut self,
+        storage: &HashedStorage,
+    ) -> Result<B256, UnresolvedNode> {
+        if storage.wiped {
+            self.clear();
+        }
+        for (hashed_slot, value) in &storage.storage {
+            if !value.is_zero() {
+                self.try_insert_encodable(*hashed_slot, value)?;
+            }
+        }
+        for (hashed_slot, value) in &storage.storage {
+            if value.is_zero() {
+                self.try_remove(*hashed_slot)?;
+            }
+        }
+        Ok(self.hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+    use crate::trie::B256Map;
+    use alloy_primitives::{Bytes, U256};
+
+    fn slot(i: u64) -> B256 {
+        keccak256(B256::from(U256::from(i)))
+    }
+
+    fn storage_trie(slots: impl IntoIterator<Item = u64>) -> Trie {
+        let mut trie = Trie::new();
+        for i in slots {
+            trie.insert_encodable(slot(i), &U256::from(i));
+        }
+        trie
+    }
+
+    #[test]
+    fn applies_hashed_storage() {
+        let mut storage = HashedStorage::new(false);
+        storage.storage.insert(slot(1), U256::ZERO);
+        storage.storage.insert(slot(2), U256::from(20));
+        storage.storage.insert(slot(17), U256::from(17));
+        storage.storage.insert(slot(18), U256::ZERO);
+        let mut trie = storage_trie(1..=16);
+        let mut expected = storage_trie(2..=17);
+        expected.insert_encodable(slot(2), &U256::from(20));
+        assert_eq!(trie.clone().apply_hashed_storage(&storage), expected.hash());
+
+        // a trie revealing only the first slot misses nodes, which are fetched until it succeeds
+        let mut nodes = B256Map::default();
+        for i in 1..=16 {
+            for rlp in trie.proof(slot(i)).unwrap() {
+                nodes.insert(keccak256(&rlp), rlp);
+            }
+        }
+        let root = trie.hash();
+        let first: B256Map<Bytes> = trie
+            .proof(slot(1))
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+        let mut partial = Trie::reveal_from_rlp(root, &first);
+        assert!(partial.clone().try_apply_hashed_storage(&storage).is_err());
+        let storage_root = partial
+            .resolving(&nodes, |trie| trie.try_apply_hashed_storage(&storage))
+            .unwrap();
+        assert_eq!(storage_root, expected.hash());
+
+        storage.wiped = true;
+        assert_eq!(
+            trie.apply_hashed_storage(&storage),
+            storage_trie([2, 17]).apply_hashed_storage(&storage)
+        );
+        expected = Trie::new();
+        expected.insert_encodable(slot(2), &U256::from(20));
+        expected.insert_encodable(slot(17), &U256::from(17));
+        assert_eq!(trie.hash(), expected.hash());
+    }
+}
@@ -0,0 +1,131 @@
+//! Lookups recording the nodes they traverse, to localize diverging tries.
+use super::UnresolvedNode;
+use super::nodes::TrieNode;
+use crate::trie::Trie;
+use crate::trie::TrieNode::{Branch, Digest, Leaf};
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes};
+use alloy_trie::Nibbles;
+
+/// The kind of a traversed node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// A branch, held by an extension node if its path is not empty.
+    Branch,
+    /// A leaf.
+    Leaf,
+    /// A node only known by its digest, held by an extension node if its path is not empty.
+    Digest,
+}
+
+/// A node traversed by [`Trie::get_with_trace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceNode {
+    /// The kind of the node.
+    pub kind: NodeKind,
+    /// The path fragment of the node: the path of its extension, or the remaining key of a leaf.
+    pub path: Nibbles,
+    /// The cached hash of the node, if it did not change since the trie was last hashed.
+    pub hash: Option<B256>,
+}
+
+impl TrieNode {
+    fn get_with_trace(
+        &self,
+        path: Nibbles,
+        trace: &mut Vec<TraceNode>,
+    ) -> Result<Option<&Bytes>, UnresolvedNode> {
+        let (kind, node_path, hash) = match self {
+            Branch(branch) => (NodeKind::Branch, &branch.path, branch.hash),
+            Leaf(leaf) => (NodeKind::Leaf, &leaf.path, leaf.hash),
+            Digest(digest) => (NodeKind::Digest, &digest.path, digest.hash),
+        };
+        trace.push(TraceNode {
+            kind,
+            path: node_path.clone(),
+            hash,
+        });
+        match self {
+            Branch(branch) => {
+                if !path.starts_with(&branch.path) || path.len() == branch.path.len() {
+                    return Ok(None);
+                }
+                let nibble = path[branch.path.len()];
+                match branch.children.get(nibble as usize) {
+                    Some(child) => child.get_with_trace(path.slice(branch.path.len() + 1..), trace),
+                    None => Ok(None),
+                }
+            }
+            _ => self.get(path),
+        }
+    }
+}
+
+impl Trie {
+    /// Gets the value associated with a pre-hashed 32-byte `key`, with the nodes traversed from
+    /// the root down. The trace of a lookup failing on an unresolved node ends with the digest node.
+    ///
+    /// Comparing the traces of one key in two tries shows the first node where they diverge.
+    pub fn get_with_trace(
+        &self,
+        key: B256,
+    ) -> (Result<Option<&Bytes>, UnresolvedNode>, Vec<TraceNode>) {
+        let mut trace = Vec::new();
+        let value = match self.root.as_ref() {
+            Some(root) => root.get_with_trace(Nibbles::unpack(key), &mut trace),
+            None => Ok(None),
+        };
+        (value, trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+    use crate::trie::B256Map;
+    use std::vec;
+
+    #[test]
+    fn trace_follows_key_path() {
+        let mut trie = Trie::new();
+        let (value, trace) = trie.get_with_trace(B256::ZERO);
+        assert_eq!((value, trace), (Ok(None), vec![]));
+
+        let keys: Vec<B256> = (0_u64..40).map(|i| keccak256(i.to_be_bytes())).collect();
+        for key in &keys {
+            trie.insert(*key, Bytes::copy_from_slice(&key[..]));
+        }
+        let root = trie.hash();
+
+        let (value, trace) = trie.get_with_trace(keys[7]);
+        assert_eq!(value, Ok(Some(&Bytes::copy_from_slice(&keys[7][..]))));
+        assert_eq!(trace[0].hash, Some(root));
+        assert_eq!(trace.last().unwrap().kind, NodeKind::Leaf);
+        assert!(
+            trace[..trace.len() - 1]
+                .iter()
+                .all(|node| node.kind == NodeKind::Branch)
+        );
+        // the nibbles taken at each branch and the path fragments add up to the key
+        let len: usize = trace.iter().map(|node| node.path.len()).sum();
+        assert_eq!(len + trace.len() - 1, 64);
+
+        // a modified node has no cached hash
+        trie.insert(keys[7], Bytes::from_static(&[1]));
+        let (_, trace) = trie.get_with_trace(keys[7]);
+        assert!(trace.iter().all(|node| node.hash.is_none()));
+
+        // the trace of an unresolved lookup ends with the digest
+        let nodes: B256Map<Bytes> = trie
+            .proof(keys[0])
+            .unwrap()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+        let revealed = Trie::reveal_from_rlp(trie.hash(), &nodes);
+        let (value, trace) = revealed.get_with_trace(keys[7]);
+        assert!(value.is_err());
+        assert_eq!(trace.last().unwrap().kind, NodeKind::Digest);
+    }
+}
@@ -1,9 +1,14 @@
 //! Implementation of the simple MPT for state/storage trie.
-use super::nodes::{DigestNode, LeafNode};
-use crate::trie::Trie;
+use super::nodes::{DigestNode, LeafNode, TrieNode};
+#[cfg(feature = "journal")]
+use crate::trie::JournalEntry;
+use crate::trie::{InvalidNode, Trie, UnresolvedNode};
 use crate::trie::TrieNode::{Digest, Leaf};
+use crate::witness_index::{RlpNodes, WitnessMap};
 use alloy_primitives::map::{FbBuildHasher, HashMap};
 use alloy_primitives::{B256, Bytes};
+use alloy_rlp::{Decodable, Encodable};
+use alloy_trie::proof::ProofNodes;
 use alloy_trie::{EMPTY_ROOT_HASH, Nibbles};
 
 /// Added only to make an IDE happy. It is defined in alloy_primitives::map
@@ -12,16 +17,73 @@ pub type B256Map<V> = HashMap<B256, V, FbBuildHasher<32>>;
 impl Trie {
     /// Creates empty trie.
     pub fn new() -> Self {
-        Self { root: None }
+        Self::from_root(None)
+    }
+
+    pub(super) const fn from_root(root: Option<TrieNode>) -> Self {
+        Self {
+            root,
+            #[cfg(feature = "journal")]
+            journal: None,
+        }
     }
 
     /// Inserts a value under the `key` key. Overrides previous values if exists.
     /// `key` must be a pre-hashed 32-byte key (state/storage trie key).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below an unresolved node, see [`Trie::try_insert`].
     pub fn insert(&mut self, key: B256, value: Bytes) {
-        self.insert_path(Nibbles::unpack(key), value);
+        self.try_insert(key, value)
+            .expect("MPT: Unresolved node access");
+    }
+
+    /// Inserts a value under the `key` key, failing instead of panicking if the key lies below an
+    /// unresolved node. The trie is left unchanged on error.
+    pub fn try_insert(&mut self, key: B256, value: Bytes) -> Result<(), UnresolvedNode> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(target: "ref_mpt", "insert", %key).entered();
+        #[cfg(feature = "journal")]
+        let entry = JournalEntry::Insert {
+            key,
+            value: value.clone(),
+        };
+        self.try_insert_path(Nibbles::unpack(key), value)?;
+        #[cfg(feature = "journal")]
+        self.record(entry);
+        Ok(())
+    }
+
+    /// Inserts the RLP encoding of `value` under the `key` key, see [`Trie::insert`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below an unresolved node, see [`Trie::try_insert_encodable`].
+    pub fn insert_encodable<T: Encodable + ?Sized>(&mut self, key: B256, value: &T) {
+        self.insert(key, alloy_rlp::encode(value).into());
+    }
+
+    /// Inserts the RLP encoding of `value` under the `key` key, failing instead of panicking if
+    /// the key lies below an unresolved node, see [`Trie::try_insert`].
+    pub fn try_insert_encodable<T: Encodable + ?Sized>(
+        &mut self,
+        key: B256,
+        value: &T,
+    ) -> Result<(), UnresolvedNode> {
+        self.try_insert(key, alloy_rlp::encode(value).into())
     }
 
     pub(crate) fn insert_path(&mut self, path: Nibbles, value: Bytes) {
+        self.try_insert_path(path, value)
+            .expect("MPT: Unresolved node access");
+    }
+
+    pub(crate) fn try_insert_path(
+        &mut self,
+        path: Nibbles,
+        value: Bytes,
+    ) -> Result<(), UnresolvedNode> {
         match self.root.as_mut() {
             Some(root) => root.insert(path, value),
             None => {
@@ -29,19 +91,47 @@ impl Trie {
                     path,
                     value,
                     hash: None,
-                }))
+                }));
+                Ok(())
             }
         }
     }
 
     /// Gets a value associated with a pre-hashed 32-byte `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below an unresolved node, see [`Trie::try_get`].
     pub fn get(&self, key: B256) -> Option<&Bytes> {
         self.get_path(Nibbles::unpack(key))
     }
 
+    /// Gets a value associated with a pre-hashed 32-byte `key`, failing instead of panicking if
+    /// the key lies below an unresolved node.
+    pub fn try_get(&self, key: B256) -> Result<Option<&Bytes>, UnresolvedNode> {
+        self.try_get_path(Nibbles::unpack(key))
+    }
+
+    /// Gets the value associated with a pre-hashed 32-byte `key`, decoded from its RLP encoding.
+    /// Fails if the value is not exactly the encoding of a `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lies below an unresolved node, see [`Trie::try_get`].
+    pub fn get_decoded<T: Decodable>(&self, key: B256) -> Result<Option<T>, alloy_rlp::Error> {
+        self.get(key)
+            .map(|value| alloy_rlp::decode_exact(value))
+            .transpose()
+    }
+
     pub(crate) fn get_path(&self, path: Nibbles) -> Option<&Bytes> {
+        self.try_get_path(path)
+            .expect("MPT: Unresolved node access")
+    }
+
+    pub(crate) fn try_get_path(&self, path: Nibbles) -> Result<Option<&Bytes>, UnresolvedNode> {
         if self.root.is_none() {
-            None
+            Ok(None)
         } else {
             self.root.as_ref().unwrap().get(path)
         }
@@ -49,43 +139,157 @@ impl Trie {
 
     /// Returns a root hash of the trie
     pub fn hash(&mut self) -> B256 {
-        match self.root.as_mut() {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(target: "ref_mpt", "hash").entered();
+        let root = match self.root.as_mut() {
             Some(root) => root.hash(),
             None => EMPTY_ROOT_HASH,
-        }
+        };
+        #[cfg(feature = "journal")]
+        self.record(JournalEntry::Hash { root });
+        root
     }
 
     /// Removes an element from the trie by pre-hashed 32-byte `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the removal needs an unresolved node, see [`Trie::try_remove`].
     pub fn remove(&mut self, key: B256) {
-        self.remove_path(Nibbles::unpack(key));
+        self.try_remove(key).expect("MPT: Unresolved node access");
+    }
+
+    /// Removes an element from the trie by pre-hashed 32-byte `key`, failing instead of panicking
+    /// if the removal needs an unresolved node: either the key lies below one, or removing it
+    /// would collapse a branch onto one. The trie is left unchanged on error.
+    pub fn try_remove(&mut self, key: B256) -> Result<(), UnresolvedNode> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(target: "ref_mpt", "remove", %key).entered();
+        self.try_remove_path(Nibbles::unpack(key))?;
+        #[cfg(feature = "journal")]
+        self.record(JournalEntry::Remove { key });
+        Ok(())
     }
 
     pub(crate) fn remove_path(&mut self, path: Nibbles) {
+        self.try_remove_path(path)
+            .expect("MPT: Unresolved node access");
+    }
+
+    pub(crate) fn try_remove_path(&mut self, path: Nibbles) -> Result<(), UnresolvedNode> {
         match self.root.as_mut() {
             Some(root) => match root {
                 Leaf(leaf) => {
                     if path.eq(&leaf.path) {
                         self.root = None;
                     }
+                    Ok(())
                 }
                 _ => root.remove(path),
             },
-            None => return,
+            None => Ok(()),
         }
     }
 
-    /// Build a trie according to elements encoded in a hash->value map starting from the `root_hash`
-    pub fn reveal_from_rlp(root_hash: B256, rlp_rep_map: &B256Map<Bytes>) -> Self {
+    /// Removes all elements from the trie.
+    pub fn clear(&mut self) {
+        self.root = None;
+        #[cfg(feature = "journal")]
+        self.record(JournalEntry::Clear);
+    }
+
+    /// Build a trie according to elements encoded in a hash->value map starting from the `root_hash`.
+    /// The map is a [`B256Map`] or a [`WitnessIndex`](crate::WitnessIndex).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a node of the map does not decode, see [`Trie::try_reveal_from_rlp`].
+    pub fn reveal_from_rlp<M: RlpNodes + ?Sized>(root_hash: B256, rlp_rep_map: &M) -> Self {
+        Self::try_reveal_from_rlp(root_hash, rlp_rep_map).expect("MPT: Failed to decode trie node")
+    }
+
+    /// Builds a trie like [`Trie::reveal_from_rlp`], failing instead of panicking if a node found
+    /// in the map does not decode.
+    pub fn try_reveal_from_rlp<M: RlpNodes + ?Sized>(
+        root_hash: B256,
+        rlp_rep_map: &M,
+    ) -> Result<Self, InvalidNode> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            target: "ref_mpt",
+            "reveal",
+            %root_hash,
+            nodes = rlp_rep_map.node_count()
+        )
+        .entered();
         let mut trie = Trie::new();
         if root_hash == EMPTY_ROOT_HASH {
-            return trie;
+            return Ok(trie);
         }
         trie.root = Some(Digest(DigestNode {
             value: root_hash,
             hash: Some(root_hash),
             path: Nibbles::default(),
         }));
-        trie.root.as_mut().unwrap().reveal(rlp_rep_map);
+        trie.root.as_mut().unwrap().try_reveal(rlp_rep_map)?;
+        Ok(trie)
+    }
+
+    /// Builds a trie from the nodes retained by an alloy `HashBuilder`, as returned by
+    /// `HashBuilder::take_proof_nodes`, starting from `root_hash`. The nodes are hashed, and
+    /// those inlined in their parent are ignored.
+    pub fn from_proof_nodes(nodes: &ProofNodes, root_hash: B256) -> Self {
+        let rlp_map: WitnessMap = nodes.values().cloned().collect();
+        Self::reveal_from_rlp(root_hash, &rlp_map)
+    }
+
+    /// Reveals the unresolved nodes of the trie found in `rlp_rep_map`, e.g. nodes fetched after
+    /// an operation failed with [`UnresolvedNode`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a node of the map does not decode, see [`Trie::try_reveal`].
+    pub fn reveal<M: RlpNodes + ?Sized>(&mut self, rlp_rep_map: &M) {
+        self.try_reveal(rlp_rep_map)
+            .expect("MPT: Failed to decode trie node");
+    }
+
+    /// Reveals the unresolved nodes of the trie like [`Trie::reveal`], failing instead of
+    /// panicking if a node found in the map does not decode. The node stays unresolved, and the
+    /// nodes revealed before it stay revealed.
+    pub fn try_reveal<M: RlpNodes + ?Sized>(&mut self, rlp_rep_map: &M) -> Result<(), InvalidNode> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            target: "ref_mpt",
+            "reveal",
+            nodes = rlp_rep_map.node_count()
+        )
+        .entered();
+        #[cfg(feature = "journal")]
+        if let Some(revealed) = self.reveal_recorded(rlp_rep_map) {
+            return revealed;
+        }
+        match self.root.as_mut() {
+            Some(root) => root.try_reveal(rlp_rep_map),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Extend<(B256, Bytes)> for Trie {
+    /// Inserts the values under their pre-hashed 32-byte keys, see [`Trie::insert`].
+    fn extend<I: IntoIterator<Item = (B256, Bytes)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl FromIterator<(B256, Bytes)> for Trie {
+    /// Builds a fully revealed trie holding the values under their pre-hashed 32-byte keys.
+    fn from_iter<I: IntoIterator<Item = (B256, Bytes)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
         trie
     }
 }
@@ -94,8 +298,9 @@ impl Trie {
 mod tests {
     use super::*;
     use alloc::string::ToString;
-    use alloy_primitives::{Bytes, hex, keccak256};
-    use alloy_trie::{HashBuilder, Nibbles};
+    use crate::keccak256;
+    use alloy_primitives::{Bytes, hex};
+    use alloy_trie::{HashBuilder, Nibbles, proof::ProofRetainer};
     use std::collections::BTreeMap;
     use std::{println, vec};
     use std::vec::Vec;
@@ -431,6 +636,30 @@ mod tests {
         assert_eq!(trie.hash(), root_before);
     }
 
+    #[test]
+    fn encodable_values() {
+        use alloy_primitives::U256;
+
+        let list = vec![1_u64, 2, 3];
+        let mut trie = Trie::new();
+        trie.insert_encodable(B256::ZERO, &list);
+        trie.try_insert_encodable(B256::with_last_byte(1), &U256::from(7))
+            .unwrap();
+        assert_eq!(
+            trie.get(B256::ZERO),
+            Some(&Bytes::from(alloy_rlp::encode(&list)))
+        );
+        assert_eq!(trie.get_decoded(B256::ZERO), Ok(Some(list)));
+        assert_eq!(
+            trie.get_decoded(B256::with_last_byte(1)),
+            Ok(Some(U256::from(7)))
+        );
+        assert_eq!(trie.get_decoded::<U256>(B256::with_last_byte(2)), Ok(None));
+        // the encoding of a list is not the encoding of a number
+        assert!(trie.get_decoded::<U256>(B256::ZERO).is_err());
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
     fn insertion_order_independence() {
         let entries = [
@@ -453,11 +682,15 @@ mod tests {
         }
         let reverse_root = reverse.hash();
 
+        let mut collected: Trie = entries.iter().cloned().collect();
+        assert_eq!(collected.hash(), forward_root);
+
         let ordered_map: BTreeMap<_, _> = entries.into_iter().collect();
         assert_eq!(forward_root, reverse_root);
         assert_eq!(forward_root, hash_builder_root(&ordered_map));
     }
 
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
     fn randomized_differential_root_equivalence() {
         let mut model = BTreeMap::<B256, Bytes>::new();
@@ -481,6 +714,113 @@ mod tests {
         }
     }
 
+    // Reveals only the nodes on the paths to `targets` of the trie built from `entries`.
+    fn reveal_partial(entries: &BTreeMap<B256, Bytes>, targets: &[B256]) -> Trie {
+        let retainer = ProofRetainer::new(targets.iter().map(Nibbles::unpack).collect());
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(retainer);
+        for (key, value) in entries {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = hash_builder.root();
+        Trie::from_proof_nodes(&hash_builder.take_proof_nodes(), root)
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn from_proof_nodes() {
+        let entries: BTreeMap<B256, Bytes> = (0_u64..64)
+            .map(|i| {
+                // values of all sizes, so that some nodes are inlined in their parent
+                let value = vec![7; 1 + i as usize];
+                (keccak256(i.to_be_bytes()), Bytes::from(value))
+            })
+            .collect();
+        let targets: Vec<B256> = entries.keys().copied().step_by(9).collect();
+        let mut trie = reveal_partial(&entries, &targets);
+        assert_eq!(trie.hash(), simple_trie_root(&entries));
+        for key in &targets {
+            assert_eq!(trie.get(*key), entries.get(key));
+        }
+        let revealed = trie.iter().count();
+        assert!(revealed >= targets.len() && revealed < entries.len());
+
+        let mut hash_builder = HashBuilder::default();
+        assert_eq!(
+            Trie::from_proof_nodes(&hash_builder.take_proof_nodes(), hash_builder.root()).hash(),
+            EMPTY_ROOT_HASH
+        );
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn remove_collapsing_onto_unresolved_sibling_fails() {
+        let key_a = B256::repeat_byte(0x10);
+        let mut entries = BTreeMap::new();
+        entries.insert(key_a, Bytes::from([1_u8]));
+        entries.insert(B256::repeat_byte(0x20), Bytes::from([2_u8]));
+        entries.insert(B256::repeat_byte(0x21), Bytes::from([3_u8]));
+
+        // The branch below nibble 2 is only known by its hash.
+        let mut trie = reveal_partial(&entries, &[key_a]);
+        let root = trie.hash();
+        assert!(trie.try_remove(key_a).is_err());
+        assert_eq!(trie.hash(), root);
+        assert_eq!(trie.get(key_a), Some(&Bytes::from([1_u8])));
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn reveal_extension_with_inlined_branch() {
+        // Both leaves below the extension are tiny, so the branch is inlined in the extension.
+        let key_b = B256::repeat_byte(0x22);
+        let mut key_c = key_b;
+        key_c[31] = 0x23;
+        let mut entries = BTreeMap::new();
+        entries.insert(B256::repeat_byte(0x10), Bytes::from([1_u8]));
+        entries.insert(key_b, Bytes::from([2_u8]));
+        entries.insert(key_c, Bytes::from([3_u8]));
+
+        let mut trie = reveal_partial(&entries, &entries.keys().copied().collect::<Vec<_>>());
+        assert_eq!(trie.get(key_c), Some(&Bytes::from([3_u8])));
+        trie.insert(key_c, Bytes::from([4_u8]));
+        entries.insert(key_c, Bytes::from([4_u8]));
+        assert_eq!(trie.hash(), hash_builder_root(&entries));
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn remove_collapsing_onto_unresolved_extension() {
+        let key_a = B256::repeat_byte(0x10);
+        let mut entries = BTreeMap::new();
+        entries.insert(key_a, Bytes::from([1_u8]));
+        // Values are large enough for the branch below the extension to be referenced by hash.
+        entries.insert(B256::repeat_byte(0x22), Bytes::from([2_u8; 32]));
+        let mut key_c = B256::repeat_byte(0x22);
+        key_c[31] = 0x23;
+        entries.insert(key_c, Bytes::from([3_u8; 32]));
+
+        // Diverges inside the extension below nibble 2, so the extension is revealed but not
+        // the branch it points to.
+        let mut absent = B256::repeat_byte(0x22);
+        absent[0] = 0x23;
+        let mut trie = reveal_partial(&entries, &[key_a, absent]);
+        let root = trie.hash();
+
+        // Removing or reading a diverging key does not need the unresolved branch.
+        assert_eq!(trie.get(absent), None);
+        assert_eq!(trie.try_remove(absent), Ok(()));
+        assert_eq!(trie.hash(), root);
+        // Inserting below the extension does.
+        assert!(trie.try_insert(key_c, Bytes::from([4_u8])).is_err());
+        assert_eq!(trie.hash(), root);
+
+        // The root branch collapses onto the extension.
+        trie.remove(key_a);
+        entries.remove(&key_a);
+        assert_eq!(trie.hash(), hash_builder_root(&entries));
+    }
+
+    #[cfg(not(feature = "poseidon2"))]
     #[test]
     fn value_size_boundaries_match_hash_builder() {
         for len in [31_usize, 32, 33] {
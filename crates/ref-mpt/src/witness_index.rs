@@ -0,0 +1,242 @@
+//! Lookup of the RLP encoded trie nodes of a witness by their hash.
+use crate::keccak::keccak256;
+use crate::trie::B256Map;
+use alloc::vec::Vec;
+use alloy_primitives::{B256, Bytes};
+
+/// RLP encoded trie nodes looked up by their hash, the source of [`Trie::reveal_from_rlp`].
+///
+/// [`Trie::reveal_from_rlp`]: crate::Trie::reveal_from_rlp
+pub trait RlpNodes {
+    /// Returns the RLP encoding of the node with the given hash.
+    fn rlp(&self, digest: &B256) -> Option<&Bytes>;
+
+    /// Returns the number of nodes.
+    fn node_count(&self) -> usize;
+}
+
+impl RlpNodes for B256Map<Bytes> {
+    fn rlp(&self, digest: &B256) -> Option<&Bytes> {
+        self.get(digest)
+    }
+
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Witness nodes in a hash map by their hash, the usual source of [`Trie::reveal_from_rlp`].
+///
+/// Builds the map from the RLP encoded nodes of a witness, dropping duplicates, and keeps
+/// statistics about the nodes.
+///
+/// [`Trie::reveal_from_rlp`]: crate::Trie::reveal_from_rlp
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WitnessMap {
+    nodes: B256Map<Bytes>,
+    duplicates: usize,
+    total_bytes: usize,
+}
+
+impl WitnessMap {
+    /// Hashes the given RLP encoded nodes. Duplicates are dropped.
+    pub fn from_rlp_nodes(nodes: &[Bytes]) -> Self {
+        nodes.iter().cloned().collect()
+    }
+
+    /// Hashes the trie nodes of an execution witness. Duplicates are dropped.
+    #[cfg(feature = "stateless")]
+    pub fn from_execution_witness(witness: &stateless::ExecutionWitness) -> Self {
+        Self::from_rlp_nodes(&witness.state)
+    }
+
+    /// Adds an RLP encoded node and returns its hash. A node already in the map is counted as a
+    /// duplicate.
+    pub fn insert(&mut self, rlp: Bytes) -> B256 {
+        let digest = keccak256(&rlp);
+        let len = rlp.len();
+        if self.nodes.insert(digest, rlp).is_some() {
+            self.duplicates += 1;
+        } else {
+            self.total_bytes += len;
+        }
+        digest
+    }
+
+    /// Returns the RLP encoding of the node with the given hash.
+    pub fn get(&self, digest: &B256) -> Option<&Bytes> {
+        self.nodes.get(digest)
+    }
+
+    /// Returns the number of distinct nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the map holds no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the number of dropped duplicate nodes.
+    pub const fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Returns the total size of the distinct nodes in bytes.
+    pub const fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Iterates over the hashes and nodes, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&B256, &Bytes)> {
+        self.nodes.iter()
+    }
+
+    /// Returns the nodes by their hash.
+    pub fn into_inner(self) -> B256Map<Bytes> {
+        self.nodes
+    }
+}
+
+impl FromIterator<Bytes> for WitnessMap {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl Extend<Bytes> for WitnessMap {
+    fn extend<I: IntoIterator<Item = Bytes>>(&mut self, iter: I) {
+        for rlp in iter {
+            self.insert(rlp);
+        }
+    }
+}
+
+impl RlpNodes for WitnessMap {
+    fn rlp(&self, digest: &B256) -> Option<&Bytes> {
+        self.get(digest)
+    }
+
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Witness nodes sorted by their hash and looked up by binary search.
+///
+/// An alternative to a [`B256Map`] for zkVM guests: it is built in one pass with a single
+/// allocation, and its layout does not depend on a hasher, so lookups cost the same cycles on
+/// every run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WitnessIndex {
+    entries: Vec<(B256, Bytes)>,
+}
+
+impl WitnessIndex {
+    /// Hashes the given RLP encoded nodes and sorts them by hash. Duplicates are dropped.
+    pub fn from_nodes<'a>(nodes: impl IntoIterator<Item = &'a Bytes>) -> Self {
+        let mut entries: Vec<_> = nodes
+            .into_iter()
+            .map(|rlp| (keccak256(rlp), rlp.clone()))
+            .collect();
+        entries.sort_unstable_by_key(|(digest, _)| *digest);
+        entries.dedup_by_key(|(digest, _)| *digest);
+        Self { entries }
+    }
+
+    /// Returns the RLP encoding of the node with the given hash.
+    pub fn get(&self, digest: &B256) -> Option<&Bytes> {
+        self.entries
+            .binary_search_by(|(key, _)| key.cmp(digest))
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    /// Returns the number of distinct nodes.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the index holds no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over the hashes and nodes in hash order.
+    pub fn iter(&self) -> impl Iterator<Item = (&B256, &Bytes)> {
+        self.entries.iter().map(|(digest, rlp)| (digest, rlp))
+    }
+}
+
+impl RlpNodes for WitnessIndex {
+    fn rlp(&self, digest: &B256) -> Option<&Bytes> {
+        self.get(digest)
+    }
+
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trie;
+    use alloy_trie::{HashBuilder, Nibbles, proof::ProofRetainer};
+    use std::vec;
+
+    #[cfg(not(feature = "poseidon2"))]
+    #[test]
+    fn reveals_like_a_map() {
+        let keys: Vec<B256> = (0_u64..64).map(|i| keccak256(i.to_be_bytes())).collect();
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        let targets = sorted.iter().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+        for key in &sorted {
+            hash_builder.add_leaf(Nibbles::unpack(key), key.as_slice());
+        }
+        let root = hash_builder.root();
+        let mut nodes: Vec<Bytes> = hash_builder.take_proof_nodes().values().cloned().collect();
+        nodes.extend(nodes.clone());
+
+        let index = WitnessIndex::from_nodes(&nodes);
+        let map = WitnessMap::from_rlp_nodes(&nodes);
+        assert_eq!(index.len(), map.len());
+        assert_eq!(map.duplicates(), map.len());
+        assert_eq!(
+            map.total_bytes(),
+            nodes[..map.len()]
+                .iter()
+                .map(|rlp| rlp.len())
+                .sum::<usize>()
+        );
+        assert!(
+            index
+                .iter()
+                .all(|(digest, rlp)| map.get(digest) == Some(rlp))
+        );
+        assert_eq!(index.get(&B256::ZERO), None);
+
+        let mut from_index = Trie::reveal_from_rlp(root, &index);
+        let mut from_map = Trie::reveal_from_rlp(root, &map);
+        assert_eq!(from_index.hash(), root);
+        assert_eq!(from_map.hash(), root);
+        for key in keys {
+            assert_eq!(from_index.get(key), from_map.get(key));
+            assert_eq!(from_index.get(key).unwrap()[..], key[..]);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let index = WitnessIndex::from_nodes(vec![]);
+        assert!(index.is_empty());
+        assert!(WitnessMap::from_rlp_nodes(&[]).is_empty());
+        assert_eq!(index.get(&keccak256([0x80])), None);
+    }
+}
@@ -0,0 +1,12 @@
+//! Fetching blocks, execution witnesses and the trie nodes missing from them over JSON-RPC.
+//!
+//! [`RpcClient`] wraps the `debug` and `eth` methods needed to build a `StatelessInput` fixture,
+//! and [`RpcNodeProvider`] resolves the trie nodes a witness misses, either while a trie is used
+//! or ahead of time with [`repair_witness`].
+// the dependencies of the binary are listed for the library too
+#![allow(unused_crate_dependencies)]
+pub mod provider;
+pub mod rpc;
+
+pub use provider::{repair_witness, RpcNodeProvider};
+pub use rpc::{fetch_stateless_input, FetchError, HttpTransport, RpcClient, Transport};
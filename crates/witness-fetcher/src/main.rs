@@ -0,0 +1,138 @@
+//! Fetches a block and its execution witness from a JSON-RPC node and writes them as a
+//! `StatelessInput` JSON fixture, as consumed by the integration tests.
+//!
+//! The block is taken from `debug_getRawBlock` and the witness from `debug_executionWitness`, so
+//! the node has to expose the `debug` namespace (e.g. reth with `--http.api eth,debug`).
+//!
+//! ```text
+//! cargo run -p witness-fetcher --features fetch-block -- --rpc-url http://localhost:8545 --block 23439901
+//! ```
+//!
+//! The chain config is that of the known chain matching the node's chain id, or else the node's
+//! own, from `debug_chainConfig`.
+//!
+//! With `--repair`, the trie nodes the witness misses on the paths of its keys are fetched from
+//! the node with `debug_dbGet` or `eth_getProof` and added to the witness.
+// the dependencies of the library are listed for the binary too
+#![allow(unused_crate_dependencies)]
+use alloy_consensus::Header;
+use alloy_genesis::ChainConfig;
+use clap::{Parser, ValueEnum};
+use reth_chainspec::{ChainSpec, HOLESKY, HOODI, MAINNET, SEPOLIA};
+use std::{error::Error, fs::File, io::BufWriter, path::PathBuf, sync::Arc};
+use witness_fetcher::{fetch_stateless_input, repair_witness, RpcClient, RpcNodeProvider};
+
+/// Known chains whose config can be used without a `--chain-config` file.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Chain {
+    Mainnet,
+    Sepolia,
+    Holesky,
+    Hoodi,
+}
+
+impl Chain {
+    const ALL: [Self; 4] = [Self::Mainnet, Self::Sepolia, Self::Holesky, Self::Hoodi];
+
+    fn chain_spec(self) -> &'static Arc<ChainSpec> {
+        match self {
+            Self::Mainnet => &MAINNET,
+            Self::Sepolia => &SEPOLIA,
+            Self::Holesky => &HOLESKY,
+            Self::Hoodi => &HOODI,
+        }
+    }
+
+    fn chain_id(self) -> u64 {
+        self.chain_spec().genesis.config.chain_id
+    }
+
+    fn from_chain_id(chain_id: u64) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|chain| chain.chain_id() == chain_id)
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Writes a StatelessInput fixture for a block fetched over JSON-RPC")]
+struct Args {
+    /// HTTP endpoint of a node exposing the `debug` namespace.
+    #[arg(long)]
+    rpc_url: String,
+    /// Number of the block to fetch.
+    #[arg(long)]
+    block: u64,
+    /// Chain whose config is embedded in the fixture. Defaults to the known chain matching the
+    /// chain id of the node, or to the config returned by the node for other chains.
+    #[arg(long, value_enum)]
+    chain: Option<Chain>,
+    /// JSON file with a chain config, overriding `--chain` (e.g. for devnets).
+    #[arg(long)]
+    chain_config: Option<PathBuf>,
+    /// Output file. Defaults to `rpc_block_<block>.json`.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Add the trie nodes missing from the witness, fetched from the node.
+    #[arg(long)]
+    repair: bool,
+}
+
+/// Returns the config of the requested chain, of the known chain matching the node's chain id,
+/// or else the node's own config. Fails if the node serves another chain than the requested one,
+/// so that a fixture never embeds a mismatching config.
+fn chain_config(client: &RpcClient, chain: Option<Chain>) -> Result<ChainConfig, Box<dyn Error>> {
+    let chain_id = client.chain_id()?;
+    match chain.or_else(|| Chain::from_chain_id(chain_id)) {
+        Some(chain) if chain.chain_id() != chain_id => Err(format!(
+            "node serves chain id {chain_id}, not {chain:?} ({})",
+            chain.chain_id()
+        )
+        .into()),
+        Some(chain) => Ok(chain.chain_spec().genesis.config.clone()),
+        None => Ok(client.chain_config()?),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let client = RpcClient::new(args.rpc_url);
+    let chain_config: ChainConfig = match &args.chain_config {
+        Some(path) => serde_json::from_reader(File::open(path)?)?,
+        None => chain_config(&client, args.chain)?,
+    };
+
+    let mut input = fetch_stateless_input(&client, args.block, chain_config)?;
+    if args.repair {
+        let parent = args
+            .block
+            .checked_sub(1)
+            .ok_or("the genesis block has no witness")?;
+        let pre_state_root = input
+            .witness
+            .headers
+            .iter()
+            .filter_map(|rlp| alloy_rlp::decode_exact::<Header>(rlp).ok())
+            .find(|header| header.number == parent)
+            .ok_or("the witness does not include the parent header")?
+            .state_root;
+        let provider = RpcNodeProvider::new(client, parent);
+        let added = repair_witness(&provider, &mut input.witness, pre_state_root)?;
+        println!("added {added} missing witness nodes");
+    }
+
+    let output = args
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("rpc_block_{}.json", args.block)));
+    serde_json::to_writer(BufWriter::new(File::create(&output)?), &input)?;
+    println!(
+        "wrote block {} ({} witness nodes, {} codes) to {}",
+        args.block,
+        input.witness.state.len(),
+        input.witness.codes.len(),
+        output.display()
+    );
+
+    Ok(())
+}
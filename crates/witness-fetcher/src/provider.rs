@@ -0,0 +1,439 @@
+//! Trie nodes fetched from a JSON-RPC node, used to repair incomplete witnesses.
+use crate::rpc::{FetchError, HttpTransport, RpcClient, Transport};
+use alloy_primitives::{keccak256, Address, Bytes, B256};
+use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
+use ref_mpt::{B256Map, NodeProvider, Trie, WitnessMap};
+use stateless::ExecutionWitness;
+use std::sync::Mutex;
+
+/// Node provider fetching trie nodes from a JSON-RPC node, and caching them.
+///
+/// Nodes are looked up by hash with `debug_dbGet`, which only finds them on nodes storing the
+/// trie by hash (e.g. geth with `--state.scheme hash`). Other nodes are covered by fetching the
+/// `eth_getProof` proofs of the keys whose path is missing, which hold the same nodes.
+///
+/// The provider is blocking: [`NodeProvider::node`] sends a request on the calling thread for
+/// every node missing from the cache, so an async executor must call it from a blocking task
+/// (e.g. `tokio::task::spawn_blocking`). Prefetching the nodes of a whole witness with
+/// [`repair_witness`] batches the requests instead, one per level of the tries.
+#[derive(Debug)]
+pub struct RpcNodeProvider<T = HttpTransport> {
+    client: RpcClient<T>,
+    /// Number of the block whose post-state is the pre-state of the witness.
+    parent: u64,
+    cache: Mutex<B256Map<Bytes>>,
+}
+
+impl<T: Transport> RpcNodeProvider<T> {
+    /// Creates a provider for the pre-state of the child of the `parent` block.
+    pub fn new(client: RpcClient<T>, parent: u64) -> Self {
+        Self {
+            client,
+            parent,
+            cache: Mutex::new(B256Map::default()),
+        }
+    }
+
+    /// Caches the given nodes by their hash.
+    fn insert(&self, nodes: impl IntoIterator<Item = Bytes>) {
+        let mut cache = self.cache.lock().unwrap();
+        for rlp in nodes {
+            cache.insert(keccak256(&rlp), rlp);
+        }
+    }
+
+    fn cached(&self, hash: &B256) -> Option<Bytes> {
+        self.cache.lock().unwrap().get(hash).cloned()
+    }
+
+    /// Fetches the given nodes which are not cached yet with a single batch of `debug_dbGet`
+    /// requests. Nodes unknown to the node are skipped.
+    pub fn fetch_nodes(&self, hashes: &[B256]) -> Result<(), FetchError> {
+        let missing: Vec<B256> = hashes
+            .iter()
+            .filter(|hash| self.cached(hash).is_none())
+            .copied()
+            .collect();
+        let nodes = self.client.db_get_batch(&missing)?;
+        self.insert(
+            nodes
+                .into_iter()
+                .zip(&missing)
+                .filter_map(|(rlp, hash)| rlp.filter(|rlp| keccak256(rlp) == *hash)),
+        );
+        Ok(())
+    }
+
+    /// Fetches the proofs of the accounts with some of their storage slots with a single batch
+    /// of `eth_getProof` requests, and caches their nodes.
+    pub fn fetch_proofs(&self, accounts: &[(Address, Vec<B256>)]) -> Result<(), FetchError> {
+        let proofs = self.client.proof_batch(accounts, self.parent)?;
+        for proof in proofs.into_iter().flatten() {
+            self.insert(proof.account_proof);
+            self.insert(
+                proof
+                    .storage_proof
+                    .into_iter()
+                    .flat_map(|storage_proof| storage_proof.proof),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> NodeProvider for RpcNodeProvider<T> {
+    fn node(&self, hash: B256) -> Option<Bytes> {
+        if let Some(rlp) = self.cached(&hash) {
+            return Some(rlp);
+        }
+        self.fetch_nodes(&[hash]).ok()?;
+        self.cached(&hash)
+    }
+}
+
+/// Adds the nodes the witness misses on the paths of its keys, fetched from `provider`. Returns
+/// the number of nodes added.
+///
+/// The keys of the witness list each account address followed by its storage slots. The missing
+/// nodes are fetched level by level, with one batch per level of the trie.
+pub fn repair_witness(
+    provider: &RpcNodeProvider<impl Transport>,
+    witness: &mut ExecutionWitness,
+    pre_state_root: B256,
+) -> Result<usize, FetchError> {
+    let mut accounts: Vec<(Address, Vec<B256>)> = Vec::new();
+    for key in &witness.keys {
+        match key.len() {
+            20 => accounts.push((Address::from_slice(key), Vec::new())),
+            32 => {
+                if let Some((_, slots)) = accounts.last_mut() {
+                    slots.push(B256::from_slice(key));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut nodes = WitnessMap::from_execution_witness(witness);
+    let mut fetched = Vec::new();
+
+    let mut state = Trie::reveal_from_rlp(pre_state_root, &nodes);
+    let keys: Vec<B256> = accounts
+        .iter()
+        .map(|(address, _)| keccak256(address))
+        .collect();
+    resolve_paths(
+        provider,
+        &mut state,
+        &keys,
+        &mut nodes,
+        &mut fetched,
+        |blocked| {
+            let accounts: Vec<_> = blocked
+                .iter()
+                .map(|&i| (accounts[i].0, Vec::new()))
+                .collect();
+            provider.fetch_proofs(&accounts)
+        },
+    )?;
+
+    for ((address, slots), hashed_address) in accounts.iter().zip(keys) {
+        let storage_root = match state.get(hashed_address) {
+            Some(rlp) => {
+                alloy_rlp::decode_exact::<TrieAccount>(rlp)
+                    .map_err(FetchError::Node)?
+                    .storage_root
+            }
+            None => EMPTY_ROOT_HASH,
+        };
+        if storage_root == EMPTY_ROOT_HASH || slots.is_empty() {
+            continue;
+        }
+        let mut storage = Trie::reveal_from_rlp(storage_root, &nodes);
+        let keys: Vec<B256> = slots.iter().map(keccak256).collect();
+        resolve_paths(
+            provider,
+            &mut storage,
+            &keys,
+            &mut nodes,
+            &mut fetched,
+            |blocked| {
+                let slots = blocked.iter().map(|&i| slots[i]).collect();
+                provider.fetch_proofs(&[(*address, slots)])
+            },
+        )?;
+    }
+
+    let added = fetched.len();
+    witness.state.append(&mut fetched);
+    Ok(added)
+}
+
+/// Reveals the nodes on the paths of `keys` in the trie, one level per round, adding them to
+/// `nodes` and `fetched`. The missing nodes of a round are fetched with `debug_dbGet`, and those
+/// still missing with `fetch_proofs`, which gets the indices of the keys they block.
+fn resolve_paths<T: Transport>(
+    provider: &RpcNodeProvider<T>,
+    trie: &mut Trie,
+    keys: &[B256],
+    nodes: &mut WitnessMap,
+    fetched: &mut Vec<Bytes>,
+    fetch_proofs: impl Fn(&[usize]) -> Result<(), FetchError>,
+) -> Result<(), FetchError> {
+    loop {
+        let blocked: Vec<(usize, B256)> = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(i, key)| trie.try_get(*key).err().map(|err| (i, err.digest)))
+            .collect();
+        if blocked.is_empty() {
+            return Ok(());
+        }
+
+        let mut missing: Vec<B256> = blocked.iter().map(|(_, digest)| *digest).collect();
+        missing.sort_unstable();
+        missing.dedup();
+        provider.fetch_nodes(&missing)?;
+        let unknown: Vec<usize> = blocked
+            .iter()
+            .filter(|(_, digest)| provider.cached(digest).is_none())
+            .map(|(i, _)| *i)
+            .collect();
+        if !unknown.is_empty() {
+            fetch_proofs(&unknown)?;
+        }
+
+        let mut round = B256Map::default();
+        for digest in missing {
+            let rlp = provider
+                .cached(&digest)
+                .ok_or(FetchError::MissingNode(digest))?;
+            round.insert(digest, rlp);
+        }
+        trie.reveal(&round);
+        fetched.extend(round.values().cloned());
+        nodes.extend(round.into_values());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+    use serde_json::{json, Value};
+
+    /// A node answering `debug_dbGet` from its trie nodes, if `db_get` is set, and `eth_getProof`
+    /// from its tries, logging the method and size of every request.
+    #[derive(Debug)]
+    struct MockNode {
+        state: Mutex<Trie>,
+        storages: Mutex<B256Map<Trie>>,
+        nodes: B256Map<Bytes>,
+        db_get: bool,
+        requests: Mutex<Vec<(String, usize)>>,
+    }
+
+    impl MockNode {
+        fn answer(&self, request: &Value) -> Value {
+            let params = &request["params"];
+            let result = match request["method"].as_str().unwrap() {
+                "debug_dbGet" => {
+                    let hash: B256 = serde_json::from_value(params[0].clone()).unwrap();
+                    self.nodes
+                        .get(&hash)
+                        .filter(|_| self.db_get)
+                        .map(|rlp| json!(rlp))
+                }
+                "eth_getProof" => {
+                    let address: Address = serde_json::from_value(params[0].clone()).unwrap();
+                    let slots: Vec<B256> = serde_json::from_value(params[1].clone()).unwrap();
+                    let hashed_address = keccak256(address);
+                    let account_proof = self.state.lock().unwrap().proof(hashed_address).unwrap();
+                    let mut storages = self.storages.lock().unwrap();
+                    let storage_proof: Vec<Value> = slots
+                        .iter()
+                        .map(|slot| {
+                            let proof = storages
+                                .get_mut(&hashed_address)
+                                .map(|storage| storage.proof(keccak256(slot)).unwrap())
+                                .unwrap_or_default();
+                            json!({ "proof": proof })
+                        })
+                        .collect();
+                    Some(json!({
+                        "accountProof": account_proof,
+                        "storageProof": storage_proof,
+                    }))
+                }
+                method => panic!("unexpected method {method}"),
+            };
+            match result {
+                Some(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32000, "message": "not found" },
+                }),
+            }
+        }
+    }
+
+    impl Transport for &MockNode {
+        fn send(&self, request: &Value) -> Result<Value, FetchError> {
+            let requests = match request {
+                Value::Array(requests) => requests.as_slice(),
+                request => core::slice::from_ref(request),
+            };
+            let method = requests[0]["method"].as_str().unwrap().to_owned();
+            self.requests.lock().unwrap().push((method, requests.len()));
+            let responses: Vec<Value> = requests.iter().map(|r| self.answer(r)).collect();
+            Ok(match request {
+                Value::Array(_) => Value::Array(responses),
+                _ => responses.into_iter().next().unwrap(),
+            })
+        }
+    }
+
+    /// Builds a node with 40 accounts, the first one with 40 storage slots, and a witness of
+    /// the first two accounts and some slots of the first one, holding only the state root node.
+    fn mock_node(db_get: bool) -> (MockNode, ExecutionWitness, B256) {
+        let addresses: Vec<Address> = (1..=40_u8).map(Address::repeat_byte).collect();
+        let slots: Vec<B256> = (1..=40_u64)
+            .map(|slot| B256::from(U256::from(slot)))
+            .collect();
+
+        let mut storage = Trie::new();
+        for (i, slot) in slots.iter().enumerate() {
+            storage.insert_encodable(keccak256(slot), &U256::from(i + 1));
+        }
+        let mut state = Trie::new();
+        for (i, address) in addresses.iter().enumerate() {
+            let account = TrieAccount {
+                nonce: 1,
+                balance: U256::from(i),
+                storage_root: if i == 0 {
+                    storage.hash()
+                } else {
+                    EMPTY_ROOT_HASH
+                },
+                ..Default::default()
+            };
+            state.insert_encodable(keccak256(address), &account);
+        }
+        let pre_state_root = state.hash();
+
+        let mut nodes = B256Map::default();
+        let proofs = addresses
+            .iter()
+            .map(|address| state.proof(keccak256(address)).unwrap())
+            .chain(
+                slots
+                    .iter()
+                    .map(|slot| storage.proof(keccak256(slot)).unwrap()),
+            );
+        for rlp in proofs.flatten() {
+            nodes.insert(keccak256(&rlp), rlp);
+        }
+
+        let keys = [
+            addresses[0].as_slice(),
+            slots[2].as_slice(),
+            slots[30].as_slice(),
+            addresses[1].as_slice(),
+        ];
+        let witness = ExecutionWitness {
+            state: vec![nodes[&pre_state_root].clone()],
+            keys: keys.into_iter().map(Bytes::copy_from_slice).collect(),
+            ..Default::default()
+        };
+        let node = MockNode {
+            state: Mutex::new(state),
+            storages: Mutex::new(B256Map::from_iter([(keccak256(addresses[0]), storage)])),
+            nodes,
+            db_get,
+            requests: Mutex::default(),
+        };
+        (node, witness, pre_state_root)
+    }
+
+    /// Asserts that the witness resolves the paths of all its keys.
+    fn assert_complete(witness: &ExecutionWitness, pre_state_root: B256) {
+        let nodes = WitnessMap::from_execution_witness(witness);
+        let state = Trie::reveal_from_rlp(pre_state_root, &nodes);
+        let account = state.try_get(keccak256(&witness.keys[0])).unwrap().unwrap();
+        let storage_root = alloy_rlp::decode_exact::<TrieAccount>(account)
+            .unwrap()
+            .storage_root;
+        let storage = Trie::reveal_from_rlp(storage_root, &nodes);
+        for slot in &witness.keys[1..3] {
+            assert!(storage.try_get(keccak256(slot)).unwrap().is_some());
+        }
+        assert!(state
+            .try_get(keccak256(&witness.keys[3]))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn fetches_and_caches_nodes() {
+        let (node, _, pre_state_root) = mock_node(true);
+        let provider = RpcNodeProvider::new(RpcClient::with_transport(&node), 0);
+
+        let unknown = B256::repeat_byte(0xee);
+        provider.fetch_nodes(&[pre_state_root, unknown]).unwrap();
+        assert_eq!(
+            *node.requests.lock().unwrap(),
+            [("debug_dbGet".to_owned(), 2)]
+        );
+        assert_eq!(
+            provider.cached(&pre_state_root),
+            node.nodes.get(&pre_state_root).cloned()
+        );
+        assert_eq!(provider.cached(&unknown), None);
+
+        // cached nodes are not requested again
+        provider.fetch_nodes(&[pre_state_root]).unwrap();
+        assert_eq!(
+            provider.node(pre_state_root),
+            node.nodes.get(&pre_state_root).cloned()
+        );
+        assert_eq!(node.requests.lock().unwrap().len(), 1);
+        assert_eq!(provider.node(unknown), None);
+        assert_eq!(node.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn repairs_witness_with_db_get() {
+        let (node, mut witness, pre_state_root) = mock_node(true);
+        let provider = RpcNodeProvider::new(RpcClient::with_transport(&node), 0);
+
+        let added = repair_witness(&provider, &mut witness, pre_state_root).unwrap();
+        assert_eq!(added, witness.state.len() - 1);
+        assert_complete(&witness, pre_state_root);
+        // one batch per level of each trie, and no proofs
+        let requests = node.requests.lock().unwrap();
+        assert!(requests.iter().all(|(method, _)| method == "debug_dbGet"));
+        assert!(requests.len() < added);
+    }
+
+    #[test]
+    fn repairs_witness_with_proofs() {
+        let (node, mut witness, pre_state_root) = mock_node(false);
+        let provider = RpcNodeProvider::new(RpcClient::with_transport(&node), 0);
+
+        let added = repair_witness(&provider, &mut witness, pre_state_root).unwrap();
+        assert_eq!(added, witness.state.len() - 1);
+        assert_complete(&witness, pre_state_root);
+        // the first round fetches the whole paths, so each trie takes one round
+        let requests = node.requests.lock().unwrap();
+        assert_eq!(
+            *requests,
+            [
+                ("debug_dbGet".to_owned(), 2),
+                ("eth_getProof".to_owned(), 2),
+                ("debug_dbGet".to_owned(), 1),
+                ("eth_getProof".to_owned(), 1),
+            ]
+        );
+    }
+}
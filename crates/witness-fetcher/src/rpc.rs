@@ -0,0 +1,254 @@
+//! Minimal blocking JSON-RPC client for the methods needed to build a fixture.
+//!
+//! The client sends its requests through a [`Transport`], over HTTP with [`HttpTransport`] by
+//! default, or to a mock in tests.
+use alloy_genesis::ChainConfig;
+use alloy_primitives::{Address, Bytes, B256, U64};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use stateless::{ExecutionWitness, StatelessInput};
+use std::fmt;
+
+/// Errors returned while fetching a fixture.
+#[derive(Debug)]
+pub enum FetchError {
+    /// The HTTP request failed.
+    Transport(Box<ureq::Error>),
+    /// The response could not be read or parsed.
+    Io(std::io::Error),
+    /// The node answered with a JSON-RPC error.
+    Rpc {
+        /// JSON-RPC method that failed.
+        method: &'static str,
+        /// Error object returned by the node.
+        error: Value,
+    },
+    /// The raw block returned by the node is not a valid RLP encoded block.
+    Block(alloy_rlp::Error),
+    /// A trie node the witness is missing could not be fetched from the node.
+    MissingNode(B256),
+    /// A witness node or account is not validly RLP encoded.
+    Node(alloy_rlp::Error),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(err) => write!(f, "request failed: {err}"),
+            Self::Io(err) => write!(f, "invalid response: {err}"),
+            Self::Rpc { method, error } => write!(f, "{method} failed: {error}"),
+            Self::Block(err) => write!(f, "invalid raw block: {err}"),
+            Self::MissingNode(hash) => write!(f, "node {hash} not found"),
+            Self::Node(err) => write!(f, "invalid trie node: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<ureq::Error> for FetchError {
+    fn from(err: ureq::Error) -> Self {
+        Self::Transport(Box::new(err))
+    }
+}
+
+impl From<std::io::Error> for FetchError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Sends JSON-RPC requests, single or batched, and returns the responses.
+///
+/// Implementations block the calling thread until the response is received.
+pub trait Transport {
+    /// Sends the JSON-RPC `request`, an object or an array of objects for a batch, and returns
+    /// the response.
+    fn send(&self, request: &Value) -> Result<Value, FetchError>;
+}
+
+/// Blocking HTTP transport, posting the requests to the node at a URL.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpTransport {
+    /// Creates a transport for the node at `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            agent: ureq::Agent::new(),
+        }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn send(&self, request: &Value) -> Result<Value, FetchError> {
+        Ok(self.agent.post(&self.url).send_json(request)?.into_json()?)
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    #[serde(default)]
+    id: usize,
+    result: Option<T>,
+    error: Option<Value>,
+}
+
+/// Response of `eth_getProof`, without the account fields.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProof {
+    /// State trie nodes on the path of the account.
+    pub account_proof: Vec<Bytes>,
+    /// Proofs of the requested storage slots.
+    pub storage_proof: Vec<StorageProof>,
+}
+
+/// Storage slot proof of an `eth_getProof` response.
+#[derive(Debug, Deserialize)]
+pub struct StorageProof {
+    /// Storage trie nodes on the path of the slot.
+    pub proof: Vec<Bytes>,
+}
+
+/// Blocking JSON-RPC client.
+#[derive(Debug, Clone)]
+pub struct RpcClient<T = HttpTransport> {
+    transport: T,
+}
+
+impl RpcClient {
+    /// Creates a client for the node at `url`, over HTTP.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_transport(HttpTransport::new(url))
+    }
+}
+
+impl<Tr: Transport> RpcClient<Tr> {
+    /// Creates a client sending its requests through `transport`.
+    pub const fn with_transport(transport: Tr) -> Self {
+        Self { transport }
+    }
+
+    fn request<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: Value,
+    ) -> Result<T, FetchError> {
+        let response: RpcResponse<T> = serde_json::from_value(self.transport.send(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        }))?)
+        .map_err(std::io::Error::from)?;
+
+        match (response.result, response.error) {
+            (Some(result), None) => Ok(result),
+            (_, error) => Err(FetchError::Rpc {
+                method,
+                error: error.unwrap_or(Value::Null),
+            }),
+        }
+    }
+
+    /// Sends a batch of requests for the same method, one per entry of `params`. Returns the
+    /// results in the order of `params`, `None` for the requests the node answered with an error.
+    fn batch<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: impl IntoIterator<Item = Value>,
+    ) -> Result<Vec<Option<T>>, FetchError> {
+        let requests: Vec<Value> = params
+            .into_iter()
+            .enumerate()
+            .map(|(id, params)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": method,
+                    "params": params,
+                })
+            })
+            .collect();
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let len = requests.len();
+        let responses: Vec<RpcResponse<T>> =
+            serde_json::from_value(self.transport.send(&Value::Array(requests))?)
+                .map_err(std::io::Error::from)?;
+
+        // the responses of a batch may come in any order
+        let mut results: Vec<Option<T>> = (0..len).map(|_| None).collect();
+        for response in responses {
+            if let Some(slot) = results.get_mut(response.id) {
+                *slot = response.result;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Returns the chain id of the node.
+    pub fn chain_id(&self) -> Result<u64, FetchError> {
+        let chain_id: U64 = self.request("eth_chainId", json!([]))?;
+        Ok(chain_id.to())
+    }
+
+    /// Returns the chain config of the node, as in its genesis file.
+    pub fn chain_config(&self) -> Result<ChainConfig, FetchError> {
+        self.request("debug_chainConfig", json!([]))
+    }
+
+    /// Returns the RLP encoding of the block with the given number.
+    pub fn raw_block(&self, number: u64) -> Result<Bytes, FetchError> {
+        self.request("debug_getRawBlock", json!([format!("{number:#x}")]))
+    }
+
+    /// Returns the witness needed to execute the block with the given number statelessly.
+    pub fn execution_witness(&self, number: u64) -> Result<ExecutionWitness, FetchError> {
+        self.request("debug_executionWitness", json!([format!("{number:#x}")]))
+    }
+
+    /// Returns the values stored under the given keys in the node's database, in a single batch.
+    /// With a hash-based trie storage, trie nodes are stored under their hash.
+    pub fn db_get_batch(&self, keys: &[B256]) -> Result<Vec<Option<Bytes>>, FetchError> {
+        self.batch("debug_dbGet", keys.iter().map(|key| json!([key])))
+    }
+
+    /// Returns the proofs of the accounts and their storage slots at the end of the block with
+    /// the given number, in a single batch.
+    pub fn proof_batch(
+        &self,
+        accounts: &[(Address, Vec<B256>)],
+        number: u64,
+    ) -> Result<Vec<Option<AccountProof>>, FetchError> {
+        self.batch(
+            "eth_getProof",
+            accounts
+                .iter()
+                .map(|(address, slots)| json!([address, slots, format!("{number:#x}")])),
+        )
+    }
+}
+
+/// Fetches the block with the given number and its witness, and assembles a [`StatelessInput`].
+pub fn fetch_stateless_input(
+    client: &RpcClient<impl Transport>,
+    number: u64,
+    chain_config: ChainConfig,
+) -> Result<StatelessInput, FetchError> {
+    let raw_block = client.raw_block(number)?;
+    let witness = client.execution_witness(number)?;
+
+    Ok(StatelessInput {
+        block: alloy_rlp::decode_exact(&raw_block).map_err(FetchError::Block)?,
+        witness,
+        chain_config,
+    })
+}
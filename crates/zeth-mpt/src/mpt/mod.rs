@@ -447,6 +447,29 @@ mod tests {
         assert_eq!(trie.get([1, 2, 3]), None);
     }
 
+    /// MPT test vectors exported from evmone, shared with the `ref-mpt` tests.
+    const EVMONE_VECTORS: &str = include_str!("../../../../test_vectors/evmone_state_mpt.json");
+
+    fn decode_hex(value: &serde_json::Value) -> Vec<u8> {
+        alloy_primitives::hex::decode(value.as_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn evmone_vectors() {
+        let vectors: serde_json::Value = serde_json::from_str(EVMONE_VECTORS).unwrap();
+        for test in vectors["tests"].as_array().unwrap() {
+            let mut trie = Trie::default();
+            let mut cached_trie = CachedTrie::default();
+            for entry in test["entries"].as_array().unwrap() {
+                trie.insert(decode_hex(&entry[0]), Bytes::from(decode_hex(&entry[1])));
+                cached_trie.insert(decode_hex(&entry[0]), Bytes::from(decode_hex(&entry[1])));
+            }
+            let root = decode_hex(&test["root"]);
+            assert_eq!(trie.hash_slow().as_slice(), root, "{}", test["name"]);
+            assert_eq!(cached_trie.hash().as_slice(), root, "{}", test["name"]);
+        }
+    }
+
     #[test]
     fn mpt_digest() {
         let trie = Trie::from_digest(B256::ZERO);
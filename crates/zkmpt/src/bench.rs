@@ -0,0 +1,163 @@
+//! The `bench` commands, recording criterion baselines to a JSON file and comparing them.
+//!
+//! `cargo bench -- --save-baseline <name>` leaves the estimates of every benchmark in
+//! `target/criterion/<id>/<name>/estimates.json`. `bench save` collects their mean times, in
+//! nanoseconds and by benchmark id, under `<name>` in a file holding any number of named
+//! baselines. `bench compare` fails if a benchmark of one baseline is slower than in another by
+//! more than a threshold, so that CI can reject a regression.
+use serde_json::Value;
+use std::{collections::BTreeMap, error::Error, fs, path::Path, time::Duration};
+
+/// The mean times of the benchmarks of a baseline, in nanoseconds, by benchmark id.
+type Means = BTreeMap<String, f64>;
+
+/// Reads the baselines of `file`, none if it does not exist.
+fn read(file: &Path) -> Result<BTreeMap<String, Means>, Box<dyn Error>> {
+    if !file.exists() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_slice(&fs::read(file)?)?)
+}
+
+/// Returns the mean times of the benchmarks of the criterion `baseline` saved under `criterion`.
+fn means(criterion: &Path, baseline: &str) -> Result<Means, Box<dyn Error>> {
+    let mut means = Means::new();
+    let mut dirs = vec![criterion.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let estimates = path.join(baseline).join("estimates.json");
+            if !estimates.is_file() {
+                dirs.push(path);
+                continue;
+            }
+            let json: Value = serde_json::from_slice(&fs::read(&estimates)?)?;
+            let mean = json["mean"]["point_estimate"]
+                .as_f64()
+                .ok_or_else(|| format!("{}: no mean estimate", estimates.display()))?;
+            let id = path
+                .strip_prefix(criterion)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            means.insert(id, mean);
+        }
+    }
+    if means.is_empty() {
+        return Err(format!("no baseline {baseline} under {}", criterion.display()).into());
+    }
+    Ok(means)
+}
+
+/// Returns the benchmarks of `current` slower than in `base` by more than `threshold` percent,
+/// with their change in percent.
+fn regressions(base: &Means, current: &Means, threshold: f64) -> Vec<(String, f64)> {
+    current
+        .iter()
+        .filter_map(|(id, time)| {
+            let change = (time / base.get(id)? - 1.0) * 100.0;
+            (change > threshold).then(|| (id.clone(), change))
+        })
+        .collect()
+}
+
+/// Records the criterion baseline `name` saved under `criterion` in `file`, replacing a baseline
+/// of the same name.
+pub(crate) fn save(criterion: &Path, name: &str, file: &Path) -> Result<(), Box<dyn Error>> {
+    let mut baselines = read(file)?;
+    let saved = means(criterion, name)?;
+    println!("saved {} benchmarks as {name}", saved.len());
+    baselines.insert(name.to_owned(), saved);
+    fs::write(file, serde_json::to_string_pretty(&baselines)? + "\n")?;
+    Ok(())
+}
+
+/// Prints the change of every benchmark of the baseline `current` of `file` from `base`, and
+/// fails if one regressed by more than `threshold` percent.
+pub(crate) fn compare(
+    file: &Path,
+    base: &str,
+    current: &str,
+    threshold: f64,
+) -> Result<(), Box<dyn Error>> {
+    let baselines = read(file)?;
+    let get = |name: &str| {
+        baselines
+            .get(name)
+            .ok_or_else(|| format!("no baseline {name} in {}", file.display()))
+    };
+    let (base, current) = (get(base)?, get(current)?);
+    let time = |nanos: f64| format!("{:.1?}", Duration::from_secs_f64(nanos / 1e9));
+    for (id, mean) in current {
+        match base.get(id) {
+            Some(base) => {
+                let change = (mean / base - 1.0) * 100.0;
+                println!(
+                    "{id:<60} {:>10} {:>10} {change:+7.1}%",
+                    time(*base),
+                    time(*mean)
+                );
+            }
+            None => println!("{id:<60} {:>10} {:>10}", "-", time(*mean)),
+        }
+    }
+
+    let regressions = regressions(base, current, threshold);
+    if regressions.is_empty() {
+        return Ok(());
+    }
+    for (id, change) in &regressions {
+        eprintln!("regression: {id} {change:+.1}%");
+    }
+    Err(format!(
+        "{} benchmarks regressed by more than {threshold}%",
+        regressions.len()
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn means_of_a_baseline() {
+        let criterion = std::env::temp_dir().join(format!("zkmpt-bench-{}", std::process::id()));
+        for (id, baseline, mean) in [
+            ("trie/insert/100", "main", 1_500.0),
+            ("trie/insert/100", "new", 9_999.0),
+            ("reveal/1000", "main", 2_000_000.0),
+        ] {
+            let dir = criterion.join(id).join(baseline);
+            fs::create_dir_all(&dir).unwrap();
+            let estimates = format!(r#"{{"mean":{{"point_estimate":{mean}}}}}"#);
+            fs::write(dir.join("estimates.json"), estimates).unwrap();
+        }
+        fs::create_dir_all(criterion.join("trie/report")).unwrap();
+
+        assert_eq!(
+            means(&criterion, "main").unwrap(),
+            Means::from([
+                ("reveal/1000".to_owned(), 2_000_000.0),
+                ("trie/insert/100".to_owned(), 1_500.0),
+            ])
+        );
+        assert!(means(&criterion, "other").is_err());
+        fs::remove_dir_all(criterion).unwrap();
+    }
+
+    #[test]
+    fn regressions_beyond_threshold() {
+        let base = Means::from([("a".to_owned(), 100.0), ("b".to_owned(), 100.0)]);
+        let current = Means::from([
+            ("a".to_owned(), 109.0),
+            ("b".to_owned(), 125.0),
+            ("c".to_owned(), 1_000.0),
+        ]);
+        assert_eq!(regressions(&base, &current, 10.0), [("b".to_owned(), 25.0)]);
+        assert!(regressions(&base, &current, 30.0).is_empty());
+        assert!(regressions(&current, &base, 10.0).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! Command line tool running the crates end-to-end on `StatelessInput` JSON fixtures, as written
+//! by `witness-fetcher`, inspecting the tries of exported witnesses, exporting test vectors of
+//! the `ref-mpt` trie, and recording and comparing benchmark baselines.
+//!
+//! ```text
+//! cargo run --release -p zkmpt -- verify --input test_data/rpc_block_23439901.json
+//! cargo run --release -p zkmpt -- trie proof test_data/rpc_block_23439901.json --key <address>
+//! cargo run --release -p zkmpt -- vectors --seed 0 --cases 8 --out test_vectors/ref_mpt_vectors.json
+//! cargo run --release -p zkmpt -- bench compare --base main --current head
+//! ```
+use alloy_primitives::{Bytes, B256};
+use clap::{Parser, Subcommand};
+use std::{error::Error, path::PathBuf};
+
+mod bench;
+mod trie;
+mod vectors;
+mod verify;
+
+#[derive(Debug, Parser)]
+#[command(name = "zkmpt", about = "Stateless validation and trie tooling")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Validates a block against its witness with `SimpleSparseState`.
+    Verify {
+        /// `StatelessInput` JSON fixture.
+        #[arg(long)]
+        input: PathBuf,
+    },
+    /// Inspects the trie revealed by a `StatelessInput` fixture or an `ExecutionWitness` JSON
+    /// file. The state trie of the parent block is used unless `--root` is given.
+    Trie {
+        #[command(subcommand)]
+        command: TrieCommand,
+    },
+    /// Writes JSON test vectors computed with the `ref-mpt` trie, for other MPT implementations.
+    Vectors {
+        /// Seed of the generated cases.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Number of cases of each kind.
+        #[arg(long, default_value_t = 16)]
+        cases: usize,
+        /// Output file, the standard output if not given.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Records criterion baselines to a JSON file and compares them.
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TrieCommand {
+    /// Prints the revealed trie.
+    Dump {
+        /// Witness file.
+        file: PathBuf,
+        /// Root of the trie, e.g. the storage root of an account.
+        #[arg(long)]
+        root: Option<B256>,
+    },
+    /// Prints the value and the proof nodes of a key.
+    Proof {
+        /// Witness file.
+        file: PathBuf,
+        /// Pre-hashed 32-byte key, or a 20-byte address which is hashed.
+        #[arg(long)]
+        key: Bytes,
+        /// Root of the trie, e.g. the storage root of an account.
+        #[arg(long)]
+        root: Option<B256>,
+    },
+    /// Reports the number and size of the witness nodes, the repeated ones, those reachable from
+    /// the root, and their depths.
+    Report {
+        /// Witness file.
+        file: PathBuf,
+        /// Root of the trie, e.g. the storage root of an account.
+        #[arg(long)]
+        root: Option<B256>,
+    },
+    /// Prints the leaves whose values differ between two tries.
+    Diff {
+        /// First witness file.
+        a: PathBuf,
+        /// Second witness file.
+        b: PathBuf,
+        /// Root of the first trie.
+        #[arg(long)]
+        root_a: Option<B256>,
+        /// Root of the second trie.
+        #[arg(long)]
+        root_b: Option<B256>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BenchCommand {
+    /// Records the baseline saved by `cargo bench -- --save-baseline <name>`.
+    Save {
+        /// Name of the criterion baseline.
+        #[arg(long)]
+        name: String,
+        /// Criterion output directory.
+        #[arg(long, default_value = "target/criterion")]
+        criterion: PathBuf,
+        /// File of the named baselines.
+        #[arg(long, default_value = "bench_baselines.json")]
+        file: PathBuf,
+    },
+    /// Compares two recorded baselines, failing on a regression beyond the threshold.
+    Compare {
+        /// Name of the reference baseline.
+        #[arg(long)]
+        base: String,
+        /// Name of the baseline checked against it.
+        #[arg(long)]
+        current: String,
+        /// Largest slowdown allowed, in percent.
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+        /// File of the named baselines.
+        #[arg(long, default_value = "bench_baselines.json")]
+        file: PathBuf,
+    },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    match Cli::parse().command {
+        Command::Verify { input } => verify::run(&input),
+        Command::Trie { command } => match command {
+            TrieCommand::Dump { file, root } => trie::dump(&file, root),
+            TrieCommand::Proof { file, key, root } => trie::proof(&file, root, &key),
+            TrieCommand::Report { file, root } => trie::report(&file, root),
+            TrieCommand::Diff {
+                a,
+                b,
+                root_a,
+                root_b,
+            } => trie::diff(&a, root_a, &b, root_b),
+        },
+        Command::Vectors { seed, cases, out } => vectors::run(seed, cases, out.as_deref()),
+        Command::Bench { command } => match command {
+            BenchCommand::Save {
+                name,
+                criterion,
+                file,
+            } => bench::save(&criterion, &name, &file),
+            BenchCommand::Compare {
+                base,
+                current,
+                threshold,
+                file,
+            } => bench::compare(&file, &base, &current, threshold),
+        },
+    }
+}
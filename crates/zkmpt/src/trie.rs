@@ -0,0 +1,295 @@
+//! The `trie` commands, inspecting the tries revealed by exported witnesses.
+//!
+//! A witness file is either a `StatelessInput` fixture, whose trie defaults to the state trie of
+//! the parent block, or the JSON of a bare `ExecutionWitness`, which needs an explicit root.
+//! Storage tries are inspected by passing their storage root.
+use crate::verify::parent_state_root;
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_trie::Nibbles;
+use ref_mpt::{B256Map, InvalidNode, Trie};
+use ref_mpt_state::report::analyze_witness;
+use stateless::{ExecutionWitness, StatelessInput};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs,
+    path::Path,
+};
+
+/// A witness together with the root of the trie to inspect.
+struct Witness {
+    witness: ExecutionWitness,
+    root: B256,
+}
+
+impl Witness {
+    fn load(path: &Path, root: Option<B256>) -> Result<Self, Box<dyn Error>> {
+        let json = fs::read(path)?;
+        let (witness, root) = match (serde_json::from_slice::<StatelessInput>(&json), root) {
+            (Ok(input), Some(root)) => (input.witness, root),
+            (Ok(input), None) => {
+                let root = parent_state_root(&input.witness, input.block.header.number)?;
+                (input.witness, root)
+            }
+            (Err(_), Some(root)) => (serde_json::from_slice(&json)?, root),
+            (Err(_), None) => {
+                return Err(
+                    format!("{}: not a StatelessInput, pass --root", path.display()).into(),
+                );
+            }
+        };
+        Ok(Self { witness, root })
+    }
+
+    /// Reveals the trie from the witness nodes, leaving the nodes missing from the witness
+    /// unresolved.
+    fn reveal(&self) -> Result<Trie, InvalidNode> {
+        let nodes: B256Map<Bytes> = self
+            .witness
+            .state
+            .iter()
+            .map(|rlp| (keccak256(rlp), rlp.clone()))
+            .collect();
+        Trie::try_reveal_from_rlp(self.root, &nodes)
+    }
+}
+
+/// The revealed leaves of a trie, and the subtrees missing from the witness.
+#[derive(Debug)]
+struct Leaves {
+    values: BTreeMap<B256, Bytes>,
+    missing: Vec<(Nibbles, B256)>,
+}
+
+impl Leaves {
+    fn new(trie: &Trie) -> Self {
+        Self {
+            values: trie
+                .iter()
+                .map(|(key, value)| (key, value.clone()))
+                .collect(),
+            missing: trie.unresolved(),
+        }
+    }
+
+    /// Returns the missing subtree containing `key`, if any.
+    fn missing_at(&self, key: B256) -> Option<B256> {
+        let path = Nibbles::unpack(key);
+        self.missing
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix))
+            .map(|(_, hash)| *hash)
+    }
+}
+
+/// Prints the trie revealed by the witness.
+pub(crate) fn dump(path: &Path, root: Option<B256>) -> Result<(), Box<dyn Error>> {
+    let witness = Witness::load(path, root)?;
+    let trie = witness.reveal()?;
+    println!("root {}", witness.root);
+    println!("{trie}");
+    Ok(())
+}
+
+/// Prints the proof of a key, given as a pre-hashed 32-byte key or as a 20-byte address.
+pub(crate) fn proof(path: &Path, root: Option<B256>, key: &Bytes) -> Result<(), Box<dyn Error>> {
+    let key = match key.len() {
+        32 => B256::from_slice(key),
+        20 => keccak256(key),
+        len => return Err(format!("key must be 32 bytes or a 20-byte address, got {len}").into()),
+    };
+    let witness = Witness::load(path, root)?;
+    let mut trie = witness.reveal()?;
+    println!("root  {}", witness.root);
+    println!("key   {key}");
+    match trie.try_get(key) {
+        Ok(Some(value)) => println!("value {value}"),
+        Ok(None) => println!("value none (absent)"),
+        Err(err) => println!(
+            "value unknown, node {} is missing from the witness",
+            err.digest
+        ),
+    }
+    for node in trie.proof(key)? {
+        println!("{node}");
+    }
+    Ok(())
+}
+
+/// A leaf differing between two tries, with its value in each, `None` where it is absent.
+type Difference = (B256, Option<Bytes>, Option<Bytes>);
+
+/// Returns the leaves whose values differ between two tries, in key order. A leaf only revealed
+/// in one trie, and lying in a subtree missing from the other, is not a difference.
+fn differences(a: &Leaves, b: &Leaves) -> Vec<Difference> {
+    let keys: BTreeSet<B256> = a.values.keys().chain(b.values.keys()).copied().collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let (value_a, value_b) = (a.values.get(&key), b.values.get(&key));
+            let unknown = (value_a.is_none() && a.missing_at(key).is_some())
+                || (value_b.is_none() && b.missing_at(key).is_some());
+            (value_a != value_b && !unknown).then(|| (key, value_a.cloned(), value_b.cloned()))
+        })
+        .collect()
+}
+
+/// Prints the sizes, repeated nodes, reachability from the root and depths of the witness nodes.
+pub(crate) fn report(path: &Path, root: Option<B256>) -> Result<(), Box<dyn Error>> {
+    let witness = Witness::load(path, root)?;
+    println!("root {}", witness.root);
+    print!("{}", analyze_witness(&witness.witness, witness.root));
+    Ok(())
+}
+
+/// Prints the leaves that differ between the tries of two witnesses.
+pub(crate) fn diff(
+    a: &Path,
+    root_a: Option<B256>,
+    b: &Path,
+    root_b: Option<B256>,
+) -> Result<(), Box<dyn Error>> {
+    let (a, b) = (Witness::load(a, root_a)?, Witness::load(b, root_b)?);
+    let (leaves_a, leaves_b) = (Leaves::new(&a.reveal()?), Leaves::new(&b.reveal()?));
+    println!("--- {}", a.root);
+    println!("+++ {}", b.root);
+
+    let differences = differences(&leaves_a, &leaves_b);
+    for (key, value_a, value_b) in &differences {
+        match (value_a, value_b) {
+            (Some(value_a), Some(value_b)) => println!("~ {key} {value_a} -> {value_b}"),
+            (Some(value), None) => println!("- {key} {value}"),
+            (None, Some(value)) => println!("+ {key} {value}"),
+            (None, None) => unreachable!("key of either trie"),
+        }
+    }
+
+    println!(
+        "{} differences, {} and {} subtrees missing from the witnesses",
+        differences.len(),
+        leaves_a.missing.len(),
+        leaves_b.missing.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> BTreeMap<B256, Bytes> {
+        (0u64..64)
+            .map(|i| {
+                // short values are inlined into their parent branch
+                let len = if i % 3 == 0 { 1 } else { 40 };
+                (
+                    keccak256(i.to_be_bytes()),
+                    Bytes::from(vec![i as u8 + 1; len]),
+                )
+            })
+            .collect()
+    }
+
+    // Builds a witness with all nodes of the trie of `entries`, the union of the proofs of its
+    // keys.
+    fn witness(entries: &BTreeMap<B256, Bytes>) -> Witness {
+        let mut trie: Trie = entries
+            .iter()
+            .map(|(key, value)| (*key, value.clone()))
+            .collect();
+        let root = trie.hash();
+        let nodes: BTreeSet<Bytes> = entries
+            .keys()
+            .flat_map(|key| trie.proof(*key).unwrap())
+            .collect();
+        let witness = ExecutionWitness {
+            state: nodes.into_iter().collect(),
+            ..Default::default()
+        };
+        Witness { witness, root }
+    }
+
+    // Drops the node below the root on the path of `key` from the witness, returning its hash.
+    fn drop_subtree(witness: &mut Witness, key: B256) -> B256 {
+        let proof = witness.reveal().unwrap().proof(key).unwrap();
+        witness.witness.state.retain(|rlp| *rlp != proof[1]);
+        keccak256(&proof[1])
+    }
+
+    #[test]
+    fn leaves_of_partial_witnesses() {
+        let entries = entries();
+        let mut witness = witness(&entries);
+        let leaves = Leaves::new(&witness.reveal().unwrap());
+        assert_eq!(leaves.values, entries);
+        assert!(leaves.missing.is_empty());
+
+        let key = *entries.keys().next().unwrap();
+        let dropped = drop_subtree(&mut witness, key);
+        let leaves = Leaves::new(&witness.reveal().unwrap());
+        assert_eq!(leaves.missing.len(), 1);
+        assert_eq!(leaves.missing_at(key), Some(dropped));
+        assert!(!leaves.values.contains_key(&key));
+        assert!(leaves
+            .values
+            .keys()
+            .all(|key| leaves.missing_at(*key).is_none()));
+
+        // the proof of a key below the missing node fails, the others are still proven
+        let mut trie = witness.reveal().unwrap();
+        assert_eq!(trie.proof(key).unwrap_err().digest, dropped);
+        let other = *leaves.values.keys().next().unwrap();
+        assert!(trie.proof(other).is_ok());
+    }
+
+    #[test]
+    fn invalid_witness_nodes() {
+        let junk = Bytes::from_static(&[0xc1]);
+        let witness = Witness {
+            witness: ExecutionWitness {
+                state: vec![junk.clone()],
+                ..Default::default()
+            },
+            root: keccak256(&junk),
+        };
+        assert_eq!(
+            witness.reveal().unwrap_err(),
+            InvalidNode {
+                digest: keccak256(&junk)
+            }
+        );
+    }
+
+    #[test]
+    fn differences_skip_missing_subtrees() {
+        let entries = entries();
+        let mut keys = entries.keys().copied();
+        let (updated, removed) = (keys.next().unwrap(), keys.next_back().unwrap());
+        let added = keccak256(b"added");
+        let mut changed = entries.clone();
+        changed.insert(updated, Bytes::from_static(b"updated"));
+        changed.remove(&removed);
+        changed.insert(added, Bytes::from_static(b"added"));
+
+        let leaves_a = Leaves::new(&witness(&entries).reveal().unwrap());
+        let leaves_b = Leaves::new(&witness(&changed).reveal().unwrap());
+        let mut expected = vec![
+            (
+                updated,
+                Some(entries[&updated].clone()),
+                Some(changed[&updated].clone()),
+            ),
+            (removed, Some(entries[&removed].clone()), None),
+            (added, None, Some(changed[&added].clone())),
+        ];
+        expected.sort_by_key(|(key, ..)| *key);
+        assert_eq!(differences(&leaves_a, &leaves_b), expected);
+        assert!(differences(&leaves_a, &leaves_a).is_empty());
+
+        // the leaves of a subtree missing from one witness are unknown, not removed
+        let mut partial = witness(&entries);
+        drop_subtree(&mut partial, updated);
+        let leaves_partial = Leaves::new(&partial.reveal().unwrap());
+        assert!(differences(&leaves_a, &leaves_partial).is_empty());
+        assert!(differences(&leaves_partial, &leaves_a).is_empty());
+    }
+}
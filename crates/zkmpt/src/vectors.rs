@@ -0,0 +1,198 @@
+//! The `vectors` command, writing JSON test vectors computed with the `ref-mpt` trie for other MPT
+//! implementations.
+//!
+//! Two kinds of cases are generated from a seed:
+//!
+//! - `operations`: a sequence of inserts and removals of 32-byte keys applied to an empty trie,
+//!   with the expected root;
+//! - `reveals`: the root and the nodes of a witness proving some of the keys of a trie, with the
+//!   expected revealed leaves and the subtrees left unresolved, by path.
+//!
+//! Keys share prefixes often and values straddle the 32-byte limit of nodes embedded in their
+//! parent, so the cases cover branches, extensions, and embedded and hashed leaves. Bytes are
+//! `0x`-prefixed hex strings and nibble paths are hex strings with one digit per nibble.
+//!
+//! The roots and leaves are checked against `alloy-trie`'s [`HashBuilder`] before being written,
+//! so that the vectors do not merely record what `ref-mpt` computes.
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_trie::{HashBuilder, Nibbles};
+use ref_mpt::{B256Map, Trie};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs,
+    path::Path,
+};
+
+/// Deterministic pseudo-random bytes, so that a seed always yields the same vectors.
+struct Rng {
+    seed: u64,
+    counter: u64,
+}
+
+impl Rng {
+    fn next(&mut self) -> B256 {
+        self.counter += 1;
+        keccak256([self.seed.to_be_bytes(), self.counter.to_be_bytes()].concat())
+    }
+
+    /// Returns a number below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        let word = u64::from_be_bytes(self.next()[..8].try_into().unwrap());
+        (word % n as u64) as usize
+    }
+
+    /// Returns a key whose first two bytes are drawn from a few values sharing nibbles.
+    fn key(&mut self) -> B256 {
+        const PREFIXES: [u8; 4] = [0x00, 0x0f, 0x10, 0xf0];
+        let mut key = self.next();
+        key[0] = PREFIXES[self.below(PREFIXES.len())];
+        key[1] = PREFIXES[self.below(PREFIXES.len())];
+        key
+    }
+
+    /// Returns a value of a length around the 32-byte limit of embedded nodes.
+    fn value(&mut self) -> Bytes {
+        const LENGTHS: [usize; 6] = [1, 2, 28, 31, 32, 64];
+        let len = LENGTHS[self.below(LENGTHS.len())];
+        let bytes = [self.next(), self.next()].concat();
+        Bytes::copy_from_slice(&bytes[..len])
+    }
+}
+
+/// Returns the root of the trie holding `entries`, computed by [`HashBuilder`].
+fn hash_builder_root(entries: &BTreeMap<B256, Bytes>) -> B256 {
+    let mut hash_builder = HashBuilder::default();
+    for (key, value) in entries {
+        hash_builder.add_leaf(Nibbles::unpack(key), value);
+    }
+    hash_builder.root()
+}
+
+/// Fails unless `root`, computed by `ref-mpt`, is the [`HashBuilder`] root of `entries`.
+fn check_root(name: &str, root: B256, entries: &BTreeMap<B256, Bytes>) -> Result<(), String> {
+    let expected = hash_builder_root(entries);
+    if root == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name}: ref-mpt root {root}, HashBuilder root {expected}"
+        ))
+    }
+}
+
+fn operations_case(rng: &mut Rng, name: String) -> Result<Value, String> {
+    let keys: Vec<B256> = (0..1 + rng.below(24)).map(|_| rng.key()).collect();
+    let mut trie = Trie::new();
+    let mut entries = BTreeMap::new();
+    let mut ops = Vec::new();
+    for _ in 0..1 + rng.below(40) {
+        let key = keys[rng.below(keys.len())];
+        if rng.below(4) == 0 {
+            trie.remove(key);
+            entries.remove(&key);
+            ops.push(json!({ "remove": key.to_string() }));
+        } else {
+            let value = rng.value();
+            trie.insert(key, value.clone());
+            entries.insert(key, value.clone());
+            ops.push(json!({ "insert": [key.to_string(), value.to_string()] }));
+        }
+    }
+    let root = trie.hash();
+    check_root(&name, root, &entries)?;
+    Ok(json!({ "name": name, "ops": ops, "root": root.to_string() }))
+}
+
+fn reveals_case(rng: &mut Rng, name: String) -> Result<Value, String> {
+    let mut trie = Trie::new();
+    let keys: Vec<B256> = (0..1 + rng.below(32)).map(|_| rng.key()).collect();
+    let entries: BTreeMap<B256, Bytes> = keys.iter().map(|key| (*key, rng.value())).collect();
+    for (key, value) in &entries {
+        trie.insert(*key, value.clone());
+    }
+    let root = trie.hash();
+    check_root(&name, root, &entries)?;
+
+    // the proofs of some of the keys, and of a key which may be absent
+    let mut proven: Vec<B256> = keys.iter().copied().filter(|_| rng.below(3) == 0).collect();
+    proven.push(rng.key());
+    let mut nodes = BTreeSet::new();
+    for key in proven {
+        nodes.extend(trie.proof(key).expect("the trie is fully revealed"));
+    }
+    let rlp_by_digest: B256Map<Bytes> = nodes
+        .iter()
+        .map(|rlp| (keccak256(rlp), rlp.clone()))
+        .collect();
+
+    let revealed = Trie::reveal_from_rlp(root, &rlp_by_digest);
+    if let Some((key, value)) = revealed
+        .iter()
+        .find(|(key, value)| entries.get(key) != Some(*value))
+    {
+        return Err(format!(
+            "{name}: revealed {key} = {value} is not in the trie"
+        ));
+    }
+    let leaves: Vec<Value> = revealed
+        .iter()
+        .map(|(key, value)| json!([key.to_string(), value.to_string()]))
+        .collect();
+    let unresolved: Vec<Value> = revealed
+        .unresolved()
+        .into_iter()
+        .map(|(path, digest)| {
+            let path: String = path.iter().map(|nibble| format!("{nibble:x}")).collect();
+            json!([path, digest.to_string()])
+        })
+        .collect();
+    Ok(json!({
+        "name": name,
+        "root": root.to_string(),
+        "nodes": nodes.iter().map(Bytes::to_string).collect::<Vec<_>>(),
+        "leaves": leaves,
+        "unresolved": unresolved,
+    }))
+}
+
+/// Writes `cases` cases of each kind generated from `seed`, to `out` or the standard output.
+pub(crate) fn run(seed: u64, cases: usize, out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut rng = Rng { seed, counter: 0 };
+    let operations: Vec<Value> = (0..cases)
+        .map(|i| operations_case(&mut rng, format!("operations_{i}")))
+        .collect::<Result<_, _>>()?;
+    let reveals: Vec<Value> = (0..cases)
+        .map(|i| reveals_case(&mut rng, format!("reveals_{i}")))
+        .collect::<Result<_, _>>()?;
+    let vectors = json!({
+        "source": format!("zkmpt vectors --seed {seed} --cases {cases}"),
+        "operations": operations,
+        "reveals": reveals,
+    });
+
+    let json = serde_json::to_string_pretty(&vectors)? + "\n";
+    match out {
+        Some(out) => fs::write(out, json)?,
+        None => print!("{json}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf, process};
+
+    #[test]
+    fn regenerates_the_committed_vectors() {
+        let committed = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test_vectors/ref_mpt_vectors.json");
+        let out = env::temp_dir().join(format!("ref_mpt_vectors_{}.json", process::id()));
+        run(0, 8, Some(&out)).unwrap();
+        let generated = fs::read_to_string(&out).unwrap();
+        fs::remove_file(&out).unwrap();
+        assert_eq!(generated, fs::read_to_string(committed).unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+//! The `verify` command.
+use alloy_consensus::Header;
+use alloy_primitives::{Bytes, B256};
+use alloy_rlp::Decodable;
+use ref_mpt_state::{signers::recover_public_keys, SimpleSparseState};
+use reth_chainspec::ChainSpec;
+use reth_evm_ethereum::EthEvmConfig;
+use stateless::{stateless_validation_with_trie, ExecutionWitness, Genesis, StatelessInput};
+use std::{error::Error, fmt, fs::File, io::BufReader, path::Path, sync::Arc, time::Instant};
+
+/// Validates the block of a fixture and prints its roots, the validation time and witness
+/// statistics.
+pub(crate) fn run(path: &Path) -> Result<(), Box<dyn Error>> {
+    let input: StatelessInput = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let header = &input.block.header;
+    let (number, post_state_root) = (header.number, header.state_root);
+    let pre_state_root = parent_state_root(&input.witness, number)?;
+    let stats = WitnessStats::new(&input.witness);
+
+    let public_keys = recover_public_keys(&input)?;
+    let genesis = Genesis {
+        config: input.chain_config,
+        ..Default::default()
+    };
+    let chain_spec: Arc<ChainSpec> = Arc::new(genesis.into());
+    let evm_config = EthEvmConfig::new(chain_spec.clone());
+
+    let start = Instant::now();
+    let (block_hash, _) =
+        stateless_validation_with_trie::<SimpleSparseState, ChainSpec, EthEvmConfig>(
+            input.block,
+            public_keys,
+            input.witness,
+            chain_spec,
+            evm_config,
+        )?;
+    let elapsed = start.elapsed();
+
+    println!("block           {number} ({block_hash})");
+    println!("pre-state root  {pre_state_root}");
+    println!("post-state root {post_state_root}");
+    println!("validated in    {elapsed:.1?}");
+    println!("witness         {stats}");
+    Ok(())
+}
+
+/// Returns the state root of the parent block, taken from the witness headers.
+pub(crate) fn parent_state_root(
+    witness: &ExecutionWitness,
+    number: u64,
+) -> Result<B256, Box<dyn Error>> {
+    for rlp in &witness.headers {
+        let header = Header::decode(&mut &rlp[..])
+            .map_err(|err| format!("invalid witness header: {err}"))?;
+        if header.number + 1 == number {
+            return Ok(header.state_root);
+        }
+    }
+    Err(format!("parent of block {number} missing from the witness headers").into())
+}
+
+/// Entry counts and sizes of the parts of a witness.
+#[derive(Debug)]
+struct WitnessStats {
+    state: (usize, usize),
+    codes: (usize, usize),
+    keys: usize,
+    headers: usize,
+}
+
+impl WitnessStats {
+    fn new(witness: &ExecutionWitness) -> Self {
+        let sized = |items: &[Bytes]| (items.len(), items.iter().map(|item| item.len()).sum());
+        Self {
+            state: sized(&witness.state),
+            codes: sized(&witness.codes),
+            keys: witness.keys.len(),
+            headers: witness.headers.len(),
+        }
+    }
+}
+
+impl fmt::Display for WitnessStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kib = |bytes: usize| bytes as f64 / 1024.0;
+        write!(
+            f,
+            "{} state nodes ({:.1} KiB), {} codes ({:.1} KiB), {} keys, {} headers",
+            self.state.0,
+            kib(self.state.1),
+            self.codes.0,
+            kib(self.codes.1),
+            self.keys,
+            self.headers
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parent_state_root_from_headers() {
+        let parent = Header {
+            number: 9,
+            state_root: B256::repeat_byte(1),
+            ..Default::default()
+        };
+        let witness = ExecutionWitness {
+            headers: vec![alloy_rlp::encode(&parent).into()],
+            ..Default::default()
+        };
+        assert_eq!(parent_state_root(&witness, 10).unwrap(), parent.state_root);
+        assert!(parent_state_root(&witness, 9).is_err());
+
+        let invalid = ExecutionWitness {
+            headers: vec![Bytes::from_static(&[0xc1])],
+            ..Default::default()
+        };
+        assert!(parent_state_root(&invalid, 10).is_err());
+    }
+
+    #[test]
+    fn witness_stats() {
+        let witness = ExecutionWitness {
+            state: vec![Bytes::from(vec![0; 1024]), Bytes::from(vec![0; 512])],
+            codes: vec![Bytes::from(vec![0; 2048])],
+            ..Default::default()
+        };
+        assert_eq!(
+            WitnessStats::new(&witness).to_string(),
+            "2 state nodes (1.5 KiB), 1 codes (2.0 KiB), 0 keys, 0 headers"
+        );
+    }
+}
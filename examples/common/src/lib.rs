@@ -0,0 +1,96 @@
+//! Code shared by the zkVM examples: input preparation on the host and stateless validation with
+//! `SimpleSparseState` in the guest.
+//!
+//! Hosts pass the guest the `StatelessInput` as raw JSON bytes, followed by the uncompressed public
+//! keys of the block's transaction signers. The guest commits the block hash followed by the
+//! post-state root.
+use ref_mpt_state::SimpleSparseState;
+use reth_chainspec::ChainSpec;
+use reth_evm_ethereum::EthEvmConfig;
+use stateless::{stateless_validation_with_trie, Genesis, StatelessInput, UncompressedPublicKey};
+use std::sync::Arc;
+
+/// Output committed by the guests: the block hash and the post-state root.
+pub type GuestOutput = ([u8; 32], [u8; 32]);
+
+/// Validates the block of a JSON encoded `StatelessInput` against its witness.
+///
+/// # Panics
+///
+/// Panics if the input cannot be decoded or the block is invalid, which aborts the guest.
+pub fn validate(json: &[u8], public_keys: Vec<Vec<u8>>) -> GuestOutput {
+    let input: StatelessInput = serde_json::from_slice(json).expect("valid stateless input");
+    let public_keys = public_keys
+        .into_iter()
+        .map(|key| UncompressedPublicKey(key.try_into().expect("65-byte public key")))
+        .collect();
+
+    let genesis = Genesis {
+        config: input.chain_config,
+        ..Default::default()
+    };
+    let chain_spec: Arc<ChainSpec> = Arc::new(genesis.into());
+    let evm_config = EthEvmConfig::new(chain_spec.clone());
+
+    let post_state_root = input.block.header.state_root;
+    let (block_hash, _) =
+        stateless_validation_with_trie::<SimpleSparseState, ChainSpec, EthEvmConfig>(
+            input.block,
+            public_keys,
+            input.witness,
+            chain_spec,
+            evm_config,
+        )
+        .expect("stateless validation failed");
+
+    (block_hash.0, post_state_root.0)
+}
+
+#[cfg(feature = "host")]
+pub use host::GuestInput;
+
+#[cfg(feature = "host")]
+mod host {
+    use super::GuestOutput;
+    use alloy_primitives::B256;
+    use ref_mpt_state::signers::recover_public_keys;
+    use stateless::StatelessInput;
+    use std::{error::Error, fs, path::Path};
+
+    /// Inputs of a guest, prepared from a `StatelessInput` JSON fixture.
+    #[derive(Debug)]
+    pub struct GuestInput {
+        /// The fixture.
+        pub input: StatelessInput,
+        /// The raw JSON of the fixture, as passed to the guest.
+        pub json: Vec<u8>,
+        /// Uncompressed public keys of the transaction signers, in transaction order.
+        pub public_keys: Vec<Vec<u8>>,
+    }
+
+    impl GuestInput {
+        /// Reads a fixture and recovers the public keys of its transaction signers.
+        pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+            let json = fs::read(path)?;
+            let input: StatelessInput = serde_json::from_slice(&json)?;
+            let public_keys = recover_public_keys(&input)?
+                .into_iter()
+                .map(|key| key.0.to_vec())
+                .collect();
+            Ok(Self { input, json, public_keys })
+        }
+
+        /// Checks the output committed by a guest against the block of the fixture.
+        pub fn check_output(&self, output: GuestOutput) -> Result<(), String> {
+            let (block_hash, post_state_root) = (B256::from(output.0), B256::from(output.1));
+            let header = &self.input.block.header;
+            if block_hash != header.hash_slow() {
+                return Err(format!("guest committed unexpected block hash {block_hash}"));
+            }
+            if post_state_root != header.state_root {
+                return Err(format!("guest committed unexpected post-state root {post_state_root}"));
+            }
+            Ok(())
+        }
+    }
+}
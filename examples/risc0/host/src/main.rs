@@ -0,0 +1,33 @@
+//! Proves the stateless validation of a block with the RISC Zero zkVM and checks the committed
+//! block hash and post-state root.
+//!
+//! Takes a `StatelessInput` JSON fixture, as written by `witness-fetcher`. Set
+//! `RISC0_DEV_MODE=1` to execute the guest without generating a real proof:
+//!
+//! ```text
+//! RISC0_DEV_MODE=1 cargo run --release -- ../../test_data/rpc_block_23439901.json
+//! ```
+use example_common::{GuestInput, GuestOutput};
+use methods::{STATELESS_GUEST_ELF, STATELESS_GUEST_ID};
+use risc0_zkvm::{default_prover, ExecutorEnv};
+use std::{env, error::Error};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args().nth(1).ok_or("usage: host <stateless-input.json>")?;
+    let input = GuestInput::load(path)?;
+
+    let env = ExecutorEnv::builder().write_frame(&input.json).write(&input.public_keys)?.build()?;
+    let prove_info = default_prover().prove(env, STATELESS_GUEST_ELF)?;
+    prove_info.receipt.verify(STATELESS_GUEST_ID)?;
+
+    let output: GuestOutput = prove_info.receipt.journal.decode()?;
+    input.check_output(output)?;
+    println!(
+        "proved block {} in {} cycles ({} user cycles)",
+        input.input.block.header.number,
+        prove_info.stats.total_cycles,
+        prove_info.stats.user_cycles
+    );
+
+    Ok(())
+}
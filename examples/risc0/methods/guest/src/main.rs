@@ -0,0 +1,11 @@
+//! Validates a block statelessly with `SimpleSparseState` inside the RISC Zero zkVM.
+//!
+//! Reads a JSON encoded `StatelessInput` and the uncompressed public keys of the block's
+//! transaction signers, and commits the block hash followed by the post-state root.
+use risc0_zkvm::guest::env;
+
+fn main() {
+    let json = env::read_frame();
+    let public_keys: Vec<Vec<u8>> = env::read();
+    env::commit(&example_common::validate(&json, public_keys));
+}
@@ -0,0 +1,2 @@
+//! ELF and image ID of the stateless validation guest.
+include!(concat!(env!("OUT_DIR"), "/methods.rs"));
@@ -0,0 +1,12 @@
+//! Validates a block statelessly with `SimpleSparseState` inside the SP1 zkVM.
+//!
+//! Reads a JSON encoded `StatelessInput` and the uncompressed public keys of the block's
+//! transaction signers, and commits the block hash followed by the post-state root.
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+fn main() {
+    let json = sp1_zkvm::io::read_vec();
+    let public_keys: Vec<Vec<u8>> = sp1_zkvm::io::read();
+    sp1_zkvm::io::commit(&example_common::validate(&json, public_keys));
+}
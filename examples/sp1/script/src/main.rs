@@ -0,0 +1,50 @@
+//! Executes or proves the stateless validation of a block with the SP1 zkVM and checks the
+//! committed block hash and post-state root.
+//!
+//! Takes a `StatelessInput` JSON fixture, as written by `witness-fetcher`. With `--execute`, the
+//! program only runs in the executor and the cycle count is reported, which is enough to compare
+//! it with the RISC Zero example:
+//!
+//! ```text
+//! cargo run --release -- --execute ../../test_data/rpc_block_23439901.json
+//! ```
+use example_common::{GuestInput, GuestOutput};
+use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use std::{env, error::Error};
+
+const STATELESS_PROGRAM_ELF: &[u8] = include_elf!("stateless-program");
+
+const USAGE: &str = "usage: script [--execute] <stateless-input.json>";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    sp1_sdk::utils::setup_logger();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let execute = args.first().is_some_and(|arg| arg == "--execute");
+    if execute {
+        args.remove(0);
+    }
+    let [path] = args.as_slice() else {
+        return Err(USAGE.into());
+    };
+    let input = GuestInput::load(path)?;
+
+    let mut stdin = SP1Stdin::new();
+    stdin.write_vec(input.json.clone());
+    stdin.write(&input.public_keys);
+
+    let client = ProverClient::from_env();
+    let number = input.input.block.header.number;
+    if execute {
+        let (mut public_values, report) = client.execute(STATELESS_PROGRAM_ELF, &stdin).run()?;
+        input.check_output(public_values.read::<GuestOutput>())?;
+        println!("executed block {number} in {} cycles", report.total_instruction_count());
+    } else {
+        let (pk, vk) = client.setup(STATELESS_PROGRAM_ELF);
+        let mut proof = client.prove(&pk, &stdin).run()?;
+        client.verify(&proof, &vk)?;
+        input.check_output(proof.public_values.read::<GuestOutput>())?;
+        println!("proved block {number}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Applies inserts and removals to a partially revealed `ref_mpt::Trie` and checks after every
+//! operation that the children flags of each branch match its occupied children and that
+//! `one_child_left` agrees with a naive scan.
+//!
+//! Keys only differ in their first two bytes, so branches are created and collapsed often.
+#![no_main]
+
+use alloy_primitives::{keccak256, Bytes, B256};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt::{fuzzing::check_branch_flags, B256Map, Trie};
+use std::collections::BTreeMap;
+use zkvm_ethereum_mpt_fuzz::{build_partial_trie, trie_root};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Initial entries.
+    entries: Vec<(Key, u8)>,
+    /// Keys (present or not) whose proofs are revealed.
+    revealed: Vec<Key>,
+    ops: Vec<Op>,
+}
+
+/// A key made of two leading bytes followed by zeros.
+#[derive(Debug, Arbitrary, Clone, Copy)]
+struct Key([u8; 2]);
+
+impl Key {
+    fn b256(self) -> B256 {
+        let mut key = B256::ZERO;
+        key[..2].copy_from_slice(&self.0);
+        key
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum Op {
+    Insert(Key, u8),
+    Remove(Key),
+}
+
+fuzz_target!(|input: Input| {
+    let mut entries: BTreeMap<_, _> =
+        input.entries.into_iter().map(|(key, value)| (key.b256(), vec![value])).collect();
+    let revealed: Vec<_> = input.revealed.into_iter().map(Key::b256).collect();
+
+    let mut nodes = Vec::new();
+    let root = build_partial_trie(&entries, &revealed, &mut nodes);
+    let rlp_by_digest: B256Map<_> = nodes.into_iter().map(|rlp| (keccak256(&rlp), rlp)).collect();
+    let mut trie = Trie::reveal_from_rlp(root, &rlp_by_digest);
+    check_branch_flags(&mut trie);
+
+    for op in input.ops {
+        let result = match op {
+            Op::Insert(key, value) => {
+                let key = key.b256();
+                let result = trie.try_insert(key, Bytes::from([value]));
+                result.map(|()| entries.insert(key, vec![value])).map(drop)
+            }
+            Op::Remove(key) => {
+                let key = key.b256();
+                trie.try_remove(key).map(|()| entries.remove(&key)).map(drop)
+            }
+        };
+        check_branch_flags(&mut trie);
+        if result.is_ok() {
+            assert_eq!(trie.hash(), trie_root(&entries));
+        }
+    }
+});
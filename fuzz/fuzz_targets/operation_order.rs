@@ -0,0 +1,136 @@
+//! Applies one set of inserts and removals of distinct keys to a `ref_mpt::Trie` in different
+//! orders, on a fully or a partially revealed trie.
+//!
+//! The operations commute, so every order applying all of them must yield the `HashBuilder` root
+//! of the expected entries. On a partially revealed trie an order may fail with `UnresolvedNode`
+//! when a removal collapses a branch onto a digest, but inserting before removing, as the state
+//! layer does, must succeed whenever any other order does.
+#![no_main]
+
+use alloy_primitives::{keccak256, Bytes, B256};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt::{B256Map, Trie, UnresolvedNode};
+use std::collections::BTreeMap;
+use zkvm_ethereum_mpt_fuzz::{build_partial_trie, trie_root};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Initial entries.
+    entries: Vec<(Key, Value)>,
+    /// Whether the whole trie is revealed, or only the paths to `revealed`.
+    full: bool,
+    /// Keys (present or not) whose proofs are revealed besides the keys of the operations.
+    revealed: Vec<Key>,
+    /// Operations, of which only the last one of each key is kept.
+    ops: Vec<Op>,
+    /// Seed of the shuffled order.
+    seed: u8,
+}
+
+/// A key made of three leading bytes followed by zeros.
+#[derive(Debug, Arbitrary, Clone, Copy)]
+struct Key([u8; 3]);
+
+impl Key {
+    fn b256(self) -> B256 {
+        let mut key = B256::ZERO;
+        key[..3].copy_from_slice(&self.0);
+        key
+    }
+}
+
+/// A value which is either short enough for its leaf to be inlined or not.
+#[derive(Debug, Arbitrary, Clone, Copy)]
+enum Value {
+    Short(u8),
+    Long(u8),
+}
+
+impl Value {
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Self::Short(byte) => vec![byte],
+            Self::Long(byte) => vec![byte; 32],
+        }
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum Op {
+    Insert(Key, Value),
+    Remove(Key),
+    /// Removes the initial entry with the given index, modulo the number of entries.
+    RemoveEntry(u8),
+}
+
+/// Applies `ops` in order, stopping at the first failure.
+fn apply(mut trie: Trie, ops: &[(B256, Option<Bytes>)]) -> Result<B256, UnresolvedNode> {
+    for (key, value) in ops {
+        match value {
+            Some(value) => trie.try_insert(*key, value.clone())?,
+            None => trie.try_remove(*key)?,
+        }
+    }
+    Ok(trie.hash())
+}
+
+fuzz_target!(|input: Input| {
+    let entries: BTreeMap<_, _> =
+        input.entries.into_iter().map(|(key, value)| (key.b256(), value.bytes())).collect();
+    // the operations as a value to insert or `None` to remove, one per key
+    let ops: Vec<(B256, Option<Bytes>)> = input
+        .ops
+        .into_iter()
+        .filter_map(|op| match op {
+            Op::Insert(key, value) => Some((key.b256(), Some(Bytes::from(value.bytes())))),
+            Op::Remove(key) => Some((key.b256(), None)),
+            Op::RemoveEntry(index) => {
+                let key = entries.keys().nth(usize::from(index) % entries.len().max(1))?;
+                Some((*key, None))
+            }
+        })
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .collect();
+    // like a witness, a partial trie reveals the paths to the keys of the operations
+    let revealed: Vec<_> = if input.full {
+        entries.keys().copied().collect()
+    } else {
+        input.revealed.into_iter().map(Key::b256).chain(ops.iter().map(|(key, _)| *key)).collect()
+    };
+
+    let mut nodes = Vec::new();
+    let root = build_partial_trie(&entries, &revealed, &mut nodes);
+    let rlp_by_digest: B256Map<_> = nodes.into_iter().map(|rlp| (keccak256(&rlp), rlp)).collect();
+    let trie = Trie::reveal_from_rlp(root, &rlp_by_digest);
+
+    let mut expected = entries;
+    for (key, value) in &ops {
+        match value {
+            Some(value) => expected.insert(*key, value.to_vec()),
+            None => expected.remove(key),
+        };
+    }
+    let expected_root = trie_root(&expected);
+
+    let mut shuffled = ops.clone();
+    shuffled.sort_by_key(|(key, _)| keccak256([key.as_slice(), &[input.seed]].concat()));
+    let mut removals_last = ops.clone();
+    removals_last.sort_by_key(|(_, value)| value.is_none());
+    let reversed: Vec<_> = ops.iter().rev().cloned().collect();
+
+    let removals_last = apply(trie.clone(), &removals_last);
+    for order in [&ops, &shuffled, &reversed] {
+        match apply(trie.clone(), order) {
+            Ok(root) => {
+                assert_eq!(root, expected_root, "order changed the root");
+                assert_eq!(removals_last, Ok(expected_root), "removing last failed");
+            }
+            Err(_) => assert!(!input.full, "operation failed on a fully revealed trie"),
+        }
+    }
+    if input.full {
+        assert_eq!(removals_last, Ok(expected_root));
+    }
+});
@@ -0,0 +1,91 @@
+//! Reveals a `ref_mpt::Trie` from the proofs of a random subset of keys and applies inserts and
+//! removals next to the unrevealed frontier.
+//!
+//! An operation must either succeed and yield the `HashBuilder` root of the expected entries, or
+//! fail with `UnresolvedNode` and leave the trie unchanged. Keys share long prefixes so that
+//! extensions, inlined nodes and digest siblings are common.
+#![no_main]
+
+use alloy_primitives::{keccak256, Bytes, B256};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt::{B256Map, Trie};
+use std::collections::{BTreeMap, BTreeSet};
+use zkvm_ethereum_mpt_fuzz::{build_partial_trie, trie_root};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Initial entries.
+    entries: Vec<(Key, Value)>,
+    /// Keys (present or not) whose proofs are revealed.
+    revealed: Vec<Key>,
+    ops: Vec<Op>,
+}
+
+/// A key made of three leading bytes followed by zeros.
+#[derive(Debug, Arbitrary, Clone, Copy)]
+struct Key([u8; 3]);
+
+impl Key {
+    fn b256(self) -> B256 {
+        let mut key = B256::ZERO;
+        key[..3].copy_from_slice(&self.0);
+        key
+    }
+}
+
+/// A value which is either short enough for its leaf to be inlined or not.
+#[derive(Debug, Arbitrary, Clone, Copy)]
+enum Value {
+    Short(u8),
+    Long(u8),
+}
+
+impl Value {
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Self::Short(byte) => vec![byte],
+            Self::Long(byte) => vec![byte; 32],
+        }
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum Op {
+    Insert(Key, Value),
+    Remove(Key),
+}
+
+fuzz_target!(|input: Input| {
+    let mut entries: BTreeMap<_, _> =
+        input.entries.into_iter().map(|(key, value)| (key.b256(), value.bytes())).collect();
+    let revealed: Vec<_> = input.revealed.into_iter().map(Key::b256).collect();
+
+    let mut nodes = Vec::new();
+    let root = build_partial_trie(&entries, &revealed, &mut nodes);
+    let rlp_by_digest: B256Map<_> = nodes.into_iter().map(|rlp| (keccak256(&rlp), rlp)).collect();
+    let mut trie = Trie::reveal_from_rlp(root, &rlp_by_digest);
+    assert_eq!(trie.hash(), root);
+
+    let revealed: BTreeSet<_> = revealed.into_iter().collect();
+    for op in input.ops {
+        let root = trie.hash();
+        let result = match op {
+            Op::Insert(key, value) => {
+                let (key, value) = (key.b256(), value.bytes());
+                let result = trie.try_insert(key, Bytes::from(value.clone()));
+                // Everything on the path to a revealed key is resolved.
+                assert!(result.is_ok() || !revealed.contains(&key), "insert of a revealed key failed");
+                result.map(|()| entries.insert(key, value)).map(drop)
+            }
+            Op::Remove(key) => {
+                let key = key.b256();
+                trie.try_remove(key).map(|()| entries.remove(&key)).map(drop)
+            }
+        };
+        match result {
+            Ok(()) => assert_eq!(trie.hash(), trie_root(&entries)),
+            Err(_) => assert_eq!(trie.hash(), root, "failed operation modified the trie"),
+        }
+    }
+});
@@ -0,0 +1,77 @@
+//! Feeds the same witness and blocks of post-state changes to `SimpleSparseState` and the
+//! zeth-backed `SparseState`, comparing state roots, account reads and storage reads after every
+//! block.
+#![no_main]
+
+use alloy_primitives::{Address, U256};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt_state::SimpleSparseState;
+use stateless::StatelessTrie;
+use std::collections::BTreeSet;
+use zeth_mpt_state::SparseState;
+use zkvm_ethereum_mpt_fuzz::{apply_changes, build_state, build_witness, state_root, Change};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Pre-state accounts: address, balance and storage slots.
+    pre_state: Vec<(u8, u64, Vec<(u8, u64)>)>,
+    /// Blocks of post-state changes by address.
+    blocks: Vec<Vec<(u8, Change)>>,
+}
+
+// Compares the reads of every given account and, for existing accounts, of every given slot.
+// Like the EVM, storage is only read after the account has been read.
+fn assert_reads_match(
+    simple: &SimpleSparseState,
+    zeth: &SparseState,
+    addresses: &BTreeSet<u8>,
+    slots: &BTreeSet<u8>,
+) {
+    for address in addresses.iter().copied().map(Address::with_last_byte) {
+        let account = simple.account(address).unwrap();
+        assert_eq!(account, zeth.account(address).unwrap(), "account {address}");
+        if account.is_none() {
+            continue;
+        }
+        for slot in slots.iter().copied().map(U256::from) {
+            assert_eq!(
+                simple.storage(address, slot).unwrap(),
+                zeth.storage(address, slot).unwrap(),
+                "slot {slot} of {address}"
+            );
+        }
+    }
+}
+
+fuzz_target!(|input: Input| {
+    // Every address and slot appearing in the input is read back after each block.
+    let mut addresses = BTreeSet::new();
+    let mut slots = BTreeSet::new();
+    for (address, _, storage) in &input.pre_state {
+        addresses.insert(*address);
+        slots.extend(storage.iter().map(|(slot, _)| *slot));
+    }
+    for (address, change) in input.blocks.iter().flatten() {
+        addresses.insert(*address);
+        if let Change::Update { storage, .. } = change {
+            slots.extend(storage.iter().map(|(slot, _)| *slot));
+        }
+    }
+
+    let mut state = build_state(input.pre_state);
+    let (witness, pre_state_root) = build_witness(&state);
+    let (mut simple, _) =
+        SimpleSparseState::new(&witness, pre_state_root).expect("witness must be valid");
+    let (mut zeth, _) = SparseState::new(&witness, pre_state_root).expect("witness must be valid");
+    assert_reads_match(&simple, &zeth, &addresses, &slots);
+
+    for changes in input.blocks {
+        let post_state = apply_changes(&mut state, changes);
+        let simple_root = simple.calculate_state_root(post_state.clone()).unwrap();
+        let zeth_root = zeth.calculate_state_root(post_state).unwrap();
+        assert_eq!(simple_root, zeth_root);
+        assert_eq!(simple_root, state_root(&state));
+        assert_reads_match(&simple, &zeth, &addresses, &slots);
+    }
+});
@@ -0,0 +1,31 @@
+//! Compares `SimpleSparseState::calculate_state_root` against a `HashBuilder` root of the
+//! expected post-state.
+//!
+//! The pre-state is fully revealed by the witness; the post-state mixes account updates,
+//! destructions, storage wipes, slot writes and slot removals.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt_state::SimpleSparseState;
+use stateless::StatelessTrie;
+use zkvm_ethereum_mpt_fuzz::{apply_changes, build_state, build_witness, state_root, Change};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Pre-state accounts: address, balance and storage slots.
+    pre_state: Vec<(u8, u64, Vec<(u8, u64)>)>,
+    /// Post-state changes by address.
+    changes: Vec<(u8, Change)>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut state = build_state(input.pre_state);
+    let (witness, pre_state_root) = build_witness(&state);
+    let (mut sparse_state, _) =
+        SimpleSparseState::new(&witness, pre_state_root).expect("witness must be valid");
+
+    let post_state = apply_changes(&mut state, input.changes);
+    let root = sparse_state.calculate_state_root(post_state).expect("state root must be computable");
+    assert_eq!(root, state_root(&state));
+});
@@ -0,0 +1,81 @@
+//! Applies blocks of storage writes, zeroings and wipes to a single account through
+//! `SimpleSparseState::calculate_state_root`, comparing the state root after every block with a
+//! `HashBuilder` root of the expected state.
+//!
+//! Within a block, writes are applied before removals; the target checks that this ordering
+//! yields the same storage as applying the block slot by slot on a plain map, including across
+//! wipes and across blocks validated on the same state.
+#![no_main]
+
+use alloy_primitives::{keccak256, Address, B256, U256};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt_state::SimpleSparseState;
+use reth_primitives_traits::Account;
+use reth_trie_common::{HashedPostState, HashedStorage};
+use stateless::StatelessTrie;
+use std::collections::BTreeMap;
+use zkvm_ethereum_mpt_fuzz::{build_witness, state_root, State};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Initial storage of the account.
+    storage: Vec<(u8, u64)>,
+    /// Blocks changing the storage of the account.
+    blocks: Vec<Block>,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Block {
+    /// Whether the storage is wiped before the writes.
+    wiped: bool,
+    /// Slot writes. Zero values remove the slot, later writes of the same slot win.
+    writes: Vec<(u8, u64)>,
+}
+
+fuzz_target!(|input: Input| {
+    let address = Address::with_last_byte(1);
+    let storage = input
+        .storage
+        .into_iter()
+        .filter(|(_, value)| *value != 0)
+        .map(|(slot, value)| (U256::from(slot), U256::from(value)))
+        .collect();
+    // A second account keeps the state trie a branch, so the account is never the only leaf.
+    let mut state = State::from([
+        (address, (U256::from(1), storage)),
+        (Address::with_last_byte(2), (U256::from(2), BTreeMap::new())),
+    ]);
+    let (witness, pre_state_root) = build_witness(&state);
+    let (mut sparse_state, _) =
+        SimpleSparseState::new(&witness, pre_state_root).expect("witness must be valid");
+
+    let hashed_address = keccak256(address);
+    for block in input.blocks {
+        let account_storage = &mut state.get_mut(&address).unwrap().1;
+        if block.wiped {
+            account_storage.clear();
+        }
+        let mut hashed_storage = HashedStorage::new(block.wiped);
+        for (slot, value) in block.writes {
+            let (slot, value) = (U256::from(slot), U256::from(value));
+            if value.is_zero() {
+                account_storage.remove(&slot);
+            } else {
+                account_storage.insert(slot, value);
+            }
+            hashed_storage.storage.insert(keccak256(B256::from(slot)), value);
+        }
+
+        let mut post_state = HashedPostState::default();
+        post_state.accounts.insert(
+            hashed_address,
+            Some(Account { nonce: 0, balance: U256::from(1), bytecode_hash: None }),
+        );
+        post_state.storages.insert(hashed_address, hashed_storage);
+
+        let root =
+            sparse_state.calculate_state_root(post_state).expect("state root must be computable");
+        assert_eq!(root, state_root(&state));
+    }
+});
@@ -0,0 +1,18 @@
+//! Feeds raw bytes to the `ref-mpt` node decoder.
+//!
+//! Decoding must never panic, and re-encoding a decoded node must be stable: decoding the
+//! re-encoded bytes and encoding again yields the same bytes.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ref_mpt::fuzzing::decode_node;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(Some(encoded)) = decode_node(data) else {
+        return;
+    };
+    let reencoded = decode_node(&encoded)
+        .expect("re-encoded node must decode")
+        .expect("re-encoded node must not be empty");
+    assert_eq!(encoded, reencoded);
+});
@@ -0,0 +1,159 @@
+//! Mutates a valid witness with bit flips, truncations, deletions and duplications of its nodes,
+//! then validates a block of changes on a `SimpleSparseState` through its fallible paths only.
+//!
+//! A mutated node no longer hashes to the digest referencing it, so mutations amount to missing
+//! and junk nodes. Building the state must not panic. The changes are validated twice: as a whole
+//! post-state with `calculate_state_root`, and streamed after reading the declared keys. Each
+//! must either fail, with an error of `calculate_state_root`, `check_completeness` of the
+//! declared keys or the streamed changes, or read the pre-state values and yield the expected
+//! post-state root. The unmutated witness must validate.
+#![no_main]
+
+use alloy_primitives::{Address, Bytes, B256, U256};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ref_mpt_state::completeness::ExecutionWitnessKeys;
+use ref_mpt_state::SimpleSparseState;
+use reth_primitives_traits::Account;
+use stateless::validation::StatelessValidationError;
+use stateless::StatelessTrie;
+use std::collections::{BTreeMap, BTreeSet};
+use zkvm_ethereum_mpt_fuzz::{apply_changes, build_state, build_witness, state_root, Change};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Pre-state accounts: address, balance and storage slots.
+    pre_state: Vec<(u8, u64, Vec<(u8, u64)>)>,
+    /// Mutations of the witness nodes, applied in order.
+    mutations: Vec<Mutation>,
+    /// Post-state changes by address.
+    changes: Vec<(u8, Change)>,
+}
+
+/// A mutation of the node at index `node`, modulo the number of nodes.
+#[derive(Debug, Arbitrary)]
+enum Mutation {
+    BitFlip { node: u16, bit: u16 },
+    Truncate { node: u16, len: u8 },
+    Delete { node: u16 },
+    Duplicate { node: u16 },
+}
+
+impl Mutation {
+    fn apply(&self, nodes: &mut Vec<Bytes>) {
+        if nodes.is_empty() {
+            return;
+        }
+        let (Self::BitFlip { node, .. }
+        | Self::Truncate { node, .. }
+        | Self::Delete { node }
+        | Self::Duplicate { node }) = self;
+        let index = usize::from(*node) % nodes.len();
+        match self {
+            Self::BitFlip { bit, .. } if !nodes[index].is_empty() => {
+                let mut bytes = nodes[index].to_vec();
+                let bit = usize::from(*bit) % (bytes.len() * 8);
+                bytes[bit / 8] ^= 1 << (bit % 8);
+                nodes[index] = bytes.into();
+            }
+            Self::Truncate { len, .. } if !nodes[index].is_empty() => {
+                let len = usize::from(*len) % nodes[index].len();
+                nodes[index] = nodes[index].slice(..len);
+            }
+            Self::Delete { .. } => {
+                nodes.remove(index);
+            }
+            Self::Duplicate { .. } => nodes.push(nodes[index].clone()),
+            // an empty node has no bit to flip nor byte to drop
+            Self::BitFlip { .. } | Self::Truncate { .. } => {}
+        }
+    }
+}
+
+/// Streams the changes into the state, as an executor would, and returns the post-state root.
+fn stream_changes(
+    sparse_state: &mut SimpleSparseState,
+    changes: &BTreeMap<u8, Change>,
+) -> Result<B256, StatelessValidationError> {
+    for (address, change) in changes {
+        let address = Address::with_last_byte(*address);
+        match change {
+            Change::Destroy => sparse_state.apply_account_change(address, None)?,
+            Change::Update { balance, wiped, storage } => {
+                if *wiped {
+                    sparse_state.apply_account_change(address, None)?;
+                }
+                let account =
+                    Account { nonce: 0, balance: U256::from(*balance), bytecode_hash: None };
+                sparse_state.apply_account_change(address, Some(account))?;
+                for (slot, value) in storage {
+                    let (slot, value) = (U256::from(*slot), U256::from(*value));
+                    sparse_state.apply_storage_change(address, slot, value)?;
+                }
+            }
+        }
+    }
+    sparse_state.finalize_root()
+}
+
+fuzz_target!(|input: Input| {
+    let state = build_state(input.pre_state);
+    let (mut witness, pre_state_root) = build_witness(&state);
+    let mutated = !input.mutations.is_empty();
+    for mutation in &input.mutations {
+        mutation.apply(&mut witness.state);
+    }
+    let (mut sparse_state, _) =
+        SimpleSparseState::new(&witness, pre_state_root).expect("building the state never fails");
+
+    let changes: BTreeMap<u8, Change> = input.changes.into_iter().collect();
+    let mut post_state = state.clone();
+    let hashed_post_state = apply_changes(&mut post_state, changes.clone().into_iter().collect());
+    let expected_root = state_root(&post_state);
+    match sparse_state.clone().calculate_state_root(hashed_post_state) {
+        Ok(root) => assert_eq!(root, expected_root, "wrong post-state root accepted"),
+        Err(_) => assert!(mutated, "the unmutated witness fails to validate"),
+    }
+
+    // like the access list of a block, the accounts and slots the changes touch are declared
+    let addresses: BTreeSet<Address> =
+        changes.keys().copied().map(Address::with_last_byte).collect();
+    let mut slots = BTreeSet::new();
+    for change in changes.values() {
+        if let Change::Update { storage, .. } = change {
+            slots.extend(storage.iter().map(|(slot, _)| U256::from(*slot)));
+        }
+    }
+    let keys = ExecutionWitnessKeys {
+        accounts: addresses.iter().copied().collect(),
+        slots: addresses
+            .iter()
+            .flat_map(|address| slots.iter().map(|slot| (*address, (*slot).into())))
+            .collect(),
+    };
+    match sparse_state.check_completeness(&keys) {
+        Ok(missing) if missing.is_empty() => {}
+        _ => {
+            assert!(mutated, "the unmutated witness is complete");
+            return;
+        }
+    }
+
+    for address in &addresses {
+        let account = sparse_state.account(*address).unwrap();
+        let expected = state.get(address);
+        assert_eq!(account.map(|account| account.balance), expected.map(|(balance, _)| *balance));
+        let Some((_, storage)) = expected else {
+            continue;
+        };
+        for slot in &slots {
+            let value = sparse_state.storage(*address, *slot).unwrap();
+            assert_eq!(value, storage.get(slot).copied().unwrap_or_default(), "slot {slot}");
+        }
+    }
+
+    match stream_changes(&mut sparse_state, &changes) {
+        Ok(root) => assert_eq!(root, expected_root, "wrong post-state root accepted"),
+        Err(_) => assert!(mutated, "the unmutated witness fails to validate"),
+    }
+});
@@ -0,0 +1,218 @@
+//! Writes seed corpora for the fuzz targets from `StatelessInput` JSON fixtures.
+//!
+//! Every witness node of every fixture becomes a seed of `trie_node_decode`. The state targets
+//! (`state_root_diff`, `sparse_state_diff`, `witness_mutation` and `storage_wipe`) get the
+//! pre-state the fixture's block reads, with a block of changes writing every account and slot it
+//! reads: reads of present slots are rewritten, and every third of them removed. The first 256
+//! addresses and slots are numbered in key order to fit the single-byte keys of the targets, and
+//! balances and values are truncated to 64 bits, so a seed keeps the shape of the block rather
+//! than its values. Seeds are named after their hash so that running the tool again does not
+//! duplicate them:
+//!
+//! ```text
+//! cargo run --bin seed_corpus -- ../test_data/*.json
+//! ```
+//!
+//! The trie targets (`partial_reveal`, `branch_flags` and `operation_order`) draw keys from their
+//! two or three leading bytes only. Real hashed keys rarely share more than a few nibbles, so
+//! real tries would map onto flat branches of distinct keys and are not used as seeds.
+use alloy_primitives::{keccak256, Address, B256, U256};
+use alloy_rlp::Decodable;
+use arbitrary::{Arbitrary, Unstructured};
+use ref_mpt_state::SimpleSparseState;
+use reth_primitives_traits::Header;
+use stateless::{ExecutionWitness, StatelessInput, StatelessTrie};
+use std::{env, error::Error, fmt::Debug, fs, fs::File, io::BufReader, path::Path};
+use zkvm_ethereum_mpt_fuzz::Change;
+
+const CORPUS_DIR: &str = "corpus";
+
+/// Pre-state accounts as the state targets take them: address, balance and storage slots.
+type PreState = Vec<(u8, u64, Vec<(u8, u64)>)>;
+
+/// Post-state changes by address.
+type Changes = Vec<(u8, Change)>;
+
+/// A value written in the byte format `arbitrary` decodes it from.
+trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+impl Encode for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend(self.to_le_bytes());
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+}
+
+// every element is preceded by a byte with its lowest bit set, and the last one followed by zero
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for item in self {
+            out.push(1);
+            item.encode(out);
+        }
+        out.push(0);
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+        self.2.encode(out);
+    }
+}
+
+// the variant is picked by scaling a `u32` to the number of variants
+fn encode_variant(index: u64, count: u64, out: &mut Vec<u8>) {
+    let scaled = u32::try_from((index << 32).div_ceil(count)).expect("variant index");
+    out.extend(scaled.to_le_bytes());
+}
+
+impl Encode for Change {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Destroy => encode_variant(0, 2, out),
+            Self::Update { balance, wiped, storage } => {
+                encode_variant(1, 2, out);
+                (*balance, *wiped, storage.clone()).encode(out);
+            }
+        }
+    }
+}
+
+/// Encodes the input of a target, given as the tuple of the fields of its `Input`, and checks that
+/// `arbitrary` decodes it back.
+fn encode<T>(input: &T) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    T: Encode + for<'a> Arbitrary<'a> + PartialEq + Debug,
+{
+    let mut bytes = Vec::new();
+    input.encode(&mut bytes);
+    let decoded = T::arbitrary_take_rest(Unstructured::new(&bytes))?;
+    if decoded != *input {
+        return Err(
+            format!("arbitrary decodes {input:?} as {decoded:?}, update the encoding").into()
+        );
+    }
+    Ok(bytes)
+}
+
+/// Writes a seed of a target, named after its hash.
+fn write_seed(target: &str, seed: &[u8]) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(CORPUS_DIR).join(target);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(keccak256(seed).to_string()), seed)?;
+    Ok(())
+}
+
+/// Returns the state root of the parent block, taken from the witness headers.
+fn parent_state_root(witness: &ExecutionWitness, number: u64) -> Result<B256, Box<dyn Error>> {
+    for rlp in &witness.headers {
+        let header = Header::decode(&mut &rlp[..])?;
+        if header.number + 1 == number {
+            return Ok(header.state_root);
+        }
+    }
+    Err(format!("parent of block {number} missing from the witness headers").into())
+}
+
+/// Rewrites every slot, removing every third one.
+fn rewrite(storage: &[(u8, u64)]) -> Vec<(u8, u64)> {
+    storage
+        .iter()
+        .enumerate()
+        .map(|(i, (slot, value))| (*slot, if i % 3 == 0 { 0 } else { value.wrapping_add(1) }))
+        .collect()
+}
+
+/// Reads the accounts and slots named by the witness keys, numbered in key order, and returns the
+/// pre-state and a block of changes writing all of them.
+fn block_shape(input: &StatelessInput) -> Result<(PreState, Changes), Box<dyn Error>> {
+    let root = parent_state_root(&input.witness, input.block.header.number)?;
+    let (state, _) = SimpleSparseState::new(&input.witness, root)?;
+    let keys = &input.witness.keys;
+    let addresses: Vec<Address> =
+        keys.iter().filter(|key| key.len() == 20).map(|key| Address::from_slice(key)).collect();
+    let slots: Vec<U256> =
+        keys.iter().filter(|key| key.len() == 32).map(|key| U256::from_be_slice(key)).collect();
+
+    let (mut pre_state, mut changes) = (Vec::new(), Vec::new());
+    for (address, index) in addresses.into_iter().zip(0..=u8::MAX) {
+        let Some(account) = state.account(address)? else {
+            changes.push((index, Change::Update { balance: 1, wiped: false, storage: Vec::new() }));
+            continue;
+        };
+        let balance: u64 = account.balance.saturating_to();
+        // the slots of other accounts may lie in subtrees missing from the witness
+        let storage: Vec<(u8, u64)> = slots
+            .iter()
+            .zip(0..=u8::MAX)
+            .filter_map(|(slot, slot_index)| {
+                let value = state.storage(address, *slot).ok()?;
+                (!value.is_zero()).then(|| (slot_index, value.saturating_to()))
+            })
+            .collect();
+        let writes = rewrite(&storage);
+        pre_state.push((index, balance, storage));
+        changes.push((
+            index,
+            Change::Update { balance: balance.wrapping_add(1), wiped: false, storage: writes },
+        ));
+    }
+    Ok((pre_state, changes))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let fixtures: Vec<String> = env::args().skip(1).collect();
+    if fixtures.is_empty() {
+        return Err("usage: seed_corpus <fixture.json>...".into());
+    }
+
+    for fixture in fixtures {
+        let input: StatelessInput = serde_json::from_reader(BufReader::new(File::open(&fixture)?))?;
+        let nodes = &input.witness.state;
+        for rlp in nodes {
+            write_seed("trie_node_decode", rlp)?;
+        }
+
+        let (pre_state, changes) = block_shape(&input)?;
+        write_seed("state_root_diff", &encode(&(pre_state.clone(), changes.clone()))?)?;
+        write_seed("sparse_state_diff", &encode(&(pre_state.clone(), vec![changes.clone()]))?)?;
+        // no mutations, which encode alike whatever the element type
+        let no_mutations: Vec<u8> = Vec::new();
+        write_seed("witness_mutation", &encode(&(pre_state.clone(), no_mutations, changes))?)?;
+        // the account with the most slots
+        if let Some((_, _, storage)) = pre_state.iter().max_by_key(|(_, _, storage)| storage.len())
+        {
+            let writes = rewrite(storage);
+            write_seed("storage_wipe", &encode(&(storage.clone(), vec![(false, writes)]))?)?;
+        }
+        println!(
+            "{fixture}: {} trie_node_decode seeds, {} accounts in the state target seeds",
+            nodes.len(),
+            pre_state.len()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Soak test running long random sequences of inserts, removals, lookups and hash computations on
+//! `ref_mpt::Trie` and `zeth_mpt::CachedTrie` side by side, for overnight runs.
+//!
+//! Unlike the fuzz targets, which start from an empty state for every input, the tries live for
+//! the whole run. Both backends are compared with each other on every hash and lookup, and with a
+//! `HashBuilder` root of the expected entries every `--check-every` operations, when the current
+//! and peak heap usage are reported as well. Every `--reveal-every` operations both tries are
+//! rebuilt from the RLP nodes of the zeth trie, so that mutations of revealed nodes are exercised:
+//!
+//! ```text
+//! cargo run --release --bin stress -- --ops 100000000 --seed 7
+//! ```
+use alloy_primitives::{keccak256, Bytes, B256};
+use ref_mpt::{B256Map, Trie};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::BTreeMap,
+    env,
+    error::Error,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+use zeth_mpt::CachedTrie;
+use zkvm_ethereum_mpt_fuzz::trie_root;
+
+/// Heap bytes currently allocated.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+/// Highest value of `ALLOCATED` seen so far.
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Wraps the system allocator to track heap usage.
+struct CountingAllocator;
+
+impl CountingAllocator {
+    fn grow(size: usize) {
+        let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            Self::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            Self::grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Command line options, see `USAGE`.
+struct Options {
+    ops: u64,
+    seed: u64,
+    keys: u64,
+    check_every: u64,
+    reveal_every: u64,
+}
+
+const USAGE: &str = "usage: stress [--ops <n>] [--seed <n>] [--keys <n>] [--check-every <n>] \
+                     [--reveal-every <n>]";
+
+impl Options {
+    fn parse() -> Result<Self, Box<dyn Error>> {
+        let mut options = Self {
+            ops: 10_000_000,
+            seed: 0,
+            keys: 1 << 16,
+            check_every: 100_000,
+            reveal_every: 1_000_000,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value: u64 = args.next().ok_or(USAGE)?.parse()?;
+            match flag.as_str() {
+                "--ops" => options.ops = value,
+                "--seed" => options.seed = value,
+                "--keys" => options.keys = value.max(1),
+                "--check-every" => options.check_every = value.max(1),
+                "--reveal-every" => options.reveal_every = value.max(1),
+                _ => return Err(USAGE.into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// SplitMix64, good enough to drive the operations and reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// The tries under test and the expected entries.
+struct Backends {
+    entries: BTreeMap<B256, Vec<u8>>,
+    ref_trie: Trie,
+    zeth_trie: CachedTrie,
+}
+
+impl Backends {
+    fn insert(&mut self, key: B256, value: Vec<u8>) {
+        self.ref_trie.insert(key, Bytes::from(value.clone()));
+        self.zeth_trie.insert(key, value.clone());
+        self.entries.insert(key, value);
+    }
+
+    fn remove(&mut self, key: B256) {
+        self.ref_trie.remove(key);
+        let removed = self.zeth_trie.remove(key);
+        assert_eq!(removed, self.entries.remove(&key).is_some(), "zeth removal of {key}");
+    }
+
+    fn get(&self, key: B256) {
+        let expected = self.entries.get(&key).map(Vec::as_slice);
+        assert_eq!(self.ref_trie.get(key).map(|value| &value[..]), expected, "ref-mpt get {key}");
+        assert_eq!(self.zeth_trie.get(key), expected, "zeth get {key}");
+    }
+
+    fn hash(&mut self) -> B256 {
+        let root = self.ref_trie.hash();
+        assert_eq!(root, self.zeth_trie.hash(), "ref-mpt and zeth roots differ");
+        root
+    }
+
+    /// Rebuilds both tries from the RLP nodes of the zeth trie.
+    fn reveal(&mut self) {
+        let root = self.hash();
+        if self.entries.is_empty() {
+            self.ref_trie = Trie::new();
+            self.zeth_trie = CachedTrie::default();
+            return;
+        }
+        let rlp_by_digest: B256Map<Bytes> = self
+            .zeth_trie
+            .rlp_nodes()
+            .into_iter()
+            .map(|rlp| (keccak256(&rlp), rlp))
+            .collect();
+        self.ref_trie = Trie::reveal_from_rlp(root, &rlp_by_digest);
+        self.zeth_trie =
+            CachedTrie::from_prehashed_nodes(root, &rlp_by_digest).expect("zeth nodes must decode");
+        assert_eq!(self.hash(), root, "revealed roots differ");
+    }
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::parse()?;
+    let mut rng = Rng(options.seed);
+    let mut backends = Backends {
+        entries: BTreeMap::new(),
+        ref_trie: Trie::new(),
+        zeth_trie: CachedTrie::default(),
+    };
+
+    let start = Instant::now();
+    for op in 1..=options.ops {
+        let key = keccak256(rng.below(options.keys).to_be_bytes());
+        // `ref_mpt::Trie` re-encodes the whole trie on every hash, so hashing is kept rare.
+        match rng.below(1000) {
+            0..500 => {
+                // Short values give inlined leaves near the bottom of the trie, long ones do not.
+                let len = 1 + rng.below(40) as usize;
+                let value = (0..len).map(|_| rng.next() as u8).collect();
+                backends.insert(key, value);
+            }
+            500..850 => backends.remove(key),
+            850..999 => backends.get(key),
+            _ => {
+                backends.hash();
+            }
+        }
+
+        if op % options.reveal_every == 0 {
+            backends.reveal();
+        }
+        if op % options.check_every == 0 {
+            assert_eq!(backends.hash(), trie_root(&backends.entries), "root differs from model");
+            println!(
+                "{op} ops, {} entries, {:.0} ops/s, heap {:.1} MiB (peak {:.1} MiB)",
+                backends.entries.len(),
+                op as f64 / start.elapsed().as_secs_f64(),
+                mib(ALLOCATED.load(Ordering::Relaxed)),
+                mib(PEAK.load(Ordering::Relaxed)),
+            );
+        }
+    }
+
+    println!("done: {} ops with seed {} in {:.1?}", options.ops, options.seed, start.elapsed());
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Helpers shared by the fuzz targets: plain state models and witnesses built from them with
+//! alloy-trie's `HashBuilder`, which serves as the reference implementation.
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_trie::{proof::ProofRetainer, HashBuilder, Nibbles, TrieAccount, KECCAK_EMPTY};
+use arbitrary::Arbitrary;
+use reth_primitives_traits::Account;
+use reth_trie_common::{HashedPostState, HashedStorage};
+use std::collections::BTreeMap;
+use stateless::ExecutionWitness;
+
+/// Accounts (by address) with their balance and non-zero storage slots.
+pub type State = BTreeMap<Address, (U256, BTreeMap<U256, U256>)>;
+
+/// A change of an account in a post-state.
+///
+/// Addresses and slots are drawn from a single byte so that changes frequently hit existing
+/// entries.
+#[derive(Debug, Clone, PartialEq, Eq, Arbitrary)]
+pub enum Change {
+    /// The account is removed together with its storage.
+    Destroy,
+    /// The account is created or updated. Zero slot values remove the slot.
+    Update {
+        /// New balance of the account.
+        balance: u64,
+        /// Whether the storage is wiped before the slot writes.
+        wiped: bool,
+        /// Slot writes. Later writes of the same slot win.
+        storage: Vec<(u8, u64)>,
+    },
+}
+
+/// Builds a state from (address, balance, slots) tuples, dropping zero slot values.
+pub fn build_state(accounts: Vec<(u8, u64, Vec<(u8, u64)>)>) -> State {
+    let mut state = State::new();
+    for (address, balance, storage) in accounts {
+        let storage = storage
+            .into_iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(slot, value)| (U256::from(slot), U256::from(value)))
+            .collect();
+        state.insert(Address::with_last_byte(address), (U256::from(balance), storage));
+    }
+    state
+}
+
+/// Applies the changes by address to `state`, later changes of the same address winning, and
+/// returns them as a post-state.
+pub fn apply_changes(state: &mut State, changes: Vec<(u8, Change)>) -> HashedPostState {
+    let changes: BTreeMap<_, _> = changes.into_iter().collect();
+    let mut post_state = HashedPostState::default();
+    for (address, change) in changes {
+        let address = Address::with_last_byte(address);
+        let hashed_address = keccak256(address);
+        match change {
+            Change::Destroy => {
+                state.remove(&address);
+                post_state.accounts.insert(hashed_address, None);
+                post_state.storages.insert(hashed_address, HashedStorage::new(true));
+            }
+            Change::Update { balance, wiped, storage } => {
+                let (account_balance, account_storage) = state.entry(address).or_default();
+                *account_balance = U256::from(balance);
+                if wiped {
+                    account_storage.clear();
+                }
+                let mut hashed_storage = HashedStorage::new(wiped);
+                for (slot, value) in storage {
+                    let (slot, value) = (U256::from(slot), U256::from(value));
+                    if value.is_zero() {
+                        account_storage.remove(&slot);
+                    } else {
+                        account_storage.insert(slot, value);
+                    }
+                    hashed_storage.storage.insert(keccak256(B256::from(slot)), value);
+                }
+                post_state.accounts.insert(
+                    hashed_address,
+                    Some(Account { nonce: 0, balance: U256::from(balance), bytecode_hash: None }),
+                );
+                post_state.storages.insert(hashed_address, hashed_storage);
+            }
+        }
+    }
+    post_state
+}
+
+/// Builds a trie with a `HashBuilder`, appending all its nodes to `nodes`. Returns the root.
+pub fn build_trie(leaves: BTreeMap<B256, Vec<u8>>, nodes: &mut Vec<Bytes>) -> B256 {
+    let targets = leaves.keys().copied().collect::<Vec<_>>();
+    build_partial_trie(&leaves, &targets, nodes)
+}
+
+/// Builds a trie with a `HashBuilder`, appending only the nodes on the paths to `targets` to
+/// `nodes`. Returns the root.
+pub fn build_partial_trie(
+    leaves: &BTreeMap<B256, Vec<u8>>,
+    targets: &[B256],
+    nodes: &mut Vec<Bytes>,
+) -> B256 {
+    let targets = targets.iter().map(Nibbles::unpack).collect();
+    let mut hash_builder = HashBuilder::default().with_proof_retainer(ProofRetainer::new(targets));
+    for (key, value) in leaves {
+        hash_builder.add_leaf(Nibbles::unpack(key), value);
+    }
+    let root = hash_builder.root();
+    nodes.extend(hash_builder.take_proof_nodes().values().cloned());
+    root
+}
+
+/// Returns the root of the trie with the given leaves.
+pub fn trie_root(leaves: &BTreeMap<B256, Vec<u8>>) -> B256 {
+    let mut hash_builder = HashBuilder::default();
+    for (key, value) in leaves {
+        hash_builder.add_leaf(Nibbles::unpack(key), value);
+    }
+    hash_builder.root()
+}
+
+/// Builds a witness revealing the whole given state. Returns the witness and the state root.
+pub fn build_witness(state: &State) -> (ExecutionWitness, B256) {
+    let mut nodes = Vec::new();
+    let mut accounts = BTreeMap::new();
+    for (address, (balance, storage)) in state {
+        let slots = storage
+            .iter()
+            .map(|(slot, value)| (keccak256(B256::from(*slot)), alloy_rlp::encode(value)))
+            .collect();
+        let account = TrieAccount {
+            nonce: 0,
+            balance: *balance,
+            storage_root: build_trie(slots, &mut nodes),
+            code_hash: KECCAK_EMPTY,
+        };
+        accounts.insert(keccak256(address), alloy_rlp::encode(account));
+    }
+    let root = build_trie(accounts, &mut nodes);
+
+    let witness = ExecutionWitness { state: nodes, ..Default::default() };
+    (witness, root)
+}
+
+/// Returns the state root of the given state.
+pub fn state_root(state: &State) -> B256 {
+    build_witness(state).1
+}
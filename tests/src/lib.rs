@@ -1,56 +1,71 @@
 #![allow(missing_docs)]
 
+#[cfg(test)]
+mod state_tests;
+
 #[cfg(test)]
 mod tests {
-    use alloy_primitives::Signature;
+    mod eest;
+
     use reth_chainspec::ChainSpec;
     use reth_evm_ethereum::EthEvmConfig;
     use stateless::{
         stateless_validation_with_trie, validation::stateless_validation, Genesis, StatelessInput,
-        UncompressedPublicKey,
     };
-    use ref_mpt_state::SimpleSparseState;
-    use std::{fs::File, path::PathBuf, sync::Arc};
-
-    /// Recovers the uncompressed public key from a transaction signature and signing hash.
-    fn recover_public_key(sig: &Signature, hash: alloy_primitives::B256) -> UncompressedPublicKey {
-        let r = sig.r();
-        let s = sig.s();
-        let mut sig_bytes = [0u8; 64];
-        sig_bytes[..32].copy_from_slice(&r.to_be_bytes::<32>());
-        sig_bytes[32..].copy_from_slice(&s.to_be_bytes::<32>());
-
-        let signature =
-            k256::ecdsa::Signature::from_slice(&sig_bytes).expect("valid signature bytes");
-        let recid = k256::ecdsa::RecoveryId::new(sig.v(), false);
-
-        let key = k256::ecdsa::VerifyingKey::recover_from_prehash(
-            hash.as_slice(),
-            &signature,
-            recid,
-        )
-        .expect("valid public key recovery");
+    use ref_mpt_state::{
+        parallel::validate_blocks, signers::recover_public_keys, SimpleSparseState,
+    };
+    use std::{
+        fs,
+        fs::File,
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    /// Environment variable naming an extra directory of `StatelessInput` JSON fixtures.
+    const FIXTURES_DIR_ENV: &str = "STATELESS_FIXTURES_DIR";
 
-        let point = key.to_encoded_point(false);
-        let mut bytes = [0u8; 65];
-        bytes.copy_from_slice(point.as_bytes());
-        UncompressedPublicKey(bytes)
+    /// Returns the JSON files of a fixture directory, sorted by name.
+    fn json_files(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap_or_else(|err| panic!("failed to read fixture directory {dir:?}: {err}"))
+            .map(|entry| entry.expect("failed to read fixture directory entry").path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        paths
     }
 
-    #[test]
-    fn stateless_validation_test() {
-        let mut input_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        input_path.push("../test_data/rpc_block_23439901.json");
-        if !input_path.exists() {
-            eprintln!("skipping stateless_validation_test: missing fixture {input_path:?}");
-            return;
+    /// Loads every fixture from `test_data/` and from the directory named by
+    /// `STATELESS_FIXTURES_DIR`, if set. A missing `test_data/` is skipped.
+    fn load_fixtures(test_name: &str) -> Vec<(PathBuf, StatelessInput)> {
+        let mut paths = Vec::new();
+        let test_data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../test_data");
+        if test_data.exists() {
+            paths.extend(json_files(&test_data));
+        }
+        if let Some(dir) = std::env::var_os(FIXTURES_DIR_ENV) {
+            paths.extend(json_files(Path::new(&dir)));
+        }
+        if paths.is_empty() {
+            eprintln!("skipping {test_name}: no fixtures in {test_data:?} or ${FIXTURES_DIR_ENV}");
         }
 
-        let input = serde_json::from_reader::<_, StatelessInput>(
-            File::open(input_path).expect("failed to open test input"),
-        )
-        .expect("failed to parse stateless input");
+        paths
+            .into_iter()
+            .map(|path| {
+                let input = serde_json::from_reader::<_, StatelessInput>(
+                    File::open(&path).expect("failed to open test input"),
+                )
+                .unwrap_or_else(|err| panic!("failed to parse stateless input {path:?}: {err}"));
+                (path, input)
+            })
+            .collect()
+    }
 
+    /// Validates a fixture with reth's sparse trie and with `SimpleSparseState`, expecting the
+    /// same result.
+    fn replay_fixture(path: &Path, input: StatelessInput) {
         let genesis = Genesis {
             config: input.chain_config.clone(),
             ..Default::default()
@@ -58,17 +73,7 @@ mod tests {
         let chain_spec: Arc<ChainSpec> = Arc::new(genesis.into());
         let evm_config = EthEvmConfig::new(chain_spec.clone());
 
-        let public_keys: Vec<UncompressedPublicKey> = input
-            .block
-            .body
-            .transactions
-            .iter()
-            .map(|tx| {
-                let sig = tx.signature();
-                let hash = tx.signature_hash();
-                recover_public_key(sig, hash)
-            })
-            .collect();
+        let public_keys = recover_public_keys(&input).expect("valid public key recovery");
 
         let reth_result = stateless_validation(
             input.block.clone(),
@@ -77,7 +82,7 @@ mod tests {
             chain_spec.clone(),
             evm_config.clone(),
         )
-        .expect("reth stateless validation error");
+        .unwrap_or_else(|err| panic!("{path:?}: reth stateless validation error: {err}"));
 
         let simple_result =
             stateless_validation_with_trie::<SimpleSparseState, ChainSpec, EthEvmConfig>(
@@ -87,8 +92,43 @@ mod tests {
                 chain_spec,
                 evm_config,
             )
-            .expect("simple sparse stateless validation error");
+            .unwrap_or_else(|err| {
+                panic!("{path:?}: simple sparse stateless validation error: {err}")
+            });
+
+        assert_eq!(reth_result, simple_result, "{path:?}");
+    }
+
+    #[test]
+    fn stateless_validation_test() {
+        for (path, input) in load_fixtures("stateless_validation_test") {
+            replay_fixture(&path, input);
+        }
+    }
 
-        assert_eq!(reth_result, simple_result);
+    #[test]
+    fn parallel_validation_test() {
+        let fixtures = load_fixtures("parallel_validation_test");
+        // Every fixture is validated twice, so the batch holds several blocks even for a single
+        // fixture.
+        let inputs: Vec<_> = fixtures.iter().chain(&fixtures).collect();
+        let expected: Vec<_> = inputs
+            .iter()
+            .map(|(_, input)| input.block.header.hash_slow())
+            .collect();
+
+        let blocks = inputs
+            .into_iter()
+            .map(|(_, input)| {
+                let public_keys = recover_public_keys(input).expect("valid public key recovery");
+                (input.clone(), public_keys)
+            })
+            .collect();
+        let results = validate_blocks(blocks);
+
+        assert_eq!(results.len(), expected.len());
+        for (result, expected) in results.into_iter().zip(expected) {
+            assert_eq!(result.expect("parallel stateless validation error"), expected);
+        }
     }
 }
@@ -0,0 +1,134 @@
+//! Trie conformance against ethereum/tests and execution-spec-tests fixtures.
+//!
+//! Every JSON file below `$ETHEREUM_TESTS_DIR` is scanned for state allocations whose root is
+//! given by the fixture:
+//! - `pre` of blockchain tests against the genesis header state root,
+//! - `postState` of blockchain tests against the state root of the last block,
+//! - `post.<fork>[].state` of state tests against the expected post-state hash.
+//!
+//! The allocations are turned into account and storage tries with `ref_mpt::Trie`. The test is
+//! skipped when the variable is not set.
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_trie::TrieAccount;
+use ref_mpt::Trie;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable naming a directory of ethereum/tests or EEST JSON fixtures.
+const ETHEREUM_TESTS_DIR_ENV: &str = "ETHEREUM_TESTS_DIR";
+
+/// An account of a fixture allocation.
+#[derive(Debug, Deserialize)]
+struct AllocAccount {
+    balance: U256,
+    nonce: U256,
+    code: Bytes,
+    #[serde(default)]
+    storage: BTreeMap<U256, U256>,
+}
+
+/// Computes the state root of an allocation with `ref_mpt::Trie`.
+fn alloc_root(alloc: &BTreeMap<Address, AllocAccount>) -> B256 {
+    let mut state = Trie::new();
+    for (address, account) in alloc {
+        let mut storage = Trie::new();
+        for (slot, value) in account.storage.iter().filter(|(_, value)| !value.is_zero()) {
+            storage.insert(keccak256(B256::from(*slot)), alloy_rlp::encode(value).into());
+        }
+        let account = TrieAccount {
+            nonce: account.nonce.to(),
+            balance: account.balance,
+            storage_root: storage.hash(),
+            code_hash: keccak256(&account.code),
+        };
+        state.insert(keccak256(address), alloy_rlp::encode(account).into());
+    }
+    state.hash()
+}
+
+/// Collects all JSON files below `dir`.
+pub(crate) fn json_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap_or_else(|err| panic!("failed to read {dir:?}: {err}")) {
+        let path = entry.expect("failed to read directory entry").path();
+        if path.is_dir() {
+            json_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+}
+
+/// Returns the (description, allocation, expected root) triples of a single test.
+fn alloc_cases<'a>(name: &str, test: &'a Value) -> Vec<(String, &'a Value, B256)> {
+    let root = |value: &Value| serde_json::from_value::<B256>(value.clone()).ok();
+    let mut cases = Vec::new();
+
+    if let Some(expected) = root(&test["genesisBlockHeader"]["stateRoot"]) {
+        cases.push((format!("{name}: pre"), &test["pre"], expected));
+    }
+    if let (Value::Object(_), Some(last_hash)) = (&test["postState"], test["lastblockhash"].as_str())
+    {
+        let last_header = test["blocks"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|block| &block["blockHeader"])
+            .find(|header| header["hash"].as_str() == Some(last_hash));
+        if let Some(expected) = last_header.and_then(|header| root(&header["stateRoot"])) {
+            cases.push((format!("{name}: postState"), &test["postState"], expected));
+        }
+    }
+    if let Value::Object(forks) = &test["post"] {
+        for (fork, entries) in forks {
+            for (idx, entry) in entries.as_array().into_iter().flatten().enumerate() {
+                if let (Value::Object(_), Some(expected)) = (&entry["state"], root(&entry["hash"])) {
+                    cases.push((format!("{name}: post.{fork}[{idx}]"), &entry["state"], expected));
+                }
+            }
+        }
+    }
+    cases
+}
+
+#[test]
+fn fixture_alloc_roots() {
+    let Some(dir) = std::env::var_os(ETHEREUM_TESTS_DIR_ENV) else {
+        eprintln!("skipping fixture_alloc_roots: ${ETHEREUM_TESTS_DIR_ENV} is not set");
+        return;
+    };
+    let mut files = Vec::new();
+    json_files(Path::new(&dir), &mut files);
+    files.sort();
+
+    let mut checked = 0;
+    let mut failures = Vec::new();
+    for file in &files {
+        let fixture: Value = serde_json::from_reader(
+            fs::File::open(file).unwrap_or_else(|err| panic!("failed to open {file:?}: {err}")),
+        )
+        .unwrap_or_else(|err| panic!("failed to parse {file:?}: {err}"));
+        let Value::Object(tests) = fixture else {
+            continue;
+        };
+        for (name, test) in &tests {
+            for (case, alloc, expected) in alloc_cases(name, test) {
+                let alloc: BTreeMap<Address, AllocAccount> =
+                    serde_json::from_value(alloc.clone())
+                        .unwrap_or_else(|err| panic!("{file:?}: {case}: invalid alloc: {err}"));
+                checked += 1;
+                let root = alloc_root(&alloc);
+                if root != expected {
+                    failures.push(format!("{file:?}: {case}: expected {expected}, got {root}"));
+                }
+            }
+        }
+    }
+
+    eprintln!("checked {checked} state roots from {} files", files.len());
+    assert!(failures.is_empty(), "{} mismatches:\n{}", failures.len(), failures.join("\n"));
+}
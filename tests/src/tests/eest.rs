@@ -0,0 +1,147 @@
+//! Replays execution-spec-tests blockchain fixtures carrying execution witnesses through
+//! `SimpleSparseState`.
+//!
+//! Every JSON file below `$EEST_FIXTURES_DIR` is scanned for blockchain tests whose blocks carry
+//! an `executionWitness`. Each such block is validated statelessly and must yield the block hash
+//! of the fixture. Networks other than a single fork from Paris on, and blocks expected to be
+//! invalid, are skipped. The test is skipped when the variable is not set.
+use crate::state_tests::json_files;
+use alloy_genesis::ChainConfig;
+use alloy_primitives::{address, Bytes, B256, U256};
+use ref_mpt_state::{parallel::validate_blocks, signers::recover_public_keys};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use stateless::{ExecutionWitness, StatelessInput};
+use std::{fs, path::Path};
+
+/// Environment variable naming a directory of EEST blockchain test fixtures.
+const EEST_FIXTURES_DIR_ENV: &str = "EEST_FIXTURES_DIR";
+
+/// Forks supported by stateless validation, in activation order.
+const FORKS: [&str; 5] = ["Paris", "Shanghai", "Cancun", "Prague", "Osaka"];
+
+/// Returns the config of a chain with every fork up to `network` active at genesis.
+fn chain_config(network: &str) -> Option<ChainConfig> {
+    let network = if network == "Merge" { "Paris" } else { network };
+    let fork = FORKS.iter().position(|name| *name == network)?;
+    let activation = |name: &str| {
+        FORKS
+            .iter()
+            .position(|other| *other == name)
+            .is_some_and(|idx| idx <= fork)
+            .then_some(0)
+    };
+
+    Some(ChainConfig {
+        chain_id: 1,
+        homestead_block: Some(0),
+        eip150_block: Some(0),
+        eip155_block: Some(0),
+        eip158_block: Some(0),
+        byzantium_block: Some(0),
+        constantinople_block: Some(0),
+        petersburg_block: Some(0),
+        istanbul_block: Some(0),
+        berlin_block: Some(0),
+        london_block: Some(0),
+        merge_netsplit_block: Some(0),
+        terminal_total_difficulty: Some(U256::ZERO),
+        terminal_total_difficulty_passed: true,
+        shanghai_time: activation("Shanghai"),
+        cancun_time: activation("Cancun"),
+        prague_time: activation("Prague"),
+        osaka_time: activation("Osaka"),
+        deposit_contract_address: Some(address!("0x00000000219ab540356cbb839cbe05303d7705fa")),
+        ..Default::default()
+    })
+}
+
+/// Deserializes a field of a fixture block, panicking with the test case on failure.
+fn field<T: DeserializeOwned>(value: &Value, case: &str, what: &str) -> T {
+    serde_json::from_value(value.clone())
+        .unwrap_or_else(|err| panic!("{case}: invalid {what}: {err}"))
+}
+
+/// Returns the stateless inputs of the valid blocks with a witness of a single test, together
+/// with their description and expected block hash.
+fn witness_blocks(name: &str, test: &Value) -> Vec<(String, B256, StatelessInput)> {
+    let Some(chain_config) = test["network"].as_str().and_then(chain_config) else {
+        return Vec::new();
+    };
+
+    let mut blocks = Vec::new();
+    for (idx, block) in test["blocks"].as_array().into_iter().flatten().enumerate() {
+        if block.get("expectException").is_some() {
+            continue;
+        }
+        let Some(witness) = block.get("executionWitness") else {
+            continue;
+        };
+        let case = format!("{name}: block {idx}");
+        let rlp: Bytes = field(&block["rlp"], &case, "rlp");
+        let witness: ExecutionWitness = field(witness, &case, "execution witness");
+        let hash: B256 = field(&block["blockHeader"]["hash"], &case, "block hash");
+        let input = StatelessInput {
+            block: alloy_rlp::decode_exact(&rlp)
+                .unwrap_or_else(|err| panic!("{case}: invalid block: {err}")),
+            witness,
+            chain_config: chain_config.clone(),
+        };
+        blocks.push((case, hash, input));
+    }
+    blocks
+}
+
+#[test]
+fn eest_witness_fixtures() {
+    let Some(dir) = std::env::var_os(EEST_FIXTURES_DIR_ENV) else {
+        eprintln!("skipping eest_witness_fixtures: ${EEST_FIXTURES_DIR_ENV} is not set");
+        return;
+    };
+    let mut files = Vec::new();
+    json_files(Path::new(&dir), &mut files);
+    files.sort();
+
+    let mut cases = Vec::new();
+    let mut inputs = Vec::new();
+    for file in &files {
+        let fixture: Value = serde_json::from_reader(
+            fs::File::open(file).unwrap_or_else(|err| panic!("failed to open {file:?}: {err}")),
+        )
+        .unwrap_or_else(|err| panic!("failed to parse {file:?}: {err}"));
+        let Value::Object(tests) = fixture else {
+            continue;
+        };
+        for (name, test) in &tests {
+            for (case, hash, input) in witness_blocks(name, test) {
+                let public_keys = recover_public_keys(&input).expect("valid public key recovery");
+                cases.push((format!("{file:?}: {case}"), hash));
+                inputs.push((input, public_keys));
+            }
+        }
+    }
+
+    let failures: Vec<_> = validate_blocks(inputs)
+        .into_iter()
+        .zip(&cases)
+        .filter_map(|(result, (case, expected))| match result {
+            Ok(hash) if hash == *expected => None,
+            Ok(hash) => Some(format!(
+                "{case}: expected block hash {expected}, got {hash}"
+            )),
+            Err(err) => Some(format!("{case}: {err}")),
+        })
+        .collect();
+
+    eprintln!(
+        "validated {} blocks from {} files",
+        cases.len(),
+        files.len()
+    );
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}